@@ -109,6 +109,8 @@ const api = {
   stops: () => api.json("GET", "/stops"),
   setStopChannel: (stopId, ch, active) =>
     api.json("POST", `/stops/${stopId}/channels/${ch}`, { active }),
+  setCouplerChannel: (couplerId, ch, active) =>
+    api.json("POST", `/couplers/${couplerId}/channels/${ch}`, { active }),
   presets: () => api.json("GET", "/presets"),
   loadPreset: (slot) => api.json("POST", `/presets/${slot}/load`),
   savePreset: (slot, name) =>
@@ -477,6 +479,7 @@ function renderStops() {
     stops.forEach((stop) => {
       const tile = document.createElement("div");
       tile.className = "stop-tile";
+      if (stop.kind === "coupler") tile.classList.add("coupler");
       const isActive = stop.active_channels.includes(state.channel);
       if (isActive) tile.classList.add("active");
       tile.textContent = stopDisplayName(stop);
@@ -484,7 +487,8 @@ function renderStops() {
 
       bindActivation(tile, {
         onTap: () => toggleStop(stop, !isActive),
-        onLong: () => openStopActions(stop),
+        // MIDI learn is only available for stops
+        onLong: () => stop.kind !== "coupler" && openStopActions(stop),
       });
 
       grid.appendChild(tile);
@@ -497,7 +501,11 @@ function renderStops() {
 
 async function toggleStop(stop, active) {
   try {
-    await api.setStopChannel(stop.index, state.channel, active);
+    if (stop.kind === "coupler") {
+      await api.setCouplerChannel(stop.index, state.channel, active);
+    } else {
+      await api.setStopChannel(stop.index, state.channel, active);
+    }
     const set = new Set(stop.active_channels);
     if (active) set.add(state.channel);
     else set.delete(state.channel);
@@ -0,0 +1,80 @@
+//! CPU cost per voice of each interpolator.
+//!
+//! Run with `cargo bench --bench interpolation`. Mixes one voice at a slowly
+//! modulated pitch, as under a tremulant, for a few seconds of audio and
+//! reports the time per block and the share of the real-time budget it takes.
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rusty_pipes::audio_interpolation::{Interpolation, Interpolator, Ramp};
+use rusty_pipes::voice::CHANNEL_COUNT;
+
+const SAMPLE_RATE: f32 = 48000.0;
+const BLOCK_FRAMES: usize = 256;
+const BLOCKS: usize = 20_000;
+
+fn main() {
+    // A second of noise, looped, so the kernel sees realistic data
+    let mut state = 0x1234_5678u32;
+    let input: Vec<f32> = (0..SAMPLE_RATE as usize * CHANNEL_COUNT)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as f32 / u32::MAX as f32 - 0.5
+        })
+        .collect();
+    let input_frames = input.len() / CHANNEL_COUNT;
+    let block_secs = BLOCK_FRAMES as f64 / SAMPLE_RATE as f64;
+    let mut mix = vec![0.0f32; BLOCK_FRAMES * CHANNEL_COUNT];
+
+    println!(
+        "{} blocks of {} frames at {} Hz, one voice",
+        BLOCKS, BLOCK_FRAMES, SAMPLE_RATE
+    );
+    println!(
+        "{:<10} {:>14} {:>16} {:>18}",
+        "kernel", "ns / block", "ns / frame", "voices @ 100% CPU"
+    );
+    for kind in Interpolation::ALL {
+        let interpolator = Interpolator::new(kind);
+        let mut position = 0.0f32;
+        let started = Instant::now();
+        for block in 0..BLOCKS {
+            // +/- 0.5% at 6 Hz
+            let pitch = 1.0 + 0.005 * (block as f32 * 0.2).sin();
+            let pitch_next = 1.0 + 0.005 * ((block + 1) as f32 * 0.2).sin();
+            let pitch_delta = (pitch_next - pitch) / BLOCK_FRAMES as f32;
+
+            // Wrap the read position so the kernel always has enough input
+            let span = BLOCK_FRAMES * 2 + interpolator.taps();
+            if position as usize + span > input_frames {
+                position = position.fract();
+            }
+            let start = position as usize;
+            let mut cursor = position.fract();
+            interpolator.mix(
+                &input[start * CHANNEL_COUNT..],
+                mix.chunks_exact_mut(CHANNEL_COUNT),
+                &mut cursor,
+                Ramp {
+                    pitch,
+                    pitch_delta,
+                    gain: 0.5,
+                    gain_delta: 0.0,
+                },
+            );
+            position = start as f32 + cursor;
+            black_box(&mut mix);
+        }
+        let per_block = started.elapsed().as_secs_f64() / BLOCKS as f64;
+        println!(
+            "{:<10} {:>14.0} {:>16.2} {:>18.0}",
+            format!("{kind:?}"),
+            per_block * 1e9,
+            per_block * 1e9 / BLOCK_FRAMES as f64,
+            block_secs / per_block
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! A minimal headless CLAP host, for trying the plugin without a DAW.
+//!
+//! Loads the plugin, draws its first stop, plays a chord and prints the output
+//! level, then saves the plugin state and loads it back:
+//!
+//! ```text
+//! cargo build -p rusty-pipes-clap
+//! RUSTY_PIPES_ORGAN=/path/to/organ.organ cargo run -p rusty-pipes-clap \
+//!     --example headless_host -- target/debug/librusty_pipes_clap.so
+//! ```
+
+use anyhow::{Result, anyhow, bail};
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON, CLAP_EVENT_PARAM_VALUE,
+    clap_event_header, clap_event_note, clap_event_param_value, clap_input_events,
+    clap_output_events,
+};
+use clap_sys::ext::params::{CLAP_EXT_PARAMS, clap_param_info, clap_plugin_params};
+use clap_sys::ext::state::{CLAP_EXT_STATE, clap_plugin_state};
+use clap_sys::factory::plugin_factory::{CLAP_PLUGIN_FACTORY_ID, clap_plugin_factory};
+use clap_sys::host::clap_host;
+use clap_sys::plugin::clap_plugin;
+use clap_sys::process::{CLAP_PROCESS_ERROR, clap_process};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+use libloading::Library;
+use std::ffi::{CStr, CString, c_char, c_void};
+use std::io::{Cursor, Read};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const SAMPLE_RATE: f64 = 48000.0;
+const BLOCK_FRAMES: usize = 512;
+/// The chord starts this many frames into the first block, to exercise event timing
+const CHORD_FRAME: usize = 100;
+const CHORD: [i16; 3] = [60, 64, 67];
+const HOLD_SECS: f64 = 2.0;
+/// Rendered after the release, for the release samples and the reverb
+const TAIL_SECS: f64 = 2.0;
+/// The level is reported this often
+const REPORT_SECS: f64 = 0.5;
+/// How long to wait for the plugin to load its organ in the background
+const LOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Set when the plugin asks for an `on_main_thread` call
+static CALLBACK_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// The events of one block, handed out through `clap_input_events`. Parameter
+/// changes are listed first, so every event here must be at or after them.
+#[derive(Default)]
+struct EventList {
+    params: Vec<clap_event_param_value>,
+    notes: Vec<clap_event_note>,
+}
+
+fn header(type_: u16, size: usize, time: usize) -> clap_event_header {
+    clap_event_header {
+        size: size as u32,
+        time: time as u32,
+        space_id: CLAP_CORE_EVENT_SPACE_ID,
+        type_,
+        flags: 0,
+    }
+}
+
+impl EventList {
+    fn param(&mut self, time: usize, param_id: u32, value: f64) {
+        self.params.push(clap_event_param_value {
+            header: header(
+                CLAP_EVENT_PARAM_VALUE,
+                size_of::<clap_event_param_value>(),
+                time,
+            ),
+            param_id,
+            cookie: ptr::null_mut(),
+            note_id: -1,
+            port_index: -1,
+            channel: -1,
+            key: -1,
+            value,
+        });
+    }
+
+    fn note(&mut self, time: usize, type_: u16, key: i16) {
+        self.notes.push(clap_event_note {
+            header: header(type_, size_of::<clap_event_note>(), time),
+            note_id: -1,
+            port_index: 0,
+            channel: 0,
+            key,
+            velocity: 0.8,
+        });
+    }
+}
+
+unsafe extern "C" fn events_size(list: *const clap_input_events) -> u32 {
+    let events = unsafe { &*((*list).ctx as *const EventList) };
+    (events.params.len() + events.notes.len()) as u32
+}
+
+unsafe extern "C" fn events_get(
+    list: *const clap_input_events,
+    index: u32,
+) -> *const clap_event_header {
+    let events = unsafe { &*((*list).ctx as *const EventList) };
+    let index = index as usize;
+    match events.params.get(index) {
+        Some(event) => &event.header,
+        None => events
+            .notes
+            .get(index - events.params.len())
+            .map_or(ptr::null(), |event| &event.header),
+    }
+}
+
+unsafe extern "C" fn out_events_try_push(
+    _list: *const clap_output_events,
+    _event: *const clap_event_header,
+) -> bool {
+    true
+}
+
+unsafe extern "C" fn host_get_extension(
+    _host: *const clap_host,
+    _extension_id: *const c_char,
+) -> *const c_void {
+    ptr::null()
+}
+
+unsafe extern "C" fn host_request(_host: *const clap_host) {}
+
+unsafe extern "C" fn host_request_callback(_host: *const clap_host) {
+    CALLBACK_REQUESTED.store(true, Ordering::Release);
+}
+
+unsafe extern "C" fn ostream_write(
+    stream: *const clap_ostream,
+    buffer: *const c_void,
+    size: u64,
+) -> i64 {
+    let bytes = unsafe { &mut *((*stream).ctx as *mut Vec<u8>) };
+    let data = unsafe { std::slice::from_raw_parts(buffer as *const u8, size as usize) };
+    bytes.extend_from_slice(data);
+    size as i64
+}
+
+unsafe extern "C" fn istream_read(
+    stream: *const clap_istream,
+    buffer: *mut c_void,
+    size: u64,
+) -> i64 {
+    let cursor = unsafe { &mut *((*stream).ctx as *mut Cursor<Vec<u8>>) };
+    let dest = unsafe { std::slice::from_raw_parts_mut(buffer as *mut u8, size as usize) };
+    cursor.read(dest).map_or(-1, |read| read as i64)
+}
+
+fn main() -> Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .ok_or_else(|| anyhow!("Usage: headless_host <path to the plugin library>"))?;
+    let c_path = CString::new(path.clone())?;
+
+    unsafe {
+        let library = Library::new(&path)?;
+        let entry = &**library.get::<*const clap_plugin_entry>(b"clap_entry")?;
+        let (Some(init), Some(deinit), Some(get_factory)) =
+            (entry.init, entry.deinit, entry.get_factory)
+        else {
+            bail!("Incomplete clap_entry");
+        };
+        if !init(c_path.as_ptr()) {
+            bail!("clap_entry.init failed");
+        }
+        let result = run_factory(get_factory(CLAP_PLUGIN_FACTORY_ID.as_ptr()));
+        deinit();
+        result
+    }
+}
+
+unsafe fn run_factory(factory: *const c_void) -> Result<()> {
+    unsafe {
+        let factory = factory as *const clap_plugin_factory;
+        if factory.is_null() {
+            bail!("The library has no plugin factory");
+        }
+        let (Some(get_descriptor), Some(create_plugin)) =
+            ((*factory).get_plugin_descriptor, (*factory).create_plugin)
+        else {
+            bail!("Incomplete plugin factory");
+        };
+        let descriptor = get_descriptor(factory, 0);
+        if descriptor.is_null() {
+            bail!("The factory has no plugins");
+        }
+        println!(
+            "Plugin: {} ({})",
+            CStr::from_ptr((*descriptor).name).to_string_lossy(),
+            CStr::from_ptr((*descriptor).id).to_string_lossy()
+        );
+
+        let host = clap_host {
+            clap_version: CLAP_VERSION,
+            host_data: ptr::null_mut(),
+            name: c"Rusty Pipes headless host".as_ptr(),
+            vendor: c"dividebysandwich".as_ptr(),
+            url: c"https://rusty-pipes.com".as_ptr(),
+            version: c"1.0".as_ptr(),
+            get_extension: Some(host_get_extension),
+            request_restart: Some(host_request),
+            request_process: Some(host_request),
+            request_callback: Some(host_request_callback),
+        };
+        let plugin = create_plugin(factory, &host, (*descriptor).id);
+        if plugin.is_null() {
+            bail!("create_plugin failed");
+        }
+        let result = match (*plugin).init {
+            Some(init) if init(plugin) => run_plugin(plugin),
+            _ => Err(anyhow!("Plugin init failed")),
+        };
+        if let Some(destroy) = (*plugin).destroy {
+            destroy(plugin);
+        }
+        result
+    }
+}
+
+/// The plugin's extension `id`, or an error if it doesn't have it.
+unsafe fn extension<T>(plugin: *const clap_plugin, id: &CStr) -> Result<&'static T> {
+    unsafe {
+        let get_extension = (*plugin)
+            .get_extension
+            .ok_or_else(|| anyhow!("Plugin has no get_extension"))?;
+        let ext = get_extension(plugin, id.as_ptr()) as *const T;
+        ext.as_ref()
+            .ok_or_else(|| anyhow!("Plugin lacks {}", id.to_string_lossy()))
+    }
+}
+
+/// Runs the plugin's main-thread callbacks until it has parameters, i.e. until
+/// the organ it loads in the background is in. Returns the parameter count.
+unsafe fn wait_for_organ(plugin: *const clap_plugin, params: &clap_plugin_params) -> u32 {
+    unsafe {
+        let started = Instant::now();
+        loop {
+            if CALLBACK_REQUESTED.swap(false, Ordering::AcqRel)
+                && let Some(on_main_thread) = (*plugin).on_main_thread
+            {
+                on_main_thread(plugin);
+            }
+            let param_count = params.count.map_or(0, |count| count(plugin));
+            if param_count > 0 || started.elapsed() >= LOAD_TIMEOUT {
+                return param_count;
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+unsafe fn run_plugin(plugin: *const clap_plugin) -> Result<()> {
+    unsafe {
+        let params: &clap_plugin_params = extension(plugin, CLAP_EXT_PARAMS)?;
+        println!("Waiting for the organ to load...");
+        let param_count = wait_for_organ(plugin, params);
+        let mut info: clap_param_info = std::mem::zeroed();
+        if param_count == 0 || !params.get_info.is_some_and(|get| get(plugin, 0, &mut info)) {
+            bail!("The plugin has no stops. Is an organ set in RUSTY_PIPES_ORGAN?");
+        }
+        println!(
+            "{} parameters, drawing '{}'",
+            param_count,
+            CStr::from_ptr(info.name.as_ptr()).to_string_lossy()
+        );
+
+        let (Some(activate), Some(deactivate), Some(start), Some(stop), Some(process)) = (
+            (*plugin).activate,
+            (*plugin).deactivate,
+            (*plugin).start_processing,
+            (*plugin).stop_processing,
+            (*plugin).process,
+        ) else {
+            bail!("Incomplete plugin");
+        };
+        if !activate(plugin, SAMPLE_RATE, 1, BLOCK_FRAMES as u32) || !start(plugin) {
+            bail!("Plugin failed to start");
+        }
+
+        let release_frame = CHORD_FRAME + (HOLD_SECS * SAMPLE_RATE) as usize;
+        let total_frames = release_frame + (TAIL_SECS * SAMPLE_RATE) as usize;
+        let report_frames = (REPORT_SECS * SAMPLE_RATE) as usize;
+        let mut left = vec![0.0f32; BLOCK_FRAMES];
+        let mut right = vec![0.0f32; BLOCK_FRAMES];
+        let mut overall_peak = 0.0f32;
+        let mut report_peak = 0.0f32;
+
+        for block_start in (0..total_frames).step_by(BLOCK_FRAMES) {
+            let block = block_start..block_start + BLOCK_FRAMES;
+            let mut events = EventList::default();
+            if block_start == 0 {
+                events.param(0, info.id, 1.0);
+            }
+            for (frame, type_) in [
+                (CHORD_FRAME, CLAP_EVENT_NOTE_ON),
+                (release_frame, CLAP_EVENT_NOTE_OFF),
+            ] {
+                if block.contains(&frame) {
+                    for key in CHORD {
+                        events.note(frame - block_start, type_, key);
+                    }
+                }
+            }
+            let in_events = clap_input_events {
+                ctx: &mut events as *mut EventList as *mut c_void,
+                size: Some(events_size),
+                get: Some(events_get),
+            };
+            let out_events = clap_output_events {
+                ctx: ptr::null_mut(),
+                try_push: Some(out_events_try_push),
+            };
+            let mut channels = [left.as_mut_ptr(), right.as_mut_ptr()];
+            let mut output = clap_sys::audio_buffer::clap_audio_buffer {
+                data32: channels.as_mut_ptr(),
+                data64: ptr::null_mut(),
+                channel_count: 2,
+                latency: 0,
+                constant_mask: 0,
+            };
+            let context = clap_process {
+                steady_time: block_start as i64,
+                frames_count: BLOCK_FRAMES as u32,
+                transport: ptr::null(),
+                audio_inputs: ptr::null(),
+                audio_outputs: &mut output,
+                audio_inputs_count: 0,
+                audio_outputs_count: 1,
+                in_events: &in_events,
+                out_events: &out_events,
+            };
+            if process(plugin, &context) == CLAP_PROCESS_ERROR {
+                bail!("process failed");
+            }
+
+            let peak = left
+                .iter()
+                .chain(&right)
+                .fold(0.0f32, |p, s| p.max(s.abs()));
+            report_peak = report_peak.max(peak);
+            overall_peak = overall_peak.max(peak);
+            let block_end = block_start + BLOCK_FRAMES;
+            if block_end / report_frames != block_start / report_frames {
+                println!(
+                    "{:5.1} s  peak {:6.1} dBFS",
+                    block_end as f64 / SAMPLE_RATE,
+                    20.0 * report_peak.max(1e-9).log10()
+                );
+                report_peak = 0.0;
+            }
+        }
+        stop(plugin);
+        deactivate(plugin);
+
+        // Round-trip the plugin state, as a host does with projects
+        let state: &clap_plugin_state = extension(plugin, CLAP_EXT_STATE)?;
+        let (Some(save), Some(load)) = (state.save, state.load) else {
+            bail!("Incomplete state extension");
+        };
+        let mut saved = Vec::new();
+        let ostream = clap_ostream {
+            ctx: &mut saved as *mut Vec<u8> as *mut c_void,
+            write: Some(ostream_write),
+        };
+        if !save(plugin, &ostream) {
+            bail!("Saving the state failed");
+        }
+        println!("State: {}", String::from_utf8_lossy(&saved));
+        let mut cursor = Cursor::new(saved);
+        let istream = clap_istream {
+            ctx: &mut cursor as *mut Cursor<Vec<u8>> as *mut c_void,
+            read: Some(istream_read),
+        };
+        if !load(plugin, &istream) {
+            bail!("Loading the state back failed");
+        }
+
+        if overall_peak == 0.0 {
+            bail!("The plugin stayed silent");
+        }
+        println!("OK");
+        Ok(())
+    }
+}
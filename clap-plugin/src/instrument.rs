@@ -0,0 +1,634 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, mpsc};
+
+use rusty_pipes::app::{AppMessage, TuiMessage};
+use rusty_pipes::audio_command::EngineCommand;
+use rusty_pipes::audio_engine::{AudioEngine, EngineConfig};
+use rusty_pipes::audio_event::AttackSelection;
+use rusty_pipes::audio_interpolation::Interpolation;
+use rusty_pipes::audio_loader::{LoaderHandle, default_loader_workers, spawn_loader_pool};
+use rusty_pipes::organ::Organ;
+use rusty_pipes::tuning::Tuning;
+use rusty_pipes::velocity::VelocityCurves;
+use rusty_pipes::voice::{CHANNEL_COUNT, MAX_NEW_VOICES_PER_BLOCK};
+use rusty_pipes::wav_converter::CacheFormat;
+
+/// Frames the engine renders per block. Host blocks of any size are served from a FIFO.
+pub const ENGINE_BLOCK_FRAMES: usize = 64;
+/// Parameter IDs from here on are tremulants. Stops use their index as ID.
+pub const TREMULANT_PARAM_BASE: u32 = 10000;
+/// Environment variable naming the organ to load when the host has no saved state.
+const ORGAN_ENV_VAR: &str = "RUSTY_PIPES_ORGAN";
+/// Velocity of each key by MIDI channel and key, with none held.
+const NO_KEYS_HELD: [[u8; 128]; 16] = [[0; 128]; 16];
+
+/// The subset of the standalone application's settings the plugin honours.
+/// Read from the same settings file so both sound the same.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PluginSettings {
+    pub organ_file: Option<PathBuf>,
+    pub ir_file: Option<PathBuf>,
+    pub reverb_mix: f32,
+    pub max_ram_gb: f32,
+    pub precache: bool,
+    pub mmap_samples: bool,
+    pub convert_to_16bit: bool,
+    pub cache_format: CacheFormat,
+    pub original_tuning: bool,
+    pub gain: f32,
+    pub polyphony: usize,
+    pub max_new_voices_per_block: usize,
+    pub attack_selection: AttackSelection,
+    pub interpolation: Interpolation,
+    pub tracker_delay_scale: f32,
+    pub sample_rate: u32,
+}
+
+impl Default for PluginSettings {
+    fn default() -> Self {
+        Self {
+            organ_file: None,
+            ir_file: None,
+            reverb_mix: 0.5,
+            max_ram_gb: 8.0,
+            precache: false,
+            mmap_samples: false,
+            convert_to_16bit: false,
+            cache_format: CacheFormat::default(),
+            original_tuning: false,
+            gain: 0.4,
+            polyphony: 128,
+            max_new_voices_per_block: MAX_NEW_VOICES_PER_BLOCK,
+            attack_selection: AttackSelection::default(),
+            interpolation: Interpolation::default(),
+            tracker_delay_scale: 1.0,
+            sample_rate: 48000,
+        }
+    }
+}
+
+impl PluginSettings {
+    /// Reads the standalone settings file. Unlike `confy::load`, a missing file is
+    /// not created, so merely scanning the plugin leaves no trace on disk.
+    pub fn load() -> Self {
+        match confy::get_configuration_file_path("rusty-pipes", "settings") {
+            Ok(path) if path.exists() => confy::load_path(&path).unwrap_or_else(|e| {
+                log::warn!("[Plugin] Could not read settings, using defaults: {}", e);
+                Self::default()
+            }),
+            _ => Self::default(),
+        }
+    }
+}
+
+/// What the host stores in projects and plugin presets.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SavedState {
+    pub organ_file: Option<PathBuf>,
+    /// IDs of the drawn stops. Names repeat across manuals, IDs don't.
+    pub stops: Vec<String>,
+    /// IDs of the active tremulants
+    pub tremulants: Vec<String>,
+}
+
+/// Everything needed to load an organ (and start an engine on it) without
+/// holding the plugin lock, as reading the samples takes a while.
+pub struct OrganLoad {
+    path: PathBuf,
+    sample_rate: u32,
+    settings: PluginSettings,
+    /// Start an engine too, as one runs on the organ being replaced
+    with_engine: bool,
+    offline: bool,
+    loader: LoaderHandle,
+}
+
+impl OrganLoad {
+    pub fn run(self) -> Result<LoadedOrgan> {
+        log::info!("[Plugin] Loading organ: {}", self.path.display());
+        let organ = Organ::load(
+            &self.path,
+            self.settings.convert_to_16bit,
+            self.settings.cache_format,
+            self.settings.precache,
+            self.settings.mmap_samples,
+            self.settings.original_tuning,
+            self.sample_rate,
+            None,
+            (self.settings.max_ram_gb * 1024.0) as usize,
+        )
+        .map_err(|e| anyhow!("Could not load organ {}: {}", self.path.display(), e))?;
+        let organ = Arc::new(organ);
+        let engine = self.with_engine.then(|| {
+            build_engine(
+                &organ,
+                &self.settings,
+                self.sample_rate,
+                self.offline,
+                &self.loader,
+            )
+        });
+        Ok(LoadedOrgan {
+            path: self.path,
+            sample_rate: self.sample_rate,
+            organ,
+            engine,
+        })
+    }
+}
+
+/// The result of an `OrganLoad`, ready to be swapped in.
+pub struct LoadedOrgan {
+    path: PathBuf,
+    sample_rate: u32,
+    organ: Arc<Organ>,
+    engine: Option<AudioEngine>,
+}
+
+impl LoadedOrgan {
+    /// Gives up on the organ, to be dropped outside the plugin lock.
+    fn retire(self) -> RetiredOrgan {
+        RetiredOrgan {
+            _organ: Some(self.organ),
+            _engine: self.engine,
+        }
+    }
+}
+
+/// The organ and engine an organ change replaced. Dropping them frees the old
+/// samples, which can take a while, so drop this after releasing the plugin lock.
+#[derive(Default)]
+pub struct RetiredOrgan {
+    _organ: Option<Arc<Organ>>,
+    _engine: Option<AudioEngine>,
+}
+
+/// Starts an engine on `organ` with the plugin's settings.
+fn build_engine(
+    organ: &Arc<Organ>,
+    settings: &PluginSettings,
+    sample_rate: u32,
+    offline: bool,
+    loader: &LoaderHandle,
+) -> AudioEngine {
+    // Nobody listens to the engine's status messages inside a plugin
+    let (tui_tx, _tui_rx) = mpsc::channel::<TuiMessage>();
+    let config = EngineConfig {
+        sample_rate,
+        buffer_size_frames: ENGINE_BLOCK_FRAMES,
+        gain: settings.gain,
+        polyphony: settings.polyphony,
+        max_new_voices_per_block: settings.max_new_voices_per_block,
+        attack_selection: settings.attack_selection,
+        interpolation: settings.interpolation,
+        tracker_delay_scale: settings.tracker_delay_scale,
+        output_channels: CHANNEL_COUNT,
+        output_routes: Vec::new(),
+    };
+    let mut engine = AudioEngine::new(
+        Arc::clone(organ),
+        &config,
+        tui_tx,
+        Arc::new(Mutex::new(None)),
+        loader,
+    );
+    if offline {
+        engine.use_virtual_clock();
+    }
+    // Same tuning and velocity curves as the standalone app uses for this organ
+    engine.handle_message(AppMessage::SetTuning(Tuning::load(&organ.name)));
+    engine.handle_message(AppMessage::SetVelocityCurves(VelocityCurves::load(
+        &organ.name,
+    )));
+    if let Some(ir_path) = settings.ir_file.as_ref().filter(|p| p.exists())
+        && let Err(e) = engine.load_reverb_ir(ir_path, settings.reverb_mix)
+    {
+        log::error!("[Plugin] Failed to load IR: {}", e);
+    }
+    engine
+}
+
+/// A plugin parameter, resolved from its ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    Stop(usize),
+    Tremulant(usize),
+}
+
+/// The organ as seen by the plugin: a loaded organ, the engine rendering it, and
+/// the registration. Notes are routed to stops here, the way `AppState` does it
+/// for the standalone application, so the engine only ever sees per-stop notes.
+pub struct Instrument {
+    settings: PluginSettings,
+    organ_file: Option<PathBuf>,
+    organ: Option<Arc<Organ>>,
+    /// Sample rate the organ's samples were prepared for
+    organ_sample_rate: u32,
+    engine: Option<AudioEngine>,
+    /// Sample rate the host activated the plugin at, while it's active
+    active_sample_rate: Option<u32>,
+    offline: bool,
+    loader: LoaderHandle,
+
+    /// MIDI channel each stop listens on: the position of its manual, or any channel
+    stop_channels: Vec<Option<u8>>,
+    stops_active: Vec<bool>,
+    /// Sorted, so parameter IDs stay stable between sessions. This is the order
+    /// of `OrganIndex::tremulant_ids`, so an index here is the engine's index too.
+    tremulant_ids: Vec<String>,
+    tremulants_active: Vec<bool>,
+    /// Velocity of each held key, by MIDI channel and key (0 = not held).
+    /// A fixed table, so playing never allocates on the host's audio thread.
+    held_keys: [[u8; 128]; 16],
+    /// Interleaved engine output not yet handed to the host
+    output: VecDeque<f32>,
+}
+
+impl Instrument {
+    /// An instrument without an organ. Loading one takes far longer than a host
+    /// waits for `init`, so that is left to `initial_organ_load`.
+    pub fn new() -> Self {
+        let settings = PluginSettings::load();
+        let sample_rate = settings.sample_rate;
+        Self {
+            settings,
+            organ_file: None,
+            organ: None,
+            organ_sample_rate: sample_rate,
+            engine: None,
+            active_sample_rate: None,
+            offline: false,
+            loader: spawn_loader_pool(default_loader_workers()).jobs,
+            stop_channels: Vec::new(),
+            stops_active: Vec::new(),
+            tremulant_ids: Vec::new(),
+            tremulants_active: Vec::new(),
+            held_keys: NO_KEYS_HELD,
+            output: VecDeque::with_capacity(ENGINE_BLOCK_FRAMES * CHANNEL_COUNT),
+        }
+    }
+
+    /// The load of the organ to start with, until the host restores a state:
+    /// the one named by the environment, else the standalone app's last organ.
+    pub fn initial_organ_load(&self) -> Option<OrganLoad> {
+        std::env::var_os(ORGAN_ENV_VAR)
+            .map(PathBuf::from)
+            .or_else(|| self.settings.organ_file.clone())
+            .filter(|p| !p.as_os_str().is_empty())
+            .map(|path| self.organ_load(path, self.organ_sample_rate))
+    }
+
+    /// Swaps in the organ `initial_organ_load` asked for, unless the host has
+    /// restored a state with an organ in the meantime. If the host activated the
+    /// plugin at another sample rate while it was loading, the organ has to be
+    /// loaded again, and the load for that is handed back instead.
+    pub fn finish_initial_load(
+        &mut self,
+        loaded: LoadedOrgan,
+    ) -> (RetiredOrgan, Option<OrganLoad>) {
+        if self.organ.is_some() {
+            return (loaded.retire(), None);
+        }
+        if let Some(sample_rate) = self.active_sample_rate
+            && sample_rate != loaded.sample_rate
+        {
+            let reload = self.organ_load(loaded.path.clone(), sample_rate);
+            return (loaded.retire(), Some(reload));
+        }
+        let retired = self.install_organ(loaded);
+        if let Some(sample_rate) = self.active_sample_rate {
+            self.start_engine(sample_rate);
+        }
+        (retired, None)
+    }
+
+    /// Loads an organ and resets the registration. The engine, if running, is
+    /// restarted on the new organ.
+    fn load_organ(&mut self, path: PathBuf, sample_rate: u32) -> Result<()> {
+        let loaded = self.organ_load(path, sample_rate).run()?;
+        drop(self.install_organ(loaded));
+        Ok(())
+    }
+
+    /// What loading the organ at `path` takes, to be run without `&mut self`.
+    fn organ_load(&self, path: PathBuf, sample_rate: u32) -> OrganLoad {
+        OrganLoad {
+            path,
+            sample_rate,
+            settings: self.settings.clone(),
+            with_engine: self.engine.is_some(),
+            offline: self.offline,
+            loader: self.loader.clone(),
+        }
+    }
+
+    /// Swaps in a loaded organ and resets the registration. The organ and
+    /// engine it replaces are handed back, to be dropped by the caller.
+    fn install_organ(&mut self, loaded: LoadedOrgan) -> RetiredOrgan {
+        let LoadedOrgan {
+            path,
+            sample_rate,
+            organ,
+            engine,
+        } = loaded;
+        self.stop_channels = organ
+            .stops
+            .iter()
+            .map(|stop| {
+                let manual_id = stop.manual_id.as_ref()?;
+                let position = organ.manuals.iter().position(|m| &m.id_str == manual_id)?;
+                Some(position as u8)
+            })
+            .collect();
+        self.stops_active = vec![false; organ.stops.len()];
+        self.tremulant_ids = organ.tremulants.keys().cloned().collect();
+        self.tremulant_ids.sort();
+        self.tremulants_active = vec![false; self.tremulant_ids.len()];
+        self.held_keys = NO_KEYS_HELD;
+        self.output.clear();
+
+        self.organ_file = Some(path);
+        self.organ_sample_rate = sample_rate;
+        RetiredOrgan {
+            _organ: self.organ.replace(organ),
+            _engine: std::mem::replace(&mut self.engine, engine),
+        }
+    }
+
+    /// Prepares for processing at the host's sample rate. Samples are resampled
+    /// while loading, so a rate other than the one we loaded at means a reload.
+    pub fn activate(&mut self, sample_rate: u32) {
+        self.active_sample_rate = Some(sample_rate);
+        if sample_rate != self.organ_sample_rate
+            && let Some(path) = self.organ_file.clone()
+        {
+            let saved = self.save_state();
+            match self.load_organ(path, sample_rate) {
+                Ok(()) => self.restore_registration(&saved),
+                Err(e) => log::error!("[Plugin] {}", e),
+            }
+        }
+        self.start_engine(sample_rate);
+    }
+
+    pub fn deactivate(&mut self) {
+        self.active_sample_rate = None;
+        self.engine = None;
+        self.output.clear();
+        self.held_keys = NO_KEYS_HELD;
+    }
+
+    fn start_engine(&mut self, sample_rate: u32) {
+        self.output.clear();
+        self.held_keys = NO_KEYS_HELD;
+        let Some(organ) = &self.organ else {
+            self.engine = None;
+            return;
+        };
+        let mut engine = build_engine(
+            organ,
+            &self.settings,
+            sample_rate,
+            self.offline,
+            &self.loader,
+        );
+        for (tremulant, _) in self
+            .tremulants_active
+            .iter()
+            .enumerate()
+            .filter(|(_, active)| **active)
+        {
+            engine.handle_command(EngineCommand::SetTremulantActive {
+                tremulant,
+                active: true,
+            });
+        }
+        self.engine = Some(engine);
+    }
+
+    /// In offline mode (bouncing) the engine runs on a virtual clock and waits
+    /// for samples to load instead of dropping them.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = offline;
+        if offline && let Some(engine) = &mut self.engine {
+            engine.use_virtual_clock();
+        }
+    }
+
+    pub fn param_count(&self) -> usize {
+        self.stops_active.len() + self.tremulants_active.len()
+    }
+
+    /// (ID, name, module) of the parameter at `index`. Modules group stops by division.
+    pub fn param_info(&self, index: usize) -> Option<(u32, String, String)> {
+        let organ = self.organ.as_ref()?;
+        if let Some(stop) = organ.stops.get(index) {
+            return Some((index as u32, stop.name.clone(), stop.division_id.clone()));
+        }
+        let trem_index = index - organ.stops.len();
+        let id = self.tremulant_ids.get(trem_index)?;
+        let name = organ.tremulants[id].name.clone();
+        Some((
+            TREMULANT_PARAM_BASE + trem_index as u32,
+            name,
+            "Tremulants".to_string(),
+        ))
+    }
+
+    pub fn param(&self, id: u32) -> Option<Param> {
+        if id >= TREMULANT_PARAM_BASE {
+            let index = (id - TREMULANT_PARAM_BASE) as usize;
+            (index < self.tremulants_active.len()).then_some(Param::Tremulant(index))
+        } else {
+            let index = id as usize;
+            (index < self.stops_active.len()).then_some(Param::Stop(index))
+        }
+    }
+
+    pub fn param_value(&self, id: u32) -> Option<f64> {
+        let active = match self.param(id)? {
+            Param::Stop(index) => self.stops_active[index],
+            Param::Tremulant(index) => self.tremulants_active[index],
+        };
+        Some(if active { 1.0 } else { 0.0 })
+    }
+
+    pub fn set_param(&mut self, id: u32, value: f64) {
+        let active = value >= 0.5;
+        match self.param(id) {
+            Some(Param::Stop(index)) => self.set_stop(index, active),
+            Some(Param::Tremulant(index)) => self.set_tremulant(index, active),
+            None => {}
+        }
+    }
+
+    fn set_stop(&mut self, index: usize, active: bool) {
+        if self.stops_active[index] == active {
+            return;
+        }
+        self.stops_active[index] = active;
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+        // Keys already held start or stop sounding on this stop, like on a real console
+        for (channel, keys) in self.held_keys.iter().enumerate() {
+            if self.stop_channels[index].is_some_and(|c| c as usize != channel) {
+                continue;
+            }
+            for (key, &velocity) in keys.iter().enumerate().filter(|(_, v)| **v != 0) {
+                let note = key as u8;
+                let command = if active {
+                    EngineCommand::NoteOn {
+                        note,
+                        velocity,
+                        stop: index,
+                    }
+                } else {
+                    EngineCommand::NoteOff { note, stop: index }
+                };
+                engine.handle_command(command);
+            }
+        }
+    }
+
+    fn set_tremulant(&mut self, index: usize, active: bool) {
+        if self.tremulants_active[index] == active {
+            return;
+        }
+        self.tremulants_active[index] = active;
+        if let Some(engine) = &mut self.engine {
+            engine.handle_command(EngineCommand::SetTremulantActive {
+                tremulant: index,
+                active,
+            });
+        }
+    }
+
+    pub fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        if velocity == 0 {
+            self.note_off(channel, key);
+            return;
+        }
+        // A repeated NoteOn without NoteOff must not leave the old notes hanging
+        self.note_off(channel, key);
+        let Some(held) = self.held_key(channel, key) else {
+            return;
+        };
+        *held = velocity;
+        self.send_to_stops(channel, |stop| EngineCommand::NoteOn {
+            note: key,
+            velocity,
+            stop,
+        });
+    }
+
+    pub fn note_off(&mut self, channel: u8, key: u8) {
+        let Some(held) = self.held_key(channel, key) else {
+            return;
+        };
+        if std::mem::take(held) != 0 {
+            self.send_to_stops(channel, |stop| EngineCommand::NoteOff { note: key, stop });
+        }
+    }
+
+    /// The velocity a key is held with, or `None` outside the MIDI range.
+    fn held_key(&mut self, channel: u8, key: u8) -> Option<&mut u8> {
+        self.held_keys
+            .get_mut(channel as usize)?
+            .get_mut(key as usize)
+    }
+
+    pub fn all_notes_off(&mut self) {
+        self.held_keys = NO_KEYS_HELD;
+        if let Some(engine) = &mut self.engine {
+            engine.handle_command(EngineCommand::AllNotesOff);
+        }
+    }
+
+    fn send_to_stops(&mut self, channel: u8, make_command: impl Fn(usize) -> EngineCommand) {
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+        for index in 0..self.stops_active.len() {
+            if self.stops_active[index] && self.stop_channels[index].is_none_or(|c| c == channel) {
+                engine.handle_command(make_command(index));
+            }
+        }
+    }
+
+    /// Fills the host's output channels. Silence when no organ is loaded.
+    pub fn render(&mut self, left: &mut [f32], right: &mut [f32]) {
+        let Some(engine) = &mut self.engine else {
+            left.fill(0.0);
+            right.fill(0.0);
+            return;
+        };
+        for (l, r) in left.iter_mut().zip(right.iter_mut()) {
+            if self.output.is_empty() {
+                engine.render_block(self.offline);
+                self.output.extend(engine.output());
+            }
+            *l = self.output.pop_front().unwrap_or(0.0);
+            *r = self.output.pop_front().unwrap_or(0.0);
+        }
+    }
+
+    pub fn save_state(&self) -> SavedState {
+        let stops = self
+            .organ
+            .iter()
+            .flat_map(|organ| organ.stops.iter().zip(&self.stops_active))
+            .filter(|(_, active)| **active)
+            .map(|(stop, _)| stop.id_str.clone())
+            .collect();
+        let tremulants = self
+            .tremulant_ids
+            .iter()
+            .zip(&self.tremulants_active)
+            .filter(|(_, active)| **active)
+            .map(|(id, _)| id.clone())
+            .collect();
+        SavedState {
+            organ_file: self.organ_file.clone(),
+            stops,
+            tremulants,
+        }
+    }
+
+    /// The organ load a saved state needs, if its organ differs from the current one.
+    pub fn organ_load_for(&self, state: &SavedState) -> Option<OrganLoad> {
+        state
+            .organ_file
+            .clone()
+            .filter(|path| self.organ_file.as_ref() != Some(path))
+            .map(|path| self.organ_load(path, self.organ_sample_rate))
+    }
+
+    /// Applies a saved state, with the organ `organ_load_for` asked for already
+    /// loaded. Hands back what a changed organ replaced.
+    pub fn load_state(&mut self, state: &SavedState, organ: Option<LoadedOrgan>) -> RetiredOrgan {
+        let retired = match organ {
+            Some(loaded) => self.install_organ(loaded),
+            None => RetiredOrgan::default(),
+        };
+        self.restore_registration(state);
+        retired
+    }
+
+    fn restore_registration(&mut self, state: &SavedState) {
+        let Some(organ) = self.organ.clone() else {
+            return;
+        };
+        for (index, stop) in organ.stops.iter().enumerate() {
+            self.set_stop(index, state.stops.contains(&stop.id_str));
+        }
+        for index in 0..self.tremulant_ids.len() {
+            let active = state.tremulants.contains(&self.tremulant_ids[index]);
+            self.set_tremulant(index, active);
+        }
+    }
+}
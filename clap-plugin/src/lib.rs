@@ -0,0 +1,757 @@
+//! CLAP instrument plugin for Rusty Pipes.
+//!
+//! Wraps the engine library in the CLAP C ABI: one stereo output, a note input
+//! that takes both CLAP notes and MIDI, the organ's stops and tremulants as
+//! on/off parameters, and the registration stored in the host's project.
+//!
+//! The organ comes from the saved plugin state, else from the `RUSTY_PIPES_ORGAN`
+//! environment variable, else from the organ last used by the standalone app.
+//! The latter two are loaded in the background after `init`, and the host is
+//! asked to rescan the parameters once the stops are known.
+
+mod instrument;
+
+use clap_sys::entry::clap_plugin_entry;
+use clap_sys::events::{
+    CLAP_CORE_EVENT_SPACE_ID, CLAP_EVENT_MIDI, CLAP_EVENT_NOTE_OFF, CLAP_EVENT_NOTE_ON,
+    CLAP_EVENT_PARAM_VALUE, clap_event_header, clap_event_midi, clap_event_note,
+    clap_event_param_value, clap_input_events, clap_output_events,
+};
+use clap_sys::ext::audio_ports::{
+    CLAP_AUDIO_PORT_IS_MAIN, CLAP_EXT_AUDIO_PORTS, CLAP_PORT_STEREO, clap_audio_port_info,
+    clap_plugin_audio_ports,
+};
+use clap_sys::ext::note_ports::{
+    CLAP_EXT_NOTE_PORTS, CLAP_NOTE_DIALECT_CLAP, CLAP_NOTE_DIALECT_MIDI, clap_note_port_info,
+    clap_plugin_note_ports,
+};
+use clap_sys::ext::params::{
+    CLAP_EXT_PARAMS, CLAP_PARAM_IS_AUTOMATABLE, CLAP_PARAM_IS_STEPPED, CLAP_PARAM_RESCAN_ALL,
+    CLAP_PARAM_RESCAN_VALUES, clap_host_params, clap_param_info, clap_plugin_params,
+};
+use clap_sys::ext::render::{
+    CLAP_EXT_RENDER, CLAP_RENDER_OFFLINE, clap_plugin_render, clap_plugin_render_mode,
+};
+use clap_sys::ext::state::{CLAP_EXT_STATE, clap_plugin_state};
+use clap_sys::factory::plugin_factory::{CLAP_PLUGIN_FACTORY_ID, clap_plugin_factory};
+use clap_sys::host::clap_host;
+use clap_sys::id::{CLAP_INVALID_ID, clap_id};
+use clap_sys::plugin::{clap_plugin, clap_plugin_descriptor};
+use clap_sys::plugin_features::{
+    CLAP_PLUGIN_FEATURE_INSTRUMENT, CLAP_PLUGIN_FEATURE_SAMPLER, CLAP_PLUGIN_FEATURE_STEREO,
+};
+use clap_sys::process::{
+    CLAP_PROCESS_CONTINUE, CLAP_PROCESS_ERROR, clap_process, clap_process_status,
+};
+use clap_sys::stream::{clap_istream, clap_ostream};
+use clap_sys::version::CLAP_VERSION;
+use std::ffi::{CStr, c_char, c_void};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+
+use instrument::{Instrument, OrganLoad, SavedState};
+
+const PLUGIN_ID: &CStr = c"com.dividebysandwich.rustypipes";
+
+/// Null-terminated feature list. Raw pointers aren't `Sync`, but these point
+/// into static strings.
+struct Features([*const c_char; 4]);
+unsafe impl Sync for Features {}
+
+static FEATURES: Features = Features([
+    CLAP_PLUGIN_FEATURE_INSTRUMENT.as_ptr(),
+    CLAP_PLUGIN_FEATURE_SAMPLER.as_ptr(),
+    CLAP_PLUGIN_FEATURE_STEREO.as_ptr(),
+    ptr::null(),
+]);
+
+static DESCRIPTOR: clap_plugin_descriptor = clap_plugin_descriptor {
+    clap_version: CLAP_VERSION,
+    id: PLUGIN_ID.as_ptr(),
+    name: c"Rusty Pipes".as_ptr(),
+    vendor: c"dividebysandwich".as_ptr(),
+    url: c"https://rusty-pipes.com".as_ptr(),
+    manual_url: c"https://rusty-pipes.com".as_ptr(),
+    support_url: c"https://github.com/dividebysandwich/rusty-pipes".as_ptr(),
+    version: c"1.6.13".as_ptr(),
+    description: c"Virtual pipe organ for GrandOrgue and Hauptwerk sample sets".as_ptr(),
+    features: &FEATURES.0 as *const _ as *const *const c_char,
+};
+
+/// The host, as seen from a background thread that may outlive the plugin.
+/// The pointer is cleared when the plugin is destroyed.
+struct HostLink {
+    host: Mutex<Option<HostPtr>>,
+    /// Set when the parameters changed off the main thread
+    rescan_pending: AtomicBool,
+}
+
+struct HostPtr(*const clap_host);
+// `clap_host::request_callback` may be called from any thread
+unsafe impl Send for HostPtr {}
+
+impl HostLink {
+    /// Asks the host for an `on_main_thread` call to rescan the parameters in.
+    fn request_rescan(&self) {
+        let host = self.host.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(HostPtr(host)) = *host else {
+            return;
+        };
+        self.rescan_pending.store(true, Ordering::Release);
+        unsafe {
+            if let Some(request_callback) = (*host).request_callback {
+                request_callback(host);
+            }
+        }
+    }
+}
+
+/// The object behind `clap_plugin::plugin_data`.
+struct Plugin {
+    clap_plugin: clap_plugin,
+    host: *const clap_host,
+    host_link: Arc<HostLink>,
+    instrument: Arc<Mutex<Option<Instrument>>>,
+}
+
+fn lock_instrument(instrument: &Mutex<Option<Instrument>>) -> MutexGuard<'_, Option<Instrument>> {
+    instrument.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Plugin {
+    /// # Safety
+    /// `plugin` must be a pointer handed out by `create_plugin` and not yet destroyed.
+    unsafe fn from_ptr<'a>(plugin: *const clap_plugin) -> &'a Plugin {
+        unsafe { &*((*plugin).plugin_data as *const Plugin) }
+    }
+
+    fn instrument(&self) -> MutexGuard<'_, Option<Instrument>> {
+        lock_instrument(&self.instrument)
+    }
+
+    /// Loads an organ on a thread of its own and swaps it in when done. Only
+    /// the swap takes the lock, so the host's calls carry on meanwhile.
+    fn load_in_background(&self, organ_load: OrganLoad) {
+        let instrument = Arc::clone(&self.instrument);
+        let host_link = Arc::clone(&self.host_link);
+        thread::spawn(move || {
+            let mut next_load = Some(organ_load);
+            while let Some(organ_load) = next_load.take() {
+                let loaded = match organ_load.run() {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        log::error!("[Plugin] {}", e);
+                        return;
+                    }
+                };
+                let Some((retired, reload)) = lock_instrument(&instrument)
+                    .as_mut()
+                    .map(|i| i.finish_initial_load(loaded))
+                else {
+                    return;
+                };
+                drop(retired);
+                next_load = reload;
+            }
+            host_link.request_rescan();
+        });
+    }
+
+    /// The instrument, unless another thread holds it right now. For the audio
+    /// thread, which must never wait on the main thread.
+    fn try_instrument(&self) -> Option<MutexGuard<'_, Option<Instrument>>> {
+        match self.instrument.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(e)) => Some(e.into_inner()),
+            Err(TryLockError::WouldBlock) => None,
+        }
+    }
+
+    /// Tells the host that parameter values (or the whole list) changed.
+    unsafe fn rescan_params(&self, all: bool) {
+        unsafe {
+            let Some(get_extension) = (*self.host).get_extension else {
+                return;
+            };
+            let host_params =
+                get_extension(self.host, CLAP_EXT_PARAMS.as_ptr()) as *const clap_host_params;
+            if host_params.is_null() {
+                return;
+            }
+            if let Some(rescan) = (*host_params).rescan {
+                let flags = if all {
+                    CLAP_PARAM_RESCAN_ALL
+                } else {
+                    CLAP_PARAM_RESCAN_VALUES
+                };
+                rescan(self.host, flags);
+            }
+        }
+    }
+}
+
+/// Applies one host event to the instrument. Unknown events are ignored.
+unsafe fn handle_event(instrument: &mut Instrument, header: *const clap_event_header) {
+    unsafe {
+        if (*header).space_id != CLAP_CORE_EVENT_SPACE_ID {
+            return;
+        }
+        match (*header).type_ {
+            CLAP_EVENT_NOTE_ON | CLAP_EVENT_NOTE_OFF => {
+                let event = &*(header as *const clap_event_note);
+                // Wildcards (-1) can't be routed to a manual; treat them as channel 1
+                let channel = event.channel.max(0) as u8;
+                if event.key < 0 {
+                    if (*header).type_ == CLAP_EVENT_NOTE_OFF {
+                        instrument.all_notes_off();
+                    }
+                    return;
+                }
+                let key = event.key as u8;
+                if (*header).type_ == CLAP_EVENT_NOTE_ON {
+                    let velocity = (event.velocity * 127.0).round().clamp(1.0, 127.0) as u8;
+                    instrument.note_on(channel, key, velocity);
+                } else {
+                    instrument.note_off(channel, key);
+                }
+            }
+            CLAP_EVENT_MIDI => {
+                let event = &*(header as *const clap_event_midi);
+                let [status, data1, data2] = event.data;
+                let channel = status & 0x0F;
+                match status & 0xF0 {
+                    0x90 => instrument.note_on(channel, data1, data2),
+                    0x80 => instrument.note_off(channel, data1),
+                    // All Sound Off / All Notes Off
+                    0xB0 if data1 == 120 || data1 == 123 => instrument.all_notes_off(),
+                    _ => {}
+                }
+            }
+            CLAP_EVENT_PARAM_VALUE => {
+                let event = &*(header as *const clap_event_param_value);
+                instrument.set_param(event.param_id, event.value);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The events in a host's input queue, in order.
+unsafe fn input_events(
+    events: *const clap_input_events,
+) -> impl Iterator<Item = *const clap_event_header> {
+    let (size, get) = if events.is_null() {
+        (None, None)
+    } else {
+        unsafe { ((*events).size, (*events).get) }
+    };
+    let count = size.map_or(0, |size| unsafe { size(events) });
+    (0..count).filter_map(move |i| {
+        let header = unsafe { get?(events, i) };
+        (!header.is_null()).then_some(header)
+    })
+}
+
+unsafe fn handle_events(instrument: &mut Instrument, events: *const clap_input_events) {
+    unsafe {
+        for header in input_events(events) {
+            handle_event(instrument, header);
+        }
+    }
+}
+
+/// Copies a string into a fixed-size C buffer, truncating if needed.
+fn write_c_string(dest: &mut [c_char], text: &str) {
+    let len = text.len().min(dest.len() - 1);
+    for (d, &b) in dest.iter_mut().zip(&text.as_bytes()[..len]) {
+        *d = b as c_char;
+    }
+    dest[len] = 0;
+}
+
+// --- Plugin ---
+
+unsafe extern "C" fn plugin_init(plugin: *const clap_plugin) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    let instrument = Instrument::new();
+    let organ_load = instrument.initial_organ_load();
+    *plugin.instrument() = Some(instrument);
+    if let Some(organ_load) = organ_load {
+        plugin.load_in_background(organ_load);
+    }
+    true
+}
+
+unsafe extern "C" fn plugin_destroy(plugin: *const clap_plugin) {
+    unsafe {
+        let plugin = Box::from_raw((*plugin).plugin_data as *mut Plugin);
+        // A load still running must not call into the host from now on
+        *plugin
+            .host_link
+            .host
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = None;
+        drop(plugin);
+    }
+}
+
+unsafe extern "C" fn plugin_activate(
+    plugin: *const clap_plugin,
+    sample_rate: f64,
+    _min_frames_count: u32,
+    _max_frames_count: u32,
+) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    match plugin.instrument().as_mut() {
+        Some(instrument) => {
+            instrument.activate(sample_rate.round() as u32);
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe extern "C" fn plugin_deactivate(plugin: *const clap_plugin) {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    if let Some(instrument) = plugin.instrument().as_mut() {
+        instrument.deactivate();
+    }
+}
+
+unsafe extern "C" fn plugin_start_processing(_plugin: *const clap_plugin) -> bool {
+    true
+}
+
+unsafe extern "C" fn plugin_stop_processing(_plugin: *const clap_plugin) {}
+
+unsafe extern "C" fn plugin_reset(plugin: *const clap_plugin) {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    if let Some(instrument) = plugin.instrument().as_mut() {
+        instrument.all_notes_off();
+    }
+}
+
+unsafe extern "C" fn plugin_process(
+    plugin: *const clap_plugin,
+    process: *const clap_process,
+) -> clap_process_status {
+    unsafe {
+        let plugin = Plugin::from_ptr(plugin);
+        let process = &*process;
+        if process.audio_outputs_count == 0 || process.audio_outputs.is_null() {
+            return CLAP_PROCESS_ERROR;
+        }
+        let output = &*process.audio_outputs;
+        if output.channel_count < 2 || output.data32.is_null() {
+            return CLAP_PROCESS_ERROR;
+        }
+        let frames = process.frames_count as usize;
+        let left = std::slice::from_raw_parts_mut(*output.data32, frames);
+        let right = std::slice::from_raw_parts_mut(*output.data32.add(1), frames);
+
+        // Silence while the main thread is swapping the organ
+        let mut guard = plugin.try_instrument();
+        let Some(instrument) = guard.as_mut().and_then(|guard| guard.as_mut()) else {
+            left.fill(0.0);
+            right.fill(0.0);
+            return CLAP_PROCESS_CONTINUE;
+        };
+        // Render up to each event's frame before applying it. The engine renders
+        // ahead by up to one of its blocks, so that's how late an event can be.
+        let mut rendered = 0;
+        for header in input_events(process.in_events) {
+            let time = ((*header).time as usize).clamp(rendered, frames);
+            instrument.render(&mut left[rendered..time], &mut right[rendered..time]);
+            rendered = time;
+            handle_event(instrument, header);
+        }
+        instrument.render(&mut left[rendered..], &mut right[rendered..]);
+        CLAP_PROCESS_CONTINUE
+    }
+}
+
+unsafe extern "C" fn plugin_get_extension(
+    _plugin: *const clap_plugin,
+    id: *const c_char,
+) -> *const c_void {
+    let id = unsafe { CStr::from_ptr(id) };
+    if id == CLAP_EXT_AUDIO_PORTS {
+        &AUDIO_PORTS as *const _ as *const c_void
+    } else if id == CLAP_EXT_NOTE_PORTS {
+        &NOTE_PORTS as *const _ as *const c_void
+    } else if id == CLAP_EXT_PARAMS {
+        &PARAMS as *const _ as *const c_void
+    } else if id == CLAP_EXT_STATE {
+        &STATE as *const _ as *const c_void
+    } else if id == CLAP_EXT_RENDER {
+        &RENDER as *const _ as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+unsafe extern "C" fn plugin_on_main_thread(plugin: *const clap_plugin) {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    if plugin
+        .host_link
+        .rescan_pending
+        .swap(false, Ordering::AcqRel)
+    {
+        unsafe { plugin.rescan_params(true) };
+    }
+}
+
+// --- Audio and note ports ---
+
+static AUDIO_PORTS: clap_plugin_audio_ports = clap_plugin_audio_ports {
+    count: Some(audio_ports_count),
+    get: Some(audio_ports_get),
+};
+
+unsafe extern "C" fn audio_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 0 } else { 1 }
+}
+
+unsafe extern "C" fn audio_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_audio_port_info,
+) -> bool {
+    if is_input || index != 0 {
+        return false;
+    }
+    let info = unsafe { &mut *info };
+    info.id = 0;
+    write_c_string(&mut info.name, "Output");
+    info.flags = CLAP_AUDIO_PORT_IS_MAIN;
+    info.channel_count = 2;
+    info.port_type = CLAP_PORT_STEREO.as_ptr();
+    info.in_place_pair = CLAP_INVALID_ID;
+    true
+}
+
+static NOTE_PORTS: clap_plugin_note_ports = clap_plugin_note_ports {
+    count: Some(note_ports_count),
+    get: Some(note_ports_get),
+};
+
+unsafe extern "C" fn note_ports_count(_plugin: *const clap_plugin, is_input: bool) -> u32 {
+    if is_input { 1 } else { 0 }
+}
+
+unsafe extern "C" fn note_ports_get(
+    _plugin: *const clap_plugin,
+    index: u32,
+    is_input: bool,
+    info: *mut clap_note_port_info,
+) -> bool {
+    if !is_input || index != 0 {
+        return false;
+    }
+    let info = unsafe { &mut *info };
+    info.id = 0;
+    info.supported_dialects = CLAP_NOTE_DIALECT_CLAP | CLAP_NOTE_DIALECT_MIDI;
+    info.preferred_dialect = CLAP_NOTE_DIALECT_MIDI;
+    write_c_string(&mut info.name, "Console");
+    true
+}
+
+// --- Parameters: one on/off switch per stop and tremulant ---
+
+static PARAMS: clap_plugin_params = clap_plugin_params {
+    count: Some(params_count),
+    get_info: Some(params_get_info),
+    get_value: Some(params_get_value),
+    value_to_text: Some(params_value_to_text),
+    text_to_value: Some(params_text_to_value),
+    flush: Some(params_flush),
+};
+
+unsafe extern "C" fn params_count(plugin: *const clap_plugin) -> u32 {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    plugin
+        .instrument()
+        .as_ref()
+        .map_or(0, |i| i.param_count() as u32)
+}
+
+unsafe extern "C" fn params_get_info(
+    plugin: *const clap_plugin,
+    param_index: u32,
+    param_info: *mut clap_param_info,
+) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    let guard = plugin.instrument();
+    let Some((id, name, module)) = guard
+        .as_ref()
+        .and_then(|i| i.param_info(param_index as usize))
+    else {
+        return false;
+    };
+    let info = unsafe { &mut *param_info };
+    info.id = id;
+    info.flags = CLAP_PARAM_IS_STEPPED | CLAP_PARAM_IS_AUTOMATABLE;
+    info.cookie = ptr::null_mut();
+    write_c_string(&mut info.name, &name);
+    write_c_string(&mut info.module, &module);
+    info.min_value = 0.0;
+    info.max_value = 1.0;
+    info.default_value = 0.0;
+    true
+}
+
+unsafe extern "C" fn params_get_value(
+    plugin: *const clap_plugin,
+    param_id: clap_id,
+    out_value: *mut f64,
+) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    match plugin
+        .instrument()
+        .as_ref()
+        .and_then(|i| i.param_value(param_id))
+    {
+        Some(value) => {
+            unsafe { *out_value = value };
+            true
+        }
+        None => false,
+    }
+}
+
+unsafe extern "C" fn params_value_to_text(
+    _plugin: *const clap_plugin,
+    _param_id: clap_id,
+    value: f64,
+    out_buffer: *mut c_char,
+    out_buffer_capacity: u32,
+) -> bool {
+    if out_buffer_capacity == 0 {
+        return false;
+    }
+    let dest = unsafe { std::slice::from_raw_parts_mut(out_buffer, out_buffer_capacity as usize) };
+    write_c_string(dest, if value >= 0.5 { "On" } else { "Off" });
+    true
+}
+
+unsafe extern "C" fn params_text_to_value(
+    _plugin: *const clap_plugin,
+    _param_id: clap_id,
+    param_value_text: *const c_char,
+    out_value: *mut f64,
+) -> bool {
+    let text = unsafe { CStr::from_ptr(param_value_text) }.to_string_lossy();
+    let value = match text.trim().to_lowercase().as_str() {
+        "on" | "1" => 1.0,
+        "off" | "0" => 0.0,
+        _ => return false,
+    };
+    unsafe { *out_value = value };
+    true
+}
+
+unsafe extern "C" fn params_flush(
+    plugin: *const clap_plugin,
+    in_: *const clap_input_events,
+    _out: *const clap_output_events,
+) {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    if let Some(instrument) = plugin.instrument().as_mut() {
+        unsafe { handle_events(instrument, in_) };
+    }
+}
+
+// --- State: organ and registration as JSON ---
+
+static STATE: clap_plugin_state = clap_plugin_state {
+    save: Some(state_save),
+    load: Some(state_load),
+};
+
+unsafe extern "C" fn state_save(plugin: *const clap_plugin, stream: *const clap_ostream) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    let Some(state) = plugin.instrument().as_ref().map(|i| i.save_state()) else {
+        return false;
+    };
+    let Ok(bytes) = serde_json::to_vec(&state) else {
+        return false;
+    };
+    let Some(write) = (unsafe { (*stream).write }) else {
+        return false;
+    };
+    let mut written = 0;
+    while written < bytes.len() {
+        let remaining = &bytes[written..];
+        let result = unsafe {
+            write(
+                stream,
+                remaining.as_ptr() as *const c_void,
+                remaining.len() as u64,
+            )
+        };
+        if result <= 0 {
+            return false;
+        }
+        written += result as usize;
+    }
+    true
+}
+
+unsafe extern "C" fn state_load(plugin: *const clap_plugin, stream: *const clap_istream) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    let Some(read) = (unsafe { (*stream).read }) else {
+        return false;
+    };
+    let mut bytes = Vec::new();
+    let mut chunk = [0u8; 4096];
+    loop {
+        let result = unsafe {
+            read(
+                stream,
+                chunk.as_mut_ptr() as *mut c_void,
+                chunk.len() as u64,
+            )
+        };
+        if result < 0 {
+            return false;
+        }
+        if result == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&chunk[..result as usize]);
+    }
+    let state: SavedState = match serde_json::from_slice(&bytes) {
+        Ok(state) => state,
+        Err(e) => {
+            log::error!("[Plugin] Invalid plugin state: {}", e);
+            return false;
+        }
+    };
+    // Loading an organ takes a while, so it runs without the lock `process` needs
+    let Some(organ_load) = plugin
+        .instrument()
+        .as_ref()
+        .map(|i| i.organ_load_for(&state))
+    else {
+        return false;
+    };
+    let loaded = match organ_load.map(OrganLoad::run).transpose() {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            log::error!("[Plugin] {}", e);
+            return false;
+        }
+    };
+    let organ_changed = loaded.is_some();
+    let Some(retired) = plugin
+        .instrument()
+        .as_mut()
+        .map(|instrument| instrument.load_state(&state, loaded))
+    else {
+        return false;
+    };
+    // The old organ is freed outside the lock too
+    drop(retired);
+    unsafe { plugin.rescan_params(organ_changed) };
+    true
+}
+
+// --- Render mode ---
+
+static RENDER: clap_plugin_render = clap_plugin_render {
+    has_hard_realtime_requirement: Some(render_has_hard_realtime_requirement),
+    set: Some(render_set),
+};
+
+unsafe extern "C" fn render_has_hard_realtime_requirement(_plugin: *const clap_plugin) -> bool {
+    false
+}
+
+unsafe extern "C" fn render_set(plugin: *const clap_plugin, mode: clap_plugin_render_mode) -> bool {
+    let plugin = unsafe { Plugin::from_ptr(plugin) };
+    if let Some(instrument) = plugin.instrument().as_mut() {
+        instrument.set_offline(mode == CLAP_RENDER_OFFLINE);
+    }
+    true
+}
+
+// --- Factory and entry point ---
+
+static FACTORY: clap_plugin_factory = clap_plugin_factory {
+    get_plugin_count: Some(factory_get_plugin_count),
+    get_plugin_descriptor: Some(factory_get_plugin_descriptor),
+    create_plugin: Some(factory_create_plugin),
+};
+
+unsafe extern "C" fn factory_get_plugin_count(_factory: *const clap_plugin_factory) -> u32 {
+    1
+}
+
+unsafe extern "C" fn factory_get_plugin_descriptor(
+    _factory: *const clap_plugin_factory,
+    index: u32,
+) -> *const clap_plugin_descriptor {
+    if index == 0 { &DESCRIPTOR } else { ptr::null() }
+}
+
+unsafe extern "C" fn factory_create_plugin(
+    _factory: *const clap_plugin_factory,
+    host: *const clap_host,
+    plugin_id: *const c_char,
+) -> *const clap_plugin {
+    if plugin_id.is_null() || unsafe { CStr::from_ptr(plugin_id) } != PLUGIN_ID {
+        return ptr::null();
+    }
+    let plugin = Box::into_raw(Box::new(Plugin {
+        clap_plugin: clap_plugin {
+            desc: &DESCRIPTOR,
+            plugin_data: ptr::null_mut(),
+            init: Some(plugin_init),
+            destroy: Some(plugin_destroy),
+            activate: Some(plugin_activate),
+            deactivate: Some(plugin_deactivate),
+            start_processing: Some(plugin_start_processing),
+            stop_processing: Some(plugin_stop_processing),
+            reset: Some(plugin_reset),
+            process: Some(plugin_process),
+            get_extension: Some(plugin_get_extension),
+            on_main_thread: Some(plugin_on_main_thread),
+        },
+        host,
+        host_link: Arc::new(HostLink {
+            host: Mutex::new(Some(HostPtr(host))),
+            rescan_pending: AtomicBool::new(false),
+        }),
+        instrument: Arc::new(Mutex::new(None)),
+    }));
+    unsafe {
+        (*plugin).clap_plugin.plugin_data = plugin as *mut c_void;
+        &(*plugin).clap_plugin
+    }
+}
+
+unsafe extern "C" fn entry_init(_plugin_path: *const c_char) -> bool {
+    true
+}
+
+unsafe extern "C" fn entry_deinit() {}
+
+unsafe extern "C" fn entry_get_factory(factory_id: *const c_char) -> *const c_void {
+    if !factory_id.is_null() && unsafe { CStr::from_ptr(factory_id) } == CLAP_PLUGIN_FACTORY_ID {
+        &FACTORY as *const _ as *const c_void
+    } else {
+        ptr::null()
+    }
+}
+
+#[allow(non_upper_case_globals)]
+#[unsafe(no_mangle)]
+pub static clap_entry: clap_plugin_entry = clap_plugin_entry {
+    clap_version: CLAP_VERSION,
+    init: Some(entry_init),
+    deinit: Some(entry_deinit),
+    get_factory: Some(entry_get_factory),
+};
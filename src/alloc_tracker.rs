@@ -0,0 +1,90 @@
+//! Debug-build check that the real-time mix loop never touches the heap.
+//!
+//! `TrackingAllocator` is a counting wrapper around the system allocator.
+//! `assert_no_alloc` runs a closure with tracking switched on for the current
+//! thread and panics if it allocated. The library doesn't install the
+//! allocator itself, as a process can only have one: the application does so
+//! in debug builds, and so do this crate's tests, which drive an engine
+//! through commands and blocks under the check. Without it the check passes
+//! trivially. Release builds skip the check.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+thread_local! {
+    /// Nesting depth of `assert_no_alloc` on this thread
+    static GUARD_DEPTH: Cell<u32> = const { Cell::new(0) };
+    /// Allocations made while the guard was held
+    static VIOLATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+/// The system allocator, counting allocations made inside `assert_no_alloc`.
+pub struct TrackingAllocator;
+
+#[cfg(test)]
+#[global_allocator]
+static GLOBAL: TrackingAllocator = TrackingAllocator;
+
+impl TrackingAllocator {
+    fn record() {
+        // `try_with` fails while the thread is being torn down; nothing to track then
+        let _ = GUARD_DEPTH.try_with(|depth| {
+            if depth.get() > 0 {
+                let _ = VIOLATIONS.try_with(|count| count.set(count.get() + 1));
+            }
+        });
+    }
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::record();
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::record();
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::record();
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Leaves the guarded section even if the closure panics.
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        GUARD_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Runs `f`, and in debug builds panics if it allocated on this thread.
+#[cfg(debug_assertions)]
+pub fn assert_no_alloc<T>(f: impl FnOnce() -> T) -> T {
+    let before = VIOLATIONS.with(Cell::get);
+    let result = {
+        GUARD_DEPTH.with(|depth| depth.set(depth.get() + 1));
+        let _guard = Guard;
+        f()
+    };
+    let allocations = VIOLATIONS.with(Cell::get) - before;
+    if allocations > 0 {
+        panic!("{allocations} heap allocation(s) in a section that must not allocate");
+    }
+    result
+}
+
+/// Runs `f`. Allocations are only checked in debug builds.
+#[cfg(not(debug_assertions))]
+#[inline(always)]
+pub fn assert_no_alloc<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
@@ -13,13 +13,21 @@ use utoipa::{OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::app::{AppMessage, LoadingState, MainLoopAction, WsMessage};
-use crate::app_state::{AppState, WebLearnSession, WebLearnTarget};
+use crate::app_state::{AppState, FeedbackTarget, WebLearnSession, WebLearnTarget};
 use crate::audio::get_supported_sample_rates;
+use crate::audio_command::EngineCommand;
 use crate::config::{
-    self, ConfigShared, MidiDeviceConfig, MidiEventSpec, MidiMappingMode, OrganProfile,
-    load_organ_library,
+    self, CacheFormat, ConfigShared, KeyboardZone, MidiDeviceConfig, MidiEventSpec,
+    MidiMappingMode, OrganProfile, load_organ_library,
 };
 use crate::gui_config::build_runtime_config;
+use crate::memory_plan::{MemoryReport, PlanStatus};
+use crate::midi_control::{
+    ContinuousBinding, ContinuousParameter, ContinuousSource, ControlCurve,
+    MidiFeedback,
+};
+use crate::organ_cache::{self, OrganCacheUsage, PruneSummary};
+use crate::tuning::{Temperament, Tuning};
 
 /// A handle that controls the lifecycle of the API Server.
 /// When this struct is dropped, the server shuts down and the background thread exits.
@@ -59,8 +67,10 @@ impl Drop for ApiServerHandle {
 
 #[derive(Serialize, Clone, ToSchema)]
 pub struct StopStatusResponse {
-    /// The internal index of the stop
+    /// The internal index of the stop (or of the coupler, for coupler entries)
     index: usize,
+    /// "stop" or "coupler". Couplers are toggled via `/couplers/{id}/channels/{ch}`.
+    kind: String,
     /// The name of the stop (e.g., "Principal 8'")
     name: String,
     /// List of active internal virtual channels (0-15) for this stop
@@ -83,7 +93,7 @@ pub struct PresetSlotResponse {
 
 #[derive(Deserialize, ToSchema)]
 pub struct MidiLearnStartRequest {
-    /// "stop", "tremulant", or "preset"
+    /// "stop", "tremulant", "preset" or "continuous"
     target: String,
     /// Required for "stop"
     stop_index: Option<usize>,
@@ -96,6 +106,9 @@ pub struct MidiLearnStartRequest {
     tremulant_id: Option<String>,
     /// Required for "preset": 1-based slot id
     preset_slot: Option<usize>,
+    /// Required for "continuous": "gain", "reverb_mix", "polyphony" or
+    /// "enclosure:<id>". The next Control Change or pitch bend is bound to it.
+    parameter: Option<String>,
 }
 
 #[derive(Serialize, Clone, ToSchema)]
@@ -119,6 +132,55 @@ pub struct OrganInfoResponse {
     name: String,
 }
 
+#[derive(Serialize, Clone, ToSchema)]
+pub struct RankMemoryResponse {
+    rank_id: String,
+    name: String,
+    /// Division of the rank, or of the first stop drawing it. Empty if unknown
+    division_id: String,
+    bytes: u64,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct DivisionMemoryResponse {
+    division_id: String,
+    bytes: u64,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct OrganMemoryResponse {
+    /// RAM held by each rank's preloaded, pre-cached or resident mapped samples
+    ranks: Vec<RankMemoryResponse>,
+    divisions: Vec<DivisionMemoryResponse>,
+    total_bytes: u64,
+}
+
+impl From<MemoryReport> for OrganMemoryResponse {
+    fn from(report: MemoryReport) -> Self {
+        Self {
+            ranks: report
+                .ranks
+                .into_iter()
+                .map(|r| RankMemoryResponse {
+                    rank_id: r.rank_id,
+                    name: r.name,
+                    division_id: r.division_id,
+                    bytes: r.bytes,
+                })
+                .collect(),
+            divisions: report
+                .divisions
+                .into_iter()
+                .map(|d| DivisionMemoryResponse {
+                    division_id: d.division_id,
+                    bytes: d.bytes,
+                })
+                .collect(),
+            total_bytes: report.total_bytes,
+        }
+    }
+}
+
 #[derive(Serialize, Clone, ToSchema)]
 pub struct OrganEntryResponse {
     /// Name of the organ
@@ -133,6 +195,98 @@ pub struct LoadOrganRequest {
     path: String,
 }
 
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CacheVariantResponse {
+    /// Output sample rate the samples were converted to
+    sample_rate: u32,
+    bits_per_sample: u16,
+    /// "Wav" or "Flac"
+    format: String,
+    files: usize,
+    bytes: u64,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CacheUsageResponse {
+    /// Name of the organ's cache directory, used in the `/cache/{name}/...` paths
+    name: String,
+    /// Path of the library organ the cache belongs to; None if it is not in the library
+    organ_path: Option<String>,
+    /// Converted samples, per sample rate, bit depth and format
+    variants: Vec<CacheVariantResponse>,
+    /// Samples extracted from .orgue archives, and the release alignment analysis
+    other_bytes: u64,
+    /// Preloaded attack heads
+    transient_bytes: u64,
+    total_bytes: u64,
+}
+
+impl From<OrganCacheUsage> for CacheUsageResponse {
+    fn from(usage: OrganCacheUsage) -> Self {
+        Self {
+            name: usage.name,
+            organ_path: usage.organ_file.map(|p| p.to_string_lossy().to_string()),
+            variants: usage
+                .variants
+                .into_iter()
+                .map(|v| CacheVariantResponse {
+                    sample_rate: v.sample_rate,
+                    bits_per_sample: v.bits_per_sample,
+                    format: format!("{:?}", v.format),
+                    files: v.files,
+                    bytes: v.bytes,
+                })
+                .collect(),
+            other_bytes: usage.other_bytes,
+            transient_bytes: usage.transient_bytes,
+            total_bytes: usage.total_bytes,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CacheProblemResponse {
+    /// The damaged or mismatched cache file
+    path: String,
+    problem: String,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CacheCleanupResponse {
+    /// Number of cache files deleted
+    files: usize,
+    bytes: u64,
+}
+
+impl From<PruneSummary> for CacheCleanupResponse {
+    fn from(summary: PruneSummary) -> Self {
+        Self {
+            files: summary.files,
+            bytes: summary.bytes,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PruneQuery {
+    delete: Option<bool>,
+}
+
+/// Progress of the cache rebuild started through the API.
+#[derive(Serialize, Clone, Default, ToSchema)]
+pub struct CacheRebuildStatusResponse {
+    /// Cache name of the organ being rebuilt
+    name: String,
+    running: bool,
+    /// Progress in the 0.0..=1.0 range
+    percent: f32,
+    message: String,
+    /// Cache files replaced, once the rebuild has finished
+    removed: Option<CacheCleanupResponse>,
+    /// Why the rebuild failed, if it did
+    error: Option<String>,
+}
+
 #[derive(Deserialize, ToSchema)]
 pub struct PresetSaveRequest {
     name: String,
@@ -163,12 +317,69 @@ pub struct ReverbEntry {
 pub struct AudioSettingsResponse {
     gain: f32,
     polyphony: usize,
+    /// Highest polyphony the engine was started with voices for
+    max_polyphony: usize,
+    /// Tracker delay scale (0.0 = off, 1.0 = as defined by the organ)
+    tracker_delay_scale: f32,
     reverb_mix: f32,
     active_reverb_index: Option<usize>,
     is_recording_midi: bool,
     is_recording_audio: bool,
 }
 
+#[derive(Serialize, Clone, ToSchema)]
+pub struct TuningResponse {
+    /// Active temperament, e.g. "equal", "werckmeister_iii" or "custom"
+    temperament: String,
+    /// All temperaments that can be selected
+    temperaments: Vec<String>,
+    /// Cents from equal temperament for C, C#, ... B, used by "custom"
+    custom_cents: Vec<f32>,
+    /// Transposition in semitones (-6 to +6)
+    transpose: i8,
+    /// Reference pitch of A4 in Hz (392 - 494)
+    a4_hz: f32,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct VelocityCurveResponse {
+    rank_id: String,
+    name: String,
+    /// Exponent of the velocity-to-gain curve, null if the rank ignores velocity
+    exponent: Option<f32>,
+}
+
+/// A null exponent makes the rank ignore velocity again.
+#[derive(Deserialize, ToSchema)]
+pub struct VelocityCurveRequest {
+    /// Greater than 0, at most 4. 1.0 is linear in amplitude.
+    exponent: Option<f32>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct LoaderStatusResponse {
+    /// Size of the sample loader pool
+    workers: usize,
+    /// Workers currently decoding or pushing samples
+    busy_workers: usize,
+    /// Sample starts and buffer refills waiting for a worker
+    queued_jobs: usize,
+    /// Voices with an open sample stream
+    streaming_voices: usize,
+    /// Samples opened since the audio engine was started
+    jobs_started: u64,
+}
+
+/// Fields left out keep their current value.
+#[derive(Deserialize, ToSchema)]
+pub struct TuningRequest {
+    temperament: Option<String>,
+    /// Exactly 12 values, C to B
+    custom_cents: Option<Vec<f32>>,
+    transpose: Option<i8>,
+    a4_hz: Option<f32>,
+}
+
 #[derive(Serialize, Clone, ToSchema)]
 pub struct TremulantResponse {
     id: String,
@@ -181,6 +392,76 @@ pub struct TremulantSetRequest {
     active: bool,
 }
 
+#[derive(Serialize, Clone, ToSchema)]
+pub struct EnclosureResponse {
+    id: String,
+    name: String,
+    /// Shutter position, 0.0 (closed) to 1.0 (open)
+    position: f32,
+    /// Control Change bound to this enclosure, if any. Its range, curve and
+    /// soft takeover are under /midi-bindings/continuous.
+    binding: Option<EnclosureBindingRequest>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct EnclosureSetRequest {
+    /// Shutter position, 0.0 (closed) to 1.0 (open)
+    position: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct EnclosureBindingRequest {
+    /// MIDI channel (0-15)
+    channel: u8,
+    /// Controller number (0-127), e.g. 11 for Expression
+    cc: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone, ToSchema)]
+pub struct ContinuousBindingRequest {
+    /// "gain", "reverb_mix", "polyphony" or "enclosure:<id>"
+    parameter: String,
+    /// "cc" or "pitch_bend"
+    source: String,
+    /// MIDI channel (0-15)
+    channel: u8,
+    /// Controller number (0-127), required for "cc"
+    cc: Option<u8>,
+    /// Parameter value at the bottom of the controller travel
+    min: Option<f32>,
+    /// Parameter value at the top of the controller travel
+    max: Option<f32>,
+    /// "linear" (default) or "log"
+    curve: Option<String>,
+    /// Ignore the controller until it reaches the current value
+    soft_takeover: Option<bool>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct MidiFeedbackRequest {
+    /// "auto" (derived from the learned events), "off" or "custom"
+    mode: String,
+    /// For "custom": raw MIDI sent when the control turns on, in hex, e.g. "90 24 7F"
+    on: Option<String>,
+    /// For "custom": raw MIDI sent when the control turns off, e.g. "80 24 00"
+    off: Option<String>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ContinuousBindingResponse {
+    parameter: String,
+    name: String,
+    /// Human-readable controller, e.g. "Ch1 CC 7"
+    source: String,
+    min: f32,
+    max: f32,
+    /// "linear" or "log"
+    curve: String,
+    soft_takeover: bool,
+    /// Current value of the parameter
+    value: f32,
+}
+
 // --- Shared State ---
 //
 // The server lives for the entire program lifetime. Its `mode` switches as
@@ -211,6 +492,8 @@ pub struct ApiData {
     pub mode: Arc<Mutex<Mode>>,
     pub loading_state: Arc<Mutex<LoadingState>>,
     pub ws_tx: broadcast::Sender<WsMessage>,
+    /// The latest cache rebuild started through `/cache/{name}/rebuild`.
+    pub cache_rebuild: Arc<Mutex<Option<CacheRebuildStatusResponse>>>,
 }
 
 fn broadcast(data: &web::Data<ApiData>, msg: WsMessage) {
@@ -277,15 +560,23 @@ macro_rules! require_config {
         get_organ_info,
         get_organ_library,
         load_organ,
+        get_organ_memory,
         get_stops,
         panic,
         update_stop_channel,
+        update_coupler_channel,
         get_presets,
         load_preset,
         save_preset,
         get_audio_settings,
         set_gain,
         set_polyphony,
+        set_tracker_delay_scale,
+        get_tuning,
+        set_tuning,
+        get_velocity_curves,
+        set_velocity_curve,
+        get_loader_status,
         start_stop_midi_recording,
         start_stop_audio_recording,
         get_reverbs,
@@ -293,18 +584,36 @@ macro_rules! require_config {
         set_reverb_mix,
         get_tremulants,
         set_tremulant,
+        get_enclosures,
+        set_enclosure,
+        set_enclosure_binding,
+        clear_enclosure_binding,
+        get_continuous_bindings,
+        set_continuous_binding,
+        clear_continuous_binding,
         midi_learn_start,
         midi_learn_status,
         midi_learn_cancel,
         clear_stop_binding,
         clear_tremulant_binding,
-        clear_preset_binding
+        clear_preset_binding,
+        set_stop_feedback,
+        set_tremulant_feedback,
+        resync_midi_feedback,
+        get_cache_usage,
+        verify_cache,
+        prune_cache,
+        rebuild_cache,
+        get_cache_rebuild
     ),
     components(
         schemas(
             StopStatusResponse,
             ChannelUpdateRequest,
             OrganInfoResponse,
+            OrganMemoryResponse,
+            RankMemoryResponse,
+            DivisionMemoryResponse,
             OrganEntryResponse,
             LoadOrganRequest,
             PresetSaveRequest,
@@ -314,10 +623,26 @@ macro_rules! require_config {
             ReverbMixRequest,
             ReverbEntry,
             AudioSettingsResponse,
+            TuningResponse,
+            TuningRequest,
+            VelocityCurveResponse,
+            VelocityCurveRequest,
+            LoaderStatusResponse,
             TremulantResponse,
             TremulantSetRequest,
+            EnclosureResponse,
+            EnclosureSetRequest,
+            EnclosureBindingRequest,
+            ContinuousBindingRequest,
+            ContinuousBindingResponse,
             MidiLearnStartRequest,
-            MidiLearnStatusResponse
+            MidiLearnStatusResponse,
+            MidiFeedbackRequest,
+            CacheUsageResponse,
+            CacheVariantResponse,
+            CacheProblemResponse,
+            CacheCleanupResponse,
+            CacheRebuildStatusResponse
         )
     ),
     tags(
@@ -361,6 +686,20 @@ async fn get_organ_info(data: web::Data<ApiData>) -> impl Responder {
     })
 }
 
+/// Returns the RAM the loaded organ's samples take, per rank and division.
+#[utoipa::path(
+    get, path = "/organ/memory", tag = "General",
+    responses((status = 200, body = OrganMemoryResponse))
+)]
+async fn get_organ_memory(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let organ = play.app_state.lock().unwrap().organ.clone();
+    match web::block(move || MemoryReport::of(&organ)).await {
+        Ok(report) => HttpResponse::Ok().json(OrganMemoryResponse::from(report)),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 /// Returns a list of all organs available in the library.
 #[utoipa::path(
     get, path = "/organs", tag = "General",
@@ -438,13 +777,201 @@ async fn panic(data: web::Data<ApiData>) -> impl Responder {
     let play = require_play!(data);
     let mut state = play.app_state.lock().unwrap();
 
-    let _ = play.audio_tx.send(AppMessage::AllNotesOff);
+    state.send_command(EngineCommand::AllNotesOff);
 
     state.add_midi_log("API: Executed Panic (All Notes Off)".into());
     HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
 }
 
-/// Returns a JSON list of all stops and their currently enabled virtual channels.
+/// Finds the library organ whose cache directory is `name`.
+fn find_cached_organ(name: &str) -> Option<PathBuf> {
+    let library = load_organ_library().ok()?;
+    library
+        .organs
+        .into_iter()
+        .map(|p| p.path)
+        .find(|p| organ_cache::cache_name(p).as_deref() == Some(name))
+}
+
+/// True if `name` is the cache of the organ currently being played, or of
+/// the organ being rebuilt. Its cache can't be pruned or rebuilt meanwhile.
+fn is_cache_in_use(data: &web::Data<ApiData>, name: &str) -> bool {
+    let rebuilding = data
+        .cache_rebuild
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|rebuild| rebuild.running && rebuild.name == name);
+    rebuilding
+        || get_play(data).is_some_and(|play| play.app_state.lock().unwrap().organ.name == name)
+}
+
+/// Lists the disk space used by each organ's sample cache.
+#[utoipa::path(
+    get, path = "/cache", tag = "Cache",
+    responses((status = 200, body = Vec<CacheUsageResponse>))
+)]
+async fn get_cache_usage() -> impl Responder {
+    let library: Vec<PathBuf> = load_organ_library()
+        .map(|lib| lib.organs.into_iter().map(|o| o.path).collect())
+        .unwrap_or_default();
+    match web::block(move || organ_cache::list(&library)).await {
+        Ok(Ok(usages)) => HttpResponse::Ok().json(
+            usages
+                .into_iter()
+                .map(CacheUsageResponse::from)
+                .collect::<Vec<_>>(),
+        ),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Checks a library organ's cached samples against their headers and sources.
+/// An empty list means the cache is intact.
+#[utoipa::path(
+    post, path = "/cache/{name}/verify", tag = "Cache",
+    params(("name" = String, Path, description = "Cache name from GET /cache")),
+    responses(
+        (status = 200, body = Vec<CacheProblemResponse>),
+        (status = 404, description = "No library organ with this cache name")
+    )
+)]
+async fn verify_cache(path: web::Path<String>) -> impl Responder {
+    let Some(organ_file) = find_cached_organ(&path.into_inner()) else {
+        return HttpResponse::NotFound().body("Organ not found in library");
+    };
+    match web::block(move || organ_cache::verify(&organ_file)).await {
+        Ok(Ok(problems)) => HttpResponse::Ok().json(
+            problems
+                .into_iter()
+                .map(|p| CacheProblemResponse {
+                    path: p.path.to_string_lossy().to_string(),
+                    problem: p.problem,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Lists the cached samples of a library organ that the saved sample rate,
+/// 16-bit and cache format settings no longer use, and deletes them with
+/// `?delete=true`. Refuses while any of the organ's samples can't be found.
+#[utoipa::path(
+    post, path = "/cache/{name}/prune", tag = "Cache",
+    params(
+        ("name" = String, Path, description = "Cache name from GET /cache"),
+        ("delete" = Option<bool>, Query, description = "Delete the files instead of only counting them")
+    ),
+    responses(
+        (status = 200, body = CacheCleanupResponse, description = "Files deleted, or that would be"),
+        (status = 404, description = "No library organ with this cache name"),
+        (status = 409, description = "The organ is currently playing or being rebuilt")
+    )
+)]
+async fn prune_cache(
+    path: web::Path<String>,
+    query: web::Query<PruneQuery>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let name = path.into_inner();
+    if is_cache_in_use(&data, &name) {
+        return HttpResponse::Conflict().body("Organ is currently playing or being rebuilt");
+    }
+    let Some(organ_file) = find_cached_organ(&name) else {
+        return HttpResponse::NotFound().body("Organ not found in library");
+    };
+    let settings = config::load_settings().unwrap_or_default();
+    let dry_run = !query.delete.unwrap_or(false);
+    let prune = move || organ_cache::prune(&organ_file, &settings.cache_settings(), dry_run);
+    match web::block(prune).await {
+        Ok(Ok(summary)) => HttpResponse::Ok().json(CacheCleanupResponse::from(summary)),
+        Ok(Err(e)) => HttpResponse::InternalServerError().body(e.to_string()),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Starts deleting a library organ's cache and converting all its samples
+/// again with the saved settings. Runs in the background; follow it with
+/// GET /cache/rebuild.
+#[utoipa::path(
+    post, path = "/cache/{name}/rebuild", tag = "Cache",
+    params(("name" = String, Path, description = "Cache name from GET /cache")),
+    responses(
+        (status = 202, body = CacheRebuildStatusResponse, description = "Rebuild started"),
+        (status = 404, description = "No library organ with this cache name"),
+        (status = 409, description = "The organ is currently playing, or a rebuild is running")
+    )
+)]
+async fn rebuild_cache(path: web::Path<String>, data: web::Data<ApiData>) -> impl Responder {
+    let name = path.into_inner();
+    if is_cache_in_use(&data, &name) {
+        return HttpResponse::Conflict().body("Organ is currently playing or being rebuilt");
+    }
+    let Some(organ_file) = find_cached_organ(&name) else {
+        return HttpResponse::NotFound().body("Organ not found in library");
+    };
+    let status = {
+        let mut rebuild = data.cache_rebuild.lock().unwrap();
+        if rebuild.as_ref().is_some_and(|r| r.running) {
+            return HttpResponse::Conflict().body("Another cache rebuild is running");
+        }
+        let status = CacheRebuildStatusResponse {
+            name,
+            running: true,
+            ..Default::default()
+        };
+        *rebuild = Some(status.clone());
+        status
+    };
+
+    let settings = config::load_settings().unwrap_or_default().cache_settings();
+    let rebuild = Arc::clone(&data.cache_rebuild);
+    thread::spawn(move || {
+        let (progress_tx, progress_rx) = mpsc::channel::<(f32, String)>();
+        let progress = Arc::clone(&rebuild);
+        let reporter = thread::spawn(move || {
+            for (percent, message) in progress_rx {
+                if let Some(status) = progress.lock().unwrap().as_mut() {
+                    status.percent = percent;
+                    status.message = message;
+                }
+            }
+        });
+        let result = organ_cache::rebuild(&organ_file, &settings, &Some(progress_tx));
+        let _ = reporter.join();
+        if let Some(status) = rebuild.lock().unwrap().as_mut() {
+            status.running = false;
+            match result {
+                Ok(summary) => {
+                    status.percent = 1.0;
+                    status.removed = Some(summary.into());
+                }
+                Err(e) => status.error = Some(e.to_string()),
+            }
+        }
+    });
+    HttpResponse::Accepted().json(status)
+}
+
+/// Progress of the latest cache rebuild, and its result once finished.
+#[utoipa::path(
+    get, path = "/cache/rebuild", tag = "Cache",
+    responses(
+        (status = 200, body = CacheRebuildStatusResponse),
+        (status = 404, description = "No rebuild was started")
+    )
+)]
+async fn get_cache_rebuild(data: web::Data<ApiData>) -> impl Responder {
+    match data.cache_rebuild.lock().unwrap().clone() {
+        Some(status) => HttpResponse::Ok().json(status),
+        None => HttpResponse::NotFound().body("No cache rebuild was started"),
+    }
+}
+
+/// Returns a JSON list of all stops and couplers and their currently enabled virtual channels.
 #[utoipa::path(
     get, path = "/stops", tag = "Stops",
     responses((status = 200, body = Vec<StopStatusResponse>))
@@ -477,11 +1004,40 @@ async fn get_stops(data: web::Data<ApiData>) -> impl Responder {
 
         response_list.push(StopStatusResponse {
             index: i,
+            kind: "stop".to_string(),
             name: stop.name.clone(),
             active_channels,
             division,
         });
     }
+
+    // Couplers are listed after the stops, grouped with the stops of their
+    // source manual so the UI shows them in the same division.
+    for (i, coupler) in state.organ.couplers.iter().enumerate() {
+        let mut active_channels = state
+            .coupler_channels
+            .get(&i)
+            .map(|set| set.iter().cloned().collect::<Vec<u8>>())
+            .unwrap_or_default();
+        active_channels.sort();
+
+        let division = state
+            .organ
+            .stops
+            .iter()
+            .filter(|s| s.manual_id.as_deref() == Some(coupler.source_manual_id.as_str()))
+            .map(|s| s.division_id.clone())
+            .find(|d| !d.is_empty())
+            .unwrap_or_default();
+
+        response_list.push(StopStatusResponse {
+            index: i,
+            kind: "coupler".to_string(),
+            name: coupler.name.clone(),
+            active_channels,
+            division,
+        });
+    }
     HttpResponse::Ok().json(response_list)
 }
 
@@ -511,7 +1067,7 @@ async fn update_stop_channel(
         return HttpResponse::NotFound().finish();
     }
 
-    match state.set_stop_channel_state(stop_index, channel_id, body.active, &play.audio_tx) {
+    match state.set_stop_channel_state(stop_index, channel_id, body.active) {
         Ok(_) => {
             let action = if body.active { "Enabled" } else { "Disabled" };
             state.add_midi_log(format!(
@@ -526,6 +1082,47 @@ async fn update_stop_channel(
     }
 }
 
+/// Engages or disengages a specific coupler for a specific virtual MIDI channel.
+#[utoipa::path(
+    post, path = "/couplers/{coupler_id}/channels/{channel_id}", tag = "Stops",
+    request_body = ChannelUpdateRequest,
+    params(
+        ("coupler_id" = usize, Path, description = "Index of the coupler"),
+        ("channel_id" = u8, Path, description = "Virtual MIDI Channel (0-15)")
+    ),
+    responses((status = 200), (status = 404), (status = 400))
+)]
+async fn update_coupler_channel(
+    path: web::Path<(usize, u8)>,
+    body: web::Json<ChannelUpdateRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let (coupler_index, channel_id) = path.into_inner();
+    if channel_id > 15 {
+        return HttpResponse::BadRequest().body("Channel ID > 15");
+    }
+
+    let mut state = play.app_state.lock().unwrap();
+    if coupler_index >= state.organ.couplers.len() {
+        return HttpResponse::NotFound().finish();
+    }
+
+    match state.set_coupler_channel_state(coupler_index, channel_id, body.active) {
+        Ok(_) => {
+            let action = if body.active { "Engaged" } else { "Disengaged" };
+            state.add_midi_log(format!(
+                "API: {} Coupler {} for Ch {}",
+                action,
+                coupler_index,
+                channel_id + 1
+            ));
+            HttpResponse::Ok().json(serde_json::json!({ "status": "success" }))
+        }
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
 /// Recalls a stop mapping preset (1-12).
 #[utoipa::path(
     post, path = "/presets/{slot_id}/load", tag = "Presets",
@@ -542,7 +1139,7 @@ async fn load_preset(path: web::Path<usize>, data: web::Data<ApiData>) -> impl R
     }
 
     let mut state = play.app_state.lock().unwrap();
-    match state.recall_preset(slot_id - 1, &play.audio_tx) {
+    match state.recall_preset(slot_id - 1) {
         Ok(_) => {
             if state.presets[slot_id - 1].is_some() {
                 state.add_midi_log(format!("API: Loaded Preset F{}", slot_id));
@@ -594,6 +1191,8 @@ async fn get_audio_settings(data: web::Data<ApiData>) -> impl Responder {
     let resp = AudioSettingsResponse {
         gain: state.gain,
         polyphony: state.polyphony,
+        max_polyphony: state.max_polyphony,
+        tracker_delay_scale: state.tracker_delay_scale,
         reverb_mix: state.reverb_mix,
         active_reverb_index: state.selected_reverb_index,
         is_recording_midi: state.is_recording_midi,
@@ -613,7 +1212,8 @@ async fn set_gain(body: web::Json<ValueRequest>, data: web::Data<ApiData>) -> im
     let gain = {
         let mut state = play.app_state.lock().unwrap();
         state.gain = body.value.clamp(0.0, 2.0);
-        let _ = play.audio_tx.send(AppMessage::SetGain(state.gain));
+        let gain = state.gain;
+        state.send_command(EngineCommand::SetGain(gain));
         state.persist_settings();
         state.gain
     };
@@ -621,7 +1221,8 @@ async fn set_gain(body: web::Json<ValueRequest>, data: web::Data<ApiData>) -> im
     HttpResponse::Ok().json(serde_json::json!({"status": "success", "gain": gain}))
 }
 
-/// Set Polyphony limit (minimum 1).
+/// Set Polyphony limit (minimum 1). Limited to the voices allocated at start-up,
+/// `max_polyphony` in the audio settings; the response has the value applied.
 #[utoipa::path(
     post, path = "/audio/polyphony", tag = "Audio",
     request_body = ValueRequest,
@@ -629,17 +1230,177 @@ async fn set_gain(body: web::Json<ValueRequest>, data: web::Data<ApiData>) -> im
 )]
 async fn set_polyphony(body: web::Json<ValueRequest>, data: web::Data<ApiData>) -> impl Responder {
     let play = require_play!(data);
-    let polyphony = {
+    let mut state = play.app_state.lock().unwrap();
+    let requested = body.value as usize;
+    let polyphony = state.set_polyphony(requested);
+    state.persist_settings();
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "success",
+        "polyphony": polyphony,
+        "max_polyphony": state.max_polyphony,
+        "limited": requested > state.max_polyphony,
+    }))
+}
+
+/// Set the Tracker Delay scale (0.0 = off, 1.0 = as defined by the organ).
+#[utoipa::path(
+    post, path = "/audio/tracker-delay", tag = "Audio",
+    request_body = ValueRequest,
+    responses((status = 200))
+)]
+async fn set_tracker_delay_scale(
+    body: web::Json<ValueRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let scale = {
         let mut state = play.app_state.lock().unwrap();
-        state.polyphony = (body.value as usize).max(1);
-        let _ = play
-            .audio_tx
-            .send(AppMessage::SetPolyphony(state.polyphony));
+        state.tracker_delay_scale = body.value.clamp(0.0, 2.0);
+        let scale = state.tracker_delay_scale;
+        state.send_command(EngineCommand::SetTrackerDelayScale(scale));
         state.persist_settings();
-        state.polyphony
+        state.tracker_delay_scale
     };
     broadcast(&data, WsMessage::AudioChanged);
-    HttpResponse::Ok().json(serde_json::json!({"status": "success", "polyphony": polyphony}))
+    HttpResponse::Ok().json(serde_json::json!({"status": "success", "scale": scale}))
+}
+
+/// Get the sample loader pool metrics.
+#[utoipa::path(
+    get, path = "/audio/loader", tag = "Audio",
+    responses((status = 200, body = LoaderStatusResponse))
+)]
+async fn get_loader_status(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let status = play.app_state.lock().unwrap().loader_status;
+    HttpResponse::Ok().json(LoaderStatusResponse {
+        workers: status.workers,
+        busy_workers: status.busy_workers,
+        queued_jobs: status.queued_jobs,
+        streaming_voices: status.streaming_voices,
+        jobs_started: status.jobs_started,
+    })
+}
+
+fn tuning_response(tuning: &Tuning) -> TuningResponse {
+    TuningResponse {
+        temperament: tuning.temperament.id().to_string(),
+        temperaments: Temperament::ALL
+            .iter()
+            .map(|t| t.id().to_string())
+            .collect(),
+        custom_cents: tuning.custom_cents.to_vec(),
+        transpose: tuning.transpose,
+        a4_hz: tuning.a4_hz,
+    }
+}
+
+/// Get the tuning of the loaded organ.
+#[utoipa::path(
+    get, path = "/audio/tuning", tag = "Audio",
+    responses((status = 200, body = TuningResponse))
+)]
+async fn get_tuning(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let state = play.app_state.lock().unwrap();
+    HttpResponse::Ok().json(tuning_response(&state.tuning))
+}
+
+/// Change the temperament, transposition or A4 pitch. Saved for the loaded organ.
+#[utoipa::path(
+    post, path = "/audio/tuning", tag = "Audio",
+    request_body = TuningRequest,
+    responses((status = 200, body = TuningResponse), (status = 400))
+)]
+async fn set_tuning(body: web::Json<TuningRequest>, data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let mut state = play.app_state.lock().unwrap();
+    let mut tuning = state.tuning.clone();
+
+    if let Some(id) = &body.temperament {
+        match Temperament::from_id(id) {
+            Some(temperament) => tuning.temperament = temperament,
+            None => return HttpResponse::BadRequest().body("Unknown temperament"),
+        }
+    }
+    if let Some(cents) = &body.custom_cents {
+        match <[f32; 12]>::try_from(cents.as_slice()) {
+            Ok(table) => tuning.custom_cents = table,
+            Err(_) => return HttpResponse::BadRequest().body("custom_cents needs 12 values"),
+        }
+    }
+    if let Some(transpose) = body.transpose {
+        tuning.transpose = transpose;
+    }
+    if let Some(a4_hz) = body.a4_hz {
+        tuning.a4_hz = a4_hz;
+    }
+
+    state.set_tuning(tuning, &play.audio_tx);
+    HttpResponse::Ok().json(tuning_response(&state.tuning))
+}
+
+/// Get the velocity curve of every rank of the loaded organ.
+#[utoipa::path(
+    get, path = "/audio/velocity-curves", tag = "Audio",
+    responses((status = 200, body = [VelocityCurveResponse]))
+)]
+async fn get_velocity_curves(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let state = play.app_state.lock().unwrap();
+
+    let mut rank_ids: Vec<_> = state.organ.ranks.keys().collect();
+    rank_ids.sort();
+
+    let list: Vec<VelocityCurveResponse> = rank_ids
+        .into_iter()
+        .map(|id| VelocityCurveResponse {
+            rank_id: id.clone(),
+            name: state.organ.ranks[id].name.clone(),
+            exponent: state.velocity_curves.curve(id),
+        })
+        .collect();
+    HttpResponse::Ok().json(list)
+}
+
+/// Make a rank velocity sensitive, or clear its curve. Saved for the loaded organ.
+#[utoipa::path(
+    post, path = "/audio/velocity-curves/{rank_id}", tag = "Audio",
+    params(("rank_id" = String, Path, description = "Rank ID")),
+    request_body = VelocityCurveRequest,
+    responses((status = 200, body = VelocityCurveResponse), (status = 400), (status = 404))
+)]
+async fn set_velocity_curve(
+    path: web::Path<String>,
+    body: web::Json<VelocityCurveRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let rank_id = path.into_inner();
+    let mut state = play.app_state.lock().unwrap();
+
+    let Some(rank) = state.organ.ranks.get(&rank_id) else {
+        return HttpResponse::NotFound().body("Rank not found");
+    };
+    let name = rank.name.clone();
+
+    let mut curves = state.velocity_curves.clone();
+    match body.exponent {
+        Some(exponent) if exponent.is_finite() && exponent > 0.0 => {
+            curves.ranks.insert(rank_id.clone(), exponent);
+        }
+        Some(_) => return HttpResponse::BadRequest().body("exponent must be greater than 0"),
+        None => {
+            curves.ranks.remove(&rank_id);
+        }
+    }
+
+    state.set_velocity_curves(curves, &play.audio_tx);
+    HttpResponse::Ok().json(VelocityCurveResponse {
+        exponent: state.velocity_curves.curve(&rank_id),
+        rank_id,
+        name,
+    })
 }
 
 /// Start or Stop MIDI Recording.
@@ -729,7 +1490,7 @@ async fn set_reverb(body: web::Json<ReverbRequest>, data: web::Data<ApiData>) ->
         {
             let mut state = play.app_state.lock().unwrap();
             state.selected_reverb_index = None;
-            let _ = play.audio_tx.send(AppMessage::SetReverbWetDry(0.0));
+            state.send_command(EngineCommand::SetReverbWetDry(0.0));
             state.persist_settings();
         }
         broadcast(&data, WsMessage::AudioChanged);
@@ -746,9 +1507,8 @@ async fn set_reverb(body: web::Json<ReverbRequest>, data: web::Data<ApiData>) ->
         let mut state = play.app_state.lock().unwrap();
         state.selected_reverb_index = Some(u_idx);
         let _ = play.audio_tx.send(AppMessage::SetReverbIr(path.clone()));
-        let _ = play
-            .audio_tx
-            .send(AppMessage::SetReverbWetDry(state.reverb_mix));
+        let mix = state.reverb_mix;
+        state.send_command(EngineCommand::SetReverbWetDry(mix));
         state.persist_settings();
         state.add_midi_log(format!("API: Reverb set to '{}'", name));
     }
@@ -771,9 +1531,8 @@ async fn set_reverb_mix(
     let mix = {
         let mut state = play.app_state.lock().unwrap();
         state.reverb_mix = body.mix.clamp(0.0, 1.0);
-        let _ = play
-            .audio_tx
-            .send(AppMessage::SetReverbWetDry(state.reverb_mix));
+        let mix = state.reverb_mix;
+        state.send_command(EngineCommand::SetReverbWetDry(mix));
         state.persist_settings();
         state.reverb_mix
     };
@@ -828,7 +1587,7 @@ async fn set_tremulant(
         return HttpResponse::NotFound().body("Tremulant ID not found");
     }
 
-    state.set_tremulant_active(trem_id.clone(), body.active, &play.audio_tx);
+    state.set_tremulant_active(trem_id.clone(), body.active);
 
     let action = if body.active { "Enabled" } else { "Disabled" };
     state.add_midi_log(format!("API: {} Tremulant '{}'", action, trem_id));
@@ -836,6 +1595,239 @@ async fn set_tremulant(
     HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
 }
 
+/// Get list of Enclosures (swell boxes) with their shutter positions.
+#[utoipa::path(
+    get, path = "/enclosures", tag = "Enclosures",
+    responses((status = 200, body = Vec<EnclosureResponse>))
+)]
+async fn get_enclosures(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let state = play.app_state.lock().unwrap();
+
+    let mut enc_ids: Vec<_> = state.organ.enclosures.keys().collect();
+    enc_ids.sort();
+
+    let list: Vec<EnclosureResponse> = enc_ids
+        .into_iter()
+        .map(|id| EnclosureResponse {
+            id: id.clone(),
+            name: state.organ.enclosures[id].name.clone(),
+            position: state.enclosure_position(id),
+            binding: state
+                .midi_control_map
+                .continuous_binding(&ContinuousParameter::Enclosure(id.clone()))
+                .and_then(|b| match b.source {
+                    ContinuousSource::ControlChange { channel, cc } => {
+                        Some(EnclosureBindingRequest { channel, cc })
+                    }
+                    ContinuousSource::PitchBend { .. } => None,
+                }),
+        })
+        .collect();
+    HttpResponse::Ok().json(list)
+}
+
+/// Set the shutter position of an Enclosure by ID.
+#[utoipa::path(
+    post, path = "/enclosures/{enclosure_id}", tag = "Enclosures",
+    request_body = EnclosureSetRequest,
+    params(
+        ("enclosure_id" = String, Path, description = "Enclosure ID")
+    ),
+    responses((status = 200), (status = 404))
+)]
+async fn set_enclosure(
+    path: web::Path<String>,
+    body: web::Json<EnclosureSetRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let enclosure_id = path.into_inner();
+    let mut state = play.app_state.lock().unwrap();
+
+    if !state.organ.enclosures.contains_key(&enclosure_id) {
+        return HttpResponse::NotFound().body("Enclosure ID not found");
+    }
+
+    state.set_enclosure_position(enclosure_id, body.position);
+
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
+/// Binds a MIDI controller (e.g. an expression pedal) to an Enclosure.
+#[utoipa::path(
+    post, path = "/midi-bindings/enclosure/{enclosure_id}", tag = "MIDI Learn",
+    request_body = EnclosureBindingRequest,
+    params(("enclosure_id" = String, Path, description = "Enclosure ID")),
+    responses((status = 200), (status = 400), (status = 404))
+)]
+async fn set_enclosure_binding(
+    path: web::Path<String>,
+    body: web::Json<EnclosureBindingRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let enclosure_id = path.into_inner();
+    if body.channel > 15 || body.cc > 127 {
+        return HttpResponse::BadRequest().body("Invalid channel or controller number");
+    }
+    let mut state = play.app_state.lock().unwrap();
+    if !state.organ.enclosures.contains_key(&enclosure_id) {
+        return HttpResponse::NotFound().body("Enclosure ID not found");
+    }
+    state
+        .midi_control_map
+        .learn_enclosure(enclosure_id.clone(), body.channel, body.cc);
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    state.add_midi_log(format!(
+        "Bound CC {} (Ch {}) to enclosure '{}'",
+        body.cc,
+        body.channel + 1,
+        enclosure_id
+    ));
+    state.ws_broadcast(WsMessage::EnclosuresChanged);
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
+/// Clears the MIDI controller binding for an Enclosure.
+#[utoipa::path(
+    delete, path = "/midi-bindings/enclosure/{enclosure_id}", tag = "MIDI Learn",
+    params(("enclosure_id" = String, Path, description = "Enclosure ID")),
+    responses((status = 200))
+)]
+async fn clear_enclosure_binding(
+    path: web::Path<String>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let enclosure_id = path.into_inner();
+    let mut state = play.app_state.lock().unwrap();
+    state
+        .midi_control_map
+        .clear_continuous(&ContinuousParameter::Enclosure(enclosure_id.clone()));
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    state.add_midi_log(format!(
+        "Cleared MIDI binding for enclosure '{}'",
+        enclosure_id
+    ));
+    state.ws_broadcast(WsMessage::EnclosuresChanged);
+    HttpResponse::Ok().json(serde_json::json!({"status": "cleared"}))
+}
+
+/// Resolves a continuous parameter key, checking that an enclosure exists.
+fn parse_continuous_parameter(state: &AppState, key: &str) -> Option<ContinuousParameter> {
+    ContinuousParameter::from_key(key).filter(|p| match p {
+        ContinuousParameter::Enclosure(id) => state.organ.enclosures.contains_key(id),
+        _ => true,
+    })
+}
+
+/// Lists the continuous controllers bound to gain, reverb mix, polyphony and enclosures.
+#[utoipa::path(
+    get, path = "/midi-bindings/continuous", tag = "MIDI Learn",
+    responses((status = 200, body = Vec<ContinuousBindingResponse>))
+)]
+async fn get_continuous_bindings(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    let state = play.app_state.lock().unwrap();
+    let list: Vec<ContinuousBindingResponse> = state
+        .midi_control_map
+        .continuous
+        .iter()
+        .map(|b| ContinuousBindingResponse {
+            parameter: b.parameter.key(),
+            name: b.parameter.to_string(),
+            source: b.source.to_string(),
+            min: b.min,
+            max: b.max,
+            curve: match b.curve {
+                ControlCurve::Linear => "linear".into(),
+                ControlCurve::Logarithmic => "log".into(),
+            },
+            soft_takeover: b.soft_takeover,
+            value: state.continuous_parameter_value(&b.parameter),
+        })
+        .collect();
+    HttpResponse::Ok().json(list)
+}
+
+/// Binds a Control Change or pitch bend to a continuous parameter, replacing
+/// any earlier binding for that parameter.
+#[utoipa::path(
+    post, path = "/midi-bindings/continuous", tag = "MIDI Learn",
+    request_body = ContinuousBindingRequest,
+    responses((status = 200), (status = 400), (status = 404))
+)]
+async fn set_continuous_binding(
+    body: web::Json<ContinuousBindingRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    if body.channel > 15 {
+        return HttpResponse::BadRequest().body("Invalid channel");
+    }
+    let source = match (body.source.as_str(), body.cc) {
+        ("cc", Some(cc)) if cc <= 127 => ContinuousSource::ControlChange {
+            channel: body.channel,
+            cc,
+        },
+        ("cc", _) => return HttpResponse::BadRequest().body("cc (0-127) is required"),
+        ("pitch_bend", _) => ContinuousSource::PitchBend {
+            channel: body.channel,
+        },
+        (other, _) => {
+            return HttpResponse::BadRequest().body(format!("Unknown source: {}", other));
+        }
+    };
+    let curve = match body.curve.as_deref() {
+        None | Some("linear") => ControlCurve::Linear,
+        Some("log") => ControlCurve::Logarithmic,
+        Some(other) => {
+            return HttpResponse::BadRequest().body(format!("Unknown curve: {}", other));
+        }
+    };
+
+    let mut state = play.app_state.lock().unwrap();
+    let Some(parameter) = parse_continuous_parameter(&state, &body.parameter) else {
+        return HttpResponse::NotFound().body("Unknown parameter");
+    };
+    let mut binding = ContinuousBinding::new(parameter, source);
+    binding.min = body.min.unwrap_or(binding.min);
+    binding.max = body.max.unwrap_or(binding.max);
+    binding.curve = curve;
+    binding.soft_takeover = body.soft_takeover.unwrap_or(false);
+
+    state.add_midi_log(format!("Bound {} to {}", binding.source, binding.parameter));
+    state.midi_control_map.learn_continuous(binding);
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
+/// Clears the controller bound to a continuous parameter.
+#[utoipa::path(
+    delete, path = "/midi-bindings/continuous/{parameter}", tag = "MIDI Learn",
+    params(("parameter" = String, Path, description = "Parameter key, e.g. gain or enclosure:<id>")),
+    responses((status = 200), (status = 400))
+)]
+async fn clear_continuous_binding(
+    path: web::Path<String>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let Some(parameter) = ContinuousParameter::from_key(&path.into_inner()) else {
+        return HttpResponse::BadRequest().body("Unknown parameter");
+    };
+    let mut state = play.app_state.lock().unwrap();
+    state.midi_control_map.clear_continuous(&parameter);
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    state.add_midi_log(format!("Cleared MIDI binding for {}", parameter));
+    HttpResponse::Ok().json(serde_json::json!({"status": "cleared"}))
+}
+
 /// Lists all 12 preset slots with their names (if any) and occupied state.
 #[utoipa::path(
     get, path = "/presets", tag = "Presets",
@@ -874,6 +1866,9 @@ fn describe_event(event: &MidiEventSpec) -> String {
             let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
             format!("SysEx: {}", hex.join(" "))
         }
+        MidiEventSpec::ControlChange { .. } | MidiEventSpec::ProgramChange { .. } => {
+            event.to_string()
+        }
     }
 }
 
@@ -954,6 +1949,17 @@ async fn midi_learn_start(
                 format!("Preset F{}", slot),
             )
         }
+        "continuous" => {
+            let Some(parameter) = body
+                .parameter
+                .as_deref()
+                .and_then(|key| parse_continuous_parameter(&state, key))
+            else {
+                return HttpResponse::BadRequest().body("a valid parameter is required");
+            };
+            let label = parameter.to_string();
+            (WebLearnTarget::Continuous { parameter }, label)
+        }
         other => {
             return HttpResponse::BadRequest()
                 .body(format!("Unknown target type: {}", other));
@@ -1006,6 +2012,30 @@ fn tick_learn_session(state: &mut AppState) -> Option<MidiLearnStatusResponse> {
         });
     }
 
+    // Continuous parameters take the next controller that moves
+    if let WebLearnTarget::Continuous { parameter } = &session.target {
+        let source = state
+            .last_continuous_received
+            .filter(|(_, t)| *t > session.started_at)
+            .map(|(source, _)| source)?;
+        let description = source.to_string();
+        let binding = state.learned_continuous_binding(parameter.clone(), source);
+        state.midi_control_map.learn_continuous(binding);
+        let organ_name = state.organ.name.clone();
+        let _ = state.midi_control_map.save(&organ_name);
+        state.add_midi_log(format!(
+            "Web MIDI Learn: {} -> {}",
+            session.target_name, description
+        ));
+        state.web_learn_session = None;
+
+        return Some(MidiLearnStatusResponse {
+            state: "captured".into(),
+            target_name: Some(session.target_name),
+            event_description: Some(description),
+        });
+    }
+
     let event = state
         .last_midi_event_received
         .as_ref()
@@ -1032,6 +2062,7 @@ fn tick_learn_session(state: &mut AppState) -> Option<MidiLearnStatusResponse> {
         WebLearnTarget::Preset { slot_index } => {
             state.midi_control_map.learn_preset(*slot_index, event);
         }
+        WebLearnTarget::Continuous { .. } => unreachable!("handled above"),
     }
     let _ = state.midi_control_map.save(&organ_name);
     state.add_midi_log(format!(
@@ -1163,6 +2194,117 @@ async fn clear_preset_binding(
     HttpResponse::Ok().json(serde_json::json!({"status": "cleared"}))
 }
 
+/// Parses the raw hex bytes of a custom feedback message, e.g. "90 24 7F".
+fn parse_feedback_message(hex: Option<&str>) -> Result<Option<MidiEventSpec>, String> {
+    let Some(hex) = hex.map(str::trim).filter(|h| !h.is_empty()) else {
+        return Ok(None);
+    };
+    let bytes = hex
+        .split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16))
+        .collect::<Result<Vec<u8>, _>>()
+        .map_err(|_| format!("Invalid hex bytes: {}", hex))?;
+    MidiEventSpec::from_midi_bytes(&bytes)
+        .map(Some)
+        .ok_or_else(|| format!("Not a Note, CC, Program Change or SysEx message: {}", hex))
+}
+
+fn parse_feedback_request(body: &MidiFeedbackRequest) -> Result<MidiFeedback, String> {
+    match body.mode.as_str() {
+        "auto" => Ok(MidiFeedback::Auto),
+        "off" => Ok(MidiFeedback::Off),
+        "custom" => Ok(MidiFeedback::Custom {
+            on: parse_feedback_message(body.on.as_deref())?,
+            off: parse_feedback_message(body.off.as_deref())?,
+        }),
+        other => Err(format!("Unknown feedback mode: {}", other)),
+    }
+}
+
+/// Sets what is sent to the MIDI outputs when a learned stop channel changes,
+/// e.g. to light its drawknob. The new feedback is sent right away.
+#[utoipa::path(
+    post, path = "/midi-bindings/stop/{stop_index}/{channel}/feedback", tag = "MIDI Learn",
+    request_body = MidiFeedbackRequest,
+    params(
+        ("stop_index" = usize, Path, description = "Index of the stop"),
+        ("channel" = u8, Path, description = "Virtual MIDI Channel (0-15)")
+    ),
+    responses((status = 200), (status = 400), (status = 404))
+)]
+async fn set_stop_feedback(
+    path: web::Path<(usize, u8)>,
+    body: web::Json<MidiFeedbackRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let (stop_index, channel) = path.into_inner();
+    let feedback = match parse_feedback_request(&body) {
+        Ok(f) => f,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let mut state = play.app_state.lock().unwrap();
+    if !state
+        .midi_control_map
+        .set_stop_feedback(stop_index, channel, feedback)
+    {
+        return HttpResponse::NotFound().body("No MIDI binding learned for this stop channel");
+    }
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    state
+        .midi_feedback_sent
+        .remove(&FeedbackTarget::Stop(stop_index, channel));
+    state.send_midi_feedback();
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
+/// Sets what is sent to the MIDI outputs when a learned tremulant changes.
+#[utoipa::path(
+    post, path = "/midi-bindings/tremulant/{trem_id}/feedback", tag = "MIDI Learn",
+    request_body = MidiFeedbackRequest,
+    params(("trem_id" = String, Path, description = "Tremulant ID")),
+    responses((status = 200), (status = 400), (status = 404))
+)]
+async fn set_tremulant_feedback(
+    path: web::Path<String>,
+    body: web::Json<MidiFeedbackRequest>,
+    data: web::Data<ApiData>,
+) -> impl Responder {
+    let play = require_play!(data);
+    let trem_id = path.into_inner();
+    let feedback = match parse_feedback_request(&body) {
+        Ok(f) => f,
+        Err(e) => return HttpResponse::BadRequest().body(e),
+    };
+    let mut state = play.app_state.lock().unwrap();
+    if !state
+        .midi_control_map
+        .set_tremulant_feedback(&trem_id, feedback)
+    {
+        return HttpResponse::NotFound().body("No MIDI binding learned for this tremulant");
+    }
+    let organ_name = state.organ.name.clone();
+    let _ = state.midi_control_map.save(&organ_name);
+    state
+        .midi_feedback_sent
+        .remove(&FeedbackTarget::Tremulant(trem_id));
+    state.send_midi_feedback();
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
+/// Sends the state of every learned stop, tremulant and preset piston to the
+/// MIDI outputs again, e.g. after switching the console on.
+#[utoipa::path(
+    post, path = "/midi-bindings/feedback/resync", tag = "MIDI Learn",
+    responses((status = 200))
+)]
+async fn resync_midi_feedback(data: web::Data<ApiData>) -> impl Responder {
+    let play = require_play!(data);
+    play.app_state.lock().unwrap().resync_midi_feedback();
+    HttpResponse::Ok().json(serde_json::json!({"status": "success"}))
+}
+
 /// Cancels any active web MIDI-learn session.
 #[utoipa::path(
     post, path = "/midi-learn/cancel", tag = "MIDI Learn",
@@ -1206,6 +2348,8 @@ struct ConfigStateResponse {
     organ_library: Vec<ConfigOrganLibraryEntry>,
     last_used_organ: Option<String>,
     error_msg: Option<String>,
+    /// Memory estimate of the selected organ with the current settings
+    memory_plan: PlanStatus,
 }
 
 #[derive(Serialize)]
@@ -1253,17 +2397,23 @@ struct ConfigAudioSettingsRequest {
     audio_buffer_frames: Option<usize>,
     max_ram_gb: Option<f32>,
     precache: Option<bool>,
+    mmap_samples: Option<bool>,
     convert_to_16bit: Option<bool>,
+    cache_format: Option<CacheFormat>,
     original_tuning: Option<bool>,
 }
 
 #[derive(Deserialize)]
 struct ConfigMidiDeviceUpdateRequest {
     name: String,
+    /// Configures the virtual input port instead, which is renamed to `name`
+    virtual_port: Option<bool>,
     enabled: Option<bool>,
     mapping_mode: Option<String>,
     simple_target_channel: Option<u8>,
     complex_mapping: Option<Vec<u8>>,
+    /// Replaces all keyboard zones of the device (an empty list removes the split)
+    zones: Option<Vec<KeyboardZone>>,
 }
 
 #[derive(Deserialize)]
@@ -1428,6 +2578,7 @@ async fn get_config_state(data: web::Data<ApiData>) -> impl Responder {
         organ_library,
         last_used_organ,
         error_msg: st.error_msg.clone(),
+        memory_plan: st.memory_plan(),
     };
     HttpResponse::Ok().json(resp)
 }
@@ -1578,9 +2729,15 @@ async fn config_set_audio_settings(
     if let Some(v) = body.precache {
         st.precache = v;
     }
+    if let Some(v) = body.mmap_samples {
+        st.mmap_samples = v;
+    }
     if let Some(v) = body.convert_to_16bit {
         st.convert_to_16bit = v;
     }
+    if let Some(v) = body.cache_format {
+        st.cache_format = v;
+    }
     if let Some(v) = body.original_tuning {
         st.original_tuning = v;
     }
@@ -1596,12 +2753,20 @@ async fn config_update_midi_device(
 ) -> impl Responder {
     let cfg = require_config!(data);
     let mut s = cfg.lock().unwrap();
-    let dev = s
-        .state
-        .settings
-        .midi_devices
-        .iter_mut()
-        .find(|d| d.name == body.name);
+    let dev = if body.virtual_port == Some(true) {
+        let port = &mut s.state.settings.virtual_midi_input;
+        if body.name.trim().is_empty() {
+            return HttpResponse::BadRequest().body("The virtual port needs a name");
+        }
+        port.name = body.name.clone();
+        Some(port)
+    } else {
+        s.state
+            .settings
+            .midi_devices
+            .iter_mut()
+            .find(|d| d.name == body.name)
+    };
     let dev = match dev {
         Some(d) => d,
         None => {
@@ -1639,6 +2804,14 @@ async fn config_update_midi_device(
         arr.copy_from_slice(map);
         dev.complex_mapping = arr;
     }
+    if let Some(zones) = &body.zones {
+        for zone in zones {
+            if let Err(e) = zone.validate() {
+                return HttpResponse::BadRequest().body(e);
+            }
+        }
+        dev.zones = zones.clone();
+    }
     s.revision = s.revision.wrapping_add(1);
     drop(s);
     broadcast(&data, WsMessage::Refetch);
@@ -2093,6 +3266,7 @@ pub fn start_api_server(
             mode,
             loading_state,
             ws_tx,
+            cache_rebuild: Arc::new(Mutex::new(None)),
         });
 
         let openapi = ApiDoc::openapi();
@@ -2117,15 +3291,26 @@ pub fn start_api_server(
                 .route("/ws", web::get().to(ws_handler))
                 // General
                 .route("/organ", web::get().to(get_organ_info))
+                .route("/organ/memory", web::get().to(get_organ_memory))
                 .route("/organs", web::get().to(get_organ_library))
                 .route("/organs/load", web::post().to(load_organ))
                 .route("/panic", web::post().to(panic))
+                // Sample cache
+                .route("/cache", web::get().to(get_cache_usage))
+                .route("/cache/rebuild", web::get().to(get_cache_rebuild))
+                .route("/cache/{name}/verify", web::post().to(verify_cache))
+                .route("/cache/{name}/prune", web::post().to(prune_cache))
+                .route("/cache/{name}/rebuild", web::post().to(rebuild_cache))
                 // Stops
                 .route("/stops", web::get().to(get_stops))
                 .route(
                     "/stops/{stop_id}/channels/{channel_id}",
                     web::post().to(update_stop_channel),
                 )
+                .route(
+                    "/couplers/{coupler_id}/channels/{channel_id}",
+                    web::post().to(update_coupler_channel),
+                )
                 // Presets
                 .route("/presets", web::get().to(get_presets))
                 .route("/presets/{slot_id}/load", web::post().to(load_preset))
@@ -2134,6 +3319,18 @@ pub fn start_api_server(
                 .route("/audio/settings", web::get().to(get_audio_settings))
                 .route("/audio/gain", web::post().to(set_gain))
                 .route("/audio/polyphony", web::post().to(set_polyphony))
+                .route(
+                    "/audio/tracker-delay",
+                    web::post().to(set_tracker_delay_scale),
+                )
+                .route("/audio/tuning", web::get().to(get_tuning))
+                .route("/audio/tuning", web::post().to(set_tuning))
+                .route("/audio/velocity-curves", web::get().to(get_velocity_curves))
+                .route(
+                    "/audio/velocity-curves/{rank_id}",
+                    web::post().to(set_velocity_curve),
+                )
+                .route("/audio/loader", web::get().to(get_loader_status))
                 .route("/audio/reverbs", web::get().to(get_reverbs))
                 .route("/audio/reverbs/select", web::post().to(set_reverb))
                 .route("/audio/reverbs/mix", web::post().to(set_reverb_mix))
@@ -2143,6 +3340,9 @@ pub fn start_api_server(
                 // Tremulants
                 .route("/tremulants", web::get().to(get_tremulants))
                 .route("/tremulants/{trem_id}", web::post().to(set_tremulant))
+                // Enclosures
+                .route("/enclosures", web::get().to(get_enclosures))
+                .route("/enclosures/{enclosure_id}", web::post().to(set_enclosure))
                 // MIDI Learn (web flow)
                 .route("/midi-learn", web::get().to(midi_learn_status))
                 .route("/midi-learn/start", web::post().to(midi_learn_start))
@@ -2159,6 +3359,38 @@ pub fn start_api_server(
                     "/midi-bindings/preset/{slot}",
                     web::delete().to(clear_preset_binding),
                 )
+                .route(
+                    "/midi-bindings/enclosure/{enclosure_id}",
+                    web::post().to(set_enclosure_binding),
+                )
+                .route(
+                    "/midi-bindings/enclosure/{enclosure_id}",
+                    web::delete().to(clear_enclosure_binding),
+                )
+                .route(
+                    "/midi-bindings/stop/{stop_index}/{channel}/feedback",
+                    web::post().to(set_stop_feedback),
+                )
+                .route(
+                    "/midi-bindings/tremulant/{trem_id}/feedback",
+                    web::post().to(set_tremulant_feedback),
+                )
+                .route(
+                    "/midi-bindings/feedback/resync",
+                    web::post().to(resync_midi_feedback),
+                )
+                .route(
+                    "/midi-bindings/continuous",
+                    web::get().to(get_continuous_bindings),
+                )
+                .route(
+                    "/midi-bindings/continuous",
+                    web::post().to(set_continuous_binding),
+                )
+                .route(
+                    "/midi-bindings/continuous/{parameter}",
+                    web::delete().to(clear_continuous_binding),
+                )
                 // Config-mode routes (return 503 outside config mode)
                 .route("/config", web::get().to(get_config_state))
                 .route("/config/audio-device", web::post().to(config_set_audio_device))
@@ -3,6 +3,12 @@ use std::path::PathBuf;
 use std::sync::mpsc::Sender;
 use std::time::Instant;
 
+use crate::audio_loader::LoaderStatus;
+use crate::mapped_sample::MappedMemoryStatus;
+use crate::tuning::Tuning;
+use crate::velocity::VelocityCurves;
+use crate::voice::VoiceId;
+
 /// Change hints broadcast to connected web clients. The server sends a hint
 /// and the client refetches the relevant REST endpoint — this avoids having
 /// to serialize full state over the wire and keeps the REST API authoritative.
@@ -12,6 +18,7 @@ pub enum WsMessage {
     StopsChanged,
     PresetsChanged,
     TremulantsChanged,
+    EnclosuresChanged,
     AudioChanged,
     /// Instructs the web client to reload all state. Pushed by the server
     /// to every newly-connected WebSocket and on every mode change.
@@ -49,23 +56,17 @@ pub struct LoadingState {
     pub message: String,
 }
 
-/// Messages sent from the TUI and MIDI threads to the Audio thread.
+/// Messages sent from the TUI and MIDI threads to the Audio thread that carry
+/// heap data, load files or start threads. Notes and control changes go
+/// through the command queue as `EngineCommand`s instead.
 #[derive(Debug)]
 pub enum AppMessage {
-    /// MIDI Note On event. (key, velocity, stop name)
-    NoteOn(u8, u8, String),
-    /// MIDI Note Off event. (key, stop name)
-    NoteOff(u8, String),
-    /// A command to stop all currently playing notes.
-    AllNotesOff,
     /// Set the reverb impulse response file path.
     SetReverbIr(PathBuf),
-    /// Set the reverb wet/dry mix.
-    SetReverbWetDry(f32),
-    SetGain(f32),
-    SetPolyphony(usize),
-    /// Activate or Deactivate a specific Tremulant (ID, Active)
-    SetTremulantActive(String, bool),
+    /// Retune the pipes (temperament, transposition, A4 pitch)
+    SetTuning(Tuning),
+    /// Per-rank velocity sensitivity
+    SetVelocityCurves(VelocityCurves),
     StartAudioRecording,
     StopAudioRecording,
     StartMidiRecording,
@@ -93,6 +94,8 @@ pub enum TuiMessage {
     AudioUnderrun,
     ActiveVoicesUpdate(usize),
     CpuLoadUpdate(f32),
+    LoaderStatusUpdate(LoaderStatus),
+    MappedMemoryUpdate(MappedMemoryStatus),
     /// Messages for Piano Roll
     TuiNoteOn(u8, u8, Instant),
     TuiNoteOff(u8, u8, Instant),
@@ -104,6 +107,12 @@ pub enum TuiMessage {
     MidiNoteOff(u8, u8),
     /// (channel)
     MidiChannelNotesOff(u8),
+    /// (controller, value, channel)
+    MidiControlChange(u8, u8, u8),
+    /// (program, channel)
+    MidiProgramChange(u8, u8),
+    /// (14-bit value, channel)
+    MidiPitchBend(u16, u8),
     MidiPlaybackFinished,
     MidiProgress(f32, u32, u32),
     MidiSeekChannel(Sender<i32>),
@@ -117,13 +126,24 @@ pub enum TuiMessage {
 pub struct ActiveNote {
     /// The MIDI note number.
     pub note: u8,
+    /// The velocity of the note-on that started the pipe speaking.
+    pub velocity: u8,
     /// When the note-on was received.
     pub start_time: Instant,
     /// The stop this note is playing on.
     pub stop_index: usize,
-    /// The rank this note is playing on.
-    pub rank_id: String,
-    pub voice_id: u64,
+    /// The rank this note is playing on, as an index into `OrganIndex::rank_ids`.
+    pub rank_index: usize,
+    pub voice_id: VoiceId,
+    /// Stereo output pair the pipe sounds on, kept for its release sample.
+    pub output_pair: usize,
+    /// Playback rate from the tuning, kept for its release sample.
+    pub pitch_ratio: f32,
+    /// Playing the rank's wave tremulant samples, so its release does too.
+    pub is_tremulant: bool,
+    /// Number of outstanding NoteOns for this note/stop pair. Several keys can
+    /// sound the same pipe through couplers; it is released when this hits zero.
+    pub hold_count: u32,
 }
 
 pub const PIPES: &str = r"
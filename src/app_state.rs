@@ -1,20 +1,28 @@
 use crate::{
     app::{AppMessage, TuiMessage, WsMessage},
+    audio_command::{CommandProducer, EngineCommand, OrganIndex},
+    audio_loader::LoaderStatus,
     config::{LcdDisplayConfig, MidiDeviceConfig, MidiEventSpec, load_settings, save_settings},
     input::KeyboardLayout,
+    mapped_sample::MappedMemoryStatus,
     midi,
-    midi_control::{ControlAction, MidiControlMap},
+    midi_control::{
+        ContinuousBinding, ContinuousParameter, ContinuousSource, ControlAction, MidiControlMap,
+    },
     midi_recorder::MidiRecorder,
-    organ::Organ,
+    organ::{Coupler, Organ},
+    tuning::Tuning,
+    velocity::VelocityCurves,
 };
 
 use tokio::sync::broadcast;
 
 use anyhow::Result;
 use midir::{MidiInput, MidiInputConnection, MidiInputPort, MidiOutputConnection};
+use ringbuf::traits::Producer;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeSet, HashMap, VecDeque},
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     fs::File,
     io::{BufReader, BufWriter},
     path::PathBuf,
@@ -30,12 +38,26 @@ pub const PRESET_FILE_NAME: &str = "rusty-pipes.presets.json";
 pub struct Preset {
     pub name: String,
     pub stop_channels: HashMap<usize, BTreeSet<u8>>,
+    #[serde(default)]
+    pub coupler_channels: HashMap<usize, BTreeSet<u8>>,
 }
 pub type PresetBank = [Option<Preset>; 12];
 pub type PresetConfig = HashMap<String, PresetBank>;
 
 pub const MIDI_LOG_CAPACITY: usize = 10; // Max log lines
 
+/// Maximum number of couplers a key may be forwarded through. Guards against
+/// runaway chains in organ definitions that couple manuals in a loop.
+pub const MAX_COUPLER_DEPTH: usize = 4;
+
+/// A control whose lit state is echoed to the MIDI outputs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FeedbackTarget {
+    Stop(usize, u8),
+    Tremulant(String),
+    Preset(usize),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct PlayedNote {
     pub note: u8,
@@ -73,8 +95,16 @@ pub fn connect_to_midi(
 /// Holds the shared state for both TUI and GUI.
 pub struct AppState {
     pub organ: Arc<Organ>,
+    /// Tremulant and enclosure indices, the same as the engine's
+    organ_index: OrganIndex,
+    /// Note and control commands for the audio thread
+    commands: CommandProducer,
     /// Maps stop_index -> set of active MIDI channels (0-9)
     pub stop_channels: HashMap<usize, BTreeSet<u8>>,
+    /// Maps coupler_index -> set of MIDI channels the coupler is engaged on
+    pub coupler_channels: HashMap<usize, BTreeSet<u8>>,
+    /// Maps each held key (channel, note) -> the (stop_index, note) pairs it is sounding
+    pub key_targets: HashMap<(u8, u8), BTreeSet<(usize, u8)>>,
     pub midi_log: VecDeque<String>,
     pub error_msg: Option<String>,
     // Currently active notes, mapping midi note -> PlayedNote instance
@@ -91,20 +121,38 @@ pub struct AppState {
     pub presets: PresetBank,
     pub gain: f32,
     pub polyphony: usize,
+    /// Highest polyphony the engine's voice slots were sized for at start-up
+    pub max_polyphony: usize,
+    /// Scale applied to the ranks' tracker delay (0.0 = off, 1.0 = authentic)
+    pub tracker_delay_scale: f32,
+    /// Temperament, transposition and A4 pitch, saved per organ
+    pub tuning: Tuning,
+    /// Per-rank velocity sensitivity, saved per organ
+    pub velocity_curves: VelocityCurves,
     pub last_underrun: Option<Instant>, // Store when the last buffer underrun occurred
     pub active_voice_count: usize,
     pub cpu_load: f32,
+    /// Sample loader pool metrics, reported by the audio thread
+    pub loader_status: LoaderStatus,
+    /// Size and residency of the memory-mapped samples. `None` unless the organ plays from them.
+    pub mapped_memory: Option<MappedMemoryStatus>,
     pub keyboard_layout: KeyboardLayout,
     pub octave_offset: i8, // Octave offset for computer keyboard input
     pub reverb_mix: f32,
     pub selected_reverb_index: Option<usize>,
     /// Set of currently active tremulant IDs
     pub active_tremulants: BTreeSet<String>,
+    /// Shutter position per enclosure ID (0.0 = closed, 1.0 = open)
+    pub enclosure_positions: HashMap<String, f32>,
     pub is_recording_midi: bool,
     pub is_recording_audio: bool,
     pub midi_control_map: MidiControlMap,
     // Stores the last raw midi event received and when, used by the Learn UI
     pub last_midi_event_received: Option<(MidiEventSpec, Instant)>,
+    // Last Control Change or pitch bend source that moved, used by the Learn UI
+    pub last_continuous_received: Option<(ContinuousSource, Instant)>,
+    /// Value each continuous controller last asked for, used for soft takeover
+    pub continuous_controller_values: HashMap<ContinuousParameter, f32>,
     pub midi_file_path: Option<PathBuf>,
     pub is_midi_file_playing: bool,
     pub midi_playback_progress: f32,
@@ -124,6 +172,8 @@ pub struct AppState {
 
     // LCD / MIDI Out
     pub midi_out: Vec<MidiOutputConnection>,
+    /// Lit state last echoed to the MIDI outputs per learned control
+    pub midi_feedback_sent: HashMap<FeedbackTarget, bool>,
     pub lcd_displays: Vec<LcdDisplayConfig>,
 
     /// Active MIDI-learn session initiated from the web UI. The REST poll
@@ -157,6 +207,9 @@ pub enum WebLearnTarget {
     Preset {
         slot_index: usize,
     },
+    Continuous {
+        parameter: ContinuousParameter,
+    },
 }
 
 pub fn get_preset_file_path() -> PathBuf {
@@ -177,12 +230,17 @@ fn format_mm_ss(total_seconds: u32) -> String {
 impl AppState {
     pub fn new(
         organ: Arc<Organ>,
+        commands: CommandProducer,
         gain: f32,
         polyphony: usize,
+        max_polyphony: usize,
+        tracker_delay_scale: f32,
         keyboard_layout: KeyboardLayout,
     ) -> Result<Self> {
         let presets = Self::load_presets(&organ.name);
         let midi_control_map = MidiControlMap::load(&organ.name);
+        let tuning = Tuning::load(&organ.name);
+        let velocity_curves = VelocityCurves::load(&organ.name);
         let mut midi_log = VecDeque::with_capacity(MIDI_LOG_CAPACITY);
         // Initialize with empty lines
         for _ in 0..MIDI_LOG_CAPACITY - 1 {
@@ -190,8 +248,12 @@ impl AppState {
         }
 
         Ok(Self {
+            organ_index: OrganIndex::new(&organ),
             organ,
+            commands,
             stop_channels: HashMap::new(),
+            coupler_channels: HashMap::new(),
+            key_targets: HashMap::new(),
             midi_log,
             error_msg: None,
             currently_playing_notes: HashMap::new(),
@@ -201,19 +263,28 @@ impl AppState {
             channel_active_notes: HashMap::new(),
             presets,
             gain,
-            polyphony,
+            polyphony: polyphony.min(max_polyphony),
+            max_polyphony,
+            tracker_delay_scale,
+            tuning,
+            velocity_curves,
             last_underrun: None,
             active_voice_count: 0,
             cpu_load: 0.0,
+            loader_status: LoaderStatus::default(),
+            mapped_memory: None,
             keyboard_layout,
             octave_offset: 0,
             reverb_mix: 0.0,
             selected_reverb_index: None,
             active_tremulants: BTreeSet::new(),
+            enclosure_positions: HashMap::new(),
             is_recording_midi: false,
             is_recording_audio: false,
             midi_control_map,
             last_midi_event_received: None,
+            last_continuous_received: None,
+            continuous_controller_values: HashMap::new(),
             midi_file_path: None,
             is_midi_file_playing: false,
             midi_playback_progress: 0.0,
@@ -226,6 +297,7 @@ impl AppState {
             midi_seek_tx: None,
             last_sysex: None,
             midi_out: Vec::new(),
+            midi_feedback_sent: HashMap::new(),
             lcd_displays: Vec::new(),
             web_learn_session: None,
             ws_broadcaster: None,
@@ -241,6 +313,15 @@ impl AppState {
         }
     }
 
+    /// Queues a command for the audio thread. The queue only fills up if the
+    /// audio thread falls `COMMAND_QUEUE_CAPACITY` commands behind, e.g. because
+    /// it stopped; the command is dropped then.
+    pub fn send_command(&mut self, command: EngineCommand) {
+        if self.commands.try_push(command).is_err() {
+            log::warn!("Audio command queue is full, dropping {:?}", command);
+        }
+    }
+
     // Helper to calculate the actual MIDI note
     pub fn get_keyboard_midi_note(&self, semitone: u8) -> u8 {
         // Base C3 = 48
@@ -253,12 +334,14 @@ impl AppState {
         // synchronously here would block the audio thread, REST handlers, and the UI.
         let gain = self.gain;
         let polyphony = self.polyphony;
+        let tracker_delay_scale = self.tracker_delay_scale;
         let lcd_displays = self.lcd_displays.clone();
 
         std::thread::spawn(move || {
             let mut settings = load_settings().unwrap_or_default();
             settings.gain = gain;
             settings.polyphony = polyphony;
+            settings.tracker_delay_scale = tracker_delay_scale;
             settings.lcd_displays = lcd_displays;
             if let Err(e) = save_settings(&settings) {
                 log::error!("Failed to persist settings: {}", e);
@@ -266,10 +349,32 @@ impl AppState {
         });
     }
 
-    pub fn modify_gain(&mut self, delta: f32, audio_tx: &Sender<AppMessage>) {
-        self.gain = (self.gain + delta).clamp(0.0, 1.0);
-        let _ = audio_tx.send(AppMessage::SetGain(self.gain));
+    /// Retunes the organ and saves the tuning for it in the background.
+    pub fn set_tuning(&mut self, tuning: Tuning, audio_tx: &Sender<AppMessage>) {
+        self.tuning = tuning.sanitized();
+        let _ = audio_tx.send(AppMessage::SetTuning(self.tuning.clone()));
+
+        self.tuning.save_in_background(&self.organ.name);
+        self.ws_broadcast(WsMessage::AudioChanged);
+    }
+
+    /// Changes the ranks' velocity curves and saves them for the organ in the background.
+    pub fn set_velocity_curves(&mut self, curves: VelocityCurves, audio_tx: &Sender<AppMessage>) {
+        self.velocity_curves = curves.sanitized();
+        let _ = audio_tx.send(AppMessage::SetVelocityCurves(self.velocity_curves.clone()));
+
+        self.velocity_curves.save_in_background(&self.organ.name);
+        self.ws_broadcast(WsMessage::AudioChanged);
+    }
+
+    pub fn modify_gain(&mut self, delta: f32) {
+        self.set_gain((self.gain + delta).clamp(0.0, 1.0));
         self.persist_settings();
+    }
+
+    fn set_gain(&mut self, gain: f32) {
+        self.gain = gain;
+        self.send_command(EngineCommand::SetGain(self.gain));
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::AudioChanged);
     }
@@ -349,30 +454,202 @@ impl AppState {
         }
     }
 
-    pub fn modify_polyphony(&mut self, delta: i32, audio_tx: &Sender<AppMessage>) {
+    pub fn modify_polyphony(&mut self, delta: i32) {
         let new_val = (self.polyphony as i32 + delta).max(1); // Minimum 1 voice
-        self.polyphony = new_val as usize;
-        let _ = audio_tx.send(AppMessage::SetPolyphony(self.polyphony));
+        self.set_polyphony(new_val as usize);
         self.persist_settings();
+    }
+
+    /// Sets the polyphony, limited to what the engine has voice slots for.
+    /// Returns the polyphony applied.
+    pub fn set_polyphony(&mut self, polyphony: usize) -> usize {
+        let polyphony = polyphony.max(1);
+        if polyphony > self.max_polyphony {
+            log::warn!(
+                "Polyphony {} exceeds the {} voices allocated at start-up",
+                polyphony,
+                self.max_polyphony
+            );
+            self.add_midi_log(format!(
+                "Polyphony limited to {} (restart with a higher polyphony for more)",
+                self.max_polyphony
+            ));
+        }
+        self.polyphony = polyphony.min(self.max_polyphony);
+        self.send_command(EngineCommand::SetPolyphony(self.polyphony));
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::AudioChanged);
+        self.polyphony
+    }
+
+    /// Binding for a controller just learned for a parameter. Keeps the range,
+    /// curve and soft takeover of the controller it replaces.
+    pub fn learned_continuous_binding(
+        &self,
+        parameter: ContinuousParameter,
+        source: ContinuousSource,
+    ) -> ContinuousBinding {
+        match self.midi_control_map.continuous_binding(&parameter) {
+            Some(existing) => ContinuousBinding {
+                source,
+                ..existing.clone()
+            },
+            None => ContinuousBinding::new(parameter, source),
+        }
     }
 
-    pub fn set_tremulant_active(
+    /// Current value of a parameter that continuous controllers can be bound to.
+    pub fn continuous_parameter_value(&self, parameter: &ContinuousParameter) -> f32 {
+        match parameter {
+            ContinuousParameter::Gain => self.gain,
+            ContinuousParameter::ReverbMix => self.reverb_mix,
+            ContinuousParameter::Polyphony => self.polyphony as f32,
+            ContinuousParameter::Enclosure(id) => self.enclosure_position(id),
+        }
+    }
+
+    /// Applies a value sent by a bound continuous controller. With soft takeover
+    /// the value is ignored until the controller reaches or crosses the current one.
+    /// The settings file is not written here: the controller position is the
+    /// source of truth, and a sweep would otherwise save it dozens of times.
+    pub fn set_continuous_parameter(
         &mut self,
-        trem_id: String,
-        active: bool,
-        audio_tx: &Sender<AppMessage>,
+        parameter: ContinuousParameter,
+        value: f32,
+        takeover_window: Option<f32>,
     ) {
+        let current = self.continuous_parameter_value(&parameter);
+        let previous = self
+            .continuous_controller_values
+            .insert(parameter.clone(), value);
+        if let Some(window) = takeover_window {
+            let crossed = previous.is_some_and(|prev| {
+                (prev <= current && current <= value) || (value <= current && current <= prev)
+            });
+            if !crossed && (value - current).abs() > window {
+                return;
+            }
+        }
+
+        match parameter {
+            ContinuousParameter::Gain => {
+                let gain = value.max(0.0);
+                if gain != self.gain {
+                    self.set_gain(gain);
+                }
+            }
+            ContinuousParameter::ReverbMix => {
+                let mix = value.clamp(0.0, 1.0);
+                if mix != self.reverb_mix {
+                    self.reverb_mix = mix;
+                    self.send_command(EngineCommand::SetReverbWetDry(mix));
+                    self.ws_broadcast(WsMessage::AudioChanged);
+                }
+            }
+            ContinuousParameter::Polyphony => {
+                let polyphony = (value.round() as usize).clamp(1, self.max_polyphony);
+                if polyphony != self.polyphony {
+                    self.set_polyphony(polyphony);
+                }
+            }
+            ContinuousParameter::Enclosure(id) => {
+                self.set_enclosure_position(id, value);
+            }
+        }
+    }
+
+    pub fn set_tremulant_active(&mut self, trem_id: String, active: bool) {
         if active {
             self.active_tremulants.insert(trem_id.clone());
         } else {
             self.active_tremulants.remove(&trem_id);
         }
-        let _ = audio_tx.send(AppMessage::SetTremulantActive(trem_id, active));
+        if let Some(tremulant) = self.organ_index.tremulant_index(&trem_id) {
+            self.send_command(EngineCommand::SetTremulantActive { tremulant, active });
+        }
+        self.send_midi_feedback();
         self.ws_broadcast(WsMessage::TremulantsChanged);
     }
 
+    /// Echoes the stops, tremulants and preset pistons with learned MIDI
+    /// controls whose state changed since the last call to the MIDI outputs,
+    /// so lit tabs on a console follow recalls and remote changes. Only
+    /// changes are sent, which keeps a console that echoes its input from
+    /// looping.
+    pub fn send_midi_feedback(&mut self) {
+        if self.midi_out.is_empty() {
+            return;
+        }
+        let map = &self.midi_control_map;
+        let mut pending = Vec::new();
+        for (&stop_index, channels) in &map.stops {
+            let active_channels = self.stop_channels.get(&stop_index);
+            for (&channel, control) in channels {
+                let active = active_channels.is_some_and(|set| set.contains(&channel));
+                let target = FeedbackTarget::Stop(stop_index, channel);
+                if self.midi_feedback_sent.get(&target) != Some(&active) {
+                    pending.push((target, active, control.feedback_event(active)));
+                }
+            }
+        }
+        for (trem_id, control) in &map.tremulants {
+            let active = self.active_tremulants.contains(trem_id);
+            let target = FeedbackTarget::Tremulant(trem_id.clone());
+            if self.midi_feedback_sent.get(&target) != Some(&active) {
+                pending.push((target, active, control.feedback_event(active)));
+            }
+        }
+        for (&slot, trigger) in &map.presets {
+            // The last recalled piston stays lit
+            let lit = self.last_recalled_preset_slot == Some(slot + 1);
+            let target = FeedbackTarget::Preset(slot);
+            if self.midi_feedback_sent.get(&target) != Some(&lit) {
+                pending.push((target, lit, trigger.as_ref().and_then(|t| t.lamp(lit))));
+            }
+        }
+
+        for (target, active, event) in pending {
+            self.midi_feedback_sent.insert(target, active);
+            let Some(event) = event else {
+                continue;
+            };
+            let bytes = event.to_midi_bytes();
+            for conn in &mut self.midi_out {
+                if let Err(e) = conn.send(&bytes) {
+                    log::warn!("Failed to send MIDI feedback {}: {}", event, e);
+                }
+            }
+        }
+    }
+
+    /// Sends the state of every learned control again, e.g. after loading an
+    /// organ or when a console was switched on later.
+    pub fn resync_midi_feedback(&mut self) {
+        self.midi_feedback_sent.clear();
+        self.send_midi_feedback();
+    }
+
+    /// Current shutter position of an enclosure. Enclosures start fully open.
+    pub fn enclosure_position(&self, enclosure_id: &str) -> f32 {
+        *self.enclosure_positions.get(enclosure_id).unwrap_or(&1.0)
+    }
+
+    pub fn set_enclosure_position(&mut self, enclosure_id: String, position: f32) {
+        let position = position.clamp(0.0, 1.0);
+        if self.enclosure_position(&enclosure_id) == position {
+            return;
+        }
+        self.enclosure_positions
+            .insert(enclosure_id.clone(), position);
+        if let Some(enclosure) = self.organ_index.enclosure_index(&enclosure_id) {
+            self.send_command(EngineCommand::SetEnclosurePosition {
+                enclosure,
+                position,
+            });
+        }
+        self.ws_broadcast(WsMessage::EnclosuresChanged);
+    }
+
     /// Loads the MIDI channel mapping preset bank for the specified organ from the JSON file.
     fn load_presets(organ_name: &str) -> PresetBank {
         let preset_path = get_preset_file_path();
@@ -421,13 +698,38 @@ impl AppState {
         Ok(())
     }
 
+    /// Carries out the actions a MIDI event triggered through the control map.
+    fn apply_control_actions(&mut self, actions: Vec<ControlAction>) -> Result<()> {
+        for action in actions {
+            match action {
+                ControlAction::SetStop {
+                    index,
+                    internal_channel,
+                    active,
+                } => {
+                    self.set_stop_channel_state(index, internal_channel, active)?;
+                }
+                ControlAction::SetTremulant { id, active } => {
+                    self.set_tremulant_active(id, active);
+                }
+                ControlAction::LoadPreset { slot_index } => {
+                    let _ = self.recall_preset(slot_index);
+                }
+                ControlAction::SetContinuous {
+                    parameter,
+                    value,
+                    takeover_window,
+                } => {
+                    self.set_continuous_parameter(parameter, value, takeover_window);
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Processes an incoming TuiMessage, updates state, and sends AppMessages.
     /// This is the core message-handling logic for both UIs.
-    pub fn handle_tui_message(
-        &mut self,
-        msg: TuiMessage,
-        audio_tx: &Sender<AppMessage>,
-    ) -> Result<()> {
+    pub fn handle_tui_message(&mut self, msg: TuiMessage) -> Result<()> {
         match msg {
             TuiMessage::ForceClose => {}
             // --- Raw MIDI events ---
@@ -444,38 +746,15 @@ impl AppState {
 
                 // Check if this triggers any stop changes
                 let actions = self.midi_control_map.check_event(&spec);
-                for action in actions {
-                    match action {
-                        ControlAction::SetStop {
-                            index,
-                            internal_channel,
-                            active,
-                        } => {
-                            self.set_stop_channel_state(index, internal_channel, active, audio_tx)?;
-                        }
-                        ControlAction::SetTremulant { id, active } => {
-                            self.set_tremulant_active(id, active, audio_tx);
-                        }
-                        ControlAction::LoadPreset { slot_index } => {
-                            let _ = self.recall_preset(slot_index, audio_tx);
-                        }
-                    }
-                }
+                self.apply_control_actions(actions)?;
 
                 // Track the active note (for visuals/logic)
                 self.channel_active_notes
                     .entry(channel)
                     .or_default()
                     .insert(note, vel);
-                // Find all stops mapped to this channel and send AppMessage
-                for (stop_index, active_channels) in &self.stop_channels {
-                    if active_channels.contains(&channel) {
-                        if let Some(stop) = self.organ.stops.get(*stop_index) {
-                            let stop_name = stop.name.clone();
-                            audio_tx.send(AppMessage::NoteOn(note, vel, stop_name))?;
-                        }
-                    }
-                }
+                // Sound all stops reached by this key and send AppMessage
+                self.press_key(channel, note, vel);
             }
             TuiMessage::MidiNoteOff(note, channel) => {
                 // Create Spec
@@ -490,37 +769,14 @@ impl AppState {
 
                 // Check if this triggers any stop changes
                 let actions = self.midi_control_map.check_event(&spec);
-                for action in actions {
-                    match action {
-                        ControlAction::SetStop {
-                            index,
-                            internal_channel,
-                            active,
-                        } => {
-                            self.set_stop_channel_state(index, internal_channel, active, audio_tx)?;
-                        }
-                        ControlAction::SetTremulant { id, active } => {
-                            self.set_tremulant_active(id, active, audio_tx);
-                        }
-                        ControlAction::LoadPreset { slot_index } => {
-                            let _ = self.recall_preset(slot_index, audio_tx);
-                        }
-                    }
-                }
+                self.apply_control_actions(actions)?;
 
                 // Stop tracking the active note
                 if let Some(notes) = self.channel_active_notes.get_mut(&channel) {
                     notes.remove(&note);
                 }
-                // Find all stops mapped to this channel and send AppMessage
-                for (stop_index, active_channels) in &self.stop_channels {
-                    if active_channels.contains(&channel) {
-                        if let Some(stop) = self.organ.stops.get(*stop_index) {
-                            let stop_name = stop.name.clone();
-                            audio_tx.send(AppMessage::NoteOff(note, stop_name))?;
-                        }
-                    }
-                }
+                // Release everything this key was sounding
+                self.release_key(channel, note);
             }
             TuiMessage::MidiSysEx(data) => {
                 // Create Spec
@@ -532,44 +788,50 @@ impl AppState {
 
                 // Check if this SysEx triggers any stop changes (e.g. Stop Toggle via SysEx)
                 let actions = self.midi_control_map.check_event(&spec);
-                for action in actions {
-                    match action {
-                        ControlAction::SetStop {
-                            index,
-                            internal_channel,
-                            active,
-                        } => {
-                            self.set_stop_channel_state(index, internal_channel, active, audio_tx)?;
-                        }
-                        ControlAction::SetTremulant { id, active } => {
-                            self.set_tremulant_active(id, active, audio_tx);
-                        }
-                        ControlAction::LoadPreset { slot_index } => {
-                            let _ = self.recall_preset(slot_index, audio_tx);
-                        }
-                    }
-                }
+                self.apply_control_actions(actions)?;
+            }
+            TuiMessage::MidiControlChange(cc, value, channel) => {
+                // Continuous controllers (e.g. swell pedals)
+                let source = ContinuousSource::ControlChange { channel, cc };
+                self.last_continuous_received = Some((source, Instant::now()));
+                let mut actions = self.midi_control_map.check_continuous(source, value as u16);
+
+                // Switching controllers (e.g. stop tabs sending CCs)
+                let spec = MidiEventSpec::control_change(channel, cc, value);
+                self.last_midi_event_received = Some((spec.clone(), Instant::now()));
+                actions.extend(self.midi_control_map.check_event(&spec));
+                self.apply_control_actions(actions)?;
+            }
+            TuiMessage::MidiPitchBend(value, channel) => {
+                let source = ContinuousSource::PitchBend { channel };
+                self.last_continuous_received = Some((source, Instant::now()));
+                let actions = self.midi_control_map.check_continuous(source, value);
+                self.apply_control_actions(actions)?;
+            }
+            TuiMessage::MidiProgramChange(program, channel) => {
+                let spec = MidiEventSpec::ProgramChange { channel, program };
+
+                // MIDI control learning / Organ switching detection
+                self.last_midi_event_received = Some((spec.clone(), Instant::now()));
+
+                // Program changes usually recall combinations (presets)
+                let actions = self.midi_control_map.check_event(&spec);
+                self.apply_control_actions(actions)?;
             }
             TuiMessage::MidiChannelNotesOff(channel) => {
                 // Handle channel-specific all notes off
                 if let Some(notes_to_stop) = self.channel_active_notes.remove(&channel) {
-                    // Find all stops mapped to this channel
-                    for (stop_index, active_channels) in &self.stop_channels {
-                        if active_channels.contains(&channel) {
-                            if let Some(stop) = self.organ.stops.get(*stop_index) {
-                                let stop_name = stop.name.clone();
-                                // Send NoteOff for each note that was active on this channel
-                                for (&note, _) in &notes_to_stop {
-                                    audio_tx.send(AppMessage::NoteOff(note, stop_name.clone()))?;
-                                }
-                            }
-                        }
+                    // Send NoteOff for each note that was active on this channel
+                    for &note in notes_to_stop.keys() {
+                        self.release_key(channel, note);
                     }
                 }
             }
 
             // --- Other TUI messages ---
             TuiMessage::CpuLoadUpdate(cpu_load) => self.cpu_load = cpu_load,
+            TuiMessage::LoaderStatusUpdate(status) => self.loader_status = status,
+            TuiMessage::MappedMemoryUpdate(status) => self.mapped_memory = Some(status),
             TuiMessage::ActiveVoicesUpdate(count) => self.active_voice_count = count,
             TuiMessage::AudioUnderrun => self.last_underrun = Some(Instant::now()),
             TuiMessage::MidiLog(log) => self.add_midi_log(log),
@@ -609,25 +871,200 @@ impl AppState {
         }
     }
 
-    /// Sends NoteOn for every note currently held on `channel`, routed through the given stop.
-    /// Used when a stop-channel mapping becomes active while notes are being held,
-    /// so those notes start sounding through the newly activated stop.
-    fn dispatch_held_notes_on(
-        &self,
-        stop_index: usize,
-        channel: u8,
-        audio_tx: &Sender<AppMessage>,
-    ) -> Result<()> {
-        if let (Some(active_notes), Some(stop)) = (
-            self.channel_active_notes.get(&channel),
-            self.organ.stops.get(stop_index),
-        ) {
-            let stop_name = stop.name.clone();
-            for (&note, &vel) in active_notes {
-                audio_tx.send(AppMessage::NoteOn(note, vel, stop_name.clone()))?;
+    /// Resolves the (stop_index, note) pairs a key on `channel` should sound.
+    ///
+    /// Stops enabled on the channel sound at unison unless a unison-off coupler
+    /// for their manual is engaged on the channel. Couplers engaged on the
+    /// channel then forward the key to their destination manual, where every
+    /// drawn stop (enabled on any channel) sounds at the coupled pitch. Chains
+    /// follow the GrandOrgue "couple to subsequent" rules up to `MAX_COUPLER_DEPTH`.
+    fn resolve_key_targets(&self, channel: u8, note: u8) -> BTreeSet<(usize, u8)> {
+        let organ = &self.organ;
+        let mut targets = BTreeSet::new();
+
+        let engaged: Vec<&Coupler> = self
+            .coupler_channels
+            .iter()
+            .filter(|(_, channels)| channels.contains(&channel))
+            .filter_map(|(index, _)| organ.couplers.get(*index))
+            .collect();
+        let unison_off: HashSet<&str> = engaged
+            .iter()
+            .filter(|c| c.unison_off)
+            .map(|c| c.source_manual_id.as_str())
+            .collect();
+
+        // Stops played directly by this channel
+        for (stop_index, active_channels) in &self.stop_channels {
+            if !active_channels.contains(&channel) {
+                continue;
+            }
+            if let Some(stop) = organ.stops.get(*stop_index) {
+                let silenced = stop
+                    .manual_id
+                    .as_deref()
+                    .is_some_and(|m| unison_off.contains(m));
+                if !silenced {
+                    targets.insert((*stop_index, note));
+                }
             }
         }
-        Ok(())
+
+        let couplers: Vec<&Coupler> = engaged.into_iter().filter(|c| !c.unison_off).collect();
+        if couplers.is_empty() {
+            return targets;
+        }
+
+        // The manuals this channel plays: the sources of engaged couplers that
+        // aren't fed by another engaged intermanual coupler, plus the manuals
+        // of stops drawn on this channel.
+        let mut home: HashSet<&str> = couplers
+            .iter()
+            .map(|c| c.source_manual_id.as_str())
+            .filter(|m| {
+                !couplers
+                    .iter()
+                    .any(|c| !c.is_intramanual() && c.destination_manual_id == *m)
+            })
+            .collect();
+        if home.is_empty() {
+            // Every engaged coupler feeds another one; treat them all as entry points.
+            home = couplers
+                .iter()
+                .map(|c| c.source_manual_id.as_str())
+                .collect();
+        }
+        for (stop_index, active_channels) in &self.stop_channels {
+            if active_channels.contains(&channel)
+                && let Some(m) = organ
+                    .stops
+                    .get(*stop_index)
+                    .and_then(|s| s.manual_id.as_deref())
+            {
+                home.insert(m);
+            }
+        }
+
+        // Walk the coupler graph: (coupler, accumulated shift, depth)
+        let mut pending: Vec<(&Coupler, i16, usize)> = couplers
+            .iter()
+            .filter(|c| home.contains(c.source_manual_id.as_str()))
+            .map(|c| (*c, c.key_shift as i16, 1))
+            .collect();
+        let mut reached: HashSet<(&str, i16)> = HashSet::new();
+        while let Some((coupler, shift, depth)) = pending.pop() {
+            let dest = coupler.destination_manual_id.as_str();
+            if !reached.insert((dest, shift)) || depth >= MAX_COUPLER_DEPTH {
+                continue;
+            }
+            for next in &couplers {
+                if next.source_manual_id == dest && coupler.allows_subsequent(next) {
+                    pending.push((*next, shift + next.key_shift as i16, depth + 1));
+                }
+            }
+        }
+
+        for (manual_id, shift) in reached {
+            if shift == 0 && unison_off.contains(manual_id) {
+                continue;
+            }
+            let coupled_note = note as i16 + shift;
+            let in_range = match organ.manuals.iter().find(|m| m.id_str == manual_id) {
+                Some(m) if m.key_count > 0 => {
+                    let first = m.first_midi_note as i16;
+                    (first..first + m.key_count as i16).contains(&coupled_note)
+                }
+                _ => (0..=127).contains(&coupled_note),
+            };
+            if !in_range {
+                continue;
+            }
+            for (stop_index, stop) in organ.stops.iter().enumerate() {
+                let is_drawn = self
+                    .stop_channels
+                    .get(&stop_index)
+                    .is_some_and(|channels| !channels.is_empty());
+                if is_drawn && stop.manual_id.as_deref() == Some(manual_id) {
+                    targets.insert((stop_index, coupled_note as u8));
+                }
+            }
+        }
+
+        targets
+    }
+
+    /// Starts every stop/note pair reached by a newly pressed key.
+    fn press_key(&mut self, channel: u8, note: u8, velocity: u8) {
+        // A repeated NoteOn without NoteOff must not leave the old targets hanging
+        self.release_key(channel, note);
+        let targets = self.resolve_key_targets(channel, note);
+        for &(stop_index, target_note) in &targets {
+            if stop_index < self.organ.stops.len() {
+                self.send_command(EngineCommand::NoteOn {
+                    note: target_note,
+                    velocity,
+                    stop: stop_index,
+                });
+            }
+        }
+        self.key_targets.insert((channel, note), targets);
+    }
+
+    /// Stops every stop/note pair the given key was sounding.
+    fn release_key(&mut self, channel: u8, note: u8) {
+        if let Some(targets) = self.key_targets.remove(&(channel, note)) {
+            for (stop_index, target_note) in targets {
+                if stop_index < self.organ.stops.len() {
+                    self.send_command(EngineCommand::NoteOff {
+                        note: target_note,
+                        stop: stop_index,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Re-resolves every held key after a stop or coupler change, sending NoteOff
+    /// for pairs that are no longer reached and NoteOn for newly reached ones.
+    fn resync_held_keys(&mut self) {
+        let held: Vec<(u8, u8, u8)> = self
+            .channel_active_notes
+            .iter()
+            .flat_map(|(&channel, notes)| notes.iter().map(move |(&n, &v)| (channel, n, v)))
+            .collect();
+
+        for (channel, note, velocity) in held {
+            let new_targets = self.resolve_key_targets(channel, note);
+            let old_targets = self
+                .key_targets
+                .remove(&(channel, note))
+                .unwrap_or_default();
+
+            for &(stop_index, target_note) in old_targets.difference(&new_targets) {
+                if stop_index < self.organ.stops.len() {
+                    self.send_command(EngineCommand::NoteOff {
+                        note: target_note,
+                        stop: stop_index,
+                    });
+                }
+            }
+            for &(stop_index, target_note) in new_targets.difference(&old_targets) {
+                if stop_index < self.organ.stops.len() {
+                    self.send_command(EngineCommand::NoteOn {
+                        note: target_note,
+                        velocity,
+                        stop: stop_index,
+                    });
+                }
+            }
+            self.key_targets.insert((channel, note), new_targets);
+        }
+    }
+
+    /// Drops all held-key bookkeeping. Used alongside `EngineCommand::AllNotesOff`.
+    pub fn clear_held_keys(&mut self) {
+        self.channel_active_notes.clear();
+        self.key_targets.clear();
     }
 
     pub fn set_stop_channel_state(
@@ -635,7 +1072,6 @@ impl AppState {
         stop_index: usize,
         channel: u8,
         active: bool,
-        audio_tx: &Sender<AppMessage>,
     ) -> Result<()> {
         let was_active = self
             .stop_channels
@@ -647,25 +1083,51 @@ impl AppState {
                 .entry(stop_index)
                 .or_default()
                 .insert(channel);
-            self.dispatch_held_notes_on(stop_index, channel, audio_tx)?;
+            self.resync_held_keys();
         } else if !active && was_active {
             if let Some(stop_set) = self.stop_channels.get_mut(&stop_index) {
                 stop_set.remove(&channel);
             }
-            if let Some(notes_to_stop) = self.channel_active_notes.get(&channel) {
-                if let Some(stop) = self.organ.stops.get(stop_index) {
-                    let stop_name = stop.name.clone();
-                    for (&note, _) in notes_to_stop {
-                        audio_tx.send(AppMessage::NoteOff(note, stop_name.clone()))?;
-                    }
-                }
-            }
+            self.resync_held_keys();
         }
 
         // Update LCD info
         if let Some(stop) = self.organ.stops.get(stop_index) {
             self.last_stop_change_name = self.get_stop_activity_label(active) + &stop.name.clone();
         }
+        self.send_midi_feedback();
+        self.refresh_lcds();
+        self.ws_broadcast(WsMessage::StopsChanged);
+
+        Ok(())
+    }
+
+    /// Engages or disengages a coupler for a specific channel.
+    pub fn set_coupler_channel_state(
+        &mut self,
+        coupler_index: usize,
+        channel: u8,
+        active: bool,
+    ) -> Result<()> {
+        let changed = if active {
+            self.coupler_channels
+                .entry(coupler_index)
+                .or_default()
+                .insert(channel)
+        } else {
+            self.coupler_channels
+                .get_mut(&coupler_index)
+                .is_some_and(|set| set.remove(&channel))
+        };
+
+        if changed {
+            self.resync_held_keys();
+        }
+
+        // Update LCD info
+        if let Some(coupler) = self.organ.couplers.get(coupler_index) {
+            self.last_stop_change_name = self.get_stop_activity_label(active) + &coupler.name;
+        }
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::StopsChanged);
 
@@ -674,7 +1136,7 @@ impl AppState {
 
     /// Simulates a MIDI event from the computer keyboard on Channel 1 (Index 0).
     /// handles audio dispatching and visual state updates.
-    pub fn handle_keyboard_note(&mut self, note: u8, velocity: u8, audio_tx: &Sender<AppMessage>) {
+    pub fn handle_keyboard_note(&mut self, note: u8, velocity: u8) {
         let channel = 0; // Computer keyboard mimics MIDI Channel 1
         let now = Instant::now();
         let note_name = crate::midi::midi_note_to_name(note); // Ensure this helper is public in midi.rs
@@ -700,17 +1162,8 @@ impl AppState {
             // Update Log
             self.add_midi_log(format!("Key On: {} (Ch 1, Vel {})", note_name, velocity));
 
-            // Dispatch Audio for mapped stops
-            // We iterate all loaded stops to see which ones are listening to Channel 0
-            for (stop_idx, stop) in self.organ.stops.iter().enumerate() {
-                if let Some(channels) = self.stop_channels.get(&stop_idx) {
-                    if channels.contains(&channel) {
-                        // This stop is mapped to Ch 1, play it!
-                        let _ =
-                            audio_tx.send(AppMessage::NoteOn(note, velocity, stop.name.clone()));
-                    }
-                }
-            }
+            // Dispatch Audio for mapped stops and couplers
+            self.press_key(channel, note, velocity);
         } else {
             // --- NOTE OFF ---
 
@@ -731,38 +1184,17 @@ impl AppState {
             self.add_midi_log(format!("Key Off: {} (Ch 1)", note_name));
 
             // Dispatch Audio
-            for (stop_idx, stop) in self.organ.stops.iter().enumerate() {
-                if let Some(channels) = self.stop_channels.get(&stop_idx) {
-                    if channels.contains(&channel) {
-                        let _ = audio_tx.send(AppMessage::NoteOff(note, stop.name.clone()));
-                    }
-                }
-            }
+            self.release_key(channel, note);
         }
     }
 
     /// Toggles a specific channel (0-9) for the specified stop.
-    pub fn toggle_stop_channel(
-        &mut self,
-        stop_index: usize,
-        channel: u8,
-        audio_tx: &Sender<AppMessage>,
-    ) -> Result<()> {
+    pub fn toggle_stop_channel(&mut self, stop_index: usize, channel: u8) -> Result<()> {
         let is_active = {
             let stop_set = self.stop_channels.entry(stop_index).or_default();
 
             if stop_set.contains(&channel) {
                 stop_set.remove(&channel);
-
-                // --- Send NoteOff for all active notes on this channel for this stop ---
-                if let Some(notes_to_stop) = self.channel_active_notes.get(&channel) {
-                    if let Some(stop) = self.organ.stops.get(stop_index) {
-                        let stop_name = stop.name.clone();
-                        for (&note, _) in notes_to_stop {
-                            audio_tx.send(AppMessage::NoteOff(note, stop_name.clone()))?;
-                        }
-                    }
-                }
                 false
             } else {
                 stop_set.insert(channel);
@@ -770,15 +1202,15 @@ impl AppState {
             }
         };
 
-        if is_active {
-            self.dispatch_held_notes_on(stop_index, channel, audio_tx)?;
-        }
+        // Start or stop held notes through this stop
+        self.resync_held_keys();
 
         // Update LCD info
         if let Some(stop) = self.organ.stops.get(stop_index) {
             self.last_stop_change_name =
                 self.get_stop_activity_label(is_active) + &stop.name.clone();
         }
+        self.send_midi_feedback();
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::StopsChanged);
 
@@ -786,24 +1218,16 @@ impl AppState {
     }
 
     /// Activates all channels for the specified stop.
-    pub fn select_all_channels_for_stop(
-        &mut self,
-        stop_index: usize,
-        audio_tx: &Sender<AppMessage>,
-    ) -> Result<()> {
-        let newly_added: Vec<u8> = {
-            let stop_set = self.stop_channels.entry(stop_index).or_default();
-            (0..16u8).filter(|&c| stop_set.insert(c)).collect()
-        };
-
-        for channel in newly_added {
-            self.dispatch_held_notes_on(stop_index, channel, audio_tx)?;
-        }
+    pub fn select_all_channels_for_stop(&mut self, stop_index: usize) -> Result<()> {
+        let stop_set = self.stop_channels.entry(stop_index).or_default();
+        stop_set.extend(0..16u8);
+        self.resync_held_keys();
 
         // Update LCD info
         if let Some(stop) = self.organ.stops.get(stop_index) {
             self.last_stop_change_name = self.get_stop_activity_label(true) + &stop.name.clone();
         }
+        self.send_midi_feedback();
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::StopsChanged);
 
@@ -811,42 +1235,18 @@ impl AppState {
     }
 
     /// Deactivates all channels for the specified stop.
-    pub fn select_none_channels_for_stop(
-        &mut self,
-        stop_index: usize,
-        audio_tx: &Sender<AppMessage>,
-    ) -> Result<()> {
+    pub fn select_none_channels_for_stop(&mut self, stop_index: usize) -> Result<()> {
         if let Some(stop_set) = self.stop_channels.get_mut(&stop_index) {
-            // Collect channels to deactivate
-            let channels_to_deactivate: Vec<u8> =
-                stop_set.iter().copied().filter(|&c| c < 16).collect();
-
-            if !channels_to_deactivate.is_empty() {
-                if let Some(stop) = self.organ.stops.get(stop_index) {
-                    let stop_name = stop.name.clone();
-                    for channel in channels_to_deactivate {
-                        // --- Send NoteOff for all active notes on this channel for this stop ---
-                        if let Some(notes_to_stop) = self.channel_active_notes.get(&channel) {
-                            for (&note, _) in notes_to_stop {
-                                audio_tx.send(AppMessage::NoteOff(note, stop_name.clone()))?;
-                            }
-                        }
-                        // Now remove it from the state
-                        stop_set.remove(&channel);
-                    }
-                } else {
-                    // Fallback (shouldn't happen)
-                    for channel in channels_to_deactivate {
-                        stop_set.remove(&channel);
-                    }
-                }
-            }
+            stop_set.retain(|&c| c >= 16);
         }
+        // Send NoteOff for all held notes this stop was sounding
+        self.resync_held_keys();
 
         // Update LCD info
         if let Some(stop) = self.organ.stops.get(stop_index) {
             self.last_stop_change_name = self.get_stop_activity_label(false) + &stop.name.clone();
         }
+        self.send_midi_feedback();
         self.refresh_lcds();
         self.ws_broadcast(WsMessage::StopsChanged);
 
@@ -916,6 +1316,7 @@ impl AppState {
         let new_preset = Preset {
             name: name.clone(),
             stop_channels: self.stop_channels.clone(),
+            coupler_channels: self.coupler_channels.clone(),
         };
         self.presets[slot] = Some(new_preset);
 
@@ -929,81 +1330,38 @@ impl AppState {
         self.ws_broadcast(WsMessage::PresetsChanged);
     }
 
-    /// Recalls a preset from a slot into `stop_channels`.
-    /// Only releases notes if their controlling MIDI channel is no longer mapped to the stop.
-    pub fn recall_preset(&mut self, slot: usize, audio_tx: &Sender<AppMessage>) -> Result<()> {
+    /// Recalls a preset from a slot into `stop_channels` and `coupler_channels`.
+    /// Held notes are re-resolved, so only stops that are no longer reached get released.
+    pub fn recall_preset(&mut self, slot: usize) -> Result<()> {
         if slot >= 12 {
             return Ok(());
         }
         if let Some(preset_data) = &self.presets[slot] {
             let new_preset_map = &preset_data.stop_channels;
-            let _preset_name = &preset_data.name;
+            let new_coupler_map = &preset_data.coupler_channels;
+            let preset_name = preset_data.name.clone();
 
             let is_valid = new_preset_map
                 .keys()
-                .all(|&stop_index| stop_index < self.organ.stops.len());
+                .all(|&stop_index| stop_index < self.organ.stops.len())
+                && new_coupler_map
+                    .keys()
+                    .all(|&coupler_index| coupler_index < self.organ.couplers.len());
 
             if is_valid {
-                // Snapshot the current configuration before we change it
-                let old_map = self.stop_channels.clone();
-
                 // Update the state to the new preset immediately
                 // Any new notes played after this line will use the new mapping
                 self.stop_channels = new_preset_map.clone();
+                self.coupler_channels = new_coupler_map.clone();
 
-                // We iterate over the OLD map to find Stop -> Channel mappings that have been removed.
-                for (stop_index, old_active_channels) in &old_map {
-                    // Get the set of channels enabled for this stop in the new preset
-                    let new_active_channels_opt = self.stop_channels.get(stop_index);
-
-                    for &channel in old_active_channels {
-                        // Check if this specific channel is still mapped to this stop in the new preset
-                        let is_still_mapped = match new_active_channels_opt {
-                            Some(new_set) => new_set.contains(&channel),
-                            None => false, // The stop was completely disabled in the new preset
-                        };
-
-                        // If the channel is no longer mapped to this stop, we must cut the audio
-                        // for any notes currently being held on this MIDI channel.
-                        if !is_still_mapped {
-                            if let Some(active_notes_on_channel) =
-                                self.channel_active_notes.get(&channel)
-                            {
-                                if let Some(stop) = self.organ.stops.get(*stop_index) {
-                                    let stop_name = stop.name.clone();
-
-                                    // Send NoteOff for currently active notes on this specific channel/stop combo
-                                    for (&note, _) in active_notes_on_channel {
-                                        audio_tx
-                                            .send(AppMessage::NoteOff(note, stop_name.clone()))?;
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-
-                let added_mappings: Vec<(usize, u8)> = self
-                    .stop_channels
-                    .iter()
-                    .flat_map(|(&stop_index, new_channels)| {
-                        let old_opt = old_map.get(&stop_index);
-                        new_channels
-                            .iter()
-                            .copied()
-                            .filter(move |c| old_opt.map_or(true, |old| !old.contains(c)))
-                            .map(move |c| (stop_index, c))
-                    })
-                    .collect();
-
-                for (stop_index, channel) in added_mappings {
-                    self.dispatch_held_notes_on(stop_index, channel, audio_tx)?;
-                }
+                // Cut notes whose stop is no longer reached and start newly reached ones
+                self.resync_held_keys();
 
                 log::info!("Recalled preset from slot F{}", slot + 1);
-                self.last_recalled_preset_name = format!("F{}: {}", slot + 1, _preset_name);
+                self.last_recalled_preset_name = format!("F{}: {}", slot + 1, preset_name);
                 self.last_recalled_preset_slot = Some(slot + 1);
                 self.add_midi_log(format!("Recalled preset F{}", slot + 1));
+                self.send_midi_feedback();
                 self.ws_broadcast(WsMessage::StopsChanged);
                 self.ws_broadcast(WsMessage::PresetsChanged);
             } else {
@@ -0,0 +1,181 @@
+use ringbuf::traits::Split;
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use std::collections::HashMap;
+
+use crate::audio_routing::normalize_group_id;
+use crate::organ::Organ;
+
+/// Commands the UI can queue ahead of the audio thread. A preset recall with
+/// keys held sends a NoteOff and a NoteOn per stop and key, all at once.
+pub const COMMAND_QUEUE_CAPACITY: usize = 4096;
+
+pub type CommandProducer = HeapProd<EngineCommand>;
+pub type CommandConsumer = HeapCons<EngineCommand>;
+
+/// A single-producer, single-consumer queue of engine commands. Its storage is
+/// allocated here, so neither end allocates or blocks afterwards.
+pub fn command_queue() -> (CommandProducer, CommandConsumer) {
+    HeapRb::new(COMMAND_QUEUE_CAPACITY).split()
+}
+
+/// A control event for the engine. Stops, tremulants and enclosures are given
+/// by their index in `OrganIndex`, so commands carry no heap data and can be
+/// handled on the audio thread without allocating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EngineCommand {
+    NoteOn {
+        note: u8,
+        velocity: u8,
+        stop: usize,
+    },
+    NoteOff {
+        note: u8,
+        stop: usize,
+    },
+    AllNotesOff,
+    SetGain(f32),
+    SetPolyphony(usize),
+    SetReverbWetDry(f32),
+    SetTrackerDelayScale(f32),
+    SetTremulantActive {
+        tremulant: usize,
+        active: bool,
+    },
+    /// 0.0 = closed .. 1.0 = open
+    SetEnclosurePosition {
+        enclosure: usize,
+        position: f32,
+    },
+}
+
+/// Integer indices for the organ's ranks, windchest groups, tremulants and
+/// enclosures, resolved once when the engine starts. The engine keeps its
+/// per-object state in `Vec`s addressed by these indices.
+#[derive(Debug, Default)]
+pub struct OrganIndex {
+    /// Rank IDs, sorted
+    pub rank_ids: Vec<String>,
+    /// Rank indices sounded by each stop, in `Organ::stops` order
+    pub stop_ranks: Vec<Vec<usize>>,
+    /// Windchest group of each rank
+    pub rank_windchests: Vec<Option<usize>>,
+    /// Windchest group IDs, sorted
+    pub windchest_ids: Vec<String>,
+    /// Enclosure each windchest group is mixed through. A windchest in several
+    /// enclosures is routed through the first one only.
+    pub windchest_enclosures: Vec<Option<usize>>,
+    /// Tremulants acting on each windchest group
+    pub windchest_tremulants: Vec<Vec<usize>>,
+    /// Tremulant that switches each rank to its wave tremulant samples
+    pub rank_wave_tremulants: Vec<Option<usize>>,
+    /// Tremulant IDs, sorted
+    pub tremulant_ids: Vec<String>,
+    /// Enclosure IDs, sorted
+    pub enclosure_ids: Vec<String>,
+    tremulant_lookup: HashMap<String, usize>,
+    enclosure_lookup: HashMap<String, usize>,
+}
+
+fn sorted_keys<V>(map: &HashMap<String, V>) -> Vec<String> {
+    let mut keys: Vec<String> = map.keys().cloned().collect();
+    keys.sort();
+    keys
+}
+
+fn lookup_of(ids: &[String]) -> HashMap<String, usize> {
+    ids.iter()
+        .enumerate()
+        .map(|(i, id)| (id.clone(), i))
+        .collect()
+}
+
+impl OrganIndex {
+    pub fn new(organ: &Organ) -> Self {
+        let rank_ids = sorted_keys(&organ.ranks);
+        let windchest_ids = sorted_keys(&organ.windchest_groups);
+        let tremulant_ids = sorted_keys(&organ.tremulants);
+        let enclosure_ids = sorted_keys(&organ.enclosures);
+
+        let rank_lookup = lookup_of(&rank_ids);
+        let tremulant_lookup = lookup_of(&tremulant_ids);
+        let enclosure_lookup = lookup_of(&enclosure_ids);
+        // Windchest group IDs appear both padded ("002") and unpadded ("2")
+        let windchest_lookup: HashMap<String, usize> = windchest_ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (normalize_group_id(id), i))
+            .collect();
+
+        let stop_ranks = organ
+            .stops
+            .iter()
+            .map(|stop| {
+                stop.rank_ids
+                    .iter()
+                    .filter_map(|id| rank_lookup.get(id).copied())
+                    .collect()
+            })
+            .collect();
+        let rank_windchests = rank_ids
+            .iter()
+            .map(|id| {
+                organ.ranks[id]
+                    .windchest_group_id
+                    .as_deref()
+                    .and_then(|wc_id| windchest_lookup.get(&normalize_group_id(wc_id)))
+                    .copied()
+            })
+            .collect();
+        let rank_wave_tremulants = rank_ids
+            .iter()
+            .map(|id| {
+                organ.ranks[id]
+                    .wave_tremulant_id
+                    .as_ref()
+                    .and_then(|trem_id| tremulant_lookup.get(trem_id))
+                    .copied()
+            })
+            .collect();
+        let windchest_enclosures = windchest_ids
+            .iter()
+            .map(|id| {
+                organ.windchest_groups[id]
+                    .enclosure_ids
+                    .iter()
+                    .find_map(|enc_id| enclosure_lookup.get(enc_id).copied())
+            })
+            .collect();
+        let windchest_tremulants = windchest_ids
+            .iter()
+            .map(|id| {
+                organ.windchest_groups[id]
+                    .tremulant_ids
+                    .iter()
+                    .filter_map(|trem_id| tremulant_lookup.get(trem_id).copied())
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            rank_ids,
+            stop_ranks,
+            rank_windchests,
+            windchest_ids,
+            windchest_enclosures,
+            windchest_tremulants,
+            rank_wave_tremulants,
+            tremulant_ids,
+            enclosure_ids,
+            tremulant_lookup,
+            enclosure_lookup,
+        }
+    }
+
+    pub fn tremulant_index(&self, id: &str) -> Option<usize> {
+        self.tremulant_lookup.get(id).copied()
+    }
+
+    pub fn enclosure_index(&self, id: &str) -> Option<usize> {
+        self.enclosure_lookup.get(id).copied()
+    }
+}
@@ -48,6 +48,7 @@ pub fn resample_interleaved(
 }
 
 /// A stereo FFT convolver for reverb processing.
+#[derive(Clone)]
 pub struct StereoConvolver {
     convolver_l: FFTConvolver<f32>,
     convolver_r: FFTConvolver<f32>,
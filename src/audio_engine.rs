@@ -0,0 +1,1225 @@
+use anyhow::Result;
+use ringbuf::traits::Observer;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::alloc_tracker;
+use crate::app::{AppMessage, TuiMessage};
+use crate::audio_command::{EngineCommand, OrganIndex};
+use crate::audio_convolver::StereoConvolver;
+use crate::audio_event::{
+    AttackSelection, AttackSelector, EngineIo, VoiceState, enforce_voice_limit, process_command,
+    process_message, process_note_on,
+};
+use crate::audio_interpolation::{Interpolation, Interpolator, Ramp};
+use crate::audio_loader::LoaderHandle;
+use crate::audio_routing::{OutputRoute, OutputRouting};
+use crate::midi_recorder::MidiRecorder;
+use crate::organ::Organ;
+use crate::tuning::Tuning;
+use crate::voice::{
+    CHANNEL_COUNT, ENCLOSURE_CLOSED_CUTOFF_HZ, ENCLOSURE_SMOOTHING_TIME, EnclosureState,
+    TREMULANT_AM_BOOST, TremulantLfo, VoiceId, VoiceLoader, VoiceSlab,
+};
+
+/// An attack voice to fade out, with the release taking over and its start frame.
+type Crossfade = (VoiceId, Option<(VoiceId, usize)>);
+
+/// Fewest voice slots an engine allocates.
+const MIN_VOICE_SLOTS: usize = 256;
+/// Note Ons that can wait for their turn without the queue growing.
+const PENDING_NOTE_CAPACITY: usize = 256;
+/// Pipes a key can sound before its list of active notes grows.
+const ACTIVE_NOTES_PER_KEY: usize = 16;
+
+/// Output pair of every rank of every stop, in `OrganIndex::stop_ranks` order.
+fn resolve_output_pairs(
+    organ: &Organ,
+    index: &OrganIndex,
+    routing: &OutputRouting,
+) -> Vec<Vec<usize>> {
+    organ
+        .stops
+        .iter()
+        .zip(&index.stop_ranks)
+        .map(|(stop, rank_indices)| {
+            rank_indices
+                .iter()
+                .map(|&rank_index| {
+                    routing.pair_for(stop, &organ.ranks[&index.rank_ids[rank_index]])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Settings an engine starts with. Gain, polyphony, tracker delay and the
+/// rest can be changed later by command.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub sample_rate: u32,
+    pub buffer_size_frames: usize,
+    pub gain: f32,
+    pub polyphony: usize,
+    pub max_new_voices_per_block: usize,
+    pub attack_selection: AttackSelection,
+    pub interpolation: Interpolation,
+    pub tracker_delay_scale: f32,
+    /// Output channels to open, see `AudioEngine::set_output_layout`
+    pub output_channels: usize,
+    pub output_routes: Vec<OutputRoute>,
+}
+
+impl EngineConfig {
+    /// Voices the engine can play at once, counting those fading out. Each
+    /// has its buffers allocated up front.
+    pub fn voice_slots(&self) -> usize {
+        (self.polyphony * 2).max(MIN_VOICE_SLOTS)
+    }
+
+    /// Highest polyphony the voice slots have room for, leaving each voice a
+    /// second slot for its release.
+    pub fn max_polyphony(&self) -> usize {
+        self.voice_slots() / 2
+    }
+}
+
+/// The mixing engine. It owns the voices and all per-block DSP state, and is
+/// driven one block at a time by whoever hosts it: the real-time audio thread,
+/// the offline renderer or a plugin host. It never owns threads or audio streams;
+/// sample loading is handed to the loader pool it was started with.
+///
+/// All voice and per-block state is preallocated and addressed by the indices in
+/// `OrganIndex`, so commands and the mix never allocate (checked in debug builds).
+pub struct AudioEngine {
+    state: VoiceState,
+    io: EngineIo,
+    sample_rate: u32,
+    buffer_size_frames: usize,
+    system_gain: f32,
+    polyphony: usize,
+    max_new_voices_per_block: usize,
+    ir_loader_rx: mpsc::Receiver<Result<Vec<StereoConvolver>>>,
+
+    interpolator: Interpolator,
+    // Scratch lists, as large as the voice slab
+    voices_to_remove: Vec<VoiceId>,
+    crossfades_to_start: Vec<Crossfade>,
+    steal_candidates: Vec<(VoiceId, Instant)>,
+
+    // Output routing: every output pair has its own stereo bus, and the buses are
+    // interleaved into `mix_buffer`, which holds `EngineIo::output_channels` channels.
+    routing: OutputRouting,
+    pair_buses: Vec<Vec<f32>>,
+    mix_buffer: Vec<f32>,
+    // Scratch buffers for Reverb
+    reverb_dry_l: Vec<f32>,
+    reverb_dry_r: Vec<f32>,
+    wet_buffer_l: Vec<f32>,
+    wet_buffer_r: Vec<f32>,
+    /// One reverb per output pair, so routed divisions keep their reverb
+    convolvers: Vec<StereoConvolver>,
+    wet_dry_ratio: f32,
+
+    // Tremulant LFOs, and the amplitude modulation they put on each windchest group
+    tremulant_lfos: Vec<TremulantLfo>,
+    tremulant_mods: Vec<f32>,
+    windchest_mods: Vec<f32>,
+    prev_windchest_mods: Vec<f32>,
+
+    // Enclosures: voices on an enclosed windchest are mixed into a per-enclosure bus
+    // first, so the swell shutters can be applied once per bus instead of per voice.
+    enclosure_states: Vec<EnclosureState>,
+    /// One stereo bus per enclosure and output pair.
+    enclosure_buses: Vec<Vec<Vec<f32>>>,
+    enclosure_smoothing: f32,
+    enclosure_open_cutoff_hz: f32,
+
+    scratch_read_buffer: Vec<f32>,
+
+    /// Start of the timeline when rendering offline. `None` follows the wall clock.
+    virtual_clock_origin: Option<Instant>,
+    rendered_frames: u64,
+}
+
+impl AudioEngine {
+    pub fn new(
+        organ: Arc<Organ>,
+        config: &EngineConfig,
+        tui_tx: mpsc::Sender<TuiMessage>,
+        shared_midi_recorder: Arc<Mutex<Option<MidiRecorder>>>,
+        loader: &LoaderHandle,
+    ) -> Self {
+        let sample_rate = config.sample_rate;
+        let buffer_size_frames = config.buffer_size_frames;
+        let (ir_loader_tx, ir_loader_rx) = mpsc::channel::<Result<Vec<StereoConvolver>>>();
+
+        let index = Arc::new(OrganIndex::new(&organ));
+        let enclosure_states: Vec<EnclosureState> = index
+            .enclosure_ids
+            .iter()
+            .map(|id| EnclosureState {
+                target_position: 1.0,
+                current_position: 1.0,
+                min_amplitude: organ.enclosures[id].min_amplitude,
+                filter_state: vec![[0.0; CHANNEL_COUNT]],
+            })
+            .collect();
+        let enclosure_buses: Vec<Vec<Vec<f32>>> =
+            vec![vec![vec![0.0; buffer_size_frames * CHANNEL_COUNT]]; index.enclosure_ids.len()];
+        let routing = OutputRouting::default();
+        let output_pairs = resolve_output_pairs(&organ, &index, &routing);
+        let tremulant_count = index.tremulant_ids.len();
+        let windchest_count = index.windchest_ids.len();
+        let buffer_duration_secs = buffer_size_frames as f32 / sample_rate as f32;
+        let enclosure_smoothing = 1.0 - (-buffer_duration_secs / ENCLOSURE_SMOOTHING_TIME).exp();
+        let enclosure_open_cutoff_hz = sample_rate as f32 * 0.45;
+
+        // Worst-case: needed_frames = ceil(buffer_size_frames * pitch_max) + taps, and
+        // to_read = min(available, needed_frames * 2). Sized for pitch_max = 2.0 (well
+        // beyond any realistic tremulant modulation combined with the tuning limits in
+        // `tuning.rs`) and the widest kernel in use, so resize never fires on the
+        // audio thread.
+        let interpolator = Interpolator::new(config.interpolation);
+        let scratch_capacity = (buffer_size_frames * 2 + interpolator.taps()) * 2 * CHANNEL_COUNT;
+        let voice_slots = config.voice_slots();
+        // Every stop can sound each of its ranks on a key
+        let notes_per_key = index
+            .stop_ranks
+            .iter()
+            .map(Vec::len)
+            .sum::<usize>()
+            .max(ACTIVE_NOTES_PER_KEY);
+        let voice_loader = VoiceLoader {
+            organ: Arc::clone(&organ),
+            index: Arc::clone(&index),
+            sample_rate,
+            // Each slot has at most one job queued
+            jobs: loader.job_queue(voice_slots),
+        };
+
+        let attack_selector = AttackSelector::new(config.attack_selection, index.rank_ids.len());
+        let velocity_curves = vec![None; index.rank_ids.len()];
+
+        let state = VoiceState {
+            organ,
+            index,
+            sample_rate,
+            // Every voice reads through its input buffer, sized like the scratch buffer
+            voices: VoiceSlab::new(
+                voice_slots,
+                scratch_capacity,
+                interpolator.lead_in_frames(),
+                voice_loader,
+            ),
+            active_notes: (0..=u8::MAX)
+                .map(|_| Vec::with_capacity(notes_per_key))
+                .collect(),
+            pending_notes: VecDeque::with_capacity(PENDING_NOTE_CAPACITY),
+            attack_selector,
+            tracker_delay_scale: config.tracker_delay_scale,
+            tuning: Tuning::default(),
+            velocity_curves,
+            output_pairs,
+            active_tremulants: vec![false; tremulant_count],
+            dropped_voices: 0,
+        };
+        let io = EngineIo {
+            buffer_size_frames,
+            output_channels: CHANNEL_COUNT,
+            tui_tx,
+            shared_midi_recorder,
+            ir_loader_tx,
+            audio_recorder: None,
+        };
+
+        let mut engine = Self {
+            state,
+            io,
+            sample_rate,
+            buffer_size_frames,
+            system_gain: config.gain,
+            polyphony: config.polyphony,
+            max_new_voices_per_block: config.max_new_voices_per_block,
+            ir_loader_rx,
+            interpolator,
+            voices_to_remove: Vec::with_capacity(voice_slots),
+            crossfades_to_start: Vec::with_capacity(voice_slots),
+            steal_candidates: Vec::with_capacity(voice_slots),
+            routing,
+            pair_buses: vec![vec![0.0; buffer_size_frames * CHANNEL_COUNT]],
+            mix_buffer: vec![0.0; buffer_size_frames * CHANNEL_COUNT],
+            reverb_dry_l: vec![0.0; buffer_size_frames],
+            reverb_dry_r: vec![0.0; buffer_size_frames],
+            wet_buffer_l: vec![0.0; buffer_size_frames],
+            wet_buffer_r: vec![0.0; buffer_size_frames],
+            convolvers: vec![StereoConvolver::new(buffer_size_frames)],
+            wet_dry_ratio: 0.0,
+            tremulant_lfos: (0..tremulant_count)
+                .map(|_| TremulantLfo {
+                    phase: 0.0,
+                    current_level: 0.0,
+                })
+                .collect(),
+            tremulant_mods: vec![1.0; tremulant_count],
+            windchest_mods: vec![1.0; windchest_count],
+            prev_windchest_mods: vec![1.0; windchest_count],
+            enclosure_states,
+            enclosure_buses,
+            enclosure_smoothing,
+            enclosure_open_cutoff_hz,
+            scratch_read_buffer: vec![0.0; scratch_capacity],
+            virtual_clock_origin: None,
+            rendered_frames: 0,
+        };
+        engine.set_output_layout(config.output_channels, &config.output_routes);
+        engine
+    }
+
+    /// Switches timing to a virtual clock that only advances with rendered audio,
+    /// so key press durations and voice ages don't depend on how fast we render.
+    pub fn use_virtual_clock(&mut self) {
+        self.virtual_clock_origin = Some(Instant::now());
+        self.rendered_frames = 0;
+    }
+
+    fn now(&self) -> Instant {
+        match self.virtual_clock_origin {
+            Some(origin) => {
+                origin
+                    + Duration::from_secs_f64(self.rendered_frames as f64 / self.sample_rate as f64)
+            }
+            None => Instant::now(),
+        }
+    }
+
+    /// Opens `channel_count` output channels and sends divisions and windchest
+    /// groups to their pairs as given by `routes`. The engine starts out with the
+    /// layout of its `EngineConfig`. Must be called before playing, as it drops
+    /// the enclosure filter state.
+    pub fn set_output_layout(&mut self, channel_count: usize, routes: &[OutputRoute]) {
+        let channel_count = channel_count.max(CHANNEL_COUNT);
+        let bus_len = self.buffer_size_frames * CHANNEL_COUNT;
+        self.routing = OutputRouting::new(routes, channel_count);
+        self.state.output_pairs =
+            resolve_output_pairs(&self.state.organ, &self.state.index, &self.routing);
+        let pair_count = self.routing.pair_count();
+
+        self.io.output_channels = channel_count;
+        self.pair_buses = vec![vec![0.0; bus_len]; pair_count];
+        self.mix_buffer = vec![0.0; self.buffer_size_frames * channel_count];
+        for buses in self.enclosure_buses.iter_mut() {
+            *buses = vec![vec![0.0; bus_len]; pair_count];
+        }
+        for enc in self.enclosure_states.iter_mut() {
+            enc.filter_state = vec![[0.0; CHANNEL_COUNT]; pair_count];
+        }
+        self.convolvers = vec![self.convolvers[0].clone(); pair_count];
+    }
+
+    pub fn output_channels(&self) -> usize {
+        self.io.output_channels
+    }
+
+    /// The last rendered block, interleaved with `output_channels` channels.
+    pub fn output(&self) -> &[f32] {
+        &self.mix_buffer
+    }
+
+    pub fn active_voice_count(&self) -> usize {
+        self.state.voices.len()
+    }
+
+    /// True once nothing is sounding and no note is waiting to start.
+    pub fn is_silent(&self) -> bool {
+        self.state.voices.is_empty() && self.state.pending_notes.is_empty()
+    }
+
+    /// Loads a reverb IR synchronously instead of on a background thread.
+    pub fn load_reverb_ir(&mut self, path: &Path, wet_dry_ratio: f32) -> Result<()> {
+        let convolver =
+            StereoConvolver::from_file(path, self.sample_rate, self.buffer_size_frames)?;
+        self.convolvers = vec![convolver; self.pair_buses.len()];
+        self.wet_dry_ratio = wet_dry_ratio.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    /// Handles a message from the UI. These may allocate, load files or start
+    /// threads; notes and control changes come through `handle_command`.
+    pub fn handle_message(&mut self, msg: AppMessage) {
+        process_message(msg, &mut self.state, &mut self.io);
+    }
+
+    /// Handles a command without allocating. We differentiate "Immediate" vs
+    /// "Deferrable" events: Note Ons are queued and started at a throttled
+    /// rate by `render_block`.
+    pub fn handle_command(&mut self, command: EngineCommand) {
+        let now = self.now();
+        alloc_tracker::assert_no_alloc(|| match command {
+            EngineCommand::NoteOn { .. } => {
+                let pending_notes = &mut self.state.pending_notes;
+                if pending_notes.len() == pending_notes.capacity() {
+                    // Start the oldest right away rather than grow the queue
+                    if let Some(oldest) = pending_notes.pop_front() {
+                        start_note(oldest, now, &mut self.state);
+                    }
+                }
+                self.state.pending_notes.push_back(command);
+            }
+            EngineCommand::SetEnclosurePosition {
+                enclosure,
+                position,
+            } => {
+                if let Some(enc) = self.enclosure_states.get_mut(enclosure) {
+                    enc.target_position = position.clamp(0.0, 1.0);
+                }
+            }
+            EngineCommand::SetReverbWetDry(r) => self.wet_dry_ratio = r.clamp(0.0, 1.0),
+            EngineCommand::SetGain(g) => self.system_gain = g,
+            EngineCommand::SetPolyphony(p) => {
+                self.polyphony = p.min(self.state.voices.capacity() / 2);
+            }
+            _ => process_command(command, now, &mut self.state),
+        });
+    }
+
+    /// Renders the next block into the output buffer. With `wait_for_samples` set,
+    /// voices block on their loaders instead of dropping out, which makes the
+    /// result independent of disk speed (used by the offline renderer).
+    pub fn render_block(&mut self, wait_for_samples: bool) {
+        let now = self.now();
+
+        // Receive Reverb IR
+        if let Ok(Ok(convolvers)) = self.ir_loader_rx.try_recv() {
+            self.convolvers = convolvers;
+            if self.wet_dry_ratio == 0.0 {
+                self.wet_dry_ratio = 0.3;
+            }
+        }
+
+        let stolen_voices = alloc_tracker::assert_no_alloc(|| {
+            // Throttle Note Ons
+            for _ in 0..self.max_new_voices_per_block {
+                let Some(command) = self.state.pending_notes.pop_front() else {
+                    break;
+                };
+                start_note(command, now, &mut self.state);
+            }
+
+            let stolen_voices = enforce_voice_limit(
+                &mut self.state.voices,
+                &mut self.steal_candidates,
+                now,
+                self.sample_rate,
+                self.polyphony,
+            );
+
+            self.mix(wait_for_samples);
+
+            // Finished voices hand their slots back; the buffers stay with the slot
+            for voice_id in self.voices_to_remove.drain(..) {
+                let voices = &mut self.state.voices;
+                // An attack that ends before its crossfade hands over to the release at once
+                let handover = voices
+                    .get(voice_id)
+                    .filter(|voice| voice.is_awaiting_release_sample)
+                    .and_then(|voice| {
+                        voice
+                            .release_voice_id
+                            .map(|release_id| (release_id, voice.release_delay_frames))
+                    });
+                voices.remove(voice_id);
+                if let Some((release_id, delay_frames)) = handover
+                    && let Some(rv) = voices.get_mut(release_id)
+                {
+                    rv.is_waiting_for_crossfade = false;
+                    rv.is_fading_in = true;
+                    rv.delay_frames = delay_frames;
+                }
+            }
+            stolen_voices
+        });
+        // Logging may allocate, so it waits until the mix is done
+        if stolen_voices > 0 {
+            log::warn!("[AudioThread] Stole {} voice(s)", stolen_voices);
+        }
+        if self.state.dropped_voices > 0 {
+            log::warn!(
+                "[AudioThread] All {} voice slots in use, {} voice(s) not started.",
+                self.state.voices.capacity(),
+                self.state.dropped_voices
+            );
+            self.state.dropped_voices = 0;
+        }
+
+        // Recording
+        if let Some(rec) = &mut self.io.audio_recorder {
+            rec.push(&self.mix_buffer);
+        }
+
+        self.rendered_frames += self.buffer_size_frames as u64;
+    }
+
+    /// Mixes every voice through the tremulants, enclosures and reverb into
+    /// the output buffer. Must not allocate.
+    fn mix(&mut self, wait_for_samples: bool) {
+        let buffer_size_frames = self.buffer_size_frames;
+        let sample_rate = self.sample_rate;
+
+        for bus in self.pair_buses.iter_mut() {
+            bus.fill(0.0);
+        }
+        for bus in self.enclosure_buses.iter_mut().flatten() {
+            bus.fill(0.0);
+        }
+
+        // Update Tremulants
+        let dt = buffer_size_frames as f32 / sample_rate as f32;
+        for (trem_index, trem_id) in self.state.index.tremulant_ids.iter().enumerate() {
+            let trem_def = &self.state.organ.tremulants[trem_id];
+            let is_active = self.state.active_tremulants[trem_index];
+            let target_level = if is_active { 1.0 } else { 0.0 };
+            let lfo = &mut self.tremulant_lfos[trem_index];
+
+            if lfo.current_level != target_level {
+                let rate = if is_active {
+                    if trem_def.start_rate > 0.0 {
+                        trem_def.start_rate
+                    } else {
+                        1000.0
+                    }
+                } else {
+                    if trem_def.stop_rate > 0.0 {
+                        trem_def.stop_rate
+                    } else {
+                        1000.0
+                    }
+                };
+                let change = rate * dt;
+                if lfo.current_level < target_level {
+                    lfo.current_level = (lfo.current_level + change).min(target_level);
+                } else {
+                    lfo.current_level = (lfo.current_level - change).max(target_level);
+                }
+            }
+
+            if lfo.current_level <= 0.0 && !is_active {
+                self.tremulant_mods[trem_index] = 1.0;
+                continue;
+            }
+
+            let freq = if trem_def.period > 0.0 {
+                1000.0 / trem_def.period
+            } else {
+                0.0
+            };
+            let phase_inc = (freq * buffer_size_frames as f32) / sample_rate as f32;
+            lfo.phase = (lfo.phase + phase_inc) % 1.0;
+
+            let sine_val = (lfo.phase * std::f32::consts::TAU).sin();
+            let am_swing = trem_def.amp_mod_depth * 0.01 * TREMULANT_AM_BOOST;
+            let active_am = 1.0 + (sine_val * am_swing * 0.5);
+            self.tremulant_mods[trem_index] = 1.0 + (active_am - 1.0) * lfo.current_level;
+        }
+        for (wc_mod, trem_indices) in self
+            .windchest_mods
+            .iter_mut()
+            .zip(&self.state.index.windchest_tremulants)
+        {
+            *wc_mod = trem_indices
+                .iter()
+                .map(|&trem_index| self.tremulant_mods[trem_index])
+                .product();
+        }
+
+        // Crossfade Logic
+        // Checks if any attack voices are waiting for their release samples to be ready
+        self.crossfades_to_start.clear();
+        for (attack_id, attack_voice) in self.state.voices.iter() {
+            // Tracker delay: the release takes over only once the key action has moved
+            if attack_voice.is_awaiting_release_sample
+                && attack_voice.release_delay_frames < buffer_size_frames
+            {
+                if let Some(release_id) = attack_voice.release_voice_id {
+                    if let Some(rv) = self.state.voices.get(release_id) {
+                        // Start the release where it continues the attack's phase: at the
+                        // frame matching the attack's current level and slope, moved on by
+                        // what the attack plays before the release starts this block.
+                        let lead_in = self.interpolator.lead_in_frames();
+                        let offset =
+                            match (&rv.phase_alignment, attack_voice.current_level(lead_in)) {
+                                (Some(alignment), Some((level, slope))) => {
+                                    let attack_peak = attack_voice
+                                        .phase_alignment
+                                        .as_ref()
+                                        .map_or(alignment.peak_amplitude, |a| a.peak_amplitude);
+                                    alignment.release_offset(level, slope, attack_peak)
+                                        + (attack_voice.release_delay_frames as f32
+                                            * rv.pitch_ratio)
+                                            .round()
+                                            as usize
+                                }
+                                _ => 0,
+                            };
+
+                        // Check if the release voice has buffered enough data to start playing
+                        // We need at least one buffer worth of data past the offset to be safe
+                        let needed_frames = offset + buffer_size_frames;
+                        let mut rb_available = rv.consumer.occupied_len() / CHANNEL_COUNT;
+                        if wait_for_samples {
+                            while rb_available <= needed_frames && !rv.is_finished() {
+                                thread::sleep(Duration::from_micros(100));
+                                rb_available = rv.consumer.occupied_len() / CHANNEL_COUNT;
+                            }
+                            rb_available = rv.consumer.occupied_len() / CHANNEL_COUNT;
+                        }
+
+                        if rb_available > needed_frames {
+                            self.crossfades_to_start
+                                .push((attack_id, Some((release_id, offset))));
+                        } else if rv.is_finished() && rb_available > 0 {
+                            // A release shorter than the offset starts at its beginning
+                            self.crossfades_to_start
+                                .push((attack_id, Some((release_id, 0))));
+                        } else if rv.is_finished() {
+                            // If the loader finished but gave us no data, abort the wait
+                            self.crossfades_to_start.push((attack_id, None));
+                        }
+                    } else {
+                        // Release voice died?
+                        self.crossfades_to_start.push((attack_id, None));
+                    }
+                } else {
+                    // No release sample: just fade out
+                    self.crossfades_to_start.push((attack_id, None));
+                }
+            }
+        }
+
+        // Apply the crossfade state changes
+        let lead_in = self.interpolator.lead_in_frames();
+        for &(aid, release) in &self.crossfades_to_start {
+            let mut waiting_release = None;
+            let mut takeover_frame = 0;
+            if let Some(av) = self.state.voices.get_mut(aid) {
+                av.is_cancelled.store(true, Ordering::SeqCst);
+                av.is_fading_out = true;
+                av.is_awaiting_release_sample = false;
+                waiting_release = av.release_voice_id.take();
+                takeover_frame = av.release_delay_frames;
+            }
+            if let Some((rid, offset)) = release {
+                if let Some(rv) = self.state.voices.get_mut(rid) {
+                    rv.skip_to(offset, lead_in);
+                    rv.is_waiting_for_crossfade = false;
+                    rv.is_fading_in = true;
+                    // The release speaks on the frame the attack starts fading
+                    rv.delay_frames = takeover_frame;
+                }
+            } else if let Some(rv) = waiting_release.and_then(|rid| self.state.voices.get_mut(rid))
+            {
+                // The release never got data; let the voice loop clean it up
+                rv.is_waiting_for_crossfade = false;
+            }
+        }
+
+        // Voice Processing Loop
+        for (voice_id, voice) in self.state.voices.iter_mut() {
+            if voice.is_fading_out && voice.fade_level <= 0.0001 {
+                self.voices_to_remove.push(voice_id);
+                continue;
+            }
+
+            // Tracker delay: an attack handing over to its release this block keeps
+            // playing up to the takeover frame before it starts fading.
+            let hold_frames = if voice.is_fading_out && !voice.is_awaiting_release_sample {
+                voice.release_delay_frames.min(buffer_size_frames)
+            } else {
+                0
+            };
+            voice.release_delay_frames = voice
+                .release_delay_frames
+                .saturating_sub(buffer_size_frames);
+
+            // A release waiting for its attack's handover hasn't started yet, so its
+            // delay is left untouched until then
+            if voice.is_waiting_for_crossfade {
+                continue;
+            }
+
+            // Tracker delay: stay silent until the pipe speaks, then start mid-block
+            if voice.delay_frames >= buffer_size_frames {
+                voice.delay_frames -= buffer_size_frames;
+                continue;
+            }
+            let start_frame = voice.delay_frames;
+            voice.delay_frames = 0;
+            let render_frames = buffer_size_frames - start_frame;
+            let hold_frames = hold_frames.saturating_sub(start_frame).min(render_frames);
+
+            // Calculate Tremulant Impact
+            let (trem_start_am, trem_end_am) = match voice.windchest {
+                Some(wc) => (self.prev_windchest_mods[wc], self.windchest_mods[wc]),
+                None => (1.0, 1.0),
+            };
+
+            let pitch_start = voice.pitch_ratio * (1.0 + (trem_start_am - 1.0) * 0.1);
+            let pitch_end = voice.pitch_ratio * (1.0 + (trem_end_am - 1.0) * 0.1);
+            let avg_pitch = (pitch_start + pitch_end) * 0.5;
+
+            // Buffer Management (Lazy Compaction)
+            let needed_frames_float = render_frames as f32 * avg_pitch;
+            // Plus the frames the interpolation kernel reads around each position
+            let needed_frames = needed_frames_float.ceil() as usize + self.interpolator.taps();
+            let needed_samples = needed_frames * CHANNEL_COUNT;
+
+            // Read ahead up to twice what this block needs.
+            let wanted_frames = needed_frames * 2;
+
+            // If the buffer is getting too full/fragmented, compact it now.
+            // We keep valid data from buffer_start_idx onwards.
+            if voice.buffer_start_idx + wanted_frames * CHANNEL_COUNT
+                > voice.input_buffer.capacity()
+            {
+                let remaining = voice.input_buffer.len() - voice.buffer_start_idx;
+                voice.input_buffer.copy_within(voice.buffer_start_idx.., 0);
+                voice.input_buffer.truncate(remaining);
+                voice.buffer_start_idx = 0;
+            }
+
+            // Fill Buffer
+            let mut available = voice.consumer.occupied_len() / CHANNEL_COUNT;
+            if wait_for_samples {
+                // Offline: wait for the loader rather than skipping the block
+                let buffered_frames =
+                    (voice.input_buffer.len() - voice.buffer_start_idx) / CHANNEL_COUNT;
+                while buffered_frames + available < needed_frames && !voice.is_finished() {
+                    thread::sleep(Duration::from_micros(100));
+                    available = voice.consumer.occupied_len() / CHANNEL_COUNT;
+                }
+                available = voice.consumer.occupied_len() / CHANNEL_COUNT;
+            }
+            // Never read more than the buffer holds: growing it would allocate
+            let buffered_frames =
+                (voice.input_buffer.len() - voice.buffer_start_idx) / CHANNEL_COUNT;
+            let room_frames =
+                (voice.input_buffer.capacity() - voice.input_buffer.len()) / CHANNEL_COUNT;
+            let to_read = available
+                .min(wanted_frames.saturating_sub(buffered_frames))
+                .min(room_frames);
+
+            if to_read > 0 {
+                let read_samples = to_read * CHANNEL_COUNT;
+                debug_assert!(read_samples <= self.scratch_read_buffer.len());
+                let _ = voice.read_samples(&mut self.scratch_read_buffer[..read_samples]);
+                voice
+                    .input_buffer
+                    .extend_from_slice(&self.scratch_read_buffer[..read_samples]);
+            }
+
+            // Check actual available data
+            let total_valid_samples = voice.input_buffer.len() - voice.buffer_start_idx;
+            if total_valid_samples < needed_samples {
+                if voice.is_finished() {
+                    self.voices_to_remove.push(voice_id);
+                }
+                continue;
+            }
+
+            // Envelope. A fade-out held back by the tracker delay starts at `hold_frames`.
+            let env_start = voice.fade_level;
+            let env_step = if voice.is_fading_in {
+                voice.fade_increment
+            } else if voice.is_fading_out {
+                -voice.fade_increment
+            } else {
+                0.0
+            };
+            let envelope_at = |frame: usize| {
+                let fading_frames = if env_step < 0.0 {
+                    frame.saturating_sub(hold_frames)
+                } else {
+                    frame
+                };
+                (env_start + env_step * fading_frames as f32).clamp(0.0, 1.0)
+            };
+            let env_end = envelope_at(render_frames);
+            if voice.is_fading_in && env_end >= 1.0 {
+                voice.is_fading_in = false;
+            }
+            voice.fade_level = env_end;
+
+            // Tremulant and envelope gain, and pitch, at a frame of this block
+            let voice_gain = voice.gain;
+            let gain_at = |frame: usize| {
+                let t = frame as f32 / render_frames as f32;
+                (trem_start_am + (trem_end_am - trem_start_am) * t)
+                    * envelope_at(frame)
+                    * voice_gain
+            };
+            let pitch_at = |frame: usize| {
+                pitch_start + (pitch_end - pitch_start) * frame as f32 / render_frames as f32
+            };
+
+            let pair = voice.output_pair.min(self.pair_buses.len() - 1);
+            let enclosure = voice
+                .windchest
+                .and_then(|wc| self.state.index.windchest_enclosures[wc]);
+            let is_fast_path = (avg_pitch - 1.0).abs() < 0.00001;
+
+            // The ramps bend where the held fade-out starts, so mix in two segments
+            for (seg_start, seg_end) in [(0, hold_frames), (hold_frames, render_frames)] {
+                if seg_end <= seg_start {
+                    continue;
+                }
+                let seg_frames = seg_end - seg_start;
+                let mut current_gain_scalar = gain_at(seg_start);
+                let gain_delta = (gain_at(seg_end) - current_gain_scalar) / seg_frames as f32;
+
+                // Create a SAFE slice of the valid data we are about to read.
+                let input_slice = &voice.input_buffer[voice.buffer_start_idx..];
+                let target_buffer = match enclosure {
+                    Some(enc_idx) => &mut self.enclosure_buses[enc_idx][pair],
+                    None => &mut self.pair_buses[pair],
+                };
+                let mix_chunks = target_buffer
+                    .chunks_exact_mut(CHANNEL_COUNT)
+                    .skip(start_frame + seg_start)
+                    .take(seg_frames);
+
+                if is_fast_path {
+                    // Safe Fast Path
+                    // No resampling. We map input samples 1:1 to output samples,
+                    // at the position the interpolator's kernel is centred on.
+                    let start_offset = (voice.cursor_pos.round() as usize
+                        + self.interpolator.lead_in_frames())
+                        * CHANNEL_COUNT;
+                    let end_offset = start_offset + seg_frames * CHANNEL_COUNT;
+
+                    // Ensure we don't read past the end (should be covered by needed_samples check, but strict safety requires this)
+                    if let Some(valid_chunk) = input_slice.get(start_offset..end_offset) {
+                        // ZIP allows the compiler to remove bounds checks and use SIMD
+                        for (mix, input_frame) in
+                            mix_chunks.zip(valid_chunk.chunks_exact(CHANNEL_COUNT))
+                        {
+                            // input_frame is guaranteed to have 2 elements [L, R]
+                            let l = input_frame[0];
+                            let r = input_frame[1];
+                            mix[0] += l * current_gain_scalar;
+                            mix[1] += r * current_gain_scalar;
+
+                            current_gain_scalar += gain_delta;
+                        }
+                    }
+
+                    // Advance cursor
+                    voice.cursor_pos = (voice.cursor_pos.round() as usize + seg_frames) as f32;
+                } else {
+                    // Safe Slow Path
+                    // Resample with the configured interpolator.
+                    let pitch_delta = (pitch_at(seg_end) - pitch_at(seg_start)) / seg_frames as f32;
+                    self.interpolator.mix(
+                        input_slice,
+                        mix_chunks,
+                        &mut voice.cursor_pos,
+                        Ramp {
+                            pitch: pitch_at(seg_start),
+                            pitch_delta,
+                            gain: current_gain_scalar,
+                            gain_delta,
+                        },
+                    );
+                }
+            }
+
+            // Lazy Cleanup
+            // Instead of draining, just advance the integer start index
+            let samples_consumed_int = voice.cursor_pos.floor() as usize;
+            if samples_consumed_int > 0 {
+                // Move the "virtual" start of the buffer forward
+                voice.buffer_start_idx += samples_consumed_int * CHANNEL_COUNT;
+                // Adjust cursor to be relative to the new start
+                voice.cursor_pos -= samples_consumed_int as f32;
+            }
+
+            if voice.is_fading_out && voice.fade_level == 0.0 {
+                self.voices_to_remove.push(voice_id);
+            }
+        }
+
+        std::mem::swap(&mut self.prev_windchest_mods, &mut self.windchest_mods);
+
+        // Apply Enclosures (swell shutters) and sum their buses into the pair buses
+        for (enc_idx, enc) in self.enclosure_states.iter_mut().enumerate() {
+            let pos_start = enc.current_position;
+            let pos_end = pos_start + (enc.target_position - pos_start) * self.enclosure_smoothing;
+            enc.current_position = pos_end;
+
+            let gain_start = enc.min_amplitude + (1.0 - enc.min_amplitude) * pos_start;
+            let gain_end = enc.min_amplitude + (1.0 - enc.min_amplitude) * pos_end;
+            let gain_delta = (gain_end - gain_start) / buffer_size_frames as f32;
+
+            // Closing the shutters also dulls the sound. Cutoff is interpolated in
+            // the log domain so the filter sweep sounds even across the pedal travel.
+            let filter_bypass = pos_end >= 0.999;
+            let cutoff = ENCLOSURE_CLOSED_CUTOFF_HZ
+                * (self.enclosure_open_cutoff_hz / ENCLOSURE_CLOSED_CUTOFF_HZ).powf(pos_end);
+            let alpha = 1.0 - (-std::f32::consts::TAU * cutoff / sample_rate as f32).exp();
+
+            for ((pair_bus, enc_bus), filter_state) in self
+                .pair_buses
+                .iter_mut()
+                .zip(&self.enclosure_buses[enc_idx])
+                .zip(enc.filter_state.iter_mut())
+            {
+                let mut current_gain = gain_start;
+                for (mix, bus) in pair_bus
+                    .chunks_exact_mut(CHANNEL_COUNT)
+                    .zip(enc_bus.chunks_exact(CHANNEL_COUNT))
+                {
+                    for ch in 0..CHANNEL_COUNT {
+                        let state = &mut filter_state[ch];
+                        *state += (bus[ch] - *state) * alpha;
+                        let filtered = if filter_bypass { bus[ch] } else { *state };
+                        mix[ch] += filtered * current_gain;
+                    }
+                    current_gain += gain_delta;
+                }
+            }
+        }
+
+        // Apply Reverb & Global Gain. Every pair goes through its own reverb.
+        let apply_reverb =
+            self.wet_dry_ratio > 0.0 && self.convolvers.first().is_some_and(|conv| conv.is_loaded);
+        if apply_reverb {
+            let dl = (1.0 - self.wet_dry_ratio) * self.system_gain;
+            let wl = self.wet_dry_ratio * self.system_gain;
+            for (bus, convolver) in self.pair_buses.iter_mut().zip(self.convolvers.iter_mut()) {
+                for (i, frame) in bus.chunks_exact(CHANNEL_COUNT).enumerate() {
+                    self.reverb_dry_l[i] = frame[0];
+                    self.reverb_dry_r[i] = frame[1];
+                }
+                convolver.process(
+                    &self.reverb_dry_l,
+                    &self.reverb_dry_r,
+                    &mut self.wet_buffer_l,
+                    &mut self.wet_buffer_r,
+                );
+                for (i, frame) in bus.chunks_exact_mut(CHANNEL_COUNT).enumerate() {
+                    frame[0] = frame[0] * dl + self.wet_buffer_l[i] * wl;
+                    frame[1] = frame[1] * dl + self.wet_buffer_r[i] * wl;
+                }
+            }
+        } else {
+            for s in self.pair_buses.iter_mut().flatten() {
+                *s *= self.system_gain;
+            }
+        }
+
+        // Interleave the pairs into the output. A leftover odd channel stays silent.
+        if self.io.output_channels == CHANNEL_COUNT {
+            self.mix_buffer.copy_from_slice(&self.pair_buses[0]);
+        } else {
+            for (pair, bus) in self.pair_buses.iter().enumerate() {
+                for (frame, bus_frame) in self
+                    .mix_buffer
+                    .chunks_exact_mut(self.io.output_channels)
+                    .zip(bus.chunks_exact(CHANNEL_COUNT))
+                {
+                    frame[pair * CHANNEL_COUNT..(pair + 1) * CHANNEL_COUNT]
+                        .copy_from_slice(bus_frame);
+                }
+            }
+        }
+    }
+}
+
+/// Starts a queued `NoteOn`.
+fn start_note(command: EngineCommand, now: Instant, state: &mut VoiceState) {
+    if let EngineCommand::NoteOn {
+        note,
+        velocity,
+        stop,
+    } = command
+    {
+        process_note_on(note, velocity, stop, now, state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_command::command_queue;
+    use crate::audio_loader::{LoaderPool, spawn_loader_pool};
+    use crate::organ::{
+        AttackSample, Enclosure, Pipe, Rank, ReleaseSample, Stop, Tremulant, WindchestGroup,
+    };
+    use crate::voice::MAX_NEW_VOICES_PER_BLOCK;
+    use crate::wav_converter::SampleMetadata;
+    use ringbuf::traits::{Consumer, Producer};
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    const SAMPLE_RATE: u32 = 48_000;
+    const FIRST_NOTE: u8 = 36;
+    const PIPE_COUNT: u8 = 24;
+
+    /// Interleaved stereo sine, `frames` long.
+    fn sine(frames: usize, hz: f32) -> Arc<Vec<f32>> {
+        Arc::new(
+            (0..frames)
+                .flat_map(|i| {
+                    let s = (i as f32 * hz * std::f32::consts::TAU / SAMPLE_RATE as f32).sin();
+                    [s * 0.1, s * 0.1]
+                })
+                .collect(),
+        )
+    }
+
+    /// One stop with one rank on a windchest with a tremulant and an enclosure.
+    /// Every sample is in the RAM cache: a 2 s attack, looped unless the rank
+    /// is percussive, and a short release.
+    fn tiny_organ(is_percussive: bool) -> Organ {
+        let attack_path = PathBuf::from("attack.wav");
+        let release_path = PathBuf::from("release.wav");
+        let attack_frames = SAMPLE_RATE as usize * 2;
+
+        let pipes = (0..PIPE_COUNT)
+            .map(|i| {
+                let pipe = Pipe {
+                    attacks: vec![AttackSample {
+                        path: attack_path.clone(),
+                        min_velocity: 0,
+                        preloaded_bytes: None,
+                        phase_alignment: None,
+                        is_tremulant: false,
+                    }],
+                    gain_db: 0.0,
+                    pitch_tuning_cents: 0.0,
+                    releases: vec![ReleaseSample {
+                        path: release_path.clone(),
+                        max_key_press_time_ms: -1,
+                        preloaded_bytes: None,
+                        phase_alignment: None,
+                        is_tremulant: false,
+                    }],
+                };
+                (FIRST_NOTE + i, pipe)
+            })
+            .collect();
+        let rank = Rank {
+            name: "Principal 8'".into(),
+            id_str: "001".into(),
+            division_id: String::new(),
+            first_midi_note: FIRST_NOTE,
+            pipe_count: PIPE_COUNT as usize,
+            gain_db: 0.0,
+            tracker_delay_ms: 0,
+            windchest_group_id: Some("001".into()),
+            pipes,
+            is_percussive,
+            harmonic_number: 8.0,
+            wave_tremulant_id: None,
+        };
+
+        Organ {
+            name: "Test".into(),
+            stops: vec![Stop {
+                name: "Principal 8'".into(),
+                id_str: "001".into(),
+                rank_ids: vec!["001".into()],
+                division_id: String::new(),
+                manual_id: None,
+            }],
+            ranks: HashMap::from([("001".into(), rank)]),
+            windchest_groups: HashMap::from([(
+                "001".into(),
+                WindchestGroup {
+                    name: "Swell".into(),
+                    id_str: "001".into(),
+                    tremulant_ids: vec!["001".into()],
+                    enclosure_ids: vec!["001".into()],
+                },
+            )]),
+            tremulants: HashMap::from([(
+                "001".into(),
+                Tremulant {
+                    name: "Tremulant".into(),
+                    id_str: "001".into(),
+                    period: 200.0,
+                    start_rate: 5.0,
+                    stop_rate: 5.0,
+                    amp_mod_depth: 10.0,
+                    switch_ids: Vec::new(),
+                },
+            )]),
+            enclosures: HashMap::from([(
+                "001".into(),
+                Enclosure {
+                    name: "Swell box".into(),
+                    id_str: "001".into(),
+                    min_amplitude: 0.1,
+                },
+            )]),
+            sample_cache: Some(HashMap::from([
+                (attack_path.clone(), sine(attack_frames, 220.0)),
+                (release_path.clone(), sine(SAMPLE_RATE as usize / 10, 220.0)),
+            ])),
+            metadata_cache: Some(HashMap::from([
+                (
+                    attack_path,
+                    Arc::new(SampleMetadata {
+                        loop_info: Some((0, attack_frames as u32)),
+                        channel_count: 2,
+                    }),
+                ),
+                (
+                    release_path,
+                    Arc::new(SampleMetadata {
+                        loop_info: None,
+                        channel_count: 2,
+                    }),
+                ),
+            ])),
+            ..Default::default()
+        }
+    }
+
+    /// Commands sent before the given block: chords, releases while notes are
+    /// still sounding, the tremulant, the swell pedal and a lower polyphony
+    /// so voices get stolen.
+    fn commands_for_block(block: usize) -> Vec<EngineCommand> {
+        let note = |i: usize| FIRST_NOTE + (i % PIPE_COUNT as usize) as u8;
+        let mut commands = Vec::new();
+        if block >= 300 {
+            return commands;
+        }
+        if block.is_multiple_of(8) {
+            commands.extend((0..4).map(|i| EngineCommand::NoteOn {
+                note: note(block / 8 + i * 3),
+                velocity: 100,
+                stop: 0,
+            }));
+        }
+        if block % 8 == 4 && block >= 20 {
+            commands.extend((0..4).map(|i| EngineCommand::NoteOff {
+                note: note(block / 8 - 2 + i * 3),
+                stop: 0,
+            }));
+        }
+        if block % 50 == 10 {
+            commands.push(EngineCommand::SetTremulantActive {
+                tremulant: 0,
+                active: block % 100 == 10,
+            });
+        }
+        commands.push(EngineCommand::SetEnclosurePosition {
+            enclosure: 0,
+            position: (block % 64) as f32 / 63.0,
+        });
+        match block {
+            150 => commands.push(EngineCommand::SetPolyphony(4)),
+            250 => commands.push(EngineCommand::SetPolyphony(64)),
+            299 => commands.push(EngineCommand::AllNotesOff),
+            _ => {}
+        }
+        commands
+    }
+
+    /// An engine on a virtual clock, rendering blocks of 256 frames, and the
+    /// loader pool feeding it.
+    fn test_engine(organ: Organ, polyphony: usize) -> (AudioEngine, LoaderPool) {
+        let config = EngineConfig {
+            sample_rate: SAMPLE_RATE,
+            buffer_size_frames: 256,
+            gain: 1.0,
+            polyphony,
+            max_new_voices_per_block: MAX_NEW_VOICES_PER_BLOCK,
+            attack_selection: AttackSelection::default(),
+            interpolation: Interpolation::default(),
+            tracker_delay_scale: 1.0,
+            output_channels: CHANNEL_COUNT,
+            output_routes: Vec::new(),
+        };
+        let loader = spawn_loader_pool(1);
+        let (tui_tx, _tui_rx) = mpsc::channel();
+        let mut engine = AudioEngine::new(
+            Arc::new(organ),
+            &config,
+            tui_tx,
+            Arc::new(Mutex::new(None)),
+            &loader.jobs,
+        );
+        engine.use_virtual_clock();
+        (engine, loader)
+    }
+
+    #[test]
+    fn commands_and_mix_do_not_allocate() {
+        let (mut engine, _loader) = test_engine(tiny_organ(false), 32);
+        let (mut command_tx, mut command_rx) = command_queue();
+
+        let mut peak_voices = 0;
+        let mut peak_level = 0.0f32;
+        for block in 0..400 {
+            for command in commands_for_block(block) {
+                assert!(command_tx.try_push(command).is_ok());
+            }
+            alloc_tracker::assert_no_alloc(|| {
+                while let Some(command) = command_rx.try_pop() {
+                    engine.handle_command(command);
+                }
+                engine.render_block(true);
+            });
+            peak_voices = peak_voices.max(engine.active_voice_count());
+            peak_level = engine
+                .output()
+                .iter()
+                .fold(peak_level, |m, s| m.max(s.abs()));
+        }
+
+        // The notes did sound, and AllNotesOff let them all finish
+        assert!(peak_voices > 4);
+        assert!(peak_level > 0.01);
+        assert_eq!(engine.active_voice_count(), 0);
+    }
+
+    #[test]
+    fn percussive_voices_are_stolen_and_silenced() {
+        let (mut engine, _loader) = test_engine(tiny_organ(true), 4);
+        let notes = FIRST_NOTE..FIRST_NOTE + 8;
+        let render = |engine: &mut AudioEngine, blocks: usize| {
+            for _ in 0..blocks {
+                alloc_tracker::assert_no_alloc(|| engine.render_block(true));
+            }
+        };
+
+        // Struck and released at once, the pipes ring on
+        for note in notes.clone() {
+            engine.handle_command(EngineCommand::NoteOn {
+                note,
+                velocity: 100,
+                stop: 0,
+            });
+        }
+        render(&mut engine, 1);
+        for note in notes {
+            engine.handle_command(EngineCommand::NoteOff { note, stop: 0 });
+        }
+        render(&mut engine, 5);
+        assert_eq!(engine.active_voice_count(), 8);
+
+        // Over the polyphony: the oldest are stolen and fade out, the rest ring on
+        render(&mut engine, 240);
+        assert_eq!(engine.active_voice_count(), 4);
+
+        // AllNotesOff fades out what is still ringing
+        engine.handle_command(EngineCommand::AllNotesOff);
+        render(&mut engine, 40);
+        assert_eq!(engine.active_voice_count(), 0);
+    }
+}
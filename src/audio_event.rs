@@ -1,42 +1,159 @@
-use std::collections::{HashMap, VecDeque};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
 use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, mpsc};
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::TuiMessage;
-use crate::app::{ActiveNote, AppMessage};
+use crate::app::{ActiveNote, AppMessage, TuiMessage};
+use crate::audio_command::{EngineCommand, OrganIndex};
 use crate::audio_convolver::StereoConvolver;
 use crate::audio_recorder::AudioRecorder;
 use crate::midi_recorder::MidiRecorder;
-use crate::organ::Organ;
-use crate::voice::{SpawnJob, VOICE_STEALING_FADE_TIME, Voice};
+use crate::organ::{Organ, Rank};
+use crate::tuning::Tuning;
+use crate::velocity::velocity_gain_db;
+use crate::voice::{
+    CHANNEL_COUNT, PipeSample, SampleRef, VOICE_STEALING_FADE_TIME, VoiceId, VoiceSlab, VoiceStart,
+};
 
-/// If voice limit is exceeded, this finds the oldest *release* samples
-/// and forces them to fade out quickly.
-pub fn enforce_voice_limit(voices: &mut HashMap<u64, Voice>, sample_rate: u32, polyphony: usize) {
+/// Notes an attack selector keeps track of per rank: every value of a `u8`.
+const NOTE_COUNT: usize = u8::MAX as usize + 1;
+
+/// How the engine chooses between alternative attack samples of the same pipe.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum AttackSelection {
+    /// Cycle through the alternatives in order.
+    RoundRobin,
+    /// Pick a random alternative, never the same one twice in a row.
+    #[default]
+    Random,
+}
+
+/// Chooses between the alternative attack samples of a pipe, so repeated
+/// notes don't replay the identical recording.
+pub struct AttackSelector {
+    policy: AttackSelection,
+    /// Xorshift state. Fixed seed so renders are reproducible.
+    rng_state: u32,
+    /// Last alternative played, per rank index and note
+    last_played: Vec<Option<u16>>,
+}
+
+impl AttackSelector {
+    pub fn new(policy: AttackSelection, rank_count: usize) -> Self {
+        Self {
+            policy,
+            rng_state: 0x9E37_79B9,
+            last_played: vec![None; rank_count * NOTE_COUNT],
+        }
+    }
+
+    fn next_random(&mut self) -> u32 {
+        let mut x = self.rng_state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng_state = x;
+        x
+    }
+
+    /// Picks one of the alternatives in `candidates`, the indices of a pipe's attacks.
+    pub fn select(&mut self, rank_index: usize, note: u8, candidates: Range<usize>) -> usize {
+        let count = candidates.len();
+        if count <= 1 {
+            return candidates.start;
+        }
+        let key = rank_index * NOTE_COUNT + note as usize;
+        let last = self.last_played[key].map(usize::from);
+        let index = match (self.policy, last) {
+            (AttackSelection::RoundRobin, Some(last)) => (last + 1) % count,
+            (AttackSelection::RoundRobin, None) => 0,
+            (AttackSelection::Random, Some(last)) => {
+                // Pick among the others so the same attack never plays twice in a row
+                let offset = 1 + self.next_random() as usize % (count - 1);
+                (last + offset) % count
+            }
+            (AttackSelection::Random, None) => self.next_random() as usize % count,
+        };
+        self.last_played[key] = Some(index as u16);
+        candidates.start + index
+    }
+}
+
+/// The voices and the notes holding them: everything note and control events
+/// act on. Owned by the engine and handed to the event handlers by `&mut`.
+pub struct VoiceState {
+    pub organ: Arc<Organ>,
+    pub index: Arc<OrganIndex>,
+    pub sample_rate: u32,
+    pub voices: VoiceSlab,
+    /// Pipes sounding, one list per MIDI note
+    pub active_notes: Vec<Vec<ActiveNote>>,
+    /// Note Ons waiting to be started at a throttled rate
+    pub pending_notes: VecDeque<EngineCommand>,
+    pub attack_selector: AttackSelector,
+    pub tracker_delay_scale: f32,
+    pub tuning: Tuning,
+    /// Velocity curve exponent of each rank, in `OrganIndex::rank_ids` order
+    pub velocity_curves: Vec<Option<f32>>,
+    /// Output pair of each stop's ranks, in `OrganIndex::stop_ranks` order
+    pub output_pairs: Vec<Vec<usize>>,
+    pub active_tremulants: Vec<bool>,
+    /// Voices not started because every slot was taken, until the engine reports them
+    pub dropped_voices: usize,
+}
+
+/// The engine's connections to the UI, the recorders and the reverb loader,
+/// for the messages that touch files or start threads.
+pub struct EngineIo {
+    pub buffer_size_frames: usize,
+    /// Channels in the engine's output, see `AudioEngine::set_output_layout`
+    pub output_channels: usize,
+    pub tui_tx: mpsc::Sender<TuiMessage>,
+    pub shared_midi_recorder: Arc<Mutex<Option<MidiRecorder>>>,
+    pub ir_loader_tx: mpsc::Sender<Result<Vec<StereoConvolver>, anyhow::Error>>,
+    pub audio_recorder: Option<AudioRecorder>,
+}
+
+/// If voice limit is exceeded, this finds the oldest release samples and
+/// ringing percussive attacks and forces them to fade out quickly.
+/// `candidates` is scratch space. Returns the number of voices stolen.
+pub fn enforce_voice_limit(
+    voices: &mut VoiceSlab,
+    candidates: &mut Vec<(VoiceId, Instant)>,
+    now: Instant,
+    sample_rate: u32,
+    polyphony: usize,
+) -> usize {
     let active_musical_voices = voices.values().filter(|v| !v.is_fading_out).count();
 
     if active_musical_voices <= polyphony {
-        return;
+        return 0;
     }
 
     let voices_to_steal = active_musical_voices - polyphony;
     let min_age = Duration::from_millis(50);
 
-    let mut candidates: Vec<(u64, Instant)> = voices
-        .iter()
-        .filter(|(_, v)| {
-            !v.is_attack_sample && !v.is_fading_out && v.note_on_time.elapsed() > min_age
-        })
-        .map(|(id, v)| (*id, v.note_on_time))
-        .collect();
+    candidates.clear();
+    candidates.extend(
+        voices
+            .iter()
+            .filter(|(_, v)| {
+                (!v.is_attack_sample || v.is_percussive)
+                    && !v.is_fading_out
+                    && now.saturating_duration_since(v.note_on_time) > min_age
+            })
+            .map(|(id, v)| (id, v.note_on_time)),
+    );
 
-    candidates.sort_by_key(|(_, time)| *time);
+    candidates.sort_unstable_by_key(|(_, time)| *time);
 
-    for (voice_id, _) in candidates.iter().take(voices_to_steal) {
+    let mut stolen = 0;
+    for &(voice_id, _) in candidates.iter().take(voices_to_steal) {
         if let Some(voice) = voices.get_mut(voice_id) {
-            log::warn!("[AudioThread] Stealing Voice ID {}", voice_id);
+            stolen += 1;
             voice.is_fading_out = true;
             voice.is_fading_in = false;
 
@@ -48,263 +165,401 @@ pub fn enforce_voice_limit(voices: &mut HashMap<u64, Voice>, sample_rate: u32, p
             };
         }
     }
+    stolen
 }
 
-pub fn trigger_note_release(
-    stopped_note: ActiveNote,
-    organ: &Arc<Organ>,
-    voices: &mut HashMap<u64, Voice>,
-    sample_rate: u32,
-    voice_counter: &mut u64,
-    spawner_tx: &mpsc::Sender<SpawnJob>,
-) {
-    let press_duration = stopped_note.start_time.elapsed().as_millis() as i64;
+/// Key-to-pipe lag of a rank's action, in output frames.
+fn tracker_delay_frames(rank: &Rank, sample_rate: u32, tracker_delay_scale: f32) -> usize {
+    (rank.tracker_delay_ms as f32 * tracker_delay_scale.max(0.0) * sample_rate as f32 / 1000.0)
+        .round() as usize
+}
+
+pub fn trigger_note_release(stopped_note: ActiveNote, now: Instant, state: &mut VoiceState) {
+    let press_duration = now
+        .saturating_duration_since(stopped_note.start_time)
+        .as_millis() as i64;
     let note = stopped_note.note;
 
-    if let Some(rank) = organ.ranks.get(&stopped_note.rank_id) {
+    let rank_id = &state.index.rank_ids[stopped_note.rank_index];
+    if let Some(rank) = state.organ.ranks.get(rank_id) {
+        // Percussive pipes (chimes, harps...) ring out on their own: no release, no fade
+        if rank.is_percussive {
+            return;
+        }
+        let delay_frames = tracker_delay_frames(rank, state.sample_rate, state.tracker_delay_scale);
         if let Some(pipe) = rank.pipes.get(&note) {
-            let release_sample = pipe
-                .releases
-                .iter()
-                .find(|r| {
+            let releases = pipe.release_indices(stopped_note.is_tremulant);
+            let release_index = releases
+                .clone()
+                .find(|&i| {
+                    let r = &pipe.releases[i];
                     r.max_key_press_time_ms == -1 || press_duration <= r.max_key_press_time_ms
                 })
-                .or_else(|| pipe.releases.last());
+                .or_else(|| releases.last());
 
             let mut release_created = false;
 
-            if let Some(release) = release_sample {
-                let total_gain = rank.gain_db + pipe.gain_db;
-                match Voice::new(
-                    &release.path,
-                    Arc::clone(&organ),
-                    sample_rate,
-                    total_gain,
-                    false,
-                    false,
-                    Instant::now(),
-                    release.preloaded_bytes.clone(),
-                    spawner_tx,
-                    rank.windchest_group_id.clone(),
-                ) {
-                    Ok(mut voice) => {
-                        voice.fade_level = 0.0;
-                        let release_voice_id = *voice_counter;
-                        *voice_counter += 1;
-                        voices.insert(release_voice_id, voice);
-
-                        if let Some(attack_voice) = voices.get_mut(&stopped_note.voice_id) {
+            if let Some(release_index) = release_index {
+                let release = &pipe.releases[release_index];
+                let total_gain = rank.gain_db
+                    + pipe.gain_db
+                    + velocity_gain_db(
+                        state.velocity_curves[stopped_note.rank_index],
+                        stopped_note.velocity,
+                    );
+                // With an attack to take over from, the crossfade picks the start frame
+                let has_attack = state.voices.get(stopped_note.voice_id).is_some();
+                let start = VoiceStart {
+                    sample: SampleRef {
+                        rank_index: stopped_note.rank_index,
+                        note,
+                        kind: PipeSample::Release(release_index),
+                    },
+                    preloaded: release.preloaded_bytes.as_deref().map(Vec::as_slice),
+                    gain_db: total_gain,
+                    start_fading_in: false,
+                    is_attack_sample: false,
+                    is_percussive: false,
+                    note_on_time: now,
+                    start_frame: 0,
+                    windchest: state.index.rank_windchests[stopped_note.rank_index],
+                };
+                if let Some((release_voice_id, voice)) = state.voices.insert(&start) {
+                    voice.fade_level = 0.0;
+                    voice.delay_frames = delay_frames;
+                    voice.output_pair = stopped_note.output_pair;
+                    voice.pitch_ratio = stopped_note.pitch_ratio;
+                    voice.phase_alignment = release.phase_alignment.clone();
+                    voice.is_waiting_for_crossfade = has_attack;
+                    voice.is_fading_in = !has_attack;
+
+                    if let Some(attack_voice) = state.voices.get_mut(stopped_note.voice_id) {
+                        // A delayed release keeps the attack streaming until the crossfade
+                        if delay_frames == 0 {
                             attack_voice.is_cancelled.store(true, Ordering::SeqCst);
-                            attack_voice.is_awaiting_release_sample = true;
-                            attack_voice.release_voice_id = Some(release_voice_id);
-                        } else {
-                            if let Some(rv) = voices.get_mut(&release_voice_id) {
-                                rv.is_fading_in = true;
-                            }
                         }
-                        release_created = true;
+                        attack_voice.is_awaiting_release_sample = true;
+                        attack_voice.release_voice_id = Some(release_voice_id);
+                        attack_voice.release_delay_frames = delay_frames;
                     }
-                    Err(e) => log::error!("Error creating release: {}", e),
+                    release_created = true;
+                } else {
+                    state.dropped_voices += 1;
                 }
             }
 
             if !release_created {
-                if let Some(voice) = voices.get_mut(&stopped_note.voice_id) {
-                    voice.is_cancelled.store(true, Ordering::SeqCst);
-                    voice.is_fading_out = true;
+                if let Some(voice) = state.voices.get_mut(stopped_note.voice_id) {
+                    if delay_frames == 0 {
+                        voice.is_cancelled.store(true, Ordering::SeqCst);
+                        voice.is_fading_out = true;
+                    } else {
+                        // Fade out once the tracker delay has elapsed
+                        voice.is_awaiting_release_sample = true;
+                        voice.release_voice_id = None;
+                        voice.release_delay_frames = delay_frames;
+                    }
                 }
             }
         }
     }
 }
 
-pub fn handle_note_off(
+/// Releases every pipe sounding at `note`.
+pub fn handle_note_off(note: u8, now: Instant, state: &mut VoiceState) {
+    // Pop instead of draining, so the list keeps its capacity and nothing allocates
+    while let Some(stopped_note) = state.active_notes[note as usize].pop() {
+        trigger_note_release(stopped_note, now, state);
+    }
+}
+
+/// Starts the pipes of one stop for a key.
+pub fn process_note_on(
     note: u8,
-    organ: &Arc<Organ>,
-    voices: &mut HashMap<u64, Voice>,
-    active_notes: &mut HashMap<u8, Vec<ActiveNote>>,
-    sample_rate: u32,
-    voice_counter: &mut u64,
-    spawner_tx: &mpsc::Sender<SpawnJob>,
+    velocity: u8,
+    stop_index: usize,
+    now: Instant,
+    state: &mut VoiceState,
 ) {
-    if let Some(notes_to_stop) = active_notes.remove(&note) {
-        for stopped_note in notes_to_stop {
-            trigger_note_release(
-                stopped_note,
-                organ,
-                voices,
-                sample_rate,
-                voice_counter,
-                spawner_tx,
+    let note_on_time = now;
+    let Some(rank_indices) = state.index.stop_ranks.get(stop_index) else {
+        return;
+    };
+    let list = &mut state.active_notes[note as usize];
+
+    // The pipe is already speaking for another key: just take another hold on it.
+    let mut already_sounding = false;
+    for active_note in list.iter_mut().filter(|n| n.stop_index == stop_index) {
+        active_note.hold_count += 1;
+        already_sounding = true;
+    }
+    if already_sounding {
+        return;
+    }
+
+    for (&rank_index, &output_pair) in rank_indices.iter().zip(&state.output_pairs[stop_index]) {
+        let Some(rank) = state.organ.ranks.get(&state.index.rank_ids[rank_index]) else {
+            continue;
+        };
+        let Some(pipe) = rank.pipes.get(&note) else {
+            continue;
+        };
+        let is_tremulant = state.index.rank_wave_tremulants[rank_index]
+            .is_some_and(|tremulant| state.active_tremulants[tremulant]);
+        let Some(candidates) = pipe.attack_indices(velocity, is_tremulant) else {
+            continue;
+        };
+        let attack_index = state.attack_selector.select(rank_index, note, candidates);
+        let attack = &pipe.attacks[attack_index];
+        let total_gain = rank.gain_db
+            + pipe.gain_db
+            + velocity_gain_db(state.velocity_curves[rank_index], velocity);
+        let start = VoiceStart {
+            sample: SampleRef {
+                rank_index,
+                note,
+                kind: PipeSample::Attack(attack_index),
+            },
+            preloaded: attack.preloaded_bytes.as_deref().map(Vec::as_slice),
+            gain_db: total_gain,
+            start_fading_in: false,
+            is_attack_sample: true,
+            is_percussive: rank.is_percussive,
+            note_on_time,
+            start_frame: 0,
+            windchest: state.index.rank_windchests[rank_index],
+        };
+        let Some((voice_id, voice)) = state.voices.insert(&start) else {
+            state.dropped_voices += 1;
+            continue;
+        };
+        voice.delay_frames =
+            tracker_delay_frames(rank, state.sample_rate, state.tracker_delay_scale);
+        voice.output_pair = output_pair;
+        voice.phase_alignment = attack.phase_alignment.clone();
+        let pitch_ratio = state.tuning.pitch_ratio(note, rank.sounding_interval());
+        voice.pitch_ratio = pitch_ratio;
+        list.push(ActiveNote {
+            note,
+            velocity,
+            start_time: note_on_time,
+            stop_index,
+            rank_index,
+            voice_id,
+            output_pair,
+            pitch_ratio,
+            is_tremulant,
+            hold_count: 1,
+        });
+    }
+}
+
+/// Moves the pipes sounding on ranks with wave tremulant samples over to the
+/// samples for the tremulant's new state. The new sample fades in at the point
+/// it would have reached had it been playing since the key went down.
+fn switch_wave_tremulant(tremulant: usize, active: bool, now: Instant, state: &mut VoiceState) {
+    for active_note in state.active_notes.iter_mut().flatten() {
+        if active_note.is_tremulant == active
+            || state.index.rank_wave_tremulants[active_note.rank_index] != Some(tremulant)
+        {
+            continue;
+        }
+        let Some(rank) = state
+            .organ
+            .ranks
+            .get(&state.index.rank_ids[active_note.rank_index])
+        else {
+            continue;
+        };
+        let Some(pipe) = rank
+            .pipes
+            .get(&active_note.note)
+            .filter(|pipe| pipe.has_tremulant_layer() && !rank.is_percussive)
+        else {
+            continue;
+        };
+
+        // A pipe still waiting out its tracker delay starts from the beginning
+        let delay_frames = state
+            .voices
+            .get(active_note.voice_id)
+            .map_or(0, |voice| voice.delay_frames);
+        let start_frame = if delay_frames > 0 {
+            0
+        } else {
+            let elapsed = now.saturating_duration_since(active_note.start_time);
+            (elapsed.as_secs_f32() * state.sample_rate as f32 * active_note.pitch_ratio) as usize
+        };
+        let Some(attacks) = pipe.attack_indices(active_note.velocity, active) else {
+            continue;
+        };
+        let attack_index = attacks.start;
+        let attack = &pipe.attacks[attack_index];
+        let total_gain = rank.gain_db
+            + pipe.gain_db
+            + velocity_gain_db(
+                state.velocity_curves[active_note.rank_index],
+                active_note.velocity,
             );
+        let start = VoiceStart {
+            sample: SampleRef {
+                rank_index: active_note.rank_index,
+                note: active_note.note,
+                kind: PipeSample::Attack(attack_index),
+            },
+            preloaded: attack.preloaded_bytes.as_deref().map(Vec::as_slice),
+            gain_db: total_gain,
+            start_fading_in: true,
+            is_attack_sample: true,
+            is_percussive: false,
+            note_on_time: active_note.start_time,
+            start_frame,
+            windchest: state.index.rank_windchests[active_note.rank_index],
+        };
+        let Some((voice_id, voice)) = state.voices.insert(&start) else {
+            state.dropped_voices += 1;
+            continue;
+        };
+        voice.delay_frames = delay_frames;
+        voice.output_pair = active_note.output_pair;
+        voice.phase_alignment = attack.phase_alignment.clone();
+        voice.pitch_ratio = active_note.pitch_ratio;
+        if let Some(old_voice) = state.voices.get_mut(active_note.voice_id) {
+            old_voice.is_cancelled.store(true, Ordering::SeqCst);
+            old_voice.is_fading_in = false;
+            old_voice.is_fading_out = true;
         }
+        active_note.voice_id = voice_id;
+        active_note.is_tremulant = active;
     }
 }
 
-pub fn process_note_on(
-    msg: AppMessage,
-    active_notes: &mut HashMap<u8, Vec<ActiveNote>>,
-    organ: &Arc<Organ>,
-    voices: &mut HashMap<u64, Voice>,
-    voice_counter: &mut u64,
-    stop_map: &HashMap<String, usize>,
-    sample_rate: u32,
-    spawner_tx: &mpsc::Sender<SpawnJob>,
-) {
-    if let AppMessage::NoteOn(note, _vel, stop_name) = msg {
-        let note_on_time = Instant::now();
-        if let Some(stop_index) = stop_map.get(&stop_name) {
-            let stop = &organ.stops[*stop_index];
-            let mut new_notes = Vec::new();
-
-            for rank_id in &stop.rank_ids {
-                if let Some(rank) = organ.ranks.get(rank_id) {
-                    if let Some(pipe) = rank.pipes.get(&note) {
-                        let total_gain = rank.gain_db + pipe.gain_db;
-                        match Voice::new(
-                            &pipe.attack_sample_path,
-                            Arc::clone(&organ),
-                            sample_rate,
-                            total_gain,
-                            false,
-                            true,
-                            note_on_time,
-                            pipe.preloaded_bytes.clone(),
-                            spawner_tx,
-                            rank.windchest_group_id.clone(),
-                        ) {
-                            Ok(voice) => {
-                                let voice_id = *voice_counter;
-                                *voice_counter += 1;
-                                voices.insert(voice_id, voice);
-                                new_notes.push(ActiveNote {
-                                    note,
-                                    start_time: note_on_time,
-                                    stop_index: *stop_index,
-                                    rank_id: rank_id.clone(),
-                                    voice_id,
-                                });
-                            }
-                            Err(e) => log::error!("Error creating attack voice: {}", e),
-                        }
-                    }
+/// Handles the note and tremulant commands. The engine queues `NoteOn`s to
+/// start them at a throttled rate with `process_note_on`, and applies the mix
+/// settings itself. Doesn't allocate.
+pub fn process_command(command: EngineCommand, now: Instant, state: &mut VoiceState) {
+    match command {
+        EngineCommand::NoteOff { note, stop } => {
+            // A NoteOff cancels its own NoteOn if that is still waiting in the queue.
+            let pending_pos = state.pending_notes.iter().position(|pending| {
+                matches!(*pending, EngineCommand::NoteOn { note: pending_note, stop: pending_stop, .. }
+                    if pending_note == note && pending_stop == stop)
+            });
+            if let Some(pos) = pending_pos {
+                state.pending_notes.remove(pos);
+                return;
+            }
+
+            // Drop one hold from this stop's voices and release the ones no longer held
+            for active_note in state.active_notes[note as usize]
+                .iter_mut()
+                .filter(|an| an.stop_index == stop)
+            {
+                active_note.hold_count = active_note.hold_count.saturating_sub(1);
+            }
+            while let Some(pos) = state.active_notes[note as usize]
+                .iter()
+                .position(|an| an.stop_index == stop && an.hold_count == 0)
+            {
+                let stopped = state.active_notes[note as usize].swap_remove(pos);
+                trigger_note_release(stopped, now, state);
+            }
+        }
+        EngineCommand::AllNotesOff => {
+            state.pending_notes.clear();
+            for note in 0..state.active_notes.len() {
+                handle_note_off(note as u8, now, state);
+            }
+            // Percussive pipes have no release to silence them: fade them out
+            for (_, voice) in state.voices.iter_mut() {
+                if voice.is_percussive && !voice.is_fading_out {
+                    voice.is_cancelled.store(true, Ordering::SeqCst);
+                    voice.is_fading_out = true;
                 }
             }
-            if !new_notes.is_empty() {
-                active_notes.entry(note).or_default().extend(new_notes);
+        }
+        EngineCommand::SetTrackerDelayScale(scale) => {
+            state.tracker_delay_scale = scale.max(0.0);
+        }
+        EngineCommand::SetTremulantActive { tremulant, active } => {
+            if let Some(is_active) = state.active_tremulants.get_mut(tremulant) {
+                *is_active = active;
+                switch_wave_tremulant(tremulant, active, now, state);
             }
         }
+        EngineCommand::NoteOn { .. }
+        | EngineCommand::SetGain(_)
+        | EngineCommand::SetPolyphony(_)
+        | EngineCommand::SetReverbWetDry(_)
+        | EngineCommand::SetEnclosurePosition { .. } => {}
     }
 }
 
-pub fn process_message(
-    msg: AppMessage,
-    wet_dry_ratio: &mut f32,
-    system_gain: &mut f32,
-    polyphony: &mut usize,
-    ir_loader_tx: &mpsc::Sender<Result<StereoConvolver, anyhow::Error>>,
-    sample_rate: u32,
-    buffer_size_frames: usize,
-    active_notes: &mut HashMap<u8, Vec<ActiveNote>>,
-    organ: &Arc<Organ>,
-    voices: &mut HashMap<u64, Voice>,
-    voice_counter: &mut u64,
-    stop_map: &HashMap<String, usize>,
-    spawner_tx: &mpsc::Sender<SpawnJob>,
-    pending_queue: &mut VecDeque<AppMessage>,
-    active_tremulants: &mut HashMap<String, bool>,
-    audio_recorder: &mut Option<AudioRecorder>,
-    tui_tx: &mpsc::Sender<TuiMessage>,
-    shared_midi_recorder: &Arc<Mutex<Option<MidiRecorder>>>,
-) {
+/// Handles the messages that aren't engine commands: those that carry heap
+/// data, load files or start threads. Not meant for the audio thread's hot path.
+pub fn process_message(msg: AppMessage, state: &mut VoiceState, io: &mut EngineIo) {
     match msg {
-        AppMessage::NoteOff(n, s) => {
-            let mut removed_from_queue = false;
-            if !pending_queue.is_empty() {
-                pending_queue.retain(|pending_msg| {
-                    if let AppMessage::NoteOn(pending_note, _, pending_stop) = pending_msg {
-                        if *pending_note == n && *pending_stop == s {
-                            removed_from_queue = true;
-                            return false;
-                        }
-                    }
-                    true
-                });
-            }
-
-            if let Some(idx) = stop_map.get(&s) {
-                if let Some(list) = active_notes.get_mut(&n) {
-                    // Partition into notes to release and notes to keep
-                    let (to_release, to_keep) = list
-                        .drain(..)
-                        .partition(|active_note| active_note.stop_index == *idx);
-                    *list = to_keep;
-
-                    for stopped in to_release {
-                        trigger_note_release(
-                            stopped,
-                            organ,
-                            voices,
-                            sample_rate,
-                            voice_counter,
-                            spawner_tx,
-                        );
-                    }
-
-                    if list.is_empty() {
-                        active_notes.remove(&n);
-                    }
+        AppMessage::SetTuning(new_tuning) => {
+            state.tuning = new_tuning.sanitized();
+            // Retune the pipes that are speaking; their releases pick it up from the note
+            for active_note in state.active_notes.iter_mut().flatten() {
+                let rank = &state.organ.ranks[&state.index.rank_ids[active_note.rank_index]];
+                active_note.pitch_ratio = state
+                    .tuning
+                    .pitch_ratio(active_note.note, rank.sounding_interval());
+                if let Some(voice) = state.voices.get_mut(active_note.voice_id) {
+                    voice.pitch_ratio = active_note.pitch_ratio;
                 }
             }
         }
-        AppMessage::AllNotesOff => {
-            pending_queue.clear();
-            let notes: Vec<u8> = active_notes.keys().cloned().collect();
-            for note in notes {
-                handle_note_off(
-                    note,
-                    organ,
-                    voices,
-                    active_notes,
-                    sample_rate,
-                    voice_counter,
-                    spawner_tx,
-                );
+        AppMessage::SetVelocityCurves(curves) => {
+            let curves = curves.sanitized();
+            for (curve, rank_id) in state.velocity_curves.iter_mut().zip(&state.index.rank_ids) {
+                *curve = curves.curve(rank_id);
             }
         }
-        AppMessage::SetTremulantActive(id, active) => {
-            active_tremulants.insert(id, active);
-        }
         AppMessage::StartAudioRecording => {
-            match AudioRecorder::start(organ.name.clone(), sample_rate) {
+            match AudioRecorder::start(
+                state.organ.name.clone(),
+                state.sample_rate,
+                io.output_channels,
+            ) {
                 Ok(rec) => {
-                    *audio_recorder = Some(rec);
-                    let _ = tui_tx.send(TuiMessage::MidiLog("Audio Recording Started".into()));
+                    io.audio_recorder = Some(rec);
+                    let _ = io
+                        .tui_tx
+                        .send(TuiMessage::MidiLog("Audio Recording Started".into()));
                 }
                 Err(e) => {
-                    let _ = tui_tx.send(TuiMessage::Error(format!("Rec Error: {}", e)));
+                    let _ = io
+                        .tui_tx
+                        .send(TuiMessage::Error(format!("Rec Error: {}", e)));
                 }
             }
         }
         AppMessage::StopAudioRecording => {
-            if let Some(rec) = audio_recorder.take() {
+            if let Some(rec) = io.audio_recorder.take() {
                 rec.stop();
-                let _ = tui_tx.send(TuiMessage::MidiLog("Audio Recording Stopped/Saved".into()));
+                let _ = io
+                    .tui_tx
+                    .send(TuiMessage::MidiLog("Audio Recording Stopped/Saved".into()));
             }
         }
         AppMessage::StartMidiRecording => {
-            let mut guard = shared_midi_recorder.lock().unwrap();
+            let mut guard = io.shared_midi_recorder.lock().unwrap();
             if guard.is_none() {
-                *guard = Some(MidiRecorder::new(organ.name.clone()));
-                let _ = tui_tx.send(TuiMessage::MidiLog("MIDI Recording Started".into()));
+                *guard = Some(MidiRecorder::new(state.organ.name.clone()));
+                let _ = io
+                    .tui_tx
+                    .send(TuiMessage::MidiLog("MIDI Recording Started".into()));
             }
         }
         AppMessage::StopMidiRecording => {
             // Take the recorder under a short-held lock, then drop the lock before
             // doing any file I/O — otherwise we'd block the audio thread on disk.
-            let recorder_opt = shared_midi_recorder.lock().unwrap().take();
+            let recorder_opt = io.shared_midi_recorder.lock().unwrap().take();
             if let Some(recorder) = recorder_opt {
-                let tui_tx_bg = tui_tx.clone();
+                let tui_tx_bg = io.tui_tx.clone();
                 thread::spawn(move || match recorder.save() {
                     Ok(path) => {
                         let _ = tui_tx_bg.send(TuiMessage::MidiLog(format!("Saved: {}", path)));
@@ -316,24 +571,24 @@ pub fn process_message(
                 });
             }
         }
-        AppMessage::SetReverbWetDry(r) => *wet_dry_ratio = r.clamp(0.0, 1.0),
         AppMessage::SetReverbIr(p) => {
-            let tx = ir_loader_tx.clone();
+            let tx = io.ir_loader_tx.clone();
+            let sample_rate = state.sample_rate;
+            let buffer_size_frames = io.buffer_size_frames;
+            // Every output pair has its own convolver; they are copied here, as
+            // that allocates, so the audio thread only has to swap them in.
+            let pair_count = (io.output_channels / CHANNEL_COUNT).max(1);
             thread::spawn(move || {
-                let _ = tx.send(StereoConvolver::from_file(
-                    &p,
-                    sample_rate,
-                    buffer_size_frames,
-                ));
+                let _ = tx.send(
+                    StereoConvolver::from_file(&p, sample_rate, buffer_size_frames)
+                        .map(|convolver| vec![convolver; pair_count]),
+                );
             });
         }
-        AppMessage::SetGain(g) => *system_gain = g,
-        AppMessage::SetPolyphony(p) => *polyphony = p,
         AppMessage::Quit => {
             // tell the Logic Thread to close the Window.
             // This allows main.rs to finish the loop and handle the respawn.
-            let _ = tui_tx.send(TuiMessage::ForceClose);
+            let _ = io.tui_tx.send(TuiMessage::ForceClose);
         }
-        _ => {}
     }
 }
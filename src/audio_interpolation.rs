@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+use crate::voice::CHANNEL_COUNT;
+
+/// Taps of the windowed-sinc kernel. Eight zero crossings on either side.
+const SINC_TAPS: usize = 16;
+/// Fractional positions the sinc kernel is tabulated at. Positions in between
+/// are interpolated linearly from the two nearest phases.
+const SINC_PHASES: usize = 256;
+/// Passband edge as a fraction of Nyquist. Leaves room for the tremulant and
+/// tuning to raise the pitch a little without folding the top octave back down.
+const SINC_CUTOFF: f32 = 0.9;
+
+/// How a voice is resampled when it plays at another rate than it was recorded
+/// at (tremulant, temperament, transposition or A4 pitch).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// 2-point linear. Cheapest, but dulls the treble audibly.
+    #[default]
+    Linear,
+    /// 4-point cubic Hermite (Catmull-Rom). Much cleaner at little extra cost.
+    Hermite,
+    /// 16-tap Blackman-windowed sinc from a polyphase table. Best quality.
+    Sinc,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [
+        Interpolation::Linear,
+        Interpolation::Hermite,
+        Interpolation::Sinc,
+    ];
+
+    /// Input frames the kernel reads for every output frame.
+    pub fn taps(self) -> usize {
+        match self {
+            Interpolation::Linear => 2,
+            Interpolation::Hermite => 4,
+            Interpolation::Sinc => SINC_TAPS,
+        }
+    }
+
+    /// Frames the kernel reads before the position it evaluates. Voices start
+    /// with this many frames of silence so no part of the attack is skipped.
+    pub fn lead_in_frames(self) -> usize {
+        self.taps() / 2 - 1
+    }
+}
+
+/// Resamples voices with the chosen `Interpolation`. Owns the sinc table, which
+/// is built once here so the mixer never allocates.
+pub struct Interpolator {
+    kind: Interpolation,
+    /// `SINC_PHASES + 1` rows of `SINC_TAPS` coefficients, for fractional
+    /// positions 0.0 ..= 1.0. Empty unless `kind` is `Sinc`.
+    sinc_table: Vec<f32>,
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < 1e-6 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+fn build_sinc_table() -> Vec<f32> {
+    let half = (SINC_TAPS / 2) as f32;
+    let lead_in = (SINC_TAPS / 2 - 1) as f32;
+    let mut table = Vec::with_capacity((SINC_PHASES + 1) * SINC_TAPS);
+    for phase in 0..=SINC_PHASES {
+        let frac = phase as f32 / SINC_PHASES as f32;
+        let row: Vec<f32> = (0..SINC_TAPS)
+            .map(|tap| {
+                // Distance of this tap from the position being evaluated
+                let x = tap as f32 - lead_in - frac;
+                let window = if x.abs() >= half {
+                    0.0
+                } else {
+                    let w = PI * x / half;
+                    0.42 + 0.5 * w.cos() + 0.08 * (2.0 * w).cos()
+                };
+                SINC_CUTOFF * sinc(SINC_CUTOFF * x) * window
+            })
+            .collect();
+        // Unity gain at DC for every phase, so sustained notes don't ripple
+        let sum: f32 = row.iter().sum();
+        table.extend(row.iter().map(|c| c / sum));
+    }
+    table
+}
+
+impl Interpolator {
+    pub fn new(kind: Interpolation) -> Self {
+        let sinc_table = match kind {
+            Interpolation::Sinc => build_sinc_table(),
+            _ => Vec::new(),
+        };
+        Self { kind, sinc_table }
+    }
+
+    pub fn kind(&self) -> Interpolation {
+        self.kind
+    }
+
+    pub fn taps(&self) -> usize {
+        self.kind.taps()
+    }
+
+    pub fn lead_in_frames(&self) -> usize {
+        self.kind.lead_in_frames()
+    }
+
+    /// Resamples interleaved stereo `input` into `mix`, adding to what is there.
+    /// `cursor` is the fractional read position in frames, advanced by the
+    /// pitch of `ramp`. `input` must hold `taps()` frames past the last
+    /// position read.
+    pub fn mix<'a>(
+        &self,
+        input: &[f32],
+        mix: impl Iterator<Item = &'a mut [f32]>,
+        cursor: &mut f32,
+        ramp: Ramp,
+    ) {
+        match self.kind {
+            Interpolation::Linear => ramp.run(input, mix, cursor, 2, linear),
+            Interpolation::Hermite => ramp.run(input, mix, cursor, 4, hermite),
+            Interpolation::Sinc => ramp.run(input, mix, cursor, SINC_TAPS, |frames, frac| {
+                sinc_polyphase(&self.sinc_table, frames, frac)
+            }),
+        }
+    }
+}
+
+/// Pitch and gain ramps across one block. Both start at their value and move
+/// by their delta every output frame.
+pub struct Ramp {
+    pub pitch: f32,
+    pub pitch_delta: f32,
+    pub gain: f32,
+    pub gain_delta: f32,
+}
+
+impl Ramp {
+    /// The mixing loop, instantiated once per kernel so the kernel is inlined.
+    #[inline(always)]
+    fn run<'a>(
+        self,
+        input: &[f32],
+        mix: impl Iterator<Item = &'a mut [f32]>,
+        cursor: &mut f32,
+        taps: usize,
+        kernel: impl Fn(&[f32], f32) -> (f32, f32),
+    ) {
+        let mut pitch = self.pitch;
+        let mut gain = self.gain;
+        for out in mix {
+            let idx = cursor.floor() as usize;
+            let frac = *cursor - idx as f32;
+            let start = idx * CHANNEL_COUNT;
+            let (l, r) = kernel(&input[start..start + taps * CHANNEL_COUNT], frac);
+            out[0] += l * gain;
+            out[1] += r * gain;
+
+            *cursor += pitch;
+            gain += self.gain_delta;
+            pitch += self.pitch_delta;
+        }
+    }
+}
+
+#[inline(always)]
+fn linear(frames: &[f32], frac: f32) -> (f32, f32) {
+    let (s0_l, s0_r, s1_l, s1_r) = (frames[0], frames[1], frames[2], frames[3]);
+    (s0_l + (s1_l - s0_l) * frac, s0_r + (s1_r - s0_r) * frac)
+}
+
+#[inline(always)]
+fn hermite_1(xm1: f32, x0: f32, x1: f32, x2: f32, frac: f32) -> f32 {
+    let c1 = 0.5 * (x1 - xm1);
+    let c2 = xm1 - 2.5 * x0 + 2.0 * x1 - 0.5 * x2;
+    let c3 = 0.5 * (x2 - xm1) + 1.5 * (x0 - x1);
+    ((c3 * frac + c2) * frac + c1) * frac + x0
+}
+
+#[inline(always)]
+fn hermite(frames: &[f32], frac: f32) -> (f32, f32) {
+    let f = &frames[..4 * CHANNEL_COUNT];
+    (
+        hermite_1(f[0], f[2], f[4], f[6], frac),
+        hermite_1(f[1], f[3], f[5], f[7], frac),
+    )
+}
+
+#[inline(always)]
+fn sinc_polyphase(table: &[f32], frames: &[f32], frac: f32) -> (f32, f32) {
+    let position = frac * SINC_PHASES as f32;
+    let phase = (position as usize).min(SINC_PHASES - 1);
+    let blend = position - phase as f32;
+    let row_a = &table[phase * SINC_TAPS..(phase + 1) * SINC_TAPS];
+    let row_b = &table[(phase + 1) * SINC_TAPS..(phase + 2) * SINC_TAPS];
+    let frames = &frames[..SINC_TAPS * CHANNEL_COUNT];
+
+    let (mut a_l, mut a_r, mut b_l, mut b_r) = (0.0, 0.0, 0.0, 0.0);
+    for ((frame, &ca), &cb) in frames.chunks_exact(CHANNEL_COUNT).zip(row_a).zip(row_b) {
+        a_l += frame[0] * ca;
+        a_r += frame[1] * ca;
+        b_l += frame[0] * cb;
+        b_r += frame[1] * cb;
+    }
+    (a_l + (b_l - a_l) * blend, a_r + (b_r - a_r) * blend)
+}
@@ -1,246 +1,747 @@
 use anyhow::{Result, anyhow};
-use ringbuf::traits::Producer;
+use ringbuf::traits::{Consumer, Observer, Producer, Split};
+use ringbuf::{HeapCons, HeapProd, HeapRb};
+use serde::Serialize;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::BinaryHeap;
 use std::fs::File;
 use std::io::BufReader;
-use std::sync::atomic::Ordering;
-use std::thread;
-use std::time::Duration;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, mpsc};
+use std::thread::{self, Thread};
 
+use crate::flac::{FlacSampleReader, read_flac_metadata};
+use crate::mapped_sample::MappedSample;
 use crate::voice::{CHANNEL_COUNT, SpawnJob};
-use crate::wav::{WavSampleReader, parse_smpl_chunk, parse_wav_metadata};
-
-/// Worker function that loads samples from disk or cache and fills the ring buffer.
-pub fn run_loader_job(mut job: SpawnJob) {
-    // Check cancellation before doing heavy lifting
-    if job.is_cancelled.load(Ordering::Relaxed) {
-        job.is_finished.store(true, Ordering::SeqCst);
-        return;
-    }
-
-    let path_str_clone = job
-        .path
-        .file_name()
-        .unwrap_or_default()
-        .to_string_lossy()
-        .to_string();
-
-    // Wrap in catch_unwind to prevent a loader panic from crashing the whole engine
-    let panic_result = std::panic::catch_unwind(move || {
-        let result: Result<()> = (|| {
-            // Check Caches
-            let maybe_cached_data = job
-                .organ
-                .sample_cache
-                .as_ref()
-                .and_then(|c| c.get(&job.path).cloned());
-            let maybe_cached_meta = job
-                .organ
-                .metadata_cache
-                .as_ref()
-                .and_then(|c| c.get(&job.path).cloned());
-
-            let loop_info;
-            let input_channels;
-            let mut source: Option<Box<dyn Iterator<Item = f32>>> = None;
-            let mut source_is_finished;
-            let use_memory_reader;
-            let mut samples_in_memory: Vec<f32> = Vec::new();
-
-            let mut interleaved_buffer = vec![0.0f32; 1024 * CHANNEL_COUNT];
-            let frames_to_skip = job.frames_to_skip;
+use crate::wav::{IsFlacError, WavSampleReader, parse_smpl_chunk, parse_wav_metadata};
 
+/// Frames decoded and pushed in one go.
+const CHUNK_FRAMES: usize = 1024;
+/// Frames a worker streams into one voice before moving on to other work.
+const MAX_FRAMES_PER_PASS: usize = 4096;
+/// A parked stream is refilled once its ring buffer has this much room again.
+const REFILL_THRESHOLD_FRAMES: usize = 2048;
+
+/// Loader threads to use when none are configured: one per core, within reason.
+pub fn default_loader_workers() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+        .clamp(2, 8)
+}
+
+/// Loader pool counters, updated by the workers.
+#[derive(Default)]
+pub struct LoaderStats {
+    workers: AtomicUsize,
+    busy_workers: AtomicUsize,
+    queued_jobs: AtomicUsize,
+    streaming_voices: AtomicUsize,
+    jobs_started: AtomicU64,
+}
+
+/// A snapshot of `LoaderStats` for the UI and the REST API.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LoaderStatus {
+    pub workers: usize,
+    /// Workers currently decoding or pushing samples
+    pub busy_workers: usize,
+    /// Starts and refills waiting for a worker
+    pub queued_jobs: usize,
+    /// Voices with an open sample stream
+    pub streaming_voices: usize,
+    /// Samples opened since the pool was started
+    pub jobs_started: u64,
+}
+
+impl LoaderStats {
+    pub fn snapshot(&self) -> LoaderStatus {
+        LoaderStatus {
+            workers: self.workers.load(Ordering::Relaxed),
+            busy_workers: self.busy_workers.load(Ordering::Relaxed),
+            queued_jobs: self.queued_jobs.load(Ordering::Relaxed),
+            streaming_voices: self.streaming_voices.load(Ordering::Relaxed),
+            jobs_started: self.jobs_started.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The sample loader: a dispatcher thread plus a fixed number of workers.
+/// Engines open their job queues through `jobs`; everything shuts down once
+/// every handle and every queue opened through them has been dropped.
+pub struct LoaderPool {
+    pub jobs: LoaderHandle,
+    pub stats: Arc<LoaderStats>,
+}
+
+/// Opens job queues to a loader pool. Several engines can share one pool.
+#[derive(Clone)]
+pub struct LoaderHandle {
+    queues: mpsc::Sender<HeapCons<SpawnJob>>,
+    // Declared last, so the dispatcher sees the sender gone when it wakes
+    dispatcher: LoaderWaker,
+}
+
+impl LoaderHandle {
+    /// Opens a queue for one engine, holding up to `capacity` jobs at a time.
+    pub fn job_queue(&self, capacity: usize) -> JobQueue {
+        let (producer, consumer) = HeapRb::<SpawnJob>::new(capacity.max(1)).split();
+        if self.queues.send(consumer).is_err() {
+            log::error!("[LoaderPool] Loader has shut down, samples won't load.");
+        }
+        JobQueue {
+            producer,
+            dispatcher: self.dispatcher.clone(),
+        }
+    }
+}
+
+/// An engine's queue to the loader. Pushing neither allocates nor blocks, so
+/// jobs can be queued from the audio thread.
+pub struct JobQueue {
+    producer: HeapProd<SpawnJob>,
+    // Declared last, so the dispatcher sees the queue closed when it wakes
+    dispatcher: LoaderWaker,
+}
+
+impl JobQueue {
+    /// Queues a job and wakes the dispatcher. Hands the job back if the queue is full.
+    pub fn push(&mut self, job: SpawnJob) -> Result<(), SpawnJob> {
+        self.producer.try_push(job)?;
+        self.dispatcher.wake();
+        Ok(())
+    }
+
+    /// A waker for the voices whose samples this queue's jobs stream.
+    pub fn waker(&self) -> LoaderWaker {
+        self.dispatcher.clone()
+    }
+}
+
+/// Wakes the loader's dispatcher, which sleeps until there is work: a new
+/// job, a cancelled stream, or a parked stream whose ring buffer has room.
+/// Waking neither allocates nor blocks. Dropping it wakes the dispatcher too,
+/// so it notices queues and handles that have gone.
+#[derive(Clone)]
+pub struct LoaderWaker {
+    dispatcher: Thread,
+}
+
+impl LoaderWaker {
+    pub fn wake(&self) {
+        self.dispatcher.unpark();
+    }
+
+    /// Called after reading from a ring buffer the loader streams into, with
+    /// its free samples before and after. Wakes the dispatcher if the read
+    /// made enough room for the stream to be refilled.
+    pub fn samples_read(&self, vacant_before: usize, vacant_after: usize) {
+        let threshold = REFILL_THRESHOLD_FRAMES * CHANNEL_COUNT;
+        if vacant_before < threshold && vacant_after >= threshold {
+            self.wake();
+        }
+    }
+}
+
+impl Drop for LoaderWaker {
+    fn drop(&mut self) {
+        self.wake();
+    }
+}
+
+/// Lower values are served first: a pipe that should start speaking beats a
+/// release, and both beat topping up voices that are already playing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TaskPriority {
+    AttackStart,
+    ReleaseStart,
+    Refill,
+}
+
+enum LoaderTask {
+    Start(SpawnJob),
+    Refill(SampleStream),
+}
+
+struct QueuedTask {
+    priority: TaskPriority,
+    seq: u64,
+    task: LoaderTask,
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority && self.seq == other.seq
+    }
+}
+
+impl Eq for QueuedTask {}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedTask {
+    /// `BinaryHeap` pops the greatest element, so urgent and older tasks compare greater.
+    fn cmp(&self, other: &Self) -> CmpOrdering {
+        other
+            .priority
+            .cmp(&self.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[derive(Default)]
+struct QueueState {
+    tasks: BinaryHeap<QueuedTask>,
+    next_seq: u64,
+    is_shut_down: bool,
+}
+
+/// Priority queue shared by the dispatcher and the workers.
+struct TaskQueue {
+    state: Mutex<QueueState>,
+    available: Condvar,
+    stats: Arc<LoaderStats>,
+}
+
+impl TaskQueue {
+    fn push(&self, priority: TaskPriority, task: LoaderTask) {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.tasks.push(QueuedTask {
+            priority,
+            seq,
+            task,
+        });
+        self.stats
+            .queued_jobs
+            .store(state.tasks.len(), Ordering::Relaxed);
+        self.available.notify_one();
+    }
+
+    /// Blocks until a task is available. Returns `None` once the pool shuts down.
+    fn pop(&self) -> Option<LoaderTask> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.is_shut_down {
+                return None;
+            }
+            if let Some(queued) = state.tasks.pop() {
+                self.stats
+                    .queued_jobs
+                    .store(state.tasks.len(), Ordering::Relaxed);
+                return Some(queued.task);
+            }
+            state = self.available.wait(state).unwrap();
+        }
+    }
+
+    fn shut_down(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.is_shut_down = true;
+        // Dropping the streams marks their voices as finished
+        state.tasks.clear();
+        self.stats.queued_jobs.store(0, Ordering::Relaxed);
+        self.available.notify_all();
+    }
+}
+
+/// Interleaved samples that are all at hand.
+enum SampleData {
+    /// Decoded into RAM
+    Decoded(Arc<Vec<f32>>),
+    /// Read from a memory-mapped cache file
+    Mapped(Arc<MappedSample>),
+}
+
+impl SampleData {
+    fn len(&self) -> usize {
+        match self {
+            Self::Decoded(samples) => samples.len(),
+            Self::Mapped(sample) => sample.len(),
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<f32> {
+        match self {
+            Self::Decoded(samples) => samples.get(index).copied(),
+            Self::Mapped(sample) => sample.get(index),
+        }
+    }
+}
+
+/// Where a stream's samples come from.
+enum SampleSource {
+    /// Samples in memory: from the RAM cache, a mapped cache file, or a looped
+    /// sample loaded on open.
+    Memory {
+        samples: SampleData,
+        position_frame: usize,
+        /// (start, end) frames of the sustain loop
+        loop_frames: Option<(usize, usize)>,
+    },
+    /// A one-shot sample decoded from disk as it plays.
+    Disk(SampleDecoder),
+}
+
+/// Interleaved samples decoded from a WAV or FLAC file.
+type SampleDecoder = Box<dyn Iterator<Item = f32> + Send>;
+
+enum FillOutcome {
+    /// The ring buffer has room and more can be read.
+    Progress,
+    /// The ring buffer is full; wait for the voice to consume some.
+    Full,
+    /// The sample ended or the voice was cancelled.
+    Finished,
+}
+
+/// An open sample being streamed into a voice's ring buffer. Dropping it
+/// releases the ring buffer, which tells the voice its loader has finished.
+struct SampleStream {
+    job: SpawnJob,
+    source: SampleSource,
+    input_channels: usize,
+    is_source_finished: bool,
+    chunk_buffer: Vec<f32>,
+    stats: Arc<LoaderStats>,
+}
+
+impl SampleStream {
+    /// Opens the job's sample: from the RAM cache or a mapped file if present,
+    /// otherwise from disk.
+    fn open(job: SpawnJob, stats: Arc<LoaderStats>) -> Result<Self> {
+        let organ = Arc::clone(&job.organ);
+        let path = job
+            .sample
+            .path(&organ, &job.index)
+            .ok_or_else(|| anyhow!("No such sample: {:?}", job.sample))?;
+        let maybe_cached_data = organ
+            .sample_cache
+            .as_ref()
+            .and_then(|c| c.get(path).cloned());
+        let maybe_cached_meta = organ
+            .metadata_cache
+            .as_ref()
+            .and_then(|c| c.get(path).cloned());
+        let maybe_mapped = organ
+            .mapped_samples
+            .as_ref()
+            .and_then(|m| m.get(path).cloned());
+        let frames_to_skip = job.frames_to_skip;
+
+        let (source, input_channels, is_source_finished) =
             if let (Some(cached_samples), Some(cached_metadata)) =
                 (maybe_cached_data, maybe_cached_meta)
             {
                 // Fast Path: Memory Cache
-                samples_in_memory = (*cached_samples).clone();
-                loop_info = if job.is_attack_sample {
+                let loop_info = if job.use_loop {
                     cached_metadata.loop_info
                 } else {
                     None
                 };
-                input_channels = cached_metadata.channel_count as usize;
-                use_memory_reader = true;
-                source_is_finished = false;
+                let input_channels = cached_metadata.channel_count as usize;
+                (
+                    memory_source(
+                        SampleData::Decoded(cached_samples),
+                        input_channels,
+                        loop_info,
+                        frames_to_skip,
+                    ),
+                    input_channels,
+                    false,
+                )
+            } else if let Some(mapped) = maybe_mapped {
+                // Mapped Path: the OS pages the file in as it plays
+                let loop_info = if job.use_loop { mapped.loop_info } else { None };
+                let input_channels = mapped.channel_count as usize;
+                (
+                    memory_source(
+                        SampleData::Mapped(mapped),
+                        input_channels,
+                        loop_info,
+                        frames_to_skip,
+                    ),
+                    input_channels,
+                    false,
+                )
             } else {
-                // Slow Path: Disk I/O
-                let file = File::open(&job.path)?;
+                // Slow Path: Disk I/O, from a WAV or a compressed (FLAC) cache file
+                let file = File::open(path)?;
                 let mut reader = BufReader::new(file);
-                let (fmt, other_chunks, data_start, data_size) =
-                    parse_wav_metadata(&mut reader, &job.path)?;
+                let (fmt, other_chunks, decoder): (_, _, SampleDecoder) =
+                    match parse_wav_metadata(&mut reader, path) {
+                        Ok((fmt, other_chunks, data_start, data_size)) => {
+                            let decoder = WavSampleReader::new(reader, fmt, data_start, data_size)?;
+                            (fmt, other_chunks, Box::new(decoder))
+                        }
+                        Err(e) if e.is::<IsFlacError>() => {
+                            let (fmt, other_chunks) = read_flac_metadata(path)?;
+                            let decoder = FlacSampleReader::open(path)?;
+                            (fmt, other_chunks, Box::new(decoder))
+                        }
+                        Err(e) => return Err(e),
+                    };
 
                 if fmt.sample_rate != job.sample_rate {
                     return Err(anyhow!("Rate mismatch"));
                 }
 
-                let mut loop_info_from_file = None;
-                for chunk in other_chunks {
-                    if &chunk.id == b"smpl" {
-                        loop_info_from_file = parse_smpl_chunk(&chunk.data);
-                        break;
-                    }
-                }
-                loop_info = if job.is_attack_sample {
-                    loop_info_from_file
+                let loop_info = if job.use_loop {
+                    other_chunks
+                        .iter()
+                        .find(|chunk| &chunk.id == b"smpl")
+                        .and_then(|chunk| parse_smpl_chunk(&chunk.data))
                 } else {
                     None
                 };
-                input_channels = fmt.num_channels as usize;
+                let input_channels = fmt.num_channels as usize;
 
-                let decoder = WavSampleReader::new(reader, fmt, data_start, data_size)?;
-
-                if job.is_attack_sample && loop_info.is_some() {
+                if loop_info.is_some() {
                     // Small looping samples must be fully loaded into memory
-                    samples_in_memory = decoder.collect();
-                    use_memory_reader = true;
-                    source_is_finished = false;
+                    let samples = SampleData::Decoded(Arc::new(decoder.collect::<Vec<f32>>()));
+                    (
+                        memory_source(samples, input_channels, loop_info, frames_to_skip),
+                        input_channels,
+                        false,
+                    )
                 } else {
                     // Long one-shot samples are streamed
-                    let mut iterator = Box::new(decoder);
+                    let mut iterator = decoder;
 
                     // Skip frames (e.g. if we had preloaded bytes)
-                    let mut skip_successful = true;
-                    if frames_to_skip > 0 {
-                        let samples_to_skip = frames_to_skip * input_channels;
-                        for _ in 0..samples_to_skip {
-                            if iterator.next().is_none() {
-                                skip_successful = false;
-                                break;
-                            }
-                        }
-                    }
+                    let samples_to_skip = frames_to_skip * input_channels;
+                    let skip_successful =
+                        samples_to_skip == 0 || iterator.nth(samples_to_skip - 1).is_some();
 
-                    source_is_finished = !skip_successful;
-                    source = Some(iterator);
-                    use_memory_reader = false;
+                    (
+                        SampleSource::Disk(iterator),
+                        input_channels,
+                        !skip_successful,
+                    )
                 }
+            };
+
+        stats.streaming_voices.fetch_add(1, Ordering::Relaxed);
+        stats.jobs_started.fetch_add(1, Ordering::Relaxed);
+        Ok(Self {
+            job,
+            source,
+            input_channels: input_channels.max(1),
+            is_source_finished,
+            chunk_buffer: vec![0.0f32; CHUNK_FRAMES * CHANNEL_COUNT],
+            stats,
+        })
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.job.is_cancelled.load(Ordering::Relaxed)
+    }
+
+    fn vacant_frames(&self) -> usize {
+        self.job.producer.vacant_len() / CHANNEL_COUNT
+    }
+
+    /// Pushes up to `max_frames` into the ring buffer without ever waiting for room.
+    fn fill(&mut self, max_frames: usize) -> FillOutcome {
+        let mut frames_written = 0;
+        while frames_written < max_frames {
+            if self.is_cancelled() || self.is_source_finished {
+                return FillOutcome::Finished;
+            }
+            let chunk_frames = self
+                .vacant_frames()
+                .min(CHUNK_FRAMES)
+                .min(max_frames - frames_written);
+            if chunk_frames == 0 {
+                return FillOutcome::Full;
             }
 
-            let is_mono = input_channels == 1;
-            let mut current_frame_index: usize = frames_to_skip;
-            let mut loop_start_frame: usize = 0;
-            let mut loop_end_frame: usize = 0;
-            let mut is_looping_sample = job.is_attack_sample && loop_info.is_some();
-
-            // Validate loop points against loaded data size
-            if use_memory_reader && is_looping_sample {
-                let (start, end) = loop_info.unwrap();
-                loop_start_frame = start as usize;
-                let total_frames = samples_in_memory.len() / input_channels;
-                loop_end_frame = if end == 0 { total_frames } else { end as usize };
-                if loop_start_frame >= loop_end_frame || loop_end_frame > total_frames {
-                    is_looping_sample = false;
-                    current_frame_index = 0;
-                }
+            let frames_read = self.read_chunk(chunk_frames);
+            // Only this stream pushes to the ring buffer, so the chunk always fits
+            self.job
+                .producer
+                .push_slice(&self.chunk_buffer[..frames_read * CHANNEL_COUNT]);
+            frames_written += frames_read;
+
+            if frames_read == 0 {
+                // A loop with no frames would spin forever
+                return FillOutcome::Finished;
             }
+        }
+        if self.is_source_finished {
+            FillOutcome::Finished
+        } else {
+            FillOutcome::Progress
+        }
+    }
 
-            // Streaming Loop
-            'loader_loop: loop {
-                if job.is_cancelled.load(Ordering::Relaxed) {
-                    break 'loader_loop;
-                }
+    /// Decodes up to `frames` stereo frames into the chunk buffer.
+    fn read_chunk(&mut self, frames: usize) -> usize {
+        let is_mono = self.input_channels == 1;
+        let input_channels = self.input_channels;
+        let mut frames_read = 0;
 
-                let frames_to_read = 1024;
-                let mut frames_read = 0;
-
-                if use_memory_reader {
-                    for i in 0..frames_to_read {
-                        if is_looping_sample {
-                            if current_frame_index >= loop_end_frame {
-                                current_frame_index = loop_start_frame;
-                            }
-                        } else {
-                            if current_frame_index >= (samples_in_memory.len() / input_channels) {
-                                source_is_finished = true;
-                                break;
-                            }
+        match &mut self.source {
+            SampleSource::Memory {
+                samples,
+                position_frame,
+                loop_frames,
+            } => {
+                let total_frames = samples.len() / input_channels;
+                for frame in self
+                    .chunk_buffer
+                    .chunks_exact_mut(CHANNEL_COUNT)
+                    .take(frames)
+                {
+                    if let Some((loop_start, loop_end)) = *loop_frames {
+                        if *position_frame >= loop_end {
+                            *position_frame = loop_start;
                         }
-
-                        let sample_l_idx = current_frame_index * input_channels;
-                        // Manual safety checks removed for brevity, but indices are bounded above
-                        let sample_l = samples_in_memory.get(sample_l_idx).cloned().unwrap_or(0.0);
-                        let sample_r = if is_mono {
-                            sample_l
-                        } else {
-                            samples_in_memory
-                                .get(sample_l_idx + 1)
-                                .cloned()
-                                .unwrap_or(0.0)
-                        };
-
-                        interleaved_buffer[i * CHANNEL_COUNT] = sample_l;
-                        interleaved_buffer[i * CHANNEL_COUNT + 1] = sample_r;
-
-                        current_frame_index += 1;
-                        frames_read += 1;
+                    } else if *position_frame >= total_frames {
+                        self.is_source_finished = true;
+                        break;
                     }
-                } else {
-                    if !source_is_finished {
-                        if let Some(ref mut s_iter) = source {
-                            for i in 0..frames_to_read {
-                                if let Some(sample_l) = s_iter.next() {
-                                    let sample_r = if is_mono {
-                                        sample_l
-                                    } else {
-                                        s_iter.next().unwrap_or(0.0)
-                                    };
-                                    interleaved_buffer[i * CHANNEL_COUNT] = sample_l;
-                                    interleaved_buffer[i * CHANNEL_COUNT + 1] = sample_r;
-                                    frames_read += 1;
-                                } else {
-                                    source_is_finished = true;
-                                    break;
-                                }
-                            }
+
+                    let sample_l_idx = *position_frame * input_channels;
+                    let sample_l = samples.get(sample_l_idx).unwrap_or(0.0);
+                    let sample_r = if is_mono {
+                        sample_l
+                    } else {
+                        samples.get(sample_l_idx + 1).unwrap_or(0.0)
+                    };
+                    frame[0] = sample_l;
+                    frame[1] = sample_r;
+
+                    *position_frame += 1;
+                    frames_read += 1;
+                }
+            }
+            SampleSource::Disk(iterator) => {
+                for frame in self
+                    .chunk_buffer
+                    .chunks_exact_mut(CHANNEL_COUNT)
+                    .take(frames)
+                {
+                    let Some(sample_l) = iterator.next() else {
+                        self.is_source_finished = true;
+                        break;
+                    };
+                    let sample_r = if is_mono {
+                        sample_l
+                    } else {
+                        let sample_r = iterator.next().unwrap_or(0.0);
+                        // Skip any channels beyond stereo
+                        for _ in 2..input_channels {
+                            iterator.next();
                         }
-                    }
+                        sample_r
+                    };
+                    frame[0] = sample_l;
+                    frame[1] = sample_r;
+                    frames_read += 1;
+                }
+            }
+        }
+        frames_read
+    }
+}
+
+impl Drop for SampleStream {
+    fn drop(&mut self) {
+        self.stats.streaming_voices.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Builds a memory source, validating the loop points against the data.
+fn memory_source(
+    samples: SampleData,
+    input_channels: usize,
+    loop_info: Option<(u32, u32)>,
+    frames_to_skip: usize,
+) -> SampleSource {
+    let total_frames = samples.len() / input_channels.max(1);
+    let loop_frames = loop_info.and_then(|(start, end)| {
+        let loop_start = start as usize;
+        let loop_end = if end == 0 { total_frames } else { end as usize };
+        (loop_start < loop_end && loop_end <= total_frames).then_some((loop_start, loop_end))
+    });
+    let position_frame = match loop_frames {
+        // A start past the loop lands where the loop would have got to by then
+        Some((loop_start, loop_end)) if frames_to_skip >= loop_end => {
+            loop_start + (frames_to_skip - loop_start) % (loop_end - loop_start)
+        }
+        Some(_) => frames_to_skip,
+        // Invalid loop points: play the sample once from the start
+        None if loop_info.is_some() => 0,
+        None => frames_to_skip,
+    };
+    SampleSource::Memory {
+        samples,
+        position_frame,
+        loop_frames,
+    }
+}
+
+/// Starts the loader: `worker_count` threads that open and stream samples, fed
+/// by a dispatcher that takes new jobs off the engines' queues and wakes parked
+/// streams when their ring buffers have room again.
+pub fn spawn_loader_pool(worker_count: usize) -> LoaderPool {
+    let worker_count = worker_count.max(1);
+    let stats = Arc::new(LoaderStats::default());
+    stats.workers.store(worker_count, Ordering::Relaxed);
+    let queue = Arc::new(TaskQueue {
+        state: Mutex::new(QueueState::default()),
+        available: Condvar::new(),
+        stats: Arc::clone(&stats),
+    });
+    let (queues_tx, queues_rx) = mpsc::channel::<HeapCons<SpawnJob>>();
+    let (parked_tx, parked_rx) = mpsc::channel::<SampleStream>();
+
+    let dispatcher_queue = Arc::clone(&queue);
+    let dispatcher = thread::spawn(move || {
+        log::info!("[LoaderPool] Started with {} workers.", worker_count);
+        run_dispatcher(queues_rx, parked_rx, &dispatcher_queue);
+        dispatcher_queue.shut_down();
+        log::info!("[LoaderPool] Shutting down.");
+    });
+    let dispatcher = dispatcher.thread().clone();
+
+    for worker_index in 0..worker_count {
+        let queue = Arc::clone(&queue);
+        let parked_tx = parked_tx.clone();
+        let dispatcher = dispatcher.clone();
+        let stats = Arc::clone(&stats);
+        let spawn_result = thread::Builder::new()
+            .name(format!("sample-loader-{}", worker_index))
+            .spawn(move || run_worker(queue, parked_tx, dispatcher, stats));
+        if let Err(e) = spawn_result {
+            log::error!("[LoaderPool] Failed to start worker: {}", e);
+        }
+    }
+
+    LoaderPool {
+        jobs: LoaderHandle {
+            queues: queues_tx,
+            dispatcher: LoaderWaker { dispatcher },
+        },
+        stats,
+    }
+}
+
+/// Queues incoming jobs and requeues parked streams once they have room, until
+/// every loader handle and job queue has been dropped. Sleeps between rounds
+/// until a `LoaderWaker` or a worker parking a stream wakes it.
+fn run_dispatcher(
+    queues_rx: mpsc::Receiver<HeapCons<SpawnJob>>,
+    parked_rx: mpsc::Receiver<SampleStream>,
+    queue: &TaskQueue,
+) {
+    let mut job_queues: Vec<HeapCons<SpawnJob>> = Vec::new();
+    let mut parked: Vec<SampleStream> = Vec::new();
+    let mut are_handles_dropped = false;
+    loop {
+        loop {
+            match queues_rx.try_recv() {
+                Ok(jobs) => job_queues.push(jobs),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    are_handles_dropped = true;
+                    break;
                 }
+            }
+        }
 
-                // Push data to the ring buffer
-                if frames_read > 0 {
-                    let samples_to_push = frames_read * CHANNEL_COUNT;
-                    let mut offset = 0;
-                    while offset < samples_to_push {
-                        if job.is_cancelled.load(Ordering::Relaxed) {
-                            break 'loader_loop;
-                        }
+        for jobs in job_queues.iter_mut() {
+            while let Some(job) = jobs.try_pop() {
+                let priority = if job.is_attack {
+                    TaskPriority::AttackStart
+                } else {
+                    TaskPriority::ReleaseStart
+                };
+                queue.push(priority, LoaderTask::Start(job));
+            }
+        }
+        // A queue whose engine has gone is empty now
+        job_queues.retain(|jobs| jobs.write_is_held());
+        if are_handles_dropped && job_queues.is_empty() {
+            return;
+        }
+
+        parked.extend(parked_rx.try_iter());
+        let mut i = 0;
+        while i < parked.len() {
+            if parked[i].is_cancelled() {
+                parked.swap_remove(i);
+            } else if parked[i].vacant_frames() >= REFILL_THRESHOLD_FRAMES {
+                let stream = parked.swap_remove(i);
+                queue.push(TaskPriority::Refill, LoaderTask::Refill(stream));
+            } else {
+                i += 1;
+            }
+        }
+
+        // A wake that came in since this round started returns at once
+        thread::park();
+    }
+}
 
-                        let pushed = job
-                            .producer
-                            .push_slice(&interleaved_buffer[offset..samples_to_push]);
-                        offset += pushed;
+fn run_worker(
+    queue: Arc<TaskQueue>,
+    parked_tx: mpsc::Sender<SampleStream>,
+    dispatcher: Thread,
+    stats: Arc<LoaderStats>,
+) {
+    while let Some(task) = queue.pop() {
+        stats.busy_workers.fetch_add(1, Ordering::Relaxed);
 
-                        // Backpressure: If ring buffer is full, sleep briefly
-                        if offset < samples_to_push {
-                            thread::sleep(Duration::from_millis(1));
+        // Catch panics so a broken sample can't take a worker down with it.
+        // Unwinding drops the job, which marks its voice as finished.
+        let panic_result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut stream = match task {
+                LoaderTask::Start(job) => {
+                    if job.is_cancelled.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let file_name = job
+                        .sample
+                        .path(&job.organ, &job.index)
+                        .and_then(|path| path.file_name())
+                        .unwrap_or_default()
+                        .to_os_string();
+                    match SampleStream::open(job, Arc::clone(&stats)) {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            log::error!("Loader error for {:?}: {}", file_name, e);
+                            return;
                         }
                     }
                 }
+                LoaderTask::Refill(stream) => stream,
+            };
 
-                if source_is_finished && !is_looping_sample {
-                    break 'loader_loop;
+            match stream.fill(MAX_FRAMES_PER_PASS) {
+                FillOutcome::Progress => {
+                    queue.push(TaskPriority::Refill, LoaderTask::Refill(stream));
                 }
-
-                // If looping but we read 0 frames (maybe waiting for something?), prevent cpu spin
-                if is_looping_sample && frames_read == 0 {
-                    thread::sleep(Duration::from_millis(1));
+                FillOutcome::Full => {
+                    // The dispatcher has gone if the pool is shutting down; drop it then.
+                    // Its voice may have made room already, so have it look right away.
+                    let _ = parked_tx.send(stream);
+                    dispatcher.unpark();
                 }
+                FillOutcome::Finished => drop(stream),
             }
-            Ok(())
-        })();
-
-        if let Err(e) = result {
-            log::error!("Loader error: {}", e);
+        }));
+        if let Err(e) = panic_result {
+            log::error!("[LoaderPool] Loader PANICKED: {:?}", e);
         }
-    });
 
-    if let Err(e) = panic_result {
-        log::error!(
-            "[LoaderThread] PANICKED for file {:?}: {:?}",
-            path_str_clone,
-            e
-        );
+        stats.busy_workers.fetch_sub(1, Ordering::Relaxed);
     }
-
-    job.is_finished.store(true, Ordering::SeqCst);
 }
@@ -10,7 +10,8 @@ pub struct AudioRecorder {
 }
 
 impl AudioRecorder {
-    pub fn start(organ_name: String, sample_rate: u32) -> Result<Self> {
+    /// Records interleaved blocks of `channels` channels, one per output channel.
+    pub fn start(organ_name: String, sample_rate: u32, channels: usize) -> Result<Self> {
         let config_path = confy::get_configuration_file_path("rusty-pipes", "settings")?;
         let parent = config_path
             .parent()
@@ -25,7 +26,7 @@ impl AudioRecorder {
         let path = recording_dir.join(filename);
 
         let spec = hound::WavSpec {
-            channels: 2,
+            channels: channels as u16,
             sample_rate,
             bits_per_sample: 32,
             sample_format: hound::SampleFormat::Float,
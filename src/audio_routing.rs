@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::organ::{Rank, Stop};
+
+/// What an output route applies to.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum RouteSource {
+    /// All stops of a division, by its prefix (e.g. "HW", "SW", "P").
+    Division(String),
+    /// All ranks standing on a windchest group, by its ID (e.g. "002").
+    WindchestGroup(String),
+}
+
+/// Sends one division or windchest group to its own pair of output channels.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OutputRoute {
+    pub source: RouteSource,
+    /// Zero-based stereo pair: 0 = channels 1/2, 1 = channels 3/4, and so on.
+    pub output_pair: usize,
+}
+
+/// Output routes resolved for quick lookup when a pipe starts speaking.
+#[derive(Debug, Clone)]
+pub struct OutputRouting {
+    pair_count: usize,
+    divisions: HashMap<String, usize>,
+    windchest_groups: HashMap<String, usize>,
+}
+
+impl Default for OutputRouting {
+    fn default() -> Self {
+        Self {
+            pair_count: 1,
+            divisions: HashMap::new(),
+            windchest_groups: HashMap::new(),
+        }
+    }
+}
+
+/// Windchest group IDs appear both padded ("002") and unpadded ("2").
+pub(crate) fn normalize_group_id(id: &str) -> String {
+    let unpadded = id.trim().trim_start_matches('0');
+    if unpadded.is_empty() { "0" } else { unpadded }.to_string()
+}
+
+impl OutputRouting {
+    /// Resolves the routes for a device with `channel_count` outputs. Routes to a
+    /// pair the device doesn't have are dropped, so those pipes play on the first pair.
+    pub fn new(routes: &[OutputRoute], channel_count: usize) -> Self {
+        let pair_count = (channel_count / 2).max(1);
+        let mut routing = Self {
+            pair_count,
+            ..Self::default()
+        };
+        for route in routes {
+            if route.output_pair >= pair_count {
+                log::warn!(
+                    "[Routing] {:?} goes to channels {}/{}, but only {} channels are open. Using 1/2.",
+                    route.source,
+                    route.output_pair * 2 + 1,
+                    route.output_pair * 2 + 2,
+                    channel_count
+                );
+                continue;
+            }
+            match &route.source {
+                RouteSource::Division(division) => {
+                    routing
+                        .divisions
+                        .insert(division.trim().to_uppercase(), route.output_pair);
+                }
+                RouteSource::WindchestGroup(id) => {
+                    routing
+                        .windchest_groups
+                        .insert(normalize_group_id(id), route.output_pair);
+                }
+            }
+        }
+        routing
+    }
+
+    pub fn pair_count(&self) -> usize {
+        self.pair_count
+    }
+
+    /// The output pair a pipe of `rank`, played through `stop`, sounds on. A
+    /// windchest group route is more specific than a division route and wins.
+    pub fn pair_for(&self, stop: &Stop, rank: &Rank) -> usize {
+        rank.windchest_group_id
+            .as_deref()
+            .and_then(|id| self.windchest_groups.get(&normalize_group_id(id)))
+            .or_else(|| self.divisions.get(&stop.division_id.to_uppercase()))
+            .copied()
+            .unwrap_or(0)
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::{Result, anyhow};
+use clap::Subcommand;
+use rust_i18n::t;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::config::{AppSettings, load_organ_library};
+use crate::memory_plan::format_size;
+use crate::organ_cache::{self, PruneSummary};
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Show the disk space used by each organ's cache, per sample rate, bit depth and format
+    List,
+    /// Check cached samples for damaged headers, truncation and mismatches with their source
+    Verify {
+        /// Organ definition file or library organ name (default: every library organ)
+        organ: Option<String>,
+    },
+    /// List cached samples the current sample rate, 16-bit and format settings no longer use
+    Prune {
+        /// Organ definition file or library organ name (default: every library organ)
+        organ: Option<String>,
+        /// Delete the unused samples instead of only listing what would be deleted
+        #[arg(long)]
+        delete: bool,
+    },
+    /// Delete an organ's cache and convert all its samples again
+    Rebuild {
+        /// Organ definition file or library organ name
+        organ: String,
+    },
+}
+
+/// Runs a `rusty-pipes cache` command. `settings` include the command-line
+/// overrides, so e.g. `--cache-format flac cache rebuild` converts to FLAC.
+pub fn run_cache_command(settings: &AppSettings, action: &CacheAction) -> Result<()> {
+    let library: Vec<PathBuf> = load_organ_library()
+        .map(|lib| lib.organs.into_iter().map(|o| o.path).collect())
+        .unwrap_or_default();
+
+    match action {
+        CacheAction::List => {
+            for usage in organ_cache::list(&library)? {
+                let organ = usage
+                    .organ_file
+                    .as_ref()
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_else(|| t!("main.cache_not_in_library").to_string());
+                println!(
+                    "{}  {}  ({})",
+                    usage.name,
+                    format_size(usage.total_bytes),
+                    organ
+                );
+                for variant in &usage.variants {
+                    println!(
+                        "  {} Hz  {}-bit  {:?}  {} files  {}",
+                        variant.sample_rate,
+                        variant.bits_per_sample,
+                        variant.format,
+                        variant.files,
+                        format_size(variant.bytes)
+                    );
+                }
+                if usage.other_bytes > 0 {
+                    println!(
+                        "  {}  {}",
+                        t!("main.cache_other"),
+                        format_size(usage.other_bytes)
+                    );
+                }
+                if usage.transient_bytes > 0 {
+                    println!(
+                        "  {}  {}",
+                        t!("main.cache_transient"),
+                        format_size(usage.transient_bytes)
+                    );
+                }
+            }
+        }
+        CacheAction::Verify { organ } => {
+            let mut total = 0;
+            for organ_file in select_organs(organ.as_deref(), &library)? {
+                let problems = organ_cache::verify(&organ_file)?;
+                println!(
+                    "{}",
+                    t!(
+                        "main.cache_verified_fmt",
+                        path = organ_file.display(),
+                        count = problems.len()
+                    )
+                );
+                for problem in &problems {
+                    println!("  {}: {}", problem.path.display(), problem.problem);
+                }
+                total += problems.len();
+            }
+            if total > 0 {
+                return Err(anyhow!(t!("main.cache_verify_failed_fmt", count = total)));
+            }
+        }
+        CacheAction::Prune { organ, delete } => {
+            let mut total = PruneSummary::default();
+            for organ_file in select_organs(organ.as_deref(), &library)? {
+                let summary = organ_cache::prune(&organ_file, &settings.cache_settings(), !delete)?;
+                total.files += summary.files;
+                total.bytes += summary.bytes;
+            }
+            let (count, size) = (total.files, format_size(total.bytes));
+            if *delete {
+                println!(
+                    "{}",
+                    t!("main.cache_pruned_fmt", count = count, size = size)
+                );
+            } else {
+                println!(
+                    "{}",
+                    t!("main.cache_prune_dry_run_fmt", count = count, size = size)
+                );
+            }
+        }
+        CacheAction::Rebuild { organ } => {
+            let organ_file = resolve_organ(organ, &library)?;
+            println!(
+                "{}",
+                t!("main.cache_rebuilding_fmt", path = organ_file.display())
+            );
+            let (progress_tx, progress_rx) = mpsc::channel::<(f32, String)>();
+            let printer = thread::spawn(move || {
+                for (progress, message) in progress_rx {
+                    println!("  [{:>3.0}%] {}", progress * 100.0, message);
+                }
+            });
+            let result =
+                organ_cache::rebuild(&organ_file, &settings.cache_settings(), &Some(progress_tx));
+            let _ = printer.join();
+            let removed = result?;
+            println!(
+                "{}",
+                t!(
+                    "main.cache_rebuilt_fmt",
+                    count = removed.files,
+                    size = format_size(removed.bytes)
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The organ given on the command line, or every library organ.
+fn select_organs(organ: Option<&str>, library: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    match organ {
+        Some(organ) => Ok(vec![resolve_organ(organ, library)?]),
+        None => Ok(library.to_vec()),
+    }
+}
+
+/// Accepts a path to an organ definition, or the cache name of a library organ.
+pub fn resolve_organ(organ: &str, library: &[PathBuf]) -> Result<PathBuf> {
+    let path = Path::new(organ);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    library
+        .iter()
+        .find(|p| organ_cache::cache_name(p).is_some_and(|name| name.eq_ignore_ascii_case(organ)))
+        .cloned()
+        .ok_or_else(|| anyhow!(t!("main.cache_unknown_organ_fmt", name = organ)))
+}
@@ -0,0 +1,316 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::wav_converter::{CacheFileName, CacheFormat};
+
+/// File in the organ's cache directory recording what each converted sample was made from.
+pub const CACHE_MANIFEST_FILE: &str = "manifest.json";
+/// Bumped when the layout changes. An older manifest is dropped and the cache
+/// is taken as it is found.
+const CACHE_MANIFEST_VERSION: u32 = 1;
+
+/// Identifies the contents of a source sample. Size and modification time are
+/// compared first and the CRC only when they differ, so a file that was
+/// touched but not changed (e.g. extracted again) isn't converted again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFingerprint {
+    pub size: u64,
+    pub modified_ns: u64,
+    pub crc32: u32,
+}
+
+impl SourceFingerprint {
+    /// Size and modification time, without reading the file.
+    fn stat(path: &Path) -> Result<(u64, u64)> {
+        let metadata = fs::metadata(path)?;
+        let modified_ns = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Ok((metadata.len(), modified_ns))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let (size, modified_ns) = Self::stat(path)?;
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut hasher = crc32fast::Hasher::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+        }
+        Ok(Self {
+            size,
+            modified_ns,
+            crc32: hasher.finalize(),
+        })
+    }
+
+    fn matches_stat(&self, path: &Path) -> bool {
+        Self::stat(path).is_ok_and(|stat| stat == (self.size, self.modified_ns))
+    }
+}
+
+/// One converted sample: its source and the conversion parameters.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    source: String,
+    sample_rate: u32,
+    pitch_tuning_cents: f32,
+    bits_per_sample: u16,
+    format: CacheFormat,
+    is_release: bool,
+}
+
+/// Per-organ record of which source every cached sample was converted from,
+/// and what that source looked like at the time. Lets a load convert again
+/// only the samples whose source has changed since.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheManifest {
+    version: u32,
+    /// Keyed by path relative to the organ's sample directory.
+    sources: HashMap<String, SourceFingerprint>,
+    /// Keyed by path relative to the organ's cache directory.
+    entries: HashMap<String, ManifestEntry>,
+}
+
+impl Default for CacheManifest {
+    fn default() -> Self {
+        Self {
+            version: CACHE_MANIFEST_VERSION,
+            sources: HashMap::new(),
+            entries: HashMap::new(),
+        }
+    }
+}
+
+/// Manifest key of a path below `root`, with forward slashes on every platform.
+fn relative_key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+impl CacheManifest {
+    /// Reads the manifest of an organ cache. Empty if there is none yet, or
+    /// it is unreadable or from another version.
+    pub fn load(cache_dir: &Path) -> Self {
+        let path = cache_dir.join(CACHE_MANIFEST_FILE);
+        let Ok(file) = File::open(&path) else {
+            return Self::default();
+        };
+        match serde_json::from_reader::<_, Self>(BufReader::new(file)) {
+            Ok(manifest) if manifest.version == CACHE_MANIFEST_VERSION => manifest,
+            Ok(_) => {
+                log::info!(
+                    "[Cache] Manifest {:?} is from another version. Starting over.",
+                    path
+                );
+                Self::default()
+            }
+            Err(e) => {
+                log::warn!("[Cache] Ignoring unreadable manifest {:?}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn save(&self, cache_dir: &Path) -> Result<()> {
+        fs::create_dir_all(cache_dir)?;
+        let file = File::create(cache_dir.join(CACHE_MANIFEST_FILE))?;
+        serde_json::to_writer(BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    /// True if the source at `path` has the CRC `crc32`, e.g. that of the
+    /// archive entry it was extracted from. The file is only read if it was
+    /// touched since it was recorded; what is read is recorded, so a later
+    /// `invalidate_changed_sources` sees the file change if it is replaced.
+    pub fn source_matches_crc(&mut self, source_dir: &Path, path: &Path, crc32: u32) -> bool {
+        let Some(key) = relative_key(source_dir, path) else {
+            return false;
+        };
+        let recorded = self.sources.get(&key).copied();
+        if let Some(fingerprint) = recorded.filter(|f| f.matches_stat(path)) {
+            return fingerprint.crc32 == crc32;
+        }
+        match SourceFingerprint::read(path) {
+            Ok(fingerprint) => {
+                self.sources.insert(key, fingerprint);
+                fingerprint.crc32 == crc32
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Deletes the cached samples converted from sources that changed since
+    /// they were recorded, so they are converted again. Sources that are gone
+    /// are left alone. Returns the changed sources.
+    pub fn invalidate_changed_sources(
+        &mut self,
+        source_dir: &Path,
+        cache_dir: &Path,
+    ) -> Vec<String> {
+        let updates: Vec<(String, SourceFingerprint)> = self
+            .sources
+            .par_iter()
+            .filter(|(key, fingerprint)| {
+                let path = source_dir.join(key);
+                path.exists() && !fingerprint.matches_stat(&path)
+            })
+            .filter_map(|(key, _)| {
+                let fingerprint = SourceFingerprint::read(&source_dir.join(key)).ok()?;
+                Some((key.clone(), fingerprint))
+            })
+            .collect();
+
+        let mut changed = Vec::new();
+        for (key, fingerprint) in updates {
+            let previous = self.sources.insert(key.clone(), fingerprint);
+            if previous.is_some_and(|p| p.crc32 == fingerprint.crc32) {
+                continue;
+            }
+            self.entries.retain(|cache_key, entry| {
+                if entry.source != key {
+                    return true;
+                }
+                let _ = fs::remove_file(cache_dir.join(cache_key));
+                false
+            });
+            log::info!("[Cache] Source changed, converting again: {}", key);
+            changed.push(key);
+        }
+        changed.sort();
+        changed
+    }
+
+    /// Records the cached samples an organ plays from and fingerprints their
+    /// sources. Entries of cache files that no longer exist are dropped, and
+    /// so are sources that no longer exist.
+    pub fn record<'a>(
+        &mut self,
+        source_dir: &Path,
+        cache_dir: &Path,
+        cache_files: impl IntoIterator<Item = &'a Path>,
+    ) {
+        let mut sources = SourceIndex::new(source_dir, cache_dir);
+        for cache_file in cache_files {
+            let Some(cache_key) = relative_key(cache_dir, cache_file) else {
+                continue; // Played straight from the source
+            };
+            if self.entries.contains_key(&cache_key) {
+                continue;
+            }
+            let name = cache_file
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(CacheFileName::parse);
+            let Some(name) = name else {
+                continue;
+            };
+            let Some(source_key) = sources
+                .find(cache_file, &name.stem)
+                .and_then(|source| relative_key(source_dir, &source))
+            else {
+                continue;
+            };
+            self.entries.insert(
+                cache_key,
+                ManifestEntry {
+                    source: source_key,
+                    sample_rate: name.sample_rate,
+                    pitch_tuning_cents: name.pitch_tuning_cents,
+                    bits_per_sample: name.bits_per_sample,
+                    format: name.format,
+                    is_release: name.is_release,
+                },
+            );
+        }
+
+        self.entries
+            .retain(|cache_key, _| cache_dir.join(cache_key).exists());
+        self.sources.retain(|key, _| source_dir.join(key).exists());
+        let referenced: HashSet<&String> = self.entries.values().map(|e| &e.source).collect();
+
+        let stale: Vec<&String> = referenced
+            .into_iter()
+            .filter(|key| {
+                self.sources
+                    .get(*key)
+                    .is_none_or(|f| !f.matches_stat(&source_dir.join(key)))
+            })
+            .collect();
+        let fingerprints: Vec<(String, SourceFingerprint)> = stale
+            .par_iter()
+            .filter_map(|key| {
+                let fingerprint = SourceFingerprint::read(&source_dir.join(key)).ok()?;
+                Some(((*key).clone(), fingerprint))
+            })
+            .collect();
+        self.sources.extend(fingerprints);
+    }
+}
+
+/// Finds the source sample a cache file was converted from: the file with the
+/// same stem, in the same directory relative to the organ's sample directory.
+pub struct SourceIndex<'a> {
+    source_dir: &'a Path,
+    cache_dir: &'a Path,
+    directories: HashMap<PathBuf, HashMap<String, PathBuf>>,
+    /// Source directories that could not be listed, so nothing in them is found.
+    unreadable_dirs: Vec<PathBuf>,
+}
+
+impl<'a> SourceIndex<'a> {
+    pub fn new(source_dir: &'a Path, cache_dir: &'a Path) -> Self {
+        Self {
+            source_dir,
+            cache_dir,
+            directories: HashMap::new(),
+            unreadable_dirs: Vec::new(),
+        }
+    }
+
+    /// The source directories looked up so far that could not be listed.
+    pub fn unreadable_dirs(&self) -> &[PathBuf] {
+        &self.unreadable_dirs
+    }
+
+    pub fn find(&mut self, cache_file: &Path, stem: &str) -> Option<PathBuf> {
+        let relative_dir = cache_file
+            .parent()?
+            .strip_prefix(self.cache_dir)
+            .ok()?
+            .to_path_buf();
+        let source_dir = self.source_dir;
+        let unreadable_dirs = &mut self.unreadable_dirs;
+        let stems = self
+            .directories
+            .entry(relative_dir)
+            .or_insert_with_key(|dir| {
+                let Ok(entries) = fs::read_dir(source_dir.join(dir)) else {
+                    unreadable_dirs.push(source_dir.join(dir));
+                    return HashMap::new();
+                };
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file())
+                    .filter_map(|path| {
+                        let stem = path.file_stem()?.to_string_lossy().to_string();
+                        Some((stem, path))
+                    })
+                    .collect()
+            });
+        stems.get(stem).cloned()
+    }
+}
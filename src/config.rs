@@ -9,14 +9,35 @@ use std::sync::{Arc, Mutex};
 use crate::audio::{
     get_audio_device_names, get_default_audio_device_name, get_supported_sample_rates,
 };
+pub use crate::audio_event::AttackSelection;
+pub use crate::audio_interpolation::Interpolation;
+use crate::audio_routing::OutputRoute;
 use crate::input::KeyboardLayout;
+use crate::memory_plan::{MemoryPlanner, PlanInputs, PlanStatus};
+use crate::organ_cache::CacheSettings;
 use crate::voice::MAX_NEW_VOICES_PER_BLOCK;
+pub use crate::wav_converter::CacheFormat;
 
 fn default_max_new_voices_per_block() -> usize {
     MAX_NEW_VOICES_PER_BLOCK
 }
 
-/// Represents a specific MIDI trigger (Note or SysEx)
+fn default_tracker_delay_scale() -> f32 {
+    1.0
+}
+
+fn default_output_channels() -> usize {
+    2
+}
+
+fn default_virtual_midi_input() -> MidiDeviceConfig {
+    MidiDeviceConfig {
+        name: "Rusty Pipes".to_string(),
+        ..Default::default()
+    }
+}
+
+/// Represents a specific MIDI trigger (Note, SysEx, Control Change or Program Change)
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum MidiEventSpec {
     Note {
@@ -25,6 +46,135 @@ pub enum MidiEventSpec {
         is_note_off: bool,
     },
     SysEx(Vec<u8>),
+    /// A controller value within `min_value..=max_value`, e.g. a stop tab
+    /// sending 127 when drawn and 0 when retired.
+    ControlChange {
+        channel: u8,
+        controller: u8,
+        min_value: u8,
+        max_value: u8,
+    },
+    ProgramChange {
+        channel: u8,
+        program: u8, // 0-127
+    },
+}
+
+impl MidiEventSpec {
+    /// An incoming Control Change, as a trigger for exactly its value.
+    pub fn control_change(channel: u8, controller: u8, value: u8) -> Self {
+        MidiEventSpec::ControlChange {
+            channel,
+            controller,
+            min_value: value,
+            max_value: value,
+        }
+    }
+
+    /// Whether the incoming event fires this trigger. A Control Change fires
+    /// for any value in the trigger's range, everything else has to be equal.
+    pub fn matches(&self, incoming: &MidiEventSpec) -> bool {
+        match (self, incoming) {
+            (
+                MidiEventSpec::ControlChange {
+                    channel,
+                    controller,
+                    min_value,
+                    max_value,
+                },
+                MidiEventSpec::ControlChange {
+                    channel: incoming_channel,
+                    controller: incoming_controller,
+                    min_value: value,
+                    ..
+                },
+            ) => {
+                channel == incoming_channel
+                    && controller == incoming_controller
+                    && (*min_value..=*max_value).contains(value)
+            }
+            _ => self == incoming,
+        }
+    }
+
+    /// The event that shows this trigger as lit or unlit on a console: the
+    /// note switched on or off, the controller at its top value or 0, or the
+    /// SysEx itself when lit. Program changes have no lamp state.
+    pub fn lamp(&self, lit: bool) -> Option<MidiEventSpec> {
+        match self {
+            MidiEventSpec::Note { channel, note, .. } => Some(MidiEventSpec::Note {
+                channel: *channel,
+                note: *note,
+                is_note_off: !lit,
+            }),
+            MidiEventSpec::ControlChange {
+                channel,
+                controller,
+                max_value,
+                ..
+            } => Some(MidiEventSpec::control_change(
+                *channel,
+                *controller,
+                if lit { *max_value } else { 0 },
+            )),
+            MidiEventSpec::SysEx(_) => lit.then(|| self.clone()),
+            MidiEventSpec::ProgramChange { .. } => None,
+        }
+    }
+
+    /// Parses a raw Note, Control Change, Program Change or SysEx message.
+    pub fn from_midi_bytes(bytes: &[u8]) -> Option<Self> {
+        let status = *bytes.first()?;
+        let channel = status & 0x0F;
+        match status & 0xF0 {
+            0x80 | 0x90 if bytes.len() >= 3 => Some(MidiEventSpec::Note {
+                channel,
+                note: bytes[1] & 0x7F,
+                is_note_off: status & 0xF0 == 0x80 || bytes[2] == 0,
+            }),
+            0xB0 if bytes.len() >= 3 => Some(MidiEventSpec::control_change(
+                channel,
+                bytes[1] & 0x7F,
+                bytes[2] & 0x7F,
+            )),
+            0xC0 if bytes.len() >= 2 => Some(MidiEventSpec::ProgramChange {
+                channel,
+                program: bytes[1] & 0x7F,
+            }),
+            0xF0 if status == 0xF0 && bytes.last() == Some(&0xF7) => {
+                Some(MidiEventSpec::SysEx(bytes.to_vec()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Raw bytes to send this event to a MIDI output. Control changes send
+    /// the top of their range.
+    pub fn to_midi_bytes(&self) -> Vec<u8> {
+        match self {
+            MidiEventSpec::Note {
+                channel,
+                note,
+                is_note_off,
+            } => {
+                if *is_note_off {
+                    vec![0x80 | (channel & 0x0F), note & 0x7F, 0]
+                } else {
+                    vec![0x90 | (channel & 0x0F), note & 0x7F, 127]
+                }
+            }
+            MidiEventSpec::SysEx(bytes) => bytes.clone(),
+            MidiEventSpec::ControlChange {
+                channel,
+                controller,
+                max_value,
+                ..
+            } => vec![0xB0 | (channel & 0x0F), controller & 0x7F, max_value & 0x7F],
+            MidiEventSpec::ProgramChange { channel, program } => {
+                vec![0xC0 | (channel & 0x0F), program & 0x7F]
+            }
+        }
+    }
 }
 
 impl fmt::Display for MidiEventSpec {
@@ -47,6 +197,28 @@ impl fmt::Display for MidiEventSpec {
                     write!(f, "SysEx: {}", hex.join(" "))
                 }
             }
+            MidiEventSpec::ControlChange {
+                channel,
+                controller,
+                min_value,
+                max_value,
+            } => {
+                if min_value == max_value {
+                    write!(f, "Ch{} CC {} = {}", channel + 1, controller, min_value)
+                } else {
+                    write!(
+                        f,
+                        "Ch{} CC {} = {}-{}",
+                        channel + 1,
+                        controller,
+                        min_value,
+                        max_value
+                    )
+                }
+            }
+            MidiEventSpec::ProgramChange { channel, program } => {
+                write!(f, "Ch{} Program {}", channel + 1, *program as u16 + 1)
+            }
         }
     }
 }
@@ -68,6 +240,73 @@ pub struct MidiDeviceConfig {
     pub simple_target_channel: u8,
     /// Used if mode is Complex: Index = Input Channel, Value = Target Channel
     pub complex_mapping: [u8; 16],
+    /// Note ranges played on their own channel, e.g. the bass of one long
+    /// keyboard as pedal. Notes outside all zones use the channel mapping.
+    #[serde(default)]
+    pub zones: Vec<KeyboardZone>,
+}
+
+impl MidiDeviceConfig {
+    /// The first zone containing the note, if any.
+    pub fn zone_for(&self, note: u8) -> Option<&KeyboardZone> {
+        self.zones.iter().find(|z| z.contains(note))
+    }
+}
+
+/// A split of a device's keyboard, routed to an internal channel
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct KeyboardZone {
+    /// Lowest MIDI note of the zone (inclusive)
+    pub low_note: u8,
+    /// Highest MIDI note of the zone (inclusive)
+    pub high_note: u8,
+    /// Internal channel (0-15) the zone plays
+    pub target_channel: u8,
+    /// Octaves added to the notes of the zone, e.g. -1 to play the pedal an octave down
+    #[serde(default)]
+    pub octave_shift: i8,
+}
+
+impl Default for KeyboardZone {
+    fn default() -> Self {
+        // The lowest two and a half octaves of an 88-key keyboard, as pedal on channel 2
+        Self {
+            low_note: 21,
+            high_note: 52,
+            target_channel: 1,
+            octave_shift: 0,
+        }
+    }
+}
+
+impl KeyboardZone {
+    pub fn contains(&self, note: u8) -> bool {
+        (self.low_note..=self.high_note).contains(&note)
+    }
+
+    /// The note after the octave shift, or None if it leaves the MIDI range.
+    pub fn transpose(&self, note: u8) -> Option<u8> {
+        u8::try_from(note as i32 + self.octave_shift as i32 * 12)
+            .ok()
+            .filter(|n| *n <= 127)
+    }
+
+    /// Checks note range, channel and shift, describing the first problem.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.low_note > self.high_note || self.high_note > 127 {
+            return Err(format!(
+                "Invalid note range {}-{}",
+                self.low_note, self.high_note
+            ));
+        }
+        if self.target_channel > 15 {
+            return Err("target_channel must be 0..=15".to_string());
+        }
+        if !(-4..=4).contains(&self.octave_shift) {
+            return Err("octave_shift must be -4..=4".to_string());
+        }
+        Ok(())
+    }
 }
 
 impl Default for MidiDeviceConfig {
@@ -79,6 +318,7 @@ impl Default for MidiDeviceConfig {
             simple_target_channel: 0,
             // Default 1:1 mapping (0->0, 1->1, etc.)
             complex_mapping: std::array::from_fn(|i| i as u8),
+            zones: Vec::new(),
         }
     }
 }
@@ -92,7 +332,13 @@ pub struct AppSettings {
     pub audio_buffer_frames: usize,
     pub max_ram_gb: f32,
     pub precache: bool,
+    /// Play float32 cache files from memory-mapped pages instead of preloading transients.
+    #[serde(default)]
+    pub mmap_samples: bool,
     pub convert_to_16bit: bool,
+    /// Format of the resampled samples written to the organ cache.
+    #[serde(default)]
+    pub cache_format: CacheFormat,
     pub original_tuning: bool,
     pub tui_mode: bool,
     pub gain: f32,
@@ -101,11 +347,30 @@ pub struct AppSettings {
     /// load from large simultaneous note-on bursts (e.g. dense MIDI playback).
     #[serde(default = "default_max_new_voices_per_block")]
     pub max_new_voices_per_block: usize,
+    /// Policy for pipes that have several alternative attack samples.
+    #[serde(default)]
+    pub attack_selection: AttackSelection,
+    /// Resampling used when pipes play off their recorded pitch.
+    #[serde(default)]
+    pub interpolation: Interpolation,
+    /// Scales the ranks' tracker delay: 1.0 is authentic, 0.0 disables it.
+    #[serde(default = "default_tracker_delay_scale")]
+    pub tracker_delay_scale: f32,
+    /// Number of output channels to open on the audio device.
+    #[serde(default = "default_output_channels")]
+    pub output_channels: usize,
+    /// Divisions and windchest groups sent to other channel pairs than 1/2.
+    #[serde(default)]
+    pub output_routes: Vec<OutputRoute>,
     pub audio_device_name: Option<String>,
     pub sample_rate: u32,
     pub keyboard_layout: KeyboardLayout,
     #[serde(default)]
     pub midi_devices: Vec<MidiDeviceConfig>,
+    /// Virtual MIDI input port other programs can send to (ALSA, JACK and
+    /// CoreMIDI only). Its name is the port name.
+    #[serde(default = "default_virtual_midi_input")]
+    pub virtual_midi_input: MidiDeviceConfig,
     #[serde(default)]
     pub lcd_displays: Vec<LcdDisplayConfig>,
     /// Last-used UI locale (e.g. `"de"`, `"en"`, `"zh-CN"`). When unset
@@ -212,22 +477,53 @@ impl Default for AppSettings {
             audio_buffer_frames: 256,
             max_ram_gb: 8.0,
             precache: false,
+            mmap_samples: false,
             convert_to_16bit: false,
+            cache_format: CacheFormat::default(),
             original_tuning: false,
             tui_mode: false, // Default to GUI
             gain: 0.4,       // Conservative default gain
             polyphony: 128,
             max_new_voices_per_block: default_max_new_voices_per_block(),
+            attack_selection: AttackSelection::default(),
+            interpolation: Interpolation::default(),
+            tracker_delay_scale: default_tracker_delay_scale(),
+            output_channels: default_output_channels(),
+            output_routes: Vec::new(),
             audio_device_name: None,
             sample_rate: 48000,
             keyboard_layout: KeyboardLayout::Qwerty,
             midi_devices: Vec::new(),
+            virtual_midi_input: default_virtual_midi_input(),
             lcd_displays: Vec::new(),
             locale: None,
         }
     }
 }
 
+impl AppSettings {
+    /// The settings that decide which variant of an organ's cache is played.
+    pub fn cache_settings(&self) -> CacheSettings {
+        CacheSettings {
+            sample_rate: self.sample_rate,
+            convert_to_16_bit: self.convert_to_16bit,
+            cache_format: self.cache_format,
+            original_tuning: self.original_tuning,
+        }
+    }
+
+    /// What the memory estimate of the selected organ depends on.
+    pub fn plan_inputs(&self) -> Option<PlanInputs> {
+        PlanInputs::for_organ(
+            self.organ_file.as_deref(),
+            self.convert_to_16bit,
+            self.original_tuning,
+            self.sample_rate,
+            (self.max_ram_gb * 1024.0) as usize,
+        )
+    }
+}
+
 /// A complete configuration passed from the config UI to the main app.
 /// This is *not* saved to disk.
 #[derive(Clone)]
@@ -239,17 +535,26 @@ pub struct RuntimeConfig {
     pub audio_buffer_frames: usize,
     pub max_ram_gb: f32,
     pub precache: bool,
+    pub mmap_samples: bool,
     pub convert_to_16bit: bool,
+    pub cache_format: CacheFormat,
     pub original_tuning: bool,
     pub gain: f32,
     pub polyphony: usize,
     pub max_new_voices_per_block: usize,
+    pub attack_selection: AttackSelection,
+    pub interpolation: Interpolation,
+    pub tracker_delay_scale: f32,
+    pub output_channels: usize,
+    pub output_routes: Vec<OutputRoute>,
 
     // --- Runtime-Only Settings ---
     pub midi_file: Option<PathBuf>,
     pub audio_device_name: Option<String>,
     pub sample_rate: u32,
     pub active_midi_devices: Vec<(MidiInputPort, MidiDeviceConfig)>,
+    /// Created as a virtual port when enabled.
+    pub virtual_midi_input: MidiDeviceConfig,
     pub lcd_displays: Vec<LcdDisplayConfig>,
 }
 
@@ -354,6 +659,9 @@ pub struct ConfigState {
     pub selected_audio_device_name: Option<String>,
     pub available_sample_rates: Vec<u32>,
     pub available_ir_files: Vec<(String, PathBuf)>,
+
+    /// Estimates memory use of the selected organ in the background.
+    pub memory_planner: MemoryPlanner,
 }
 
 /// State shared between the local configuration UI (GUI/TUI) and the web
@@ -433,9 +741,17 @@ impl ConfigState {
             selected_audio_device_name: self.selected_audio_device_name.clone(),
             available_sample_rates: self.available_sample_rates.clone(),
             available_ir_files: self.available_ir_files.clone(),
+            memory_planner: self.memory_planner.clone(),
         }
     }
 
+    /// The memory estimate for the current settings. Starts a new one in
+    /// the background when they changed.
+    pub fn memory_plan(&self) -> PlanStatus {
+        self.memory_planner.update(self.settings.plan_inputs());
+        self.memory_planner.status()
+    }
+
     pub fn new(
         mut settings: AppSettings,
         midi_input_arc: &Arc<Mutex<Option<MidiInput>>>,
@@ -524,6 +840,7 @@ impl ConfigState {
             selected_audio_device_name,
             available_sample_rates,
             available_ir_files,
+            memory_planner: MemoryPlanner::default(),
         })
     }
 }
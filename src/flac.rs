@@ -0,0 +1,678 @@
+use anyhow::{Result, anyhow};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::Decoder;
+use symphonia::core::formats::FormatReader;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::probe::Hint;
+
+use crate::wav::{OtherChunk, WavFmt};
+
+/// Samples per channel in each FLAC frame.
+const FLAC_BLOCK_SIZE: usize = 4096;
+const FLAC_MAX_FIXED_ORDER: usize = 4;
+/// Largest Rice parameter in the 4-bit coding method (15 is the escape code).
+const FLAC_MAX_RICE_PARAM: u32 = 14;
+/// Metadata block types used here.
+const FLAC_BLOCK_STREAMINFO: u8 = 0;
+const FLAC_BLOCK_APPLICATION: u8 = 2;
+/// Application ID the reference encoder stores foreign RIFF chunks under
+/// (`flac --keep-foreign-metadata`). Used for the `smpl` and `cue ` chunks.
+const FLAC_RIFF_APPLICATION_ID: &[u8; 4] = b"riff";
+
+/// MSB-first bit packer used to assemble FLAC frames in memory.
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    nbits: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::with_capacity(FLAC_BLOCK_SIZE * 8),
+            acc: 0,
+            nbits: 0,
+        }
+    }
+
+    /// Writes the lowest `bits` bits of `value` (at most 32).
+    fn write(&mut self, value: u64, bits: u32) {
+        if bits == 0 {
+            return;
+        }
+        self.acc = (self.acc << bits) | (value & ((1u64 << bits) - 1));
+        self.nbits += bits;
+        while self.nbits >= 8 {
+            self.nbits -= 8;
+            self.bytes.push((self.acc >> self.nbits) as u8);
+        }
+    }
+
+    fn write_signed(&mut self, value: i64, bits: u32) {
+        self.write(value as u64, bits);
+    }
+
+    fn write_unary(&mut self, mut zeros: u64) {
+        while zeros >= 32 {
+            self.write(0, 32);
+            zeros -= 32;
+        }
+        self.write(1, zeros as u32 + 1);
+    }
+
+    /// FLAC's UTF-8-like variable length integer, used for the frame number.
+    fn write_utf8(&mut self, value: u64) {
+        if value < 0x80 {
+            self.write(value, 8);
+            return;
+        }
+        let mut len = 2;
+        while len < 7 && value >= 1 << (5 * len + 1) {
+            len += 1;
+        }
+        let lead = (0xFF00u32 >> len) as u64 & 0xFF;
+        self.write(lead | (value >> (6 * (len - 1))), 8);
+        for i in (0..len - 1).rev() {
+            self.write(0x80 | ((value >> (6 * i)) & 0x3F), 8);
+        }
+    }
+
+    fn align(&mut self) {
+        if self.nbits > 0 {
+            self.write(0, 8 - self.nbits);
+        }
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Residuals of FLAC's fixed polynomial predictor of the given order.
+fn fixed_residuals(samples: &[i32], order: usize) -> Vec<i64> {
+    (order..samples.len())
+        .map(|i| {
+            let x = |back: usize| samples[i - back] as i64;
+            match order {
+                0 => x(0),
+                1 => x(0) - x(1),
+                2 => x(0) - 2 * x(1) + x(2),
+                3 => x(0) - 3 * x(1) + 3 * x(2) - x(3),
+                _ => x(0) - 4 * x(1) + 6 * x(2) - 4 * x(3) + x(4),
+            }
+        })
+        .collect()
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Picks the Rice parameter with the smallest encoded size. Returns (param, bits).
+fn best_rice_param(residuals: &[i64]) -> (u32, u64) {
+    (0..=FLAC_MAX_RICE_PARAM)
+        .map(|k| {
+            let bits = residuals
+                .iter()
+                .map(|&r| (zigzag(r) >> k) + 1 + k as u64)
+                .sum::<u64>();
+            (k, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .unwrap_or((0, 0))
+}
+
+/// Streams interleaved audio into a FLAC file (16 or 24-bit, fixed predictors).
+pub struct FlacWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    channels: usize,
+    bits_per_sample: u32,
+    /// Whether RIFF chunks follow STREAMINFO, so it is not the last metadata block
+    has_riff_chunks: bool,
+    block: Vec<Vec<i32>>,
+    frame_number: u64,
+    total_samples: u64,
+    min_frame_size: u32,
+    max_frame_size: u32,
+}
+
+impl FlacWriter {
+    /// A 24-bit file for float audio, as written by the renderer.
+    pub fn create(path: &Path, sample_rate: u32, channels: usize) -> Result<Self> {
+        Self::create_pcm(path, sample_rate, channels, 24, &[])
+    }
+
+    /// A file of `bits_per_sample` (16 or 24) integer samples. `riff_chunks`
+    /// (e.g. `smpl` and `cue `) are kept in APPLICATION blocks, the way the
+    /// reference encoder keeps foreign metadata, and read back by `read_flac_metadata`.
+    pub fn create_pcm(
+        path: &Path,
+        sample_rate: u32,
+        channels: usize,
+        bits_per_sample: u32,
+        riff_chunks: &[OtherChunk],
+    ) -> Result<Self> {
+        if channels == 0 || channels > 8 {
+            return Err(anyhow!("FLAC supports 1-8 channels, got {}", channels));
+        }
+        if bits_per_sample != 16 && bits_per_sample != 24 {
+            return Err(anyhow!(
+                "Only 16 and 24-bit FLAC is written, got {}",
+                bits_per_sample
+            ));
+        }
+        let mut writer = Self {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate,
+            channels,
+            bits_per_sample,
+            has_riff_chunks: !riff_chunks.is_empty(),
+            block: vec![Vec::with_capacity(FLAC_BLOCK_SIZE); channels],
+            frame_number: 0,
+            total_samples: 0,
+            min_frame_size: u32::MAX,
+            max_frame_size: 0,
+        };
+        writer.file.write_all(b"fLaC")?;
+        // Placeholder STREAMINFO, rewritten with the real totals in `finalize`
+        writer.write_stream_info()?;
+        for (i, chunk) in riff_chunks.iter().enumerate() {
+            writer.write_riff_chunk(chunk, i + 1 == riff_chunks.len())?;
+        }
+        Ok(writer)
+    }
+
+    /// Appends interleaved samples in the -1.0..1.0 range.
+    pub fn write_interleaved(&mut self, samples: &[f32]) -> Result<()> {
+        let scale = ((1u32 << (self.bits_per_sample - 1)) - 1) as f32;
+        for frame in samples.chunks_exact(self.channels) {
+            for (ch, &s) in frame.iter().enumerate() {
+                self.block[ch].push((s.clamp(-1.0, 1.0) * scale).round() as i32);
+            }
+            if self.block[0].len() == FLAC_BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends interleaved integer samples, already at `bits_per_sample`.
+    pub fn write_interleaved_pcm(&mut self, samples: &[i32]) -> Result<()> {
+        for frame in samples.chunks_exact(self.channels) {
+            for (ch, &s) in frame.iter().enumerate() {
+                self.block[ch].push(s);
+            }
+            if self.block[0].len() == FLAC_BLOCK_SIZE {
+                self.write_frame()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flushes the last partial frame and fills in the stream totals.
+    pub fn finalize(mut self) -> Result<()> {
+        if !self.block[0].is_empty() {
+            self.write_frame()?;
+        }
+        self.file.seek(SeekFrom::Start(4))?;
+        self.write_stream_info()?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn write_stream_info(&mut self) -> Result<()> {
+        let mut bw = BitWriter::new();
+        // Metadata block header: last-block flag, type 0 (STREAMINFO), 34 bytes
+        bw.write(u64::from(!self.has_riff_chunks), 1);
+        bw.write(FLAC_BLOCK_STREAMINFO as u64, 7);
+        bw.write(34, 24);
+        bw.write(FLAC_BLOCK_SIZE as u64, 16);
+        bw.write(FLAC_BLOCK_SIZE as u64, 16);
+        let (min_frame, max_frame) = if self.max_frame_size > 0 {
+            (self.min_frame_size, self.max_frame_size)
+        } else {
+            (0, 0)
+        };
+        bw.write(min_frame as u64, 24);
+        bw.write(max_frame as u64, 24);
+        bw.write(self.sample_rate as u64, 20);
+        bw.write(self.channels as u64 - 1, 3);
+        bw.write(self.bits_per_sample as u64 - 1, 5);
+        bw.write(self.total_samples >> 32, 4);
+        bw.write(self.total_samples & 0xFFFF_FFFF, 32);
+        // MD5 signature left unset (all zeroes means "unknown")
+        for _ in 0..4 {
+            bw.write(0, 32);
+        }
+        self.file.write_all(&bw.bytes)?;
+        Ok(())
+    }
+
+    /// An APPLICATION block holding one RIFF chunk: id, little-endian size, data.
+    fn write_riff_chunk(&mut self, chunk: &OtherChunk, is_last: bool) -> Result<()> {
+        let padding = chunk.data.len() % 2;
+        let length = 4 + 8 + chunk.data.len() + padding;
+        let mut bw = BitWriter::new();
+        bw.write(u64::from(is_last), 1);
+        bw.write(FLAC_BLOCK_APPLICATION as u64, 7);
+        bw.write(length as u64, 24);
+        self.file.write_all(&bw.bytes)?;
+        self.file.write_all(FLAC_RIFF_APPLICATION_ID)?;
+        self.file.write_all(&chunk.id)?;
+        self.file
+            .write_all(&(chunk.data.len() as u32).to_le_bytes())?;
+        self.file.write_all(&chunk.data)?;
+        if padding != 0 {
+            self.file.write_all(&[0])?;
+        }
+        Ok(())
+    }
+
+    fn write_frame(&mut self) -> Result<()> {
+        let block_size = self.block[0].len();
+        let mut bw = BitWriter::new();
+
+        // Frame header
+        bw.write(0b11111111111110, 14);
+        bw.write(0, 1);
+        bw.write(0, 1); // Fixed block size stream
+        let block_size_code = if block_size == FLAC_BLOCK_SIZE {
+            0b1100
+        } else {
+            0b0111
+        };
+        bw.write(block_size_code, 4);
+        bw.write(0b0000, 4); // Sample rate from STREAMINFO
+        bw.write(self.channels as u64 - 1, 4); // Independent channels
+        bw.write(
+            if self.bits_per_sample == 16 {
+                0b100
+            } else {
+                0b110
+            },
+            3,
+        );
+        bw.write(0, 1);
+        bw.write_utf8(self.frame_number);
+        if block_size_code == 0b0111 {
+            bw.write(block_size as u64 - 1, 16);
+        }
+        let header_crc = crc8(&bw.bytes);
+        bw.write(header_crc as u64, 8);
+
+        for ch in 0..self.channels {
+            let samples = std::mem::take(&mut self.block[ch]);
+            self.write_subframe(&mut bw, &samples);
+            self.block[ch] = samples;
+            self.block[ch].clear();
+        }
+
+        bw.align();
+        let frame_crc = crc16(&bw.bytes);
+        bw.write(frame_crc as u64, 16);
+
+        self.file.write_all(&bw.bytes)?;
+        let frame_size = bw.bytes.len() as u32;
+        self.min_frame_size = self.min_frame_size.min(frame_size);
+        self.max_frame_size = self.max_frame_size.max(frame_size);
+        self.frame_number += 1;
+        self.total_samples += block_size as u64;
+        Ok(())
+    }
+
+    fn write_subframe(&self, bw: &mut BitWriter, samples: &[i32]) {
+        let bps = self.bits_per_sample;
+
+        // Subframe header: zero pad bit, 6-bit type, no wasted bits
+        if samples.iter().all(|&s| s == samples[0]) {
+            bw.write(0, 1);
+            bw.write(0b000000, 6);
+            bw.write(0, 1);
+            bw.write_signed(samples[0] as i64, bps);
+            return;
+        }
+
+        let max_order = FLAC_MAX_FIXED_ORDER.min(samples.len() - 1);
+        let (order, residuals) = (0..=max_order)
+            .map(|order| (order, fixed_residuals(samples, order)))
+            .min_by_key(|(_, residuals)| residuals.iter().map(|r| r.unsigned_abs()).sum::<u64>())
+            .unwrap();
+        let (rice_param, residual_bits) = best_rice_param(&residuals);
+        let fixed_bits = order as u64 * bps as u64 + 10 + residual_bits;
+        let verbatim_bits = samples.len() as u64 * bps as u64;
+
+        if fixed_bits >= verbatim_bits {
+            bw.write(0, 1);
+            bw.write(0b000001, 6);
+            bw.write(0, 1);
+            for &s in samples {
+                bw.write_signed(s as i64, bps);
+            }
+            return;
+        }
+
+        bw.write(0, 1);
+        bw.write(0b001000 | order as u64, 6);
+        bw.write(0, 1);
+        for &s in &samples[..order] {
+            bw.write_signed(s as i64, bps);
+        }
+        // Rice coding with 4-bit parameters, a single partition
+        bw.write(0b00, 2);
+        bw.write(0, 4);
+        bw.write(rice_param as u64, 4);
+        for &r in &residuals {
+            let u = zigzag(r);
+            bw.write_unary(u >> rice_param);
+            bw.write(u, rice_param);
+        }
+    }
+}
+
+/// Reads the format and the RIFF chunks kept by `FlacWriter::create_pcm` from
+/// the metadata blocks of a FLAC file.
+pub fn read_flac_metadata(path: &Path) -> Result<(WavFmt, Vec<OtherChunk>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut marker = [0; 4];
+    reader.read_exact(&mut marker)?;
+    if &marker != b"fLaC" {
+        return Err(anyhow!("Not a FLAC file: {:?}", path));
+    }
+
+    let mut format = None;
+    let mut riff_chunks = Vec::new();
+    loop {
+        let header = reader.read_u32::<BigEndian>()?;
+        let is_last = header >> 31 == 1;
+        let block_type = ((header >> 24) & 0x7F) as u8;
+        let length = (header & 0xFF_FFFF) as usize;
+        match block_type {
+            FLAC_BLOCK_STREAMINFO | FLAC_BLOCK_APPLICATION => {
+                let mut data = vec![0; length];
+                reader.read_exact(&mut data)?;
+                if block_type == FLAC_BLOCK_STREAMINFO {
+                    // 16+16 block sizes, 24+24 frame sizes, then rate, channels and depth
+                    let packed = Cursor::new(&data[10..18]).read_u64::<BigEndian>()?;
+                    format = Some(WavFmt {
+                        audio_format: 1,
+                        num_channels: ((packed >> 41) & 0x7) as u16 + 1,
+                        sample_rate: (packed >> 44) as u32,
+                        bits_per_sample: ((packed >> 36) & 0x1F) as u16 + 1,
+                    });
+                } else if data.len() >= 12 && &data[..4] == FLAC_RIFF_APPLICATION_ID {
+                    let mut cursor = Cursor::new(&data[4..]);
+                    let mut id = [0; 4];
+                    cursor.read_exact(&mut id)?;
+                    let size = cursor.read_u32::<LittleEndian>()? as usize;
+                    let chunk_data = data[12..].get(..size).ok_or_else(|| {
+                        anyhow!("Truncated RIFF chunk in FLAC metadata: {:?}", path)
+                    })?;
+                    riff_chunks.push(OtherChunk {
+                        id,
+                        data: chunk_data.to_vec(),
+                    });
+                }
+            }
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64))?;
+            }
+        }
+        if is_last {
+            break;
+        }
+    }
+
+    let format = format.ok_or_else(|| anyhow!("FLAC file has no STREAMINFO: {:?}", path))?;
+    Ok((format, riff_chunks))
+}
+
+/// Reads the frame count from STREAMINFO, which must be the first metadata
+/// block. `FlacWriter` only fills it in on `finalize`, so an interrupted
+/// write reports zero.
+pub fn read_flac_frame_count(path: &Path) -> Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut marker = [0; 4];
+    reader.read_exact(&mut marker)?;
+    let header = reader.read_u32::<BigEndian>()?;
+    if &marker != b"fLaC" || ((header >> 24) & 0x7F) as u8 != FLAC_BLOCK_STREAMINFO {
+        return Err(anyhow!("Not a FLAC file: {:?}", path));
+    }
+    let mut data = [0; 18];
+    reader.read_exact(&mut data)?;
+    let packed = Cursor::new(&data[10..18]).read_u64::<BigEndian>()?;
+    Ok(packed & 0xF_FFFF_FFFF)
+}
+
+/// Decodes a FLAC file a packet at a time, yielding interleaved samples in the
+/// same -1.0..1.0 scale as `WavSampleReader`.
+pub struct FlacSampleReader {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    buffer: Option<SampleBuffer<f32>>,
+    position: usize,
+}
+
+impl FlacSampleReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &Default::default(),
+            &Default::default(),
+        )?;
+        let format = probed.format;
+        let track = format
+            .default_track()
+            .ok_or_else(|| anyhow!("No track in FLAC file {:?}", path))?;
+        let track_id = track.id;
+        let decoder =
+            symphonia::default::get_codecs().make(&track.codec_params, &Default::default())?;
+        Ok(Self {
+            format,
+            decoder,
+            track_id,
+            buffer: None,
+            position: 0,
+        })
+    }
+
+    /// Decodes the next packet into the buffer. False at the end of the stream.
+    fn decode_next(&mut self) -> bool {
+        loop {
+            let Ok(packet) = self.format.next_packet() else {
+                return false;
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            let decoded = match self.decoder.decode(&packet) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    log::warn!("FLAC decode error: {}", e);
+                    return false;
+                }
+            };
+            let needed = decoded.capacity() * decoded.spec().channels.count();
+            let buffer = match &mut self.buffer {
+                Some(buffer) if buffer.capacity() >= needed => buffer,
+                buffer => buffer.insert(SampleBuffer::new(
+                    decoded.capacity() as u64,
+                    *decoded.spec(),
+                )),
+            };
+            buffer.copy_interleaved_ref(decoded);
+            self.position = 0;
+            if !buffer.samples().is_empty() {
+                return true;
+            }
+        }
+    }
+}
+
+impl Iterator for FlacSampleReader {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let is_buffer_empty = self
+            .buffer
+            .as_ref()
+            .is_none_or(|buffer| self.position >= buffer.samples().len());
+        if is_buffer_empty && !self.decode_next() {
+            return None;
+        }
+        let sample = self.buffer.as_ref()?.samples()[self.position];
+        self.position += 1;
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A file in the temp directory, removed again when dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!(
+                "rusty-pipes-{}-{}.flac",
+                std::process::id(),
+                name
+            )))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    /// Interleaved test audio: a constant first block, then a noisy ramp that
+    /// hits both ends of the range, ending in a partial block.
+    fn test_samples(bits_per_sample: u32, channels: usize) -> Vec<i32> {
+        let max = (1i32 << (bits_per_sample - 1)) - 1;
+        let min = -max - 1;
+        let frames = FLAC_BLOCK_SIZE * 2 + 1234;
+        let mut seed = 0x1234_5678u32;
+        let mut samples = Vec::with_capacity(frames * channels);
+        for i in 0..frames {
+            for ch in 0..channels {
+                let sample = if i < FLAC_BLOCK_SIZE {
+                    -1000 * (ch as i32 + 1)
+                } else if i % 997 == 0 {
+                    if ch == 0 { max } else { min }
+                } else {
+                    seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    let noise = (seed >> 16) as i32 - 0x8000;
+                    let ramp = ((i * 37) % (max as usize / 2)) as i32;
+                    (ramp + noise).clamp(min, max)
+                };
+                samples.push(sample);
+            }
+        }
+        samples
+    }
+
+    fn smpl_chunk() -> OtherChunk {
+        let mut data = vec![0; 36];
+        data[28..32].copy_from_slice(&1u32.to_le_bytes()); // One loop
+        for value in [0u32, 0, 1000, 9000, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        OtherChunk { id: *b"smpl", data }
+    }
+
+    fn round_trip(name: &str, bits_per_sample: u32, channels: usize, chunks: &[OtherChunk]) {
+        let file = TempFile::new(name);
+        let samples = test_samples(bits_per_sample, channels);
+        let mut writer =
+            FlacWriter::create_pcm(&file.0, 44100, channels, bits_per_sample, chunks).unwrap();
+        writer.write_interleaved_pcm(&samples).unwrap();
+        writer.finalize().unwrap();
+
+        let (format, read_chunks) = read_flac_metadata(&file.0).unwrap();
+        assert_eq!(format.sample_rate, 44100);
+        assert_eq!(format.num_channels as usize, channels);
+        assert_eq!(format.bits_per_sample as u32, bits_per_sample);
+        assert_eq!(read_chunks.len(), chunks.len());
+        for (read, written) in read_chunks.iter().zip(chunks) {
+            assert_eq!(read.id, written.id);
+            assert_eq!(read.data, written.data);
+        }
+        assert_eq!(
+            read_flac_frame_count(&file.0).unwrap(),
+            (samples.len() / channels) as u64
+        );
+
+        let scale = (1u32 << (bits_per_sample - 1)) as f32;
+        let decoded: Vec<i32> = FlacSampleReader::open(&file.0)
+            .unwrap()
+            .map(|s| (s * scale) as i32)
+            .collect();
+        assert_eq!(decoded.len(), samples.len());
+        if let Some(i) = (0..samples.len()).find(|&i| decoded[i] != samples[i]) {
+            panic!(
+                "sample {} decoded as {}, expected {}",
+                i, decoded[i], samples[i]
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip_16_bit_mono() {
+        round_trip("16-bit-mono", 16, 1, &[smpl_chunk()]);
+    }
+
+    #[test]
+    fn round_trip_16_bit_stereo() {
+        round_trip("16-bit-stereo", 16, 2, &[]);
+    }
+
+    #[test]
+    fn round_trip_24_bit_mono() {
+        round_trip("24-bit-mono", 24, 1, &[]);
+    }
+
+    #[test]
+    fn round_trip_24_bit_stereo() {
+        round_trip("24-bit-stereo", 24, 2, &[smpl_chunk()]);
+    }
+}
@@ -15,10 +15,12 @@ use crate::{
     app::MainLoopAction,
     app::{AppMessage, TuiMessage},
     app_state::{AppState, Preset},
+    audio_command::EngineCommand,
     config::MidiEventSpec, // Import the new Enum
     gui_midi_learn::{LearnTarget, MidiLearnState, draw_midi_learn_modal},
     gui_organ_manager::OrganManagerUi,
     input::MusicCommand,
+    midi_control::ContinuousParameter,
     organ::Organ,
 };
 
@@ -215,7 +217,7 @@ impl App for EguiApp {
                             let note = state.get_keyboard_midi_note(semitone);
                             let velocity = if *pressed { 100 } else { 0 };
 
-                            state.handle_keyboard_note(note, velocity, &self.audio_tx);
+                            state.handle_keyboard_note(note, velocity);
                         }
                         _ => {} // Ignore None or non-music keys
                     }
@@ -249,7 +251,7 @@ impl App for EguiApp {
                         self.show_preset_save_modal = true;
                     } else {
                         let mut app_state = self.app_state.lock().unwrap();
-                        if let Err(e) = app_state.recall_preset(i, &self.audio_tx) {
+                        if let Err(e) = app_state.recall_preset(i) {
                             app_state
                                 .add_midi_log(t!("errors.recall_preset_fail", err = e).to_string());
                         }
@@ -261,13 +263,13 @@ impl App for EguiApp {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .modify_gain(0.05, &self.audio_tx);
+                    .modify_gain(0.05);
             }
             if input.key_pressed(egui::Key::Minus) {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .modify_gain(-0.05, &self.audio_tx);
+                    .modify_gain(-0.05);
             }
 
             // Polyphony: [ / ]
@@ -275,21 +277,20 @@ impl App for EguiApp {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .modify_polyphony(-16, &self.audio_tx);
+                    .modify_polyphony(-16);
             }
             if input.key_pressed(egui::Key::CloseBracket) {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .modify_polyphony(16, &self.audio_tx);
+                    .modify_polyphony(16);
             }
             // Panic key: P
             if input.key_pressed(egui::Key::P) {
-                self.audio_tx
-                    .send(AppMessage::AllNotesOff)
-                    .unwrap_or_else(|e| {
-                        log::error!("ERROR sending AllNotesOff: {}", e);
-                    });
+                self.app_state
+                    .lock()
+                    .unwrap()
+                    .send_command(EngineCommand::AllNotesOff);
             }
 
             // Arrow key navigation
@@ -361,7 +362,7 @@ impl App for EguiApp {
                 if let Some(channel) = channel_to_toggle {
                     // Replicate the toggle logic from the button click
                     let mut app_state = self.app_state.lock().unwrap();
-                    if let Err(e) = app_state.toggle_stop_channel(stop_idx, channel, &self.audio_tx)
+                    if let Err(e) = app_state.toggle_stop_channel(stop_idx, channel)
                     {
                         app_state.add_midi_log(format!("ERROR: {}", e));
                     }
@@ -627,7 +628,7 @@ impl EguiApp {
                                     if btn.clicked() {
                                         if is_loaded {
                                             let mut app_state = self.app_state.lock().unwrap();
-                                            if let Err(e) = app_state.recall_preset(i, &self.audio_tx) {
+                                            if let Err(e) = app_state.recall_preset(i) {
                                                 app_state.add_midi_log(
                                                     t!("errors.recall_preset_fail", err = e).to_string(),
                                                 );
@@ -711,7 +712,6 @@ impl EguiApp {
                                             state.set_tremulant_active(
                                                 trem_id.to_string(),
                                                 !is_active,
-                                                &self.audio_tx,
                                             );
                                         }
                                         
@@ -751,8 +751,8 @@ impl EguiApp {
                             .selected_text(current_name)
                             .show_ui(ui, |ui| {
                                 if ui.selectable_label(selected_reverb_index.is_none(), t!("gui.no_reverb")).clicked() {
-                                    let _ = self.audio_tx.send(AppMessage::SetReverbWetDry(0.0));
                                     let mut state = self.app_state.lock().unwrap();
+                                    state.send_command(EngineCommand::SetReverbWetDry(0.0));
                                     state.selected_reverb_index = None;
                                     state.reverb_mix = 0.0;
                                     state.persist_settings();
@@ -775,22 +775,32 @@ impl EguiApp {
                         ui.spacing_mut().slider_width = ui.available_width() - 50.0;
 
                         ui.label(t!("gui.reverb_mix_label"));
-                        if ui.add(egui::Slider::new(&mut reverb_mix, 0.0..=1.0).show_value(true)).changed() {
+                        let mix_slider = ui.add(egui::Slider::new(&mut reverb_mix, 0.0..=1.0).show_value(true))
+                            .on_hover_text(t!("midi_learn.right_click_to_learn"));
+                        if mix_slider.changed() {
                             let mut state = self.app_state.lock().unwrap();
                             state.reverb_mix = reverb_mix;
-                            let _ = self.audio_tx.send(AppMessage::SetReverbWetDry(reverb_mix));
+                            state.send_command(EngineCommand::SetReverbWetDry(reverb_mix));
                             state.persist_settings();
                         }
+                        if mix_slider.secondary_clicked() {
+                            self.open_continuous_learn(ContinuousParameter::ReverbMix);
+                        }
 
                         ui.add_space(10.0);
 
                         ui.label(t!("gui.master_gain_label"));
-                        if ui.add(egui::Slider::new(&mut gain, 0.0..=2.0).show_value(true)).changed() {
+                        let gain_slider = ui.add(egui::Slider::new(&mut gain, 0.0..=2.0).show_value(true))
+                            .on_hover_text(t!("midi_learn.right_click_to_learn"));
+                        if gain_slider.changed() {
                             let mut state = self.app_state.lock().unwrap();
                             state.gain = gain;
-                            let _ = self.audio_tx.send(AppMessage::SetGain(gain));
+                            state.send_command(EngineCommand::SetGain(gain));
                             state.persist_settings();
                         }
+                        if gain_slider.secondary_clicked() {
+                            self.open_continuous_learn(ContinuousParameter::Gain);
+                        }
 
                         ui.label(egui::RichText::new(t!("gui.gain_keys_hint")).small().weak());
 
@@ -798,19 +808,26 @@ impl EguiApp {
 
                         // --- Polyphony Control ---
                         ui.horizontal(|ui| {
-                            // Label on the left, with toolip
-                            ui.label(t!("gui.polyphony_label"))
-                                .on_hover_text(t!("gui.polyphony_keys_hint"));
+                            // Label on the left, with toolip. Right click to learn a controller.
+                            let poly_label = ui.add(egui::Label::new(t!("gui.polyphony_label")).sense(egui::Sense::click()))
+                                .on_hover_text(format!(
+                                    "{}\n{}",
+                                    t!("gui.polyphony_keys_hint"),
+                                    t!("midi_learn.right_click_to_learn")
+                                ));
+                            if poly_label.secondary_clicked() {
+                                self.open_continuous_learn(ContinuousParameter::Polyphony);
+                            }
 
                             // Push everything else to the right
                             ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                                 let poly_btn_size = egui::vec2(25.0, 20.0);
                                 if ui.add_sized(poly_btn_size, egui::Button::new("+")).clicked() {
-                                    self.app_state.lock().unwrap().modify_polyphony(16, &self.audio_tx);
+                                    self.app_state.lock().unwrap().modify_polyphony(16);
                                 }
                                 ui.label(egui::RichText::new(format!("{}", polyphony)).strong());
                                 if ui.add_sized(poly_btn_size, egui::Button::new("-")).clicked() {
-                                    self.app_state.lock().unwrap().modify_polyphony(-16, &self.audio_tx);
+                                    self.app_state.lock().unwrap().modify_polyphony(-16);
                                 }
                             });
                         });
@@ -863,8 +880,8 @@ impl EguiApp {
                             if ui.add_enabled_ui(is_playing, |ui| {
                                 ui.add_sized(ctrl_btn_size, egui::Button::new("⏪"))
                             }).inner.on_hover_text(t!("gui.midi_player_rewind")).clicked() {
-                                let state = self.app_state.lock().unwrap();
-                                let _ = self.audio_tx.send(AppMessage::AllNotesOff);
+                                let mut state = self.app_state.lock().unwrap();
+                                state.send_command(EngineCommand::AllNotesOff);
                                 if let Some(tx) = &state.midi_seek_tx {
                                     let _ = tx.send(-15);
                                 }
@@ -878,8 +895,8 @@ impl EguiApp {
                                     state.midi_file_stop_signal.store(true, std::sync::atomic::Ordering::Relaxed);
                                     state.is_midi_file_playing = false;
                                     state.handle_tui_all_notes_off();
-                                    state.channel_active_notes.clear();
-                                    let _ = self.audio_tx.send(AppMessage::AllNotesOff);
+                                    state.clear_held_keys();
+                                    state.send_command(EngineCommand::AllNotesOff);
                                 }
                             } else {
                                 // We use add_enabled so the button looks disabled if no file is selected
@@ -904,8 +921,8 @@ impl EguiApp {
                             if ui.add_enabled_ui(is_playing, |ui| {
                                 ui.add_sized(ctrl_btn_size, egui::Button::new("⏩"))
                             }).inner.on_hover_text(t!("gui.midi_player_fastforward")).clicked() {
-                                let state = self.app_state.lock().unwrap();
-                                let _ = self.audio_tx.send(AppMessage::AllNotesOff);
+                                let mut state = self.app_state.lock().unwrap();
+                                state.send_command(EngineCommand::AllNotesOff);
                                 if let Some(tx) = &state.midi_seek_tx {
                                     let _ = tx.send(15);
                                 }
@@ -945,9 +962,9 @@ impl EguiApp {
                                 let delta = (target_time - current_time) as i32;
 
                                 // Send command
-                                let state = self.app_state.lock().unwrap();
+                                let mut state = self.app_state.lock().unwrap();
                                 // Silence notes before jumping
-                                let _ = self.audio_tx.send(AppMessage::AllNotesOff);
+                                state.send_command(EngineCommand::AllNotesOff);
                                 
                                 if let Some(tx) = &state.midi_seek_tx {
                                     // midi.rs handles the math: new_time = current + delta
@@ -981,9 +998,9 @@ impl EguiApp {
                             }
                         };
 
-                        let (active_voice_count, polyphony, cpu_load) = {
+                        let (active_voice_count, polyphony, cpu_load, loader_status, mapped_memory) = {
                             let state = self.app_state.lock().unwrap();
-                            (state.active_voice_count, state.polyphony, state.cpu_load)
+                            (state.active_voice_count, state.polyphony, state.cpu_load, state.loader_status, state.mapped_memory)
                         };
 
                         let status_btn_size = egui::vec2(ui.available_width(), 30.0);
@@ -1015,6 +1032,32 @@ impl EguiApp {
                         };
 
                         ui.add(egui::ProgressBar::new(cpu_load).fill(load_color).animate(false));
+
+                        // --- Sample Loader Pool ---
+                        ui.label(t!(
+                            "gui.loader_fmt",
+                            busy = loader_status.busy_workers,
+                            workers = loader_status.workers,
+                            queued = loader_status.queued_jobs,
+                            streaming = loader_status.streaming_voices
+                        ));
+
+                        // --- Memory-mapped Samples ---
+                        if let Some(mapped) = mapped_memory {
+                            const MB: f64 = 1024.0 * 1024.0;
+                            let resident = mapped.resident_bytes.map_or("?".to_string(), |b| {
+                                format!("{:.0}", b as f64 / MB)
+                            });
+                            ui.label(t!(
+                                "gui.mapped_memory_fmt",
+                                resident = resident,
+                                mapped = format!("{:.0}", mapped.mapped_bytes as f64 / MB)
+                            ));
+                            if let Some(resident_bytes) = mapped.resident_bytes {
+                                let fraction = resident_bytes as f32 / mapped.mapped_bytes.max(1) as f32;
+                                ui.add(egui::ProgressBar::new(fraction).animate(false));
+                            }
+                        }
                     }
                 );
             });
@@ -1030,13 +1073,13 @@ impl EguiApp {
 
                 if ui.button(t!("gui.btn_all_channels")).clicked() {
                     let mut app_state = self.app_state.lock().unwrap();
-                    if let Err(e) = app_state.select_all_channels_for_stop(idx, &self.audio_tx) {
+                    if let Err(e) = app_state.select_all_channels_for_stop(idx) {
                         app_state.add_midi_log(format!("ERROR: {}", e));
                     }
                 }
                 if ui.button(t!("gui.btn_no_channels")).clicked() {
                     let mut app_state = self.app_state.lock().unwrap();
-                    if let Err(e) = app_state.select_none_channels_for_stop(idx, &self.audio_tx) {
+                    if let Err(e) = app_state.select_none_channels_for_stop(idx) {
                         app_state.add_midi_log(format!("ERROR: {}", e));
                     }
                 }
@@ -1051,10 +1094,10 @@ impl EguiApp {
                 .on_hover_text(t!("gui.panic_tooltip"))
                 .clicked()
             {
-                let mut app_state = self.app_state.lock().unwrap();
-                if let Err(e) = self.audio_tx.send(AppMessage::AllNotesOff) {
-                    app_state.add_midi_log(format!("ERROR: {}", e));
-                }
+                self.app_state
+                    .lock()
+                    .unwrap()
+                    .send_command(EngineCommand::AllNotesOff);
             }
         });
     }
@@ -1161,7 +1204,7 @@ impl EguiApp {
                                 {
                                     let mut app_state = self.app_state.lock().unwrap();
                                     if let Err(e) =
-                                        app_state.toggle_stop_channel(i, chan, &self.audio_tx)
+                                        app_state.toggle_stop_channel(i, chan)
                                     {
                                         app_state.add_midi_log(format!("ERROR: {}", e));
                                     }
@@ -1370,6 +1413,14 @@ impl EguiApp {
         }
     }
 
+    /// Opens the MIDI learn window for a parameter a fader or pedal can control.
+    fn open_continuous_learn(&mut self, parameter: ContinuousParameter) {
+        self.midi_learn_state.is_open = true;
+        self.midi_learn_state.target_name = parameter.to_string();
+        self.midi_learn_state.target = LearnTarget::Continuous(parameter);
+        self.midi_learn_state.learning_slot = None;
+    }
+
     /// Renders a modal window for saving a preset.
     fn draw_preset_save_modal(&mut self, ctx: &egui::Context) {
         if !self.show_preset_save_modal {
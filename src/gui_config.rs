@@ -1,6 +1,6 @@
 use crate::app::{LOGO, PIPES};
 use crate::audio::get_supported_sample_rates;
-use crate::config::{AppSettings, ConfigShared, ConfigState, RuntimeConfig};
+use crate::config::{AppSettings, CacheFormat, ConfigShared, ConfigState, RuntimeConfig};
 use crate::gui_filepicker;
 use crate::gui_midi::MidiMappingWindow;
 use anyhow::Result;
@@ -291,8 +291,11 @@ impl App for ConfigApp {
         let state = &mut shared_guard.state;
 
         // Modal MIDI Mapping Window
-        self.midi_mapping_window
-            .show(ctx, &mut state.settings.midi_devices);
+        self.midi_mapping_window.show(
+            ctx,
+            &mut state.settings.midi_devices,
+            &mut state.settings.virtual_midi_input,
+        );
 
         // Modal LCD Configuration Window
         if self.show_lcd_config {
@@ -478,6 +481,7 @@ impl App for ConfigApp {
                                                 ui.label(name);
                                                 if ui.button(t!("config.btn_map")).clicked() {
                                                     self.midi_mapping_window.device_index = cfg_idx;
+                                                    self.midi_mapping_window.virtual_port = false;
                                                     self.midi_mapping_window.visible = true;
                                                 }
                                             }
@@ -487,6 +491,25 @@ impl App for ConfigApp {
                             });
                             ui.end_row();
 
+                            // --- Virtual MIDI Port (ALSA, JACK, CoreMIDI) ---
+                            if cfg!(unix) {
+                                ui.label(t!("config.group_virtual_midi"))
+                                    .on_hover_text(t!("config.tooltip_virtual_midi"));
+                                ui.horizontal(|ui| {
+                                    let port = &mut state.settings.virtual_midi_input;
+                                    ui.checkbox(&mut port.enabled, "");
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut port.name)
+                                            .desired_width(180.0),
+                                    );
+                                    if ui.button(t!("config.btn_map")).clicked() {
+                                        self.midi_mapping_window.virtual_port = true;
+                                        self.midi_mapping_window.visible = true;
+                                    }
+                                });
+                                ui.end_row();
+                            }
+
                             // --- IR File ---
                             ui.label(t!("config.group_ir_file"))
                                 .on_hover_text(t!("config.tooltip_ir_file"));
@@ -604,11 +627,32 @@ impl App for ConfigApp {
                                     t!("config.chk_precache"),
                                 )
                                 .on_hover_text(t!("config.tooltip_precache"));
+                                ui.add_enabled(
+                                    !state.settings.precache,
+                                    egui::Checkbox::new(
+                                        &mut state.settings.mmap_samples,
+                                        t!("config.chk_mmap"),
+                                    ),
+                                )
+                                .on_hover_text(t!("config.tooltip_mmap"));
                                 ui.checkbox(
                                     &mut state.settings.convert_to_16bit,
                                     t!("config.chk_convert"),
                                 )
                                 .on_hover_text(t!("config.tooltip_convert"));
+                                let mut compress_cache =
+                                    state.settings.cache_format == CacheFormat::Flac;
+                                if ui
+                                    .checkbox(&mut compress_cache, t!("config.chk_compress_cache"))
+                                    .on_hover_text(t!("config.tooltip_compress_cache"))
+                                    .changed()
+                                {
+                                    state.settings.cache_format = if compress_cache {
+                                        CacheFormat::Flac
+                                    } else {
+                                        CacheFormat::Wav
+                                    };
+                                }
                                 ui.checkbox(
                                     &mut state.settings.original_tuning,
                                     t!("config.chk_tuning"),
@@ -617,6 +661,16 @@ impl App for ConfigApp {
                             });
                             ui.end_row();
 
+                            // --- Memory Estimate ---
+                            ui.label(t!("config.group_memory_plan"))
+                                .on_hover_text(t!("config.tooltip_memory_plan"));
+                            ui.vertical(|ui| {
+                                for line in state.memory_plan().summary_lines() {
+                                    ui.label(line);
+                                }
+                            });
+                            ui.end_row();
+
                             // --- LCD Configuration ---
                             ui.label(t!("config.lcd_title"));
                             if ui.button(t!("config.lcd_button")).clicked() {
@@ -716,13 +770,21 @@ pub fn build_runtime_config(state: &ConfigState) -> RuntimeConfig {
         audio_buffer_frames: state.settings.audio_buffer_frames,
         max_ram_gb: state.settings.max_ram_gb,
         precache: state.settings.precache,
+        mmap_samples: state.settings.mmap_samples,
         convert_to_16bit: state.settings.convert_to_16bit,
+        cache_format: state.settings.cache_format,
         original_tuning: state.settings.original_tuning,
         midi_file: state.midi_file.clone(),
         active_midi_devices: active_devices,
+        virtual_midi_input: state.settings.virtual_midi_input.clone(),
         gain: state.settings.gain,
         polyphony: state.settings.polyphony,
         max_new_voices_per_block: state.settings.max_new_voices_per_block,
+        attack_selection: state.settings.attack_selection,
+        interpolation: state.settings.interpolation,
+        tracker_delay_scale: state.settings.tracker_delay_scale,
+        output_channels: state.settings.output_channels,
+        output_routes: state.settings.output_routes.clone(),
         audio_device_name: state.selected_audio_device_name.clone(),
         sample_rate: state.settings.sample_rate,
         lcd_displays: state.settings.lcd_displays.clone(),
@@ -1,7 +1,8 @@
 use eframe::egui;
 use rust_i18n::t;
 
-use crate::config::{MidiDeviceConfig, MidiMappingMode};
+use crate::config::{KeyboardZone, MidiDeviceConfig, MidiMappingMode};
+use crate::midi::midi_note_to_name;
 
 /// Manages the state and visibility of the MIDI channel mapping configuration window.
 pub struct MidiMappingWindow {
@@ -9,6 +10,8 @@ pub struct MidiMappingWindow {
     pub visible: bool,
     /// The index of the device in the `AppSettings::midi_devices` vector being edited.
     pub device_index: usize,
+    /// Edits the virtual input port instead of `device_index`.
+    pub virtual_port: bool,
 }
 
 impl MidiMappingWindow {
@@ -16,22 +19,31 @@ impl MidiMappingWindow {
         Self {
             visible: false,
             device_index: 0,
+            virtual_port: false,
         }
     }
 
     /// Renders the mapping window if it is visible.
-    pub fn show(&mut self, ctx: &egui::Context, devices: &mut Vec<MidiDeviceConfig>) {
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        devices: &mut [MidiDeviceConfig],
+        virtual_input: &mut MidiDeviceConfig,
+    ) {
         if !self.visible {
             return;
         }
 
-        // Safety check: if the device list changed and index is invalid, close window.
-        if self.device_index >= devices.len() {
-            self.visible = false;
-            return;
-        }
-
-        let device = &mut devices[self.device_index];
+        let device = if self.virtual_port {
+            virtual_input
+        } else {
+            // Safety check: if the device list changed and index is invalid, close window.
+            if self.device_index >= devices.len() {
+                self.visible = false;
+                return;
+            }
+            &mut devices[self.device_index]
+        };
 
         // We use a fixed sized window that can be resized by the user
         let window_title = t!("midi_config.window_title_fmt", name = device.name);
@@ -80,6 +92,10 @@ impl MidiMappingWindow {
                     }
                 }
 
+                ui.add_space(10.0);
+                ui.separator();
+                Self::render_zones(ui, device);
+
                 ui.add_space(15.0);
                 ui.separator();
                 ui.horizontal(|ui| {
@@ -176,4 +192,60 @@ impl MidiMappingWindow {
                     });
             });
     }
+
+    fn render_zones(ui: &mut egui::Ui, device: &mut MidiDeviceConfig) {
+        ui.label(egui::RichText::new(t!("midi_config.zones_heading")).strong());
+        ui.label(t!("midi_config.zones_desc"));
+        ui.add_space(5.0);
+
+        let mut remove = None;
+        if !device.zones.is_empty() {
+            egui::Grid::new("keyboard_zone_grid")
+                .striped(true)
+                .spacing([20.0, 8.0])
+                .show(ui, |ui| {
+                    ui.label(egui::RichText::new(t!("midi_config.col_low")).strong());
+                    ui.label(egui::RichText::new(t!("midi_config.col_high")).strong());
+                    ui.label(egui::RichText::new(t!("midi_config.col_channel")).strong());
+                    ui.label(egui::RichText::new(t!("midi_config.col_octave")).strong());
+                    ui.end_row();
+
+                    for (i, zone) in device.zones.iter_mut().enumerate() {
+                        ui.add(
+                            egui::DragValue::new(&mut zone.low_note)
+                                .range(0..=zone.high_note)
+                                .custom_formatter(|n, _| midi_note_to_name(n as u8)),
+                        );
+                        ui.add(
+                            egui::DragValue::new(&mut zone.high_note)
+                                .range(zone.low_note..=127)
+                                .custom_formatter(|n, _| midi_note_to_name(n as u8)),
+                        );
+                        egui::ComboBox::from_id_salt(format!("zone_channel_combo_{}", i))
+                            .selected_text(format!("{}", zone.target_channel + 1))
+                            .show_ui(ui, |ui| {
+                                for ch in 0..16 {
+                                    ui.selectable_value(
+                                        &mut zone.target_channel,
+                                        ch,
+                                        format!("{}", ch + 1),
+                                    );
+                                }
+                            });
+                        ui.add(egui::DragValue::new(&mut zone.octave_shift).range(-4..=4));
+                        if ui.button(t!("midi_config.btn_remove_zone")).clicked() {
+                            remove = Some(i);
+                        }
+                        ui.end_row();
+                    }
+                });
+        }
+        if let Some(i) = remove {
+            device.zones.remove(i);
+        }
+
+        if ui.button(t!("midi_config.btn_add_zone")).clicked() {
+            device.zones.push(KeyboardZone::default());
+        }
+    }
 }
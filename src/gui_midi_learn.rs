@@ -1,4 +1,5 @@
 use crate::app_state::AppState;
+use crate::midi_control::{ContinuousBinding, ContinuousParameter, ControlCurve};
 use eframe::egui;
 use rust_i18n::t;
 use std::sync::{Arc, Mutex};
@@ -9,6 +10,7 @@ pub enum LearnTarget {
     Stop(usize),
     Tremulant(String),
     Preset(usize),
+    Continuous(ContinuousParameter),
 }
 
 impl Default for LearnTarget {
@@ -51,8 +53,31 @@ pub fn draw_midi_learn_modal(
 
     let mut is_open = learn_state.is_open;
 
+    // Continuous parameters listen for the next controller that moves
+    if let (Some(_), LearnTarget::Continuous(parameter)) =
+        (learn_state.learning_slot, &learn_state.target)
+    {
+        let mut state = app_state.lock().unwrap();
+        let moved = state
+            .last_continuous_received
+            .filter(|(_, time)| *time > learn_state.last_interaction);
+        if let Some((source, _)) = moved {
+            let binding = state.learned_continuous_binding(parameter.clone(), source);
+            state.midi_control_map.learn_continuous(binding);
+            let _ = state.midi_control_map.save(&state.organ.name);
+            learn_state.learning_slot = None;
+            state.add_midi_log(
+                t!(
+                    "midi_learn.log_mapped_fmt",
+                    event = source,
+                    action = t!("midi_learn.action_continuous")
+                )
+                .to_string(),
+            );
+        }
+    }
     // We check for new MIDI events if we are in learning mode
-    if let Some((target_internal, is_enable)) = learn_state.learning_slot {
+    else if let Some((target_internal, is_enable)) = learn_state.learning_slot {
         let mut state = app_state.lock().unwrap();
         if let Some((event, time)) = &state.last_midi_event_received {
             if *time > learn_state.last_interaction {
@@ -83,6 +108,7 @@ pub fn draw_midi_learn_modal(
                                 .learn_preset(*slot, event_clone.clone());
                         }
                     }
+                    LearnTarget::Continuous(_) => {}
                 }
                 // Save immediately
                 let _ = state.midi_control_map.save(&state.organ.name);
@@ -161,6 +187,18 @@ pub fn draw_midi_learn_modal(
                     };
                     draw_preset_row(ui, learn_state, slot, trigger, app_state.clone());
                 }
+                LearnTarget::Continuous(parameter) => {
+                    ui.label(t!("midi_learn.description_continuous"));
+                    ui.add_space(10.0);
+                    let binding = {
+                        let state = app_state.lock().unwrap();
+                        state
+                            .midi_control_map
+                            .continuous_binding(&parameter)
+                            .cloned()
+                    };
+                    draw_continuous_row(ui, learn_state, &parameter, binding, app_state.clone());
+                }
             }
         });
 
@@ -275,6 +313,91 @@ fn draw_preset_row(
         });
 }
 
+fn draw_continuous_row(
+    ui: &mut egui::Ui,
+    learn_state: &mut MidiLearnState,
+    parameter: &ContinuousParameter,
+    binding: Option<ContinuousBinding>,
+    app_state: Arc<Mutex<AppState>>,
+) {
+    egui::Grid::new("continuous_learn_grid")
+        .num_columns(6)
+        .striped(true)
+        .spacing([20.0, 8.0])
+        .show(ui, |ui| {
+            ui.label(egui::RichText::new(t!("midi_learn.col_controller")).strong());
+            ui.label(egui::RichText::new(t!("midi_learn.col_min")).strong());
+            ui.label(egui::RichText::new(t!("midi_learn.col_max")).strong());
+            ui.label(egui::RichText::new(t!("midi_learn.col_curve")).strong());
+            ui.label("");
+            ui.label(egui::RichText::new(t!("midi_learn.col_actions")).strong());
+            ui.end_row();
+
+            // Controller Button
+            let txt = if learn_state.learning_slot.is_some() {
+                t!("midi_learn.status_listening").to_string()
+            } else if let Some(b) = &binding {
+                b.source.to_string()
+            } else {
+                t!("midi_learn.btn_learn").to_string()
+            };
+            if ui
+                .add(egui::Button::new(txt).selected(learn_state.learning_slot.is_some()))
+                .clicked()
+            {
+                learn_state.last_interaction = Instant::now();
+                learn_state.learning_slot = Some((0, true));
+            }
+
+            // Range, curve and takeover can only be edited once a controller is bound
+            let Some(mut edited) = binding.clone() else {
+                ui.end_row();
+                return;
+            };
+            let speed = (edited.max - edited.min).abs().max(1.0) / 100.0;
+            let min_resp = ui.add(egui::DragValue::new(&mut edited.min).speed(speed));
+            let max_resp = ui.add(egui::DragValue::new(&mut edited.max).speed(speed));
+            // Write the file once a drag ends rather than on every frame of it
+            let dragging = min_resp.dragged() || max_resp.dragged();
+            egui::ComboBox::from_id_salt("continuous_curve_combo")
+                .selected_text(match edited.curve {
+                    ControlCurve::Linear => t!("midi_learn.curve_linear"),
+                    ControlCurve::Logarithmic => t!("midi_learn.curve_log"),
+                })
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(
+                        &mut edited.curve,
+                        ControlCurve::Linear,
+                        t!("midi_learn.curve_linear"),
+                    );
+                    ui.selectable_value(
+                        &mut edited.curve,
+                        ControlCurve::Logarithmic,
+                        t!("midi_learn.curve_log"),
+                    );
+                });
+            ui.checkbox(&mut edited.soft_takeover, t!("midi_learn.soft_takeover"))
+                .on_hover_text(t!("midi_learn.soft_takeover_hint"));
+
+            // Clear Button
+            let clear = ui.button(t!("midi_learn.btn_clear")).clicked();
+            ui.end_row();
+
+            let changed = binding.as_ref() != Some(&edited);
+            if clear || changed || min_resp.drag_stopped() || max_resp.drag_stopped() {
+                let mut state = app_state.lock().unwrap();
+                if clear {
+                    state.midi_control_map.clear_continuous(parameter);
+                } else {
+                    state.midi_control_map.learn_continuous(edited);
+                }
+                if !dragging {
+                    let _ = state.midi_control_map.save(&state.organ.name);
+                }
+            }
+        });
+}
+
 fn draw_stop_grid(
     ui: &mut egui::Ui,
     learn_state: &mut MidiLearnState,
@@ -213,7 +213,7 @@ impl OrganManagerUi {
                 o.name != ignore_name
                     && o.activation_trigger
                         .as_ref()
-                        .map_or(false, |trig| trig == event)
+                        .map_or(false, |trig| trig.matches(event))
             })
             .map(|o| o.path.clone())
     }
@@ -0,0 +1,31 @@
+//! The Rusty Pipes engine: organ definition loading, sample streaming, voices,
+//! reverb and the block-based mixer. It takes note and control messages and
+//! fills audio buffers, without owning audio devices or MIDI ports, so the
+//! standalone application, the offline renderer and the CLAP plugin can share it.
+
+rust_i18n::i18n!("locales");
+
+pub mod alloc_tracker;
+pub mod app;
+pub mod audio_command;
+pub mod audio_convolver;
+pub mod audio_engine;
+pub mod audio_event;
+pub mod audio_interpolation;
+pub mod audio_loader;
+pub mod audio_recorder;
+pub mod audio_routing;
+pub mod cache_manifest;
+pub mod flac;
+pub mod mapped_sample;
+pub mod memory_plan;
+pub mod midi_recorder;
+pub mod organ;
+pub mod organ_cache;
+pub mod organ_grandorgue;
+pub mod organ_hauptwerk;
+pub mod tuning;
+pub mod velocity;
+pub mod voice;
+pub mod wav;
+pub mod wav_converter;
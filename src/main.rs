@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 use midir::MidiInput;
 use rust_i18n::t;
 use simplelog::{Config, LevelFilter, WriteLogger};
@@ -14,15 +14,17 @@ use std::time::Duration;
 
 rust_i18n::i18n!("locales");
 
+use rusty_pipes::{
+    alloc_tracker, app, audio_command, audio_engine, audio_event, audio_interpolation, audio_loader,
+    audio_routing, flac, mapped_sample, memory_plan, midi_recorder, organ, organ_cache, tuning,
+    velocity, voice, wav_converter,
+};
+
 mod api_rest;
-mod app;
 mod app_state;
 mod i18n_web;
 mod audio;
-mod audio_convolver;
-mod audio_event;
-mod audio_loader;
-mod audio_recorder;
+mod cache_cli;
 mod config;
 mod gui;
 mod gui_config;
@@ -34,10 +36,7 @@ mod input;
 mod loading_ui;
 mod midi;
 mod midi_control;
-mod midi_recorder;
-mod organ;
-mod organ_grandorgue;
-mod organ_hauptwerk;
+mod render;
 mod tui;
 mod tui_config;
 mod tui_filepicker;
@@ -46,12 +45,17 @@ mod tui_midi;
 mod tui_midi_learn;
 mod tui_organ_manager;
 mod tui_progress;
-mod voice;
-mod wav;
-mod wav_converter;
+
+/// Lets the audio thread check that it doesn't allocate (see `alloc_tracker`)
+#[cfg(debug_assertions)]
+#[global_allocator]
+static GLOBAL: rusty_pipes::alloc_tracker::TrackingAllocator =
+    rusty_pipes::alloc_tracker::TrackingAllocator;
 
 use app::{AppMessage, TuiMessage};
 use app_state::{AppState, connect_to_midi};
+use audio_command::EngineCommand;
+use audio_engine::EngineConfig;
 use config::{AppSettings, ConfigShared, ConfigState, MidiDeviceConfig, RuntimeConfig};
 use input::KeyboardLayout;
 use organ::Organ;
@@ -68,9 +72,21 @@ enum LogLevel {
     Trace,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect and maintain the converted sample cache
+    Cache {
+        #[command(subcommand)]
+        action: cache_cli::CacheAction,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Path to organ definition file (e.g., friesach/friesach.organ or friesach/OrganDefinitions/Friesach.Organ_Hauptwerk_xml)
     #[arg(value_name = "ORGAN_DEFINITION")]
     organ_file: Option<PathBuf>,
@@ -83,10 +99,18 @@ struct Args {
     #[arg(long)]
     precache: Option<bool>,
 
+    /// Play float32 cache files from memory-mapped pages, leaving residency to the OS page cache
+    #[arg(long)]
+    mmap_samples: Option<bool>,
+
     /// Convert all samples to 16-bit PCM on load (saves memory, may reduce quality)
     #[arg(long)]
     convert_to_16bit: Option<bool>,
 
+    /// Format of resampled samples in the organ cache: wav, or flac (lossless, about half the disk space)
+    #[arg(long, value_name = "FORMAT")]
+    cache_format: Option<config::CacheFormat>,
+
     /// Set the application log level
     #[arg(long, value_name = "LEVEL", default_value = "info")]
     log_level: LogLevel,
@@ -138,6 +162,18 @@ struct Args {
     /// Skip the configuration UI and start playing immediately
     #[arg(long)]
     auto_start: bool,
+
+    /// Render a MIDI file offline to the file given by --output, then exit
+    #[arg(long, value_name = "MIDI_FILE", requires = "output")]
+    render: Option<PathBuf>,
+
+    /// Output file for --render (.wav or .flac)
+    #[arg(long, value_name = "OUTPUT_FILE", requires = "render")]
+    output: Option<PathBuf>,
+
+    /// Preset slot (1-12) to recall before rendering
+    #[arg(long, value_name = "SLOT", requires = "render")]
+    preset: Option<usize>,
 }
 
 // Handle struct that manages the lifecycle for the midi thread
@@ -268,9 +304,15 @@ fn main() -> Result<()> {
     if let Some(p) = args.precache {
         settings.precache = p;
     }
+    if let Some(m) = args.mmap_samples {
+        settings.mmap_samples = m;
+    }
     if let Some(c) = args.convert_to_16bit {
         settings.convert_to_16bit = c;
     }
+    if let Some(f) = args.cache_format {
+        settings.cache_format = f;
+    }
     if let Some(o) = args.original_tuning {
         settings.original_tuning = o;
     }
@@ -278,6 +320,22 @@ fn main() -> Result<()> {
         settings.audio_device_name = Some(d);
     }
 
+    // --- Cache maintenance: runs against the merged settings, then exits ---
+    if let Some(Command::Cache { action }) = &args.command {
+        return cache_cli::run_cache_command(&settings, action);
+    }
+
+    // --- Offline render: no audio device, UI or web server needed ---
+    if let (Some(midi_path), Some(output_path)) = (&args.render, &args.output) {
+        return render::render_midi_file(
+            &settings,
+            midi_path,
+            output_path,
+            args.preset,
+            active_layout,
+        );
+    }
+
     // --- CLI: MIDI Device Selection ---
     // If a device is specified via CLI, we ensure it exists in settings and is enabled.
     // We treat it as a passthrough (1:1 mapping), which is the default for MidiDeviceConfig.
@@ -356,12 +414,20 @@ fn main() -> Result<()> {
             audio_buffer_frames: settings.audio_buffer_frames,
             max_ram_gb: settings.max_ram_gb,
             precache: settings.precache,
+            mmap_samples: settings.mmap_samples,
             convert_to_16bit: settings.convert_to_16bit,
+            cache_format: settings.cache_format,
             original_tuning: settings.original_tuning,
             active_midi_devices,
+            virtual_midi_input: settings.virtual_midi_input.clone(),
             gain: settings.gain,
             polyphony: settings.polyphony,
             max_new_voices_per_block: settings.max_new_voices_per_block,
+            attack_selection: settings.attack_selection,
+            interpolation: settings.interpolation,
+            tracker_delay_scale: settings.tracker_delay_scale,
+            output_channels: settings.output_channels,
+            output_routes: settings.output_routes.clone(),
             audio_device_name: settings.audio_device_name.clone(),
             sample_rate: settings.sample_rate,
             midi_file: args.midi_file.clone(),
@@ -456,12 +522,20 @@ fn main() -> Result<()> {
         audio_buffer_frames: config.audio_buffer_frames,
         max_ram_gb: config.max_ram_gb,
         precache: config.precache,
+        mmap_samples: config.mmap_samples,
         convert_to_16bit: config.convert_to_16bit,
+        cache_format: config.cache_format,
         original_tuning: config.original_tuning,
         midi_devices: devices_to_save,
+        virtual_midi_input: config.virtual_midi_input.clone(),
         gain: config.gain,
         polyphony: config.polyphony,
         max_new_voices_per_block: config.max_new_voices_per_block,
+        attack_selection: config.attack_selection,
+        interpolation: config.interpolation,
+        tracker_delay_scale: config.tracker_delay_scale,
+        output_channels: config.output_channels,
+        output_routes: config.output_routes.clone(),
         audio_device_name: config.audio_device_name.clone(),
         sample_rate: config.sample_rate,
         tui_mode,
@@ -549,7 +623,9 @@ fn main() -> Result<()> {
                 let load_result = Organ::load(
                     &load_config.organ_file,
                     load_config.convert_to_16bit,
+                    load_config.cache_format,
                     load_config.precache,
+                    load_config.mmap_samples,
                     load_config.original_tuning,
                     load_config.sample_rate,
                     Some(organ_progress_tx),
@@ -626,7 +702,9 @@ fn main() -> Result<()> {
                 let load_result = Organ::load(
                     &load_config.organ_file,
                     load_config.convert_to_16bit,
+                    load_config.cache_format,
                     load_config.precache,
+                    load_config.mmap_samples,
                     load_config.original_tuning,
                     load_config.sample_rate,
                     Some(organ_progress_tx),
@@ -674,6 +752,7 @@ fn main() -> Result<()> {
         }
 
         // --- Create channels for thread communication ---
+        let (command_tx, command_rx) = audio_command::command_queue();
         let (audio_tx, audio_rx) = mpsc::channel::<AppMessage>();
         let (tui_tx, tui_rx) = mpsc::channel::<TuiMessage>();
         let (gui_ctx_tx, gui_ctx_rx) = mpsc::channel::<egui::Context>();
@@ -682,15 +761,25 @@ fn main() -> Result<()> {
         if tui_mode {
             println!("{}", t!("main.starting_audio"));
         }
+        let engine_config = EngineConfig {
+            sample_rate: config.sample_rate,
+            buffer_size_frames: config.audio_buffer_frames,
+            gain: config.gain,
+            polyphony: config.polyphony,
+            max_new_voices_per_block: config.max_new_voices_per_block,
+            attack_selection: config.attack_selection,
+            interpolation: config.interpolation,
+            tracker_delay_scale: config.tracker_delay_scale,
+            output_channels: config.output_channels,
+            output_routes: config.output_routes.clone(),
+        };
+        let max_polyphony = engine_config.max_polyphony();
         let _audio_handle = audio::start_audio_playback(
+            command_rx,
             audio_rx,
             Arc::clone(&organ),
-            config.audio_buffer_frames,
-            config.gain,
-            config.polyphony,
-            config.max_new_voices_per_block,
+            engine_config,
             config.audio_device_name.clone(),
-            config.sample_rate,
             tui_tx.clone(),
             shared_midi_recorder.clone(),
         )?;
@@ -698,22 +787,14 @@ fn main() -> Result<()> {
             println!("{}", t!("main.audio_running"));
         }
 
-        // --- Load IR file ---
-        if let Some(path) = &config.ir_file {
-            if path.exists() {
-                log::info!("Loading IR file: {}", path.display());
-                audio_tx.send(AppMessage::SetReverbIr(path.clone()))?;
-                audio_tx.send(AppMessage::SetReverbWetDry(config.reverb_mix))?;
-            } else {
-                log::warn!("IR file not found: {}", path.display());
-            }
-        }
-
         // --- Create thread-safe AppState ---
         let app_state = Arc::new(Mutex::new(AppState::new(
             organ.clone(),
+            command_tx,
             config.gain,
             config.polyphony,
+            max_polyphony,
+            config.tracker_delay_scale,
             active_layout,
         )?));
 
@@ -722,6 +803,28 @@ fn main() -> Result<()> {
         // presets, tremulants, audio) to connected web clients.
         app_state.lock().unwrap().ws_broadcaster = Some(ws_broadcaster.clone());
 
+        // --- Load IR file ---
+        if let Some(path) = &config.ir_file {
+            if path.exists() {
+                log::info!("Loading IR file: {}", path.display());
+                audio_tx.send(AppMessage::SetReverbIr(path.clone()))?;
+                app_state
+                    .lock()
+                    .unwrap()
+                    .send_command(EngineCommand::SetReverbWetDry(config.reverb_mix));
+            } else {
+                log::warn!("IR file not found: {}", path.display());
+            }
+        }
+
+        // --- Apply the organ's saved tuning and velocity curves ---
+        let (tuning, velocity_curves) = {
+            let state = app_state.lock().unwrap();
+            (state.tuning.clone(), state.velocity_curves.clone())
+        };
+        audio_tx.send(AppMessage::SetTuning(tuning))?;
+        audio_tx.send(AppMessage::SetVelocityCurves(velocity_curves))?;
+
         // --- Initialize MIDI Output & LCDs ---
         {
             let mut state = app_state.lock().unwrap();
@@ -742,6 +845,9 @@ fn main() -> Result<()> {
 
             state.lcd_displays = config.lcd_displays.clone();
             state.refresh_lcds();
+
+            // Bring lit stop tabs and pistons on the console in line with the new organ
+            state.resync_midi_feedback();
         }
 
         let exit_action = Arc::new(Mutex::new(app::MainLoopAction::Exit));
@@ -762,7 +868,6 @@ fn main() -> Result<()> {
 
         // --- Spawn the dedicated MIDI logic thread ---
         let logic_app_state = Arc::clone(&app_state);
-        let logic_audio_tx = audio_tx.clone();
         // Create a stop signal
         let logic_stop_signal = Arc::new(AtomicBool::new(false));
         let logic_stop_clone = logic_stop_signal.clone();
@@ -802,7 +907,7 @@ fn main() -> Result<()> {
                         }
 
                         let mut app_state_locked = logic_app_state.lock().unwrap();
-                        if let Err(e) = app_state_locked.handle_tui_message(msg, &logic_audio_tx) {
+                        if let Err(e) = app_state_locked.handle_tui_message(msg) {
                             let err_msg = format!("Error handling TUI message: {}", e);
                             log::error!("{}", err_msg);
                             app_state_locked.add_midi_log(err_msg);
@@ -902,10 +1007,39 @@ fn main() -> Result<()> {
                 }
             }
             log::info!("MIDI initialization complete.");
-        } else if tui_mode {
+        } else if tui_mode && !config.virtual_midi_input.enabled {
             println!("{}", t!("main.no_midi_devices"));
         }
 
+        // The virtual port is created like a device connection and kept alive the same way
+        if config.virtual_midi_input.enabled {
+            let port_name = config.virtual_midi_input.name.clone();
+            log::info!("Creating virtual MIDI port: {}", port_name);
+            match MidiInput::new(&format!("Rusty Pipes - {}", port_name)) {
+                Ok(client) => match midi::create_virtual_midi_input(
+                    client,
+                    &tui_tx,
+                    config.virtual_midi_input.clone(),
+                    Arc::clone(&shared_midi_recorder),
+                ) {
+                    Ok(conn) => {
+                        midi_connections.push(conn);
+                        app_state
+                            .lock()
+                            .unwrap()
+                            .add_midi_log(format!("Virtual port: {}", port_name));
+                    }
+                    Err(e) => {
+                        log::error!("{}", e);
+                        app_state.lock().unwrap().add_midi_log(
+                            t!("errors.midi_virtual_fail", name = port_name, err = e).to_string(),
+                        );
+                    }
+                },
+                Err(e) => log::error!("Failed to create MIDI client for {}: {}", port_name, e),
+            }
+        }
+
         // --- Run the TUI or GUI on the main thread ---
         let loop_action = if tui_mode {
             tui::run_tui_loop(
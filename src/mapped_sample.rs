@@ -0,0 +1,148 @@
+use anyhow::{Result, anyhow};
+use memmap2::Mmap;
+use serde::Serialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::wav::{parse_smpl_chunk, parse_wav_metadata};
+
+const BYTES_PER_SAMPLE: usize = std::mem::size_of::<f32>();
+
+/// A float32 WAV cache file mapped into memory. Voices read straight from the
+/// mapped pages; which of them stay in RAM is left to the OS page cache.
+#[derive(Debug)]
+pub struct MappedSample {
+    map: Mmap,
+    data_offset: usize,
+    /// Interleaved samples in the data chunk.
+    sample_count: usize,
+    pub channel_count: u16,
+    pub loop_info: Option<(u32, u32)>,
+}
+
+/// Size of the mapped samples and how much of it is in RAM right now.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct MappedMemoryStatus {
+    pub mapped_bytes: u64,
+    /// `None` where the platform can't tell.
+    pub resident_bytes: Option<u64>,
+}
+
+impl MappedSample {
+    /// Maps the sample at `path`. `Ok(None)` if it isn't a float32 WAV, since
+    /// integer and compressed files have to be decoded as they play.
+    pub fn open(path: &Path, target_sample_rate: u32) -> Result<Option<Self>> {
+        let file = File::open(path)?;
+        let Ok((fmt, other_chunks, data_offset, data_size)) =
+            parse_wav_metadata(&mut BufReader::new(&file), path)
+        else {
+            return Ok(None);
+        };
+        if fmt.audio_format != 3 || fmt.bits_per_sample != 32 {
+            return Ok(None);
+        }
+        if fmt.sample_rate != target_sample_rate {
+            return Err(anyhow!(
+                "Sample rate mismatch in cache: {} != {}",
+                fmt.sample_rate,
+                target_sample_rate
+            ));
+        }
+        let loop_info = other_chunks
+            .iter()
+            .find(|chunk| &chunk.id == b"smpl")
+            .and_then(|chunk| parse_smpl_chunk(&chunk.data));
+
+        // Safety: cache files are written once by the converter and only ever
+        // replaced by deleting them, which leaves an existing mapping intact.
+        let map = unsafe { Mmap::map(&file)? };
+        let data_offset = (data_offset as usize).min(map.len());
+        let data_len = (data_size as usize).min(map.len() - data_offset);
+
+        Ok(Some(Self {
+            map,
+            data_offset,
+            sample_count: data_len / BYTES_PER_SAMPLE,
+            channel_count: fmt.num_channels,
+            loop_info,
+        }))
+    }
+
+    /// Number of interleaved samples.
+    pub fn len(&self) -> usize {
+        self.sample_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sample_count == 0
+    }
+
+    /// The interleaved sample at `index`. The data chunk needn't be aligned
+    /// for `f32`, so samples are read byte-wise.
+    pub fn get(&self, index: usize) -> Option<f32> {
+        if index >= self.sample_count {
+            return None;
+        }
+        let start = self.data_offset + index * BYTES_PER_SAMPLE;
+        let bytes = self.map[start..start + BYTES_PER_SAMPLE].try_into().ok()?;
+        Some(f32::from_le_bytes(bytes))
+    }
+
+    pub fn mapped_bytes(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Reads a byte of every page holding the first `frames` frames, so they
+    /// are in RAM before a voice needs them.
+    pub fn warm_up(&self, frames: usize) {
+        let frame_bytes = self.channel_count.max(1) as usize * BYTES_PER_SAMPLE;
+        let end = (self.data_offset + frames.saturating_mul(frame_bytes))
+            .min(self.data_offset + self.sample_count * BYTES_PER_SAMPLE);
+        let mut checksum = 0u8;
+        for offset in (self.data_offset..end).step_by(page_size()) {
+            checksum = checksum.wrapping_add(self.map[offset]);
+        }
+        std::hint::black_box(checksum);
+    }
+
+    /// Bytes of the mapping that are in RAM.
+    #[cfg(unix)]
+    pub fn resident_bytes(&self) -> Option<usize> {
+        if self.map.is_empty() {
+            return Some(0);
+        }
+        let page_size = page_size();
+        let mut pages = vec![0u8; self.map.len().div_ceil(page_size)];
+        // Safety: the mapping is page-aligned and `pages` has one entry per page.
+        let result = unsafe {
+            libc::mincore(
+                self.map.as_ptr() as *mut libc::c_void,
+                self.map.len(),
+                pages.as_mut_ptr().cast(),
+            )
+        };
+        if result != 0 {
+            return None;
+        }
+        let resident_pages = pages.iter().filter(|&&page| page & 1 != 0).count();
+        Some((resident_pages * page_size).min(self.map.len()))
+    }
+
+    #[cfg(not(unix))]
+    pub fn resident_bytes(&self) -> Option<usize> {
+        None
+    }
+}
+
+#[cfg(unix)]
+fn page_size() -> usize {
+    // Safety: sysconf has no preconditions.
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 { size as usize } else { 4096 }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> usize {
+    4096
+}
@@ -0,0 +1,435 @@
+use anyhow::Result;
+use rayon::prelude::*;
+use rust_i18n::t;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::organ::{ConversionTask, Organ, preload_frames_per_sample};
+use crate::wav_converter::{CacheFormat, SourceFormat};
+
+const BYTES_PER_SAMPLE: u64 = std::mem::size_of::<f32>() as u64;
+/// Preloaded heads are always stereo, mono samples are doubled.
+const PRELOAD_CHANNELS: u64 = 2;
+/// Bytes of an archive entry read to find its WAV header.
+const ARCHIVE_HEAD_BYTES: u64 = 64 * 1024;
+
+/// The settings a memory estimate depends on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanInputs {
+    pub organ_file: PathBuf,
+    pub convert_to_16_bit: bool,
+    pub original_tuning: bool,
+    pub target_sample_rate: u32,
+    pub max_preload_ram_mb: usize,
+}
+
+impl PlanInputs {
+    /// Inputs for `organ_file`, or `None` while no existing organ is selected.
+    pub fn for_organ(
+        organ_file: Option<&Path>,
+        convert_to_16_bit: bool,
+        original_tuning: bool,
+        target_sample_rate: u32,
+        max_preload_ram_mb: usize,
+    ) -> Option<Self> {
+        let organ_file = organ_file.filter(|p| p.exists())?;
+        Some(Self {
+            organ_file: organ_file.to_path_buf(),
+            convert_to_16_bit,
+            original_tuning,
+            target_sample_rate,
+            max_preload_ram_mb,
+        })
+    }
+}
+
+/// What loading an organ would take, worked out from the sample headers
+/// before anything is extracted or converted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MemoryPlan {
+    /// Samples the organ plays, once per tuning they are converted at.
+    pub sample_count: usize,
+    /// Samples whose header couldn't be read. They are left out of the sums.
+    pub unmeasured_samples: usize,
+    /// RAM for pre-caching every sample.
+    pub precache_bytes: u64,
+    /// Frames of each sample the RAM budget preloads, and how long they play.
+    pub preload_frames: usize,
+    pub preload_ms: f32,
+    /// RAM the preloaded frames take. Less than the budget for short samples.
+    pub preload_bytes: u64,
+    /// Disk the converted samples take with the current settings, uncompressed.
+    pub cache_bytes: u64,
+    /// The same with 16-bit conversion.
+    pub cache_bytes_16bit: u64,
+}
+
+impl MemoryPlan {
+    /// Reads the organ definition and the header of every sample it plays.
+    /// Zipped organs whose samples aren't extracted yet are measured from
+    /// the archive.
+    pub fn estimate(inputs: &PlanInputs) -> Result<Self> {
+        let rate = inputs.target_sample_rate;
+        let (organ, tasks) = Organ::plan_definition(
+            &inputs.organ_file,
+            inputs.convert_to_16_bit,
+            CacheFormat::default(),
+            inputs.original_tuning,
+            rate,
+        )?;
+        let sources = read_source_formats(&inputs.organ_file, &organ.base_path, &tasks);
+
+        let preload_frames = preload_frames_per_sample(inputs.max_preload_ram_mb, tasks.len());
+        let mut plan = Self {
+            sample_count: tasks.len(),
+            preload_frames,
+            preload_ms: preload_frames as f32 * 1000.0 / rate.max(1) as f32,
+            ..Default::default()
+        };
+
+        for task in &tasks {
+            let Some(source) = sources.get(&task.relative_path) else {
+                plan.unmeasured_samples += 1;
+                continue;
+            };
+            let cents = task.tuning_cents_int as f32 / 100.0;
+            let frames = source.converted_frames(cents, rate);
+            plan.precache_bytes += frames * source.channels as u64 * BYTES_PER_SAMPLE;
+            plan.preload_bytes +=
+                frames.min(preload_frames as u64) * PRELOAD_CHANNELS * BYTES_PER_SAMPLE;
+            plan.cache_bytes += cache_file_bytes(source, cents, inputs.convert_to_16_bit, rate);
+            plan.cache_bytes_16bit += cache_file_bytes(source, cents, true, rate);
+        }
+        Ok(plan)
+    }
+}
+
+/// Size of the cache file a sample is converted into. 0 when it is played
+/// from the source.
+fn cache_file_bytes(source: &SourceFormat, cents: f32, to_16_bit: bool, rate: u32) -> u64 {
+    source
+        .cache_file_name("", false, cents, to_16_bit, CacheFormat::default(), rate)
+        .map_or(0, |name| {
+            let frames = source.converted_frames(cents, rate);
+            frames * source.channels as u64 * name.bits_per_sample as u64 / 8
+        })
+}
+
+/// The format of every task's source, keyed by its relative path. Sources
+/// that can't be read are missing from the map.
+fn read_source_formats(
+    organ_file: &Path,
+    base_path: &Path,
+    tasks: &HashSet<ConversionTask>,
+) -> HashMap<PathBuf, SourceFormat> {
+    let paths: HashSet<&PathBuf> = tasks.iter().map(|task| &task.relative_path).collect();
+    let (found, missing): (Vec<_>, Vec<_>) = paths
+        .into_par_iter()
+        .map(|path| (path, SourceFormat::read(&base_path.join(path)).ok()))
+        .partition(|(_, format)| format.is_some());
+
+    let mut formats: HashMap<PathBuf, SourceFormat> = found
+        .into_iter()
+        .filter_map(|(path, format)| Some((path.clone(), format?)))
+        .collect();
+
+    let is_archive = organ_file.extension().and_then(|e| e.to_str()) == Some("orgue");
+    if is_archive && !missing.is_empty() {
+        let missing: Vec<&PathBuf> = missing.into_iter().map(|(path, _)| path).collect();
+        match read_archive_formats(organ_file, &missing) {
+            Ok(archived) => formats.extend(archived),
+            Err(e) => log::warn!("[Plan] Failed to read samples from {:?}: {}", organ_file, e),
+        }
+    }
+    formats
+}
+
+/// Reads the WAV headers of samples still packed in an organ archive.
+fn read_archive_formats(
+    zip_path: &Path,
+    paths: &[&PathBuf],
+) -> Result<HashMap<PathBuf, SourceFormat>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(zip_path)?)?;
+    let names: HashMap<String, usize> = (0..archive.len())
+        .filter_map(|idx| Some((archive.name_for_index(idx)?.to_lowercase(), idx)))
+        .collect();
+
+    let mut formats = HashMap::new();
+    for path in paths {
+        let entry_name = path.to_string_lossy().replace('\\', "/").to_lowercase();
+        let Some(&index) = names.get(&entry_name) else {
+            continue;
+        };
+        let mut head = Vec::new();
+        archive
+            .by_index(index)?
+            .take(ARCHIVE_HEAD_BYTES)
+            .read_to_end(&mut head)?;
+        if let Ok(format) = SourceFormat::read_wav_head(&head, path) {
+            formats.insert((*path).clone(), format);
+        }
+    }
+    Ok(formats)
+}
+
+/// Where the estimate for the current settings stands.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum PlanStatus {
+    /// No organ is selected.
+    #[default]
+    Idle,
+    Estimating,
+    Ready(MemoryPlan),
+    Failed {
+        message: String,
+    },
+}
+
+impl PlanStatus {
+    /// The estimate as lines of text for the config UIs.
+    pub fn summary_lines(&self) -> Vec<String> {
+        match self {
+            Self::Idle => vec![t!("config.memory_plan_no_organ").to_string()],
+            Self::Estimating => vec![t!("config.memory_plan_estimating").to_string()],
+            Self::Failed { message } => {
+                vec![t!("config.memory_plan_failed_fmt", error = message).to_string()]
+            }
+            Self::Ready(plan) => {
+                let mut lines = vec![
+                    t!(
+                        "config.memory_plan_precache_fmt",
+                        size = format_size(plan.precache_bytes)
+                    )
+                    .to_string(),
+                    t!(
+                        "config.memory_plan_preload_fmt",
+                        size = format_size(plan.preload_bytes),
+                        frames = plan.preload_frames,
+                        ms = format!("{:.0}", plan.preload_ms)
+                    )
+                    .to_string(),
+                    t!(
+                        "config.memory_plan_cache_fmt",
+                        size = format_size(plan.cache_bytes),
+                        size_16bit = format_size(plan.cache_bytes_16bit)
+                    )
+                    .to_string(),
+                ];
+                if plan.unmeasured_samples > 0 {
+                    lines.push(
+                        t!(
+                            "config.memory_plan_unmeasured_fmt",
+                            count = plan.unmeasured_samples,
+                            total = plan.sample_count
+                        )
+                        .to_string(),
+                    );
+                }
+                lines
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PlannerState {
+    inputs: Option<PlanInputs>,
+    status: PlanStatus,
+}
+
+/// Re-estimates in the background whenever the settings change, so the
+/// config UIs can ask for the current estimate every frame. Clones share
+/// the estimate.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryPlanner {
+    state: Arc<Mutex<PlannerState>>,
+}
+
+impl MemoryPlanner {
+    /// Starts a new estimate if `inputs` differ from the last ones.
+    pub fn update(&self, inputs: Option<PlanInputs>) {
+        let mut state = self.state.lock().unwrap();
+        if state.inputs == inputs {
+            return;
+        }
+        state.inputs = inputs.clone();
+        let Some(inputs) = inputs else {
+            state.status = PlanStatus::Idle;
+            return;
+        };
+        state.status = PlanStatus::Estimating;
+
+        let shared = Arc::clone(&self.state);
+        std::thread::spawn(move || {
+            let status = match MemoryPlan::estimate(&inputs) {
+                Ok(plan) => PlanStatus::Ready(plan),
+                Err(e) => PlanStatus::Failed {
+                    message: e.to_string(),
+                },
+            };
+            let mut state = shared.lock().unwrap();
+            // The settings may have changed again while this one ran.
+            if state.inputs.as_ref() == Some(&inputs) {
+                state.status = status;
+            }
+        });
+    }
+
+    pub fn status(&self) -> PlanStatus {
+        self.state.lock().unwrap().status.clone()
+    }
+}
+
+/// RAM the samples of one rank take.
+#[derive(Debug, Clone, Serialize)]
+pub struct RankMemory {
+    pub rank_id: String,
+    pub name: String,
+    pub division_id: String,
+    pub bytes: u64,
+}
+
+/// RAM the samples of one division's ranks take.
+#[derive(Debug, Clone, Serialize)]
+pub struct DivisionMemory {
+    pub division_id: String,
+    pub bytes: u64,
+}
+
+/// RAM a loaded organ's samples take, per rank and division.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MemoryReport {
+    /// Sorted by rank ID.
+    pub ranks: Vec<RankMemory>,
+    /// Sorted by division ID.
+    pub divisions: Vec<DivisionMemory>,
+    pub total_bytes: u64,
+}
+
+impl MemoryReport {
+    /// Counts the preloaded and pre-cached samples of each rank, and the
+    /// resident pages of its mapped ones. A sample several ranks share counts
+    /// for the first of them.
+    pub fn of(organ: &Organ) -> Self {
+        // Ranks without a division of their own (GrandOrgue) are put in the
+        // division of the first stop that draws them.
+        let mut stop_divisions: HashMap<&str, &str> = HashMap::new();
+        for stop in &organ.stops {
+            for rank_id in &stop.rank_ids {
+                stop_divisions
+                    .entry(rank_id.as_str())
+                    .or_insert(stop.division_id.as_str());
+            }
+        }
+
+        let mut rank_ids: Vec<&String> = organ.ranks.keys().collect();
+        rank_ids.sort();
+
+        let mut counted: HashSet<&Path> = HashSet::new();
+        let mut report = Self::default();
+        let mut divisions: HashMap<String, u64> = HashMap::new();
+        for rank_id in rank_ids {
+            let rank = &organ.ranks[rank_id];
+            let mut bytes = 0;
+            for pipe in rank.pipes.values() {
+                let attacks = pipe
+                    .attacks
+                    .iter()
+                    .map(|a| (a.path.as_path(), &a.preloaded_bytes));
+                let releases = pipe
+                    .releases
+                    .iter()
+                    .map(|r| (r.path.as_path(), &r.preloaded_bytes));
+                for (path, preloaded) in attacks.chain(releases) {
+                    if counted.insert(path) {
+                        bytes += sample_bytes(organ, path, preloaded);
+                    }
+                }
+            }
+
+            let division_id = if rank.division_id.is_empty() {
+                stop_divisions.get(rank_id.as_str()).copied().unwrap_or("")
+            } else {
+                rank.division_id.as_str()
+            };
+            *divisions.entry(division_id.to_string()).or_default() += bytes;
+            report.total_bytes += bytes;
+            report.ranks.push(RankMemory {
+                rank_id: rank_id.clone(),
+                name: rank.name.clone(),
+                division_id: division_id.to_string(),
+                bytes,
+            });
+        }
+
+        report.divisions = divisions
+            .into_iter()
+            .map(|(division_id, bytes)| DivisionMemory { division_id, bytes })
+            .collect();
+        report
+            .divisions
+            .sort_by(|a, b| a.division_id.cmp(&b.division_id));
+        report
+    }
+
+    /// Logs the divisions, and at debug level every rank.
+    pub fn log(&self) {
+        log::info!(
+            "[Memory] Samples take {} of RAM.",
+            format_size(self.total_bytes)
+        );
+        for division in &self.divisions {
+            log::info!(
+                "[Memory]   Division {}: {}",
+                display_division(&division.division_id),
+                format_size(division.bytes)
+            );
+        }
+        for rank in &self.ranks {
+            log::debug!(
+                "[Memory]   Rank {} ({}, {}): {}",
+                rank.rank_id,
+                rank.name,
+                display_division(&rank.division_id),
+                format_size(rank.bytes)
+            );
+        }
+    }
+}
+
+/// Formats a byte count as MB, or GB from 1 GB on.
+pub fn format_size(bytes: u64) -> String {
+    const MB: f64 = 1024.0 * 1024.0;
+    if bytes as f64 >= 1024.0 * MB {
+        format!("{:.2} GB", bytes as f64 / (1024.0 * MB))
+    } else {
+        format!("{:.1} MB", bytes as f64 / MB)
+    }
+}
+
+fn display_division(division_id: &str) -> &str {
+    if division_id.is_empty() {
+        "-"
+    } else {
+        division_id
+    }
+}
+
+/// RAM held for one sample: its pre-cached copy, preloaded head, or the
+/// resident part of its mapping.
+fn sample_bytes(organ: &Organ, path: &Path, preloaded: &Option<Arc<Vec<f32>>>) -> u64 {
+    if let Some(samples) = organ.sample_cache.as_ref().and_then(|c| c.get(path)) {
+        return samples.len() as u64 * BYTES_PER_SAMPLE;
+    }
+    if let Some(mapped) = organ.mapped_samples.as_ref().and_then(|m| m.get(path)) {
+        return mapped.resident_bytes().unwrap_or(0) as u64;
+    }
+    preloaded
+        .as_ref()
+        .map_or(0, |p| p.len() as u64 * BYTES_PER_SAMPLE)
+}
@@ -2,7 +2,7 @@ use anyhow::Result;
 use midir::{MidiInput, MidiInputPort, MidiOutput, MidiOutputConnection};
 use midly::{MetaMessage, MidiMessage as MidlyMidiMessage, Smf, TrackEventKind};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
 use std::sync::mpsc::{self, TryRecvError};
@@ -64,6 +64,52 @@ pub fn connect_to_midi(
         .map_err(|e| anyhow::anyhow!("Failed to connect to MIDI device {}: {}", device_name, e))
 }
 
+/// Creates a virtual MIDI input port named after `config.name`, which other
+/// programs (sequencers, notation software) can send to. Messages pass
+/// through the same channel mapping and recording as a hardware device.
+#[cfg(unix)]
+pub fn create_virtual_midi_input(
+    midi_input: MidiInput,
+    tui_tx: &Sender<TuiMessage>,
+    config: MidiDeviceConfig,
+    shared_recorder: Arc<Mutex<Option<MidiRecorder>>>,
+) -> Result<midir::MidiInputConnection<()>> {
+    use midir::os::unix::VirtualInput;
+
+    let tx_clone = tui_tx.clone();
+    let port_name = config.name.clone();
+
+    midi_input
+        .create_virtual(
+            &port_name,
+            move |_, message, _| {
+                process_live_midi_message(
+                    message,
+                    &tx_clone,
+                    &config,
+                    &config.name,
+                    &shared_recorder,
+                );
+            },
+            (),
+        )
+        .map_err(|e| anyhow::anyhow!("Failed to create virtual MIDI port {}: {}", port_name, e))
+}
+
+/// Virtual ports are not available with the Windows MIDI API.
+#[cfg(not(unix))]
+pub fn create_virtual_midi_input(
+    _midi_input: MidiInput,
+    _tui_tx: &Sender<TuiMessage>,
+    config: MidiDeviceConfig,
+    _shared_recorder: Arc<Mutex<Option<MidiRecorder>>>,
+) -> Result<midir::MidiInputConnection<()>> {
+    Err(anyhow::anyhow!(
+        "Virtual MIDI port {} is not supported on this platform",
+        config.name
+    ))
+}
+
 /// Processes raw MIDI bytes, applies channel mapping, and sends events to the App.
 fn process_live_midi_message(
     message: &[u8],
@@ -72,11 +118,19 @@ fn process_live_midi_message(
     _device_name: &str, // Useful if you want to log *which* device sent the message
     shared_recorder: &Arc<Mutex<Option<MidiRecorder>>>,
 ) {
-    if message.len() < 3 {
+    let Some(&status) = message.first() else {
+        return;
+    };
+    // Program Change and Channel Pressure carry one data byte, the others two
+    let data_bytes = if matches!(status & 0xF0, 0xC0 | 0xD0) {
+        1
+    } else {
+        2
+    };
+    if message.len() < 1 + data_bytes {
         return;
     }
 
-    let status = message[0];
     // Ignore system real-time messages (0xF8-0xFF)
     if status >= 0xF8 {
         return;
@@ -91,7 +145,7 @@ fn process_live_midi_message(
     let msg_type = status & 0xF0;
 
     // --- APPLY MAPPING ---
-    let target_channel = match config.mapping_mode {
+    let mut target_channel = match config.mapping_mode {
         MidiMappingMode::Simple => config.simple_target_channel,
         MidiMappingMode::Complex => {
             // Safety check for array bounds (0-15)
@@ -103,10 +157,23 @@ fn process_live_midi_message(
         }
     };
 
+    // --- APPLY KEYBOARD ZONES ---
+    // Notes (and their aftertouch) inside a zone play the zone's channel, shifted by its octaves
+    let mut data1 = message[1];
+    let is_note_message = matches!(msg_type, 0x80 | 0x90 | 0xA0);
+    if let Some(zone) = config.zone_for(data1).filter(|_| is_note_message) {
+        let Some(note) = zone.transpose(data1) else {
+            return;
+        };
+        target_channel = zone.target_channel;
+        data1 = note;
+    }
+
     if let Ok(mut recorder_guard) = shared_recorder.lock() {
         if let Some(recorder) = recorder_guard.as_mut() {
             // Record using the MAPPED target_channel, not the raw_channel
-            recorder.record(target_channel, status, message[1], message[2]);
+            let param2 = message.get(2).copied().unwrap_or(0);
+            recorder.record(target_channel, status, data1, param2);
         }
     }
 
@@ -116,10 +183,20 @@ fn process_live_midi_message(
     // Create new message buffer
     let mut mapped_message = Vec::with_capacity(message.len());
     mapped_message.push(new_status);
-    mapped_message.extend_from_slice(&message[1..]);
+    mapped_message.push(data1);
+    mapped_message.extend_from_slice(&message[2..]);
 
     // Pass to the parser
     parse_and_send(&mapped_message, tui_tx, target_channel);
+
+    // All Notes Off also releases the notes the keyboard zones routed elsewhere
+    if msg_type == 0xB0 && data1 == 123 {
+        for zone in &config.zones {
+            if zone.target_channel != target_channel {
+                let _ = tui_tx.send(TuiMessage::MidiChannelNotesOff(zone.target_channel));
+            }
+        }
+    }
 }
 
 /// Parses the (mapped) message and sends TUI/Audio events.
@@ -170,8 +247,22 @@ fn parse_and_send(message: &[u8], tui_tx: &Sender<TuiMessage>, channel: u8) {
                 let _ = tui_tx.send(TuiMessage::MidiLog(log_msg));
                 let _ = tui_tx.send(TuiMessage::MidiChannelNotesOff(channel));
                 let _ = tui_tx.send(TuiMessage::TuiAllNotesOff);
+            } else if let Some(&value) = message.get(2) {
+                let _ = tui_tx.send(TuiMessage::MidiControlChange(controller, value, channel));
             }
         }
+        0xC0 => {
+            // Program Change
+            let program = message[1] & 0x7F;
+            let log_msg = format!("Program Change: {} (Ch {})", program + 1, channel + 1);
+            let _ = tui_tx.send(TuiMessage::MidiLog(log_msg));
+            let _ = tui_tx.send(TuiMessage::MidiProgramChange(program, channel));
+        }
+        0xE0 => {
+            // Pitch Bend (LSB first)
+            let value = (message[1] as u16 & 0x7F) | ((message[2] as u16 & 0x7F) << 7);
+            let _ = tui_tx.send(TuiMessage::MidiPitchBend(value, channel));
+        }
         _ => {}
     }
 }
@@ -228,6 +319,99 @@ fn get_midi_duration_seconds(smf: &Smf) -> f64 {
     duration
 }
 
+/// Reads a MIDI file into a time-ordered list of (seconds, event) pairs, following
+/// tempo changes. Used for offline rendering, where no wall clock is involved.
+pub fn read_midi_file_events(path: &Path) -> Result<Vec<(f64, TuiMessage)>> {
+    let data = fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+
+    let tpqn = match smf.header.timing {
+        midly::Timing::Metrical(t) => t.as_int() as f64,
+        _ => 480.0,
+    };
+
+    let mut events = Vec::new();
+    let mut micros_per_quarter = 500_000.0;
+    let mut tracks: Vec<_> = smf.tracks.iter().map(|t| t.iter().peekable()).collect();
+    let mut track_next_tick: Vec<u32> = vec![0; tracks.len()];
+    let mut global_ticks = 0;
+    let mut current_time_seconds = 0.0;
+
+    loop {
+        let mut next_event_tick = u32::MAX;
+        let mut next_track_idx = None;
+
+        for (i, track) in tracks.iter_mut().enumerate() {
+            if let Some(event) = track.peek() {
+                let t = track_next_tick[i] + event.delta.as_int();
+                if t < next_event_tick {
+                    next_event_tick = t;
+                    next_track_idx = Some(i);
+                }
+            }
+        }
+
+        let idx = match next_track_idx {
+            Some(i) => i,
+            None => break,
+        };
+
+        let event = tracks[idx].next().unwrap();
+        track_next_tick[idx] = next_event_tick;
+
+        let delta_ticks = next_event_tick - global_ticks;
+        global_ticks = next_event_tick;
+        current_time_seconds += (delta_ticks as f64 * micros_per_quarter / tpqn) / 1_000_000.0;
+
+        match event.kind {
+            TrackEventKind::Midi { channel, message } => {
+                let channel_num = channel.as_int();
+                let msg = match message {
+                    MidlyMidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => {
+                        TuiMessage::MidiNoteOn(key.as_int(), vel.as_int(), channel_num)
+                    }
+                    MidlyMidiMessage::NoteOn { key, .. }
+                    | MidlyMidiMessage::NoteOff { key, .. } => {
+                        TuiMessage::MidiNoteOff(key.as_int(), channel_num)
+                    }
+                    MidlyMidiMessage::Controller { controller, .. }
+                        if controller.as_int() == 123 =>
+                    {
+                        TuiMessage::MidiChannelNotesOff(channel_num)
+                    }
+                    MidlyMidiMessage::Controller { controller, value } => {
+                        TuiMessage::MidiControlChange(
+                            controller.as_int(),
+                            value.as_int(),
+                            channel_num,
+                        )
+                    }
+                    MidlyMidiMessage::ProgramChange { program } => {
+                        TuiMessage::MidiProgramChange(program.as_int(), channel_num)
+                    }
+                    MidlyMidiMessage::PitchBend { bend } => {
+                        TuiMessage::MidiPitchBend(bend.0.as_int(), channel_num)
+                    }
+                    _ => continue,
+                };
+                events.push((current_time_seconds, msg));
+            }
+            TrackEventKind::SysEx(bytes) => {
+                // midly strips the leading F0; restore it so bindings match live input
+                let mut sysex = Vec::with_capacity(bytes.len() + 1);
+                sysex.push(0xF0);
+                sysex.extend_from_slice(bytes);
+                events.push((current_time_seconds, TuiMessage::MidiSysEx(sysex)));
+            }
+            TrackEventKind::Meta(MetaMessage::Tempo(micros)) => {
+                micros_per_quarter = micros.as_int() as f64;
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
 /// Spawns a new thread to play a MIDI file.
 pub fn play_midi_file(
     path: PathBuf,
@@ -449,15 +633,33 @@ pub fn play_midi_file(
                                         Instant::now(),
                                     ));
                                 }
-                                MidlyMidiMessage::Controller { controller, .. } => {
+                                MidlyMidiMessage::Controller { controller, value } => {
                                     // CC #123 is "All Notes Off"
                                     if controller.as_int() == 123 {
                                         let _ = tui_tx
                                             .send(TuiMessage::MidiChannelNotesOff(channel_num));
                                         let _ = tui_tx.send(TuiMessage::TuiAllNotesOff);
+                                    } else {
+                                        let _ = tui_tx.send(TuiMessage::MidiControlChange(
+                                            controller.as_int(),
+                                            value.as_int(),
+                                            channel_num,
+                                        ));
                                     }
                                     // TODO: Handle Sustain command (CC #64)
                                 }
+                                MidlyMidiMessage::ProgramChange { program } => {
+                                    let _ = tui_tx.send(TuiMessage::MidiProgramChange(
+                                        program.as_int(),
+                                        channel_num,
+                                    ));
+                                }
+                                MidlyMidiMessage::PitchBend { bend } => {
+                                    let _ = tui_tx.send(TuiMessage::MidiPitchBend(
+                                        bend.0.as_int(),
+                                        channel_num,
+                                    ));
+                                }
                                 _ => {} // Ignore other MIDI messages
                             }
                         }
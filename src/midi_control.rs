@@ -1,17 +1,186 @@
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
 use std::path::PathBuf;
 
 use crate::config::MidiEventSpec;
 
+/// What is sent to the MIDI outputs when a control changes state, so lit
+/// drawknobs and tabs on a console follow recalls and remote changes
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub enum MidiFeedback {
+    /// Derived from the learned events (see `StopChannelControl::feedback_event`)
+    #[default]
+    Auto,
+    Off,
+    Custom {
+        on: Option<MidiEventSpec>,
+        off: Option<MidiEventSpec>,
+    },
+}
+
 // Defines how a control (Stop channel or Tremulant) is toggled
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct StopChannelControl {
     pub enable_event: Option<MidiEventSpec>,
     pub disable_event: Option<MidiEventSpec>,
+    #[serde(default)]
+    pub feedback: MidiFeedback,
+}
+
+impl StopChannelControl {
+    /// The event echoed when the control becomes active or inactive. In auto
+    /// mode a SysEx disable event is sent as is, otherwise the enable event's
+    /// lamp state is used (see `MidiEventSpec::lamp`).
+    pub fn feedback_event(&self, active: bool) -> Option<MidiEventSpec> {
+        match &self.feedback {
+            MidiFeedback::Off => None,
+            MidiFeedback::Custom { on, off } => if active { on } else { off }.clone(),
+            MidiFeedback::Auto => match &self.disable_event {
+                Some(event @ MidiEventSpec::SysEx(_)) if !active => Some(event.clone()),
+                _ => self.enable_event.as_ref()?.lamp(active),
+            },
+        }
+    }
+}
+
+/// Base of the logarithmic controller curve. The curve is this many times
+/// steeper at the top of the travel than at the bottom (40 dB).
+const LOG_CURVE_BASE: f32 = 100.0;
+
+/// Fraction of a binding's range within which a soft-takeover controller
+/// picks up the parameter without having to cross it.
+const SOFT_TAKEOVER_WINDOW: f32 = 0.02;
+
+/// A parameter that can follow a continuous MIDI controller
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ContinuousParameter {
+    Gain,
+    ReverbMix,
+    Polyphony,
+    Enclosure(String),
+}
+
+impl ContinuousParameter {
+    /// Stable identifier used by the REST API ("gain", "reverb_mix",
+    /// "polyphony" or "enclosure:<id>").
+    pub fn key(&self) -> String {
+        match self {
+            ContinuousParameter::Gain => "gain".to_string(),
+            ContinuousParameter::ReverbMix => "reverb_mix".to_string(),
+            ContinuousParameter::Polyphony => "polyphony".to_string(),
+            ContinuousParameter::Enclosure(id) => format!("enclosure:{}", id),
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "gain" => Some(ContinuousParameter::Gain),
+            "reverb_mix" => Some(ContinuousParameter::ReverbMix),
+            "polyphony" => Some(ContinuousParameter::Polyphony),
+            _ => key
+                .strip_prefix("enclosure:")
+                .map(|id| ContinuousParameter::Enclosure(id.to_string())),
+        }
+    }
+
+    /// The range a freshly learned controller sweeps.
+    pub fn default_range(&self) -> (f32, f32) {
+        match self {
+            ContinuousParameter::Polyphony => (16.0, 256.0),
+            _ => (0.0, 1.0),
+        }
+    }
+}
+
+impl fmt::Display for ContinuousParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContinuousParameter::Gain => write!(f, "Gain"),
+            ContinuousParameter::ReverbMix => write!(f, "Reverb Mix"),
+            ContinuousParameter::Polyphony => write!(f, "Polyphony"),
+            ContinuousParameter::Enclosure(id) => write!(f, "Enclosure {}", id),
+        }
+    }
+}
+
+/// The MIDI message a continuous binding listens to
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContinuousSource {
+    ControlChange { channel: u8, cc: u8 },
+    PitchBend { channel: u8 },
+}
+
+impl ContinuousSource {
+    /// Largest raw value the source sends (7 bits for CC, 14 bits for pitch bend).
+    pub fn max_raw(&self) -> u16 {
+        match self {
+            ContinuousSource::ControlChange { .. } => 127,
+            ContinuousSource::PitchBend { .. } => 16383,
+        }
+    }
+}
+
+impl fmt::Display for ContinuousSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContinuousSource::ControlChange { channel, cc } => {
+                write!(f, "Ch{} CC {}", channel + 1, cc)
+            }
+            ContinuousSource::PitchBend { channel } => write!(f, "Ch{} Pitch Bend", channel + 1),
+        }
+    }
+}
+
+/// How the controller travel maps onto the parameter range
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ControlCurve {
+    #[default]
+    Linear,
+    /// Fine control at the bottom of the travel, like an audio fader
+    Logarithmic,
+}
+
+/// Binds a continuous controller to a parameter
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContinuousBinding {
+    pub parameter: ContinuousParameter,
+    pub source: ContinuousSource,
+    pub min: f32,
+    pub max: f32,
+    #[serde(default)]
+    pub curve: ControlCurve,
+    /// Ignore the controller until it reaches the current value, so a
+    /// parameter changed elsewhere does not jump when the controller moves.
+    #[serde(default)]
+    pub soft_takeover: bool,
+}
+
+impl ContinuousBinding {
+    pub fn new(parameter: ContinuousParameter, source: ContinuousSource) -> Self {
+        let (min, max) = parameter.default_range();
+        Self {
+            parameter,
+            source,
+            min,
+            max,
+            curve: ControlCurve::Linear,
+            soft_takeover: false,
+        }
+    }
+
+    /// Converts a raw controller value into the parameter value.
+    pub fn scale(&self, raw: u16) -> f32 {
+        let x = (raw as f32 / self.source.max_raw() as f32).clamp(0.0, 1.0);
+        let shaped = match self.curve {
+            ControlCurve::Linear => x,
+            ControlCurve::Logarithmic => (LOG_CURVE_BASE.powf(x) - 1.0) / (LOG_CURVE_BASE - 1.0),
+        };
+        self.min + (self.max - self.min) * shaped
+    }
 }
 
 /// Unified action type returned when checking events
@@ -29,6 +198,13 @@ pub enum ControlAction {
     LoadPreset {
         slot_index: usize,
     },
+    SetContinuous {
+        parameter: ContinuousParameter,
+        value: f32,
+        /// Distance from the current value within which the controller picks
+        /// the parameter up, or None when soft takeover is off.
+        takeover_window: Option<f32>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
@@ -43,6 +219,10 @@ pub struct MidiControlMap {
     // Map<PresetSlotIndex, Trigger> (0-11)
     #[serde(default)]
     pub presets: HashMap<usize, Option<MidiEventSpec>>,
+
+    // At most one binding per parameter
+    #[serde(default)]
+    pub continuous: Vec<ContinuousBinding>,
 }
 
 impl MidiControlMap {
@@ -51,6 +231,7 @@ impl MidiControlMap {
             stops: HashMap::new(),
             tremulants: HashMap::new(),
             presets: HashMap::new(),
+            continuous: Vec::new(),
         }
     }
 
@@ -70,7 +251,10 @@ impl MidiControlMap {
         if path.exists() {
             if let Ok(file) = File::open(&path) {
                 let reader = BufReader::new(file);
-                if let Ok(map) = serde_json::from_reader(reader) {
+                if let Some(map) = serde_json::from_reader(reader)
+                    .ok()
+                    .and_then(Self::from_json)
+                {
                     return map;
                 }
             }
@@ -78,6 +262,37 @@ impl MidiControlMap {
         Self::new()
     }
 
+    /// Parses a saved map. Enclosure controllers saved in the old `enclosures`
+    /// table (enclosure ID to `{channel, cc}`) become continuous bindings.
+    fn from_json(mut value: serde_json::Value) -> Option<Self> {
+        let legacy_enclosures = value.as_object_mut().and_then(|o| o.remove("enclosures"));
+        let mut map: Self = serde_json::from_value(value).ok()?;
+
+        let Some(serde_json::Value::Object(enclosures)) = legacy_enclosures else {
+            return Some(map);
+        };
+        for (id, binding) in enclosures {
+            let field = |name| {
+                binding
+                    .get(name)
+                    .and_then(serde_json::Value::as_u64)
+                    .and_then(|v| u8::try_from(v).ok())
+            };
+            let (Some(channel), Some(cc)) = (field("channel"), field("cc")) else {
+                continue;
+            };
+            let parameter = ContinuousParameter::Enclosure(id);
+            // A continuous binding saved alongside takes precedence
+            if map.continuous_binding(&parameter).is_none() {
+                map.continuous.push(ContinuousBinding::new(
+                    parameter,
+                    ContinuousSource::ControlChange { channel, cc },
+                ));
+            }
+        }
+        Some(map)
+    }
+
     pub fn save(&self, organ_name: &str) -> Result<()> {
         let path = Self::get_file_path(organ_name);
         let file = File::create(path)?;
@@ -121,6 +336,58 @@ impl MidiControlMap {
         self.presets.insert(slot_index, Some(event));
     }
 
+    /// Binds a Control Change to an enclosure's shutters over their full travel.
+    pub fn learn_enclosure(&mut self, enclosure_id: String, channel: u8, cc: u8) {
+        self.learn_continuous(ContinuousBinding::new(
+            ContinuousParameter::Enclosure(enclosure_id),
+            ContinuousSource::ControlChange { channel, cc },
+        ));
+    }
+
+    /// Adds a continuous binding, replacing any earlier one for the same parameter.
+    pub fn learn_continuous(&mut self, binding: ContinuousBinding) {
+        self.clear_continuous(&binding.parameter);
+        self.continuous.push(binding);
+    }
+
+    pub fn continuous_binding(
+        &self,
+        parameter: &ContinuousParameter,
+    ) -> Option<&ContinuousBinding> {
+        self.continuous.iter().find(|b| &b.parameter == parameter)
+    }
+
+    /// Sets the feedback of a learned stop channel. Returns false if nothing is learned for it.
+    pub fn set_stop_feedback(
+        &mut self,
+        stop_index: usize,
+        internal_channel: u8,
+        feedback: MidiFeedback,
+    ) -> bool {
+        match self
+            .stops
+            .get_mut(&stop_index)
+            .and_then(|m| m.get_mut(&internal_channel))
+        {
+            Some(control) => {
+                control.feedback = feedback;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets the feedback of a learned tremulant. Returns false if nothing is learned for it.
+    pub fn set_tremulant_feedback(&mut self, trem_id: &str, feedback: MidiFeedback) -> bool {
+        match self.tremulants.get_mut(trem_id) {
+            Some(control) => {
+                control.feedback = feedback;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub fn clear_stop(&mut self, stop_index: usize, internal_channel: u8) {
         if let Some(stop_entry) = self.stops.get_mut(&stop_index) {
             stop_entry.remove(&internal_channel);
@@ -135,6 +402,25 @@ impl MidiControlMap {
         self.presets.remove(&slot_index);
     }
 
+    pub fn clear_continuous(&mut self, parameter: &ContinuousParameter) {
+        self.continuous.retain(|b| &b.parameter != parameter);
+    }
+
+    /// Checks a Control Change or pitch bend value against the continuous bindings.
+    pub fn check_continuous(&self, source: ContinuousSource, raw: u16) -> Vec<ControlAction> {
+        self.continuous
+            .iter()
+            .filter(|b| b.source == source)
+            .map(|b| ControlAction::SetContinuous {
+                parameter: b.parameter.clone(),
+                value: b.scale(raw),
+                takeover_window: b
+                    .soft_takeover
+                    .then(|| (b.max - b.min).abs() * SOFT_TAKEOVER_WINDOW),
+            })
+            .collect()
+    }
+
     /// Checks incoming MIDI against the map and returns a list of actions to take.
     pub fn check_event(&self, incoming: &MidiEventSpec) -> Vec<ControlAction> {
         let mut actions = Vec::new();
@@ -143,7 +429,7 @@ impl MidiControlMap {
         for (stop_idx, channel_map) in &self.stops {
             for (internal_channel, control) in channel_map {
                 if let Some(trigger) = &control.enable_event {
-                    if trigger == incoming {
+                    if trigger.matches(incoming) {
                         actions.push(ControlAction::SetStop {
                             index: *stop_idx,
                             internal_channel: *internal_channel,
@@ -152,7 +438,7 @@ impl MidiControlMap {
                     }
                 }
                 if let Some(trigger) = &control.disable_event {
-                    if trigger == incoming {
+                    if trigger.matches(incoming) {
                         actions.push(ControlAction::SetStop {
                             index: *stop_idx,
                             internal_channel: *internal_channel,
@@ -166,7 +452,7 @@ impl MidiControlMap {
         // Check Tremulants
         for (trem_id, control) in &self.tremulants {
             if let Some(trigger) = &control.enable_event {
-                if trigger == incoming {
+                if trigger.matches(incoming) {
                     actions.push(ControlAction::SetTremulant {
                         id: trem_id.clone(),
                         active: true,
@@ -174,7 +460,7 @@ impl MidiControlMap {
                 }
             }
             if let Some(trigger) = &control.disable_event {
-                if trigger == incoming {
+                if trigger.matches(incoming) {
                     actions.push(ControlAction::SetTremulant {
                         id: trem_id.clone(),
                         active: false,
@@ -186,7 +472,7 @@ impl MidiControlMap {
         // Check Presets
         for (slot, trigger_opt) in &self.presets {
             if let Some(trigger) = trigger_opt {
-                if trigger == incoming {
+                if trigger.matches(incoming) {
                     actions.push(ControlAction::LoadPreset { slot_index: *slot });
                 }
             }
@@ -195,3 +481,54 @@ impl MidiControlMap {
         actions
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_enclosure_bindings_become_continuous() {
+        let saved = serde_json::json!({
+            "stops": {},
+            "enclosures": {
+                "001": { "channel": 0, "cc": 11 },
+                "002": { "channel": 1, "cc": 7 }
+            },
+            "continuous": [
+                ContinuousBinding::new(
+                    ContinuousParameter::Enclosure("002".into()),
+                    ContinuousSource::PitchBend { channel: 1 },
+                )
+            ]
+        });
+        let map = MidiControlMap::from_json(saved).unwrap();
+
+        let swell = map
+            .continuous_binding(&ContinuousParameter::Enclosure("001".into()))
+            .unwrap();
+        assert_eq!(
+            swell.source,
+            ContinuousSource::ControlChange { channel: 0, cc: 11 }
+        );
+        assert_eq!((swell.min, swell.max), (0.0, 1.0));
+
+        // The existing continuous binding wins over the old table
+        let choir = map
+            .continuous_binding(&ContinuousParameter::Enclosure("002".into()))
+            .unwrap();
+        assert_eq!(choir.source, ContinuousSource::PitchBend { channel: 1 });
+        assert_eq!(map.continuous.len(), 2);
+
+        // One binding per enclosure, so a CC moves the shutters once
+        let actions =
+            map.check_continuous(ContinuousSource::ControlChange { channel: 0, cc: 11 }, 127);
+        assert_eq!(actions.len(), 1);
+        assert!(
+            !serde_json::to_value(&map)
+                .unwrap()
+                .as_object()
+                .unwrap()
+                .contains_key("enclosures")
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use anyhow::Result;
 use chrono::Local;
 use midly::{
-    Format, Header, MidiMessage as MidlyMidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
-    num::*,
+    Format, Header, MidiMessage as MidlyMidiMessage, PitchBend, Smf, Timing, TrackEvent,
+    TrackEventKind, num::*,
 };
 use std::fs;
 use std::time::Instant;
@@ -59,6 +59,18 @@ impl MidiRecorder {
                     value: u7_p2,
                 },
             }),
+            0xC0 => Some(TrackEventKind::Midi {
+                channel: u4_channel,
+                message: MidlyMidiMessage::ProgramChange { program: u7_p1 },
+            }),
+            0xE0 => Some(TrackEventKind::Midi {
+                channel: u4_channel,
+                message: MidlyMidiMessage::PitchBend {
+                    bend: PitchBend(u14::from(
+                        (param1 as u16 & 0x7F) | ((param2 as u16 & 0x7F) << 7),
+                    )),
+                },
+            }),
             _ => None,
         };
 
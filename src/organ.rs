@@ -5,12 +5,16 @@ use rust_i18n::t;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{BufReader, BufWriter, Read, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, mpsc};
 
+use crate::cache_manifest::CacheManifest;
+use crate::mapped_sample::{MappedMemoryStatus, MappedSample};
+use crate::memory_plan::MemoryReport;
 use crate::wav_converter;
-use crate::wav_converter::SampleMetadata;
+use crate::wav_converter::{CacheFormat, PhaseAlignment, SampleMetadata};
 
 use crate::organ_grandorgue;
 use crate::organ_hauptwerk;
@@ -23,10 +27,14 @@ pub struct Organ {
     pub ranks: HashMap<String, Rank>, // Keyed by rank ID (e.g., "013")
     pub windchest_groups: HashMap<String, WindchestGroup>, // Keyed by group ID (e.g. "001")
     pub tremulants: HashMap<String, Tremulant>, // Keyed by tremulant ID (e.g. "001")
+    pub enclosures: HashMap<String, Enclosure>, // Keyed by enclosure ID (e.g. "001")
+    pub manuals: Vec<Manual>,         // Sorted by ID; empty when the format carries no manuals
+    pub couplers: Vec<Coupler>,       // Sorted by ID
     pub base_path: PathBuf,           // The directory containing the .organ file
     pub cache_path: PathBuf,          // The directory for cached converted samples
     pub sample_cache: Option<HashMap<PathBuf, Arc<Vec<f32>>>>, // Cache for loaded samples
     pub metadata_cache: Option<HashMap<PathBuf, Arc<SampleMetadata>>>, // Cache for loop points etc.
+    pub mapped_samples: Option<HashMap<PathBuf, Arc<MappedSample>>>, // Memory-mapped float32 samples
 }
 
 /// Represents a single stop (a button on the TUI).
@@ -38,6 +46,61 @@ pub struct Stop {
     /// Division/register prefix for grouping (e.g. "HW", "SW", "P").
     /// Empty when the organ format doesn't carry division metadata.
     pub division_id: String,
+    /// ID of the manual that owns this stop, if the organ defines manuals.
+    pub manual_id: Option<String>,
+}
+
+/// Represents a manual (keyboard or pedalboard).
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Manual {
+    pub name: String,
+    pub id_str: String, // e.g., "001"
+    pub first_midi_note: u8,
+    pub key_count: usize,
+    pub stop_ids: Vec<String>,    // IDs of stops drawn on this manual
+    pub coupler_ids: Vec<String>, // IDs of couplers owned by this manual
+}
+
+/// Represents a coupler that forwards keys from one manual to another
+/// (intermanual) or to itself at a different pitch (intramanual).
+#[derive(Debug, Clone, Default)]
+pub struct Coupler {
+    pub name: String,
+    pub id_str: String,                // e.g., "003"
+    pub source_manual_id: String,      // Manual whose keys drive the coupler
+    pub destination_manual_id: String, // Manual whose stops get played
+    pub key_shift: i8,                 // Semitones, e.g. 12 for a super-octave
+    /// Unison-off couplers silence the source manual's own stops at unison pitch.
+    pub unison_off: bool,
+    /// Whether a note arriving through this coupler may continue through
+    /// subsequent couplers of the given kind on the destination manual.
+    pub couple_to_unison_intermanual: bool,
+    pub couple_to_upward_intermanual: bool,
+    pub couple_to_downward_intermanual: bool,
+    pub couple_to_upward_intramanual: bool,
+    pub couple_to_downward_intramanual: bool,
+}
+
+impl Coupler {
+    pub fn is_intramanual(&self) -> bool {
+        self.source_manual_id == self.destination_manual_id
+    }
+
+    /// Returns true if a note that arrived through `self` may be passed on through `next`.
+    pub fn allows_subsequent(&self, next: &Coupler) -> bool {
+        if next.unison_off {
+            return false;
+        }
+        match (next.is_intramanual(), next.key_shift.signum()) {
+            (false, 0) => self.couple_to_unison_intermanual,
+            (false, 1) => self.couple_to_upward_intermanual,
+            (false, _) => self.couple_to_downward_intermanual,
+            (true, 1) => self.couple_to_upward_intramanual,
+            (true, -1) => self.couple_to_downward_intramanual,
+            (true, _) => false,
+        }
+    }
 }
 
 /// Represents a rank (a set of pipes).
@@ -55,6 +118,20 @@ pub struct Rank {
     /// Keyed by MIDI note number (e.g., 36)
     pub pipes: HashMap<u8, Pipe>,
     pub is_percussive: bool,
+    /// Harmonic the pipes sound relative to the keys, in GrandOrgue's terms:
+    /// 8 for unison (8'), 16 for 4', 12 for a Quint 2 2/3', 40 for a Tierce 1 3/5'.
+    pub harmonic_number: f32,
+    /// Tremulant that switches the pipes to their samples recorded with the
+    /// tremulant running (Hauptwerk wave tremulants).
+    pub wave_tremulant_id: Option<String>,
+}
+
+impl Rank {
+    /// Semitones between the key and the pitch the pipes sound at, to the
+    /// nearest semitone. 7 for a Quint 2 2/3', 28 for a Tierce 1 3/5'.
+    pub fn sounding_interval(&self) -> i32 {
+        (12.0 * (self.harmonic_number / 8.0).log2()).round() as i32
+    }
 }
 
 /// Represents a Windchest Group (defines shared tremulants/enclosures).
@@ -64,6 +141,17 @@ pub struct WindchestGroup {
     pub name: String,
     pub id_str: String,
     pub tremulant_ids: Vec<String>, // IDs of tremulants attached to this group
+    pub enclosure_ids: Vec<String>, // IDs of enclosures (swell boxes) this group sits in
+}
+
+/// Represents an enclosure (swell box) whose shutters attenuate the windchest groups inside it.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default)]
+pub struct Enclosure {
+    pub name: String,
+    pub id_str: String,
+    /// Amplitude (0.0-1.0) that remains when the shutters are fully closed.
+    pub min_amplitude: f32,
 }
 
 /// Represents a Tremulant definitions.
@@ -83,11 +171,70 @@ pub struct Tremulant {
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct Pipe {
-    pub attack_sample_path: PathBuf,
+    /// Sorted by ascending `min_velocity`, wave tremulant samples after the
+    /// others. Empty if none of the pipe's attack samples could be loaded.
+    pub attacks: Vec<AttackSample>,
     pub gain_db: f32,
     pub pitch_tuning_cents: f32,
+    /// Sorted by key press time, wave tremulant samples after the others.
     pub releases: Vec<ReleaseSample>,
+}
+
+impl Pipe {
+    /// Indices in `attacks` of the alternatives for the highest velocity threshold
+    /// the given velocity reaches. Usually a single sample, several if the set
+    /// provides alternatives (round-robin / random) at that velocity. `tremulant`
+    /// picks the wave tremulant samples, if the pipe has any. `None` if the
+    /// pipe has no attacks.
+    pub fn attack_indices(&self, velocity: u8, tremulant: bool) -> Option<Range<usize>> {
+        let split = self.attacks.partition_point(|a| !a.is_tremulant);
+        let layer = tremulant_layer(self.attacks.len(), split, tremulant);
+        let attacks = &self.attacks[layer.clone()];
+        let threshold = attacks
+            .iter()
+            .rev()
+            .find(|a| a.min_velocity <= velocity)
+            .or(attacks.first())?
+            .min_velocity;
+        let start = attacks.partition_point(|a| a.min_velocity < threshold);
+        let end = attacks.partition_point(|a| a.min_velocity <= threshold);
+        Some(layer.start + start..layer.start + end)
+    }
+
+    /// Indices in `releases` of those recorded with the wave tremulant on
+    /// (`tremulant`) or off.
+    pub fn release_indices(&self, tremulant: bool) -> Range<usize> {
+        let split = self.releases.partition_point(|r| !r.is_tremulant);
+        tremulant_layer(self.releases.len(), split, tremulant)
+    }
+
+    /// Whether the pipe has attacks for both states of the wave tremulant.
+    pub fn has_tremulant_layer(&self) -> bool {
+        self.attacks.first().is_some_and(|a| !a.is_tremulant)
+            && self.attacks.last().is_some_and(|a| a.is_tremulant)
+    }
+}
+
+/// The indices before (`tremulant` false) or from `split` on in a list of
+/// `len` samples, or all of them if that part is empty.
+fn tremulant_layer(len: usize, split: usize, tremulant: bool) -> Range<usize> {
+    if (tremulant && split < len) || split == 0 {
+        split..len
+    } else {
+        0..split
+    }
+}
+
+/// Represents an attack sample and the minimum key velocity that selects it.
+#[derive(Debug)]
+pub struct AttackSample {
+    pub path: PathBuf,
+    pub min_velocity: u8,
     pub preloaded_bytes: Option<Arc<Vec<f32>>>,
+    /// Level of the loop, for starting releases in phase. `None` without a loop.
+    pub phase_alignment: Option<Arc<PhaseAlignment>>,
+    /// Recorded with the rank's wave tremulant running.
+    pub is_tremulant: bool,
 }
 
 /// Represents a release sample and its trigger condition.
@@ -97,6 +244,10 @@ pub struct ReleaseSample {
     /// Max key press time in ms. -1 means "default".
     pub max_key_press_time_ms: i64,
     pub preloaded_bytes: Option<Arc<Vec<f32>>>,
+    /// Where in its head to start, to continue the attack's phase.
+    pub phase_alignment: Option<Arc<PhaseAlignment>>,
+    /// Recorded with the rank's wave tremulant running.
+    pub is_tremulant: bool,
 }
 
 /// Internal struct to track unique conversion jobs for parallel processing
@@ -106,6 +257,7 @@ pub struct ConversionTask {
     // We store cents as an integer (x100) to allow hashing/equality checks
     pub tuning_cents_int: i32,
     pub to_16bit: bool,
+    pub cache_format: CacheFormat,
 }
 
 impl Organ {
@@ -113,68 +265,169 @@ impl Organ {
     /// This function dispatches to the correct parser based on the file extension.
     ///
     /// `max_preload_ram_mb`: The maximum amount of RAM (in MB) to dedicate to preloading attack transients.
+    /// `mmap_samples`: Map float32 cache files into memory instead of preloading transients.
+    /// Ignored with `pre_cache`.
+    #[allow(clippy::too_many_arguments)]
     pub fn load(
         path: &Path,
         convert_to_16_bit: bool,
+        cache_format: CacheFormat,
         pre_cache: bool,
+        mmap_samples: bool,
         original_tuning: bool,
         target_sample_rate: u32,
         progress_tx: Option<mpsc::Sender<(f32, String)>>,
         max_preload_ram_mb: usize,
     ) -> Result<Self> {
-        let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("");
         let loader_tx = progress_tx.clone();
+        let mut organ = Self::load_definition(
+            path,
+            convert_to_16_bit,
+            cache_format,
+            original_tuning,
+            target_sample_rate,
+            &loader_tx,
+        )?;
+
+        if pre_cache {
+            log::info!("[Organ] Pre-caching mode enabled. This may take a moment...");
+
+            // Initialize the caches
+            organ.sample_cache = Some(HashMap::new());
+            organ.metadata_cache = Some(HashMap::new());
+
+            // Run the parallel loader
+            organ.run_parallel_precache(target_sample_rate, progress_tx)?;
+        } else if mmap_samples {
+            organ.map_samples(target_sample_rate, progress_tx, max_preload_ram_mb);
+        } else {
+            // Dynamically calculate frame count based on RAM budget
+            organ.preload_attack_samples(
+                target_sample_rate,
+                progress_tx,
+                max_preload_ram_mb,
+                original_tuning,
+                convert_to_16_bit,
+            )?;
+        }
+        organ.load_phase_alignment(target_sample_rate, &loader_tx);
+        MemoryReport::of(&organ).log();
+        Ok(organ)
+    }
+
+    /// Parses an organ definition and brings its sample cache up to date,
+    /// converting whatever is missing. No samples are loaded into memory.
+    pub fn load_definition(
+        path: &Path,
+        convert_to_16_bit: bool,
+        cache_format: CacheFormat,
+        original_tuning: bool,
+        target_sample_rate: u32,
+        progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+    ) -> Result<Self> {
+        let organ = Self::parse_definition(
+            path,
+            convert_to_16_bit,
+            cache_format,
+            original_tuning,
+            target_sample_rate,
+            progress_tx,
+            None,
+        )?;
+        organ.update_cache_manifest();
+        Ok(organ)
+    }
+
+    /// Parses just enough of an organ definition to list the samples loading
+    /// it would convert. Nothing is extracted or converted, and the returned
+    /// organ has no ranks or stops, only its `base_path` and `cache_path`.
+    pub fn plan_definition(
+        path: &Path,
+        convert_to_16_bit: bool,
+        cache_format: CacheFormat,
+        original_tuning: bool,
+        target_sample_rate: u32,
+    ) -> Result<(Self, HashSet<ConversionTask>)> {
+        let mut tasks = HashSet::new();
+        let organ = Self::parse_definition(
+            path,
+            convert_to_16_bit,
+            cache_format,
+            original_tuning,
+            target_sample_rate,
+            &None,
+            Some(&mut tasks),
+        )?;
+        Ok((organ, tasks))
+    }
+
+    /// Dispatches to the loader for the organ's file format.
+    fn parse_definition(
+        path: &Path,
+        convert_to_16_bit: bool,
+        cache_format: CacheFormat,
+        original_tuning: bool,
+        target_sample_rate: u32,
+        progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+        planned_tasks: Option<&mut HashSet<ConversionTask>>,
+    ) -> Result<Self> {
+        let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("");
 
         // Dispatch to specific loader modules
-        let mut organ = if extension == "organ" {
+        if extension == "organ" {
             organ_grandorgue::load_grandorgue_dir(
                 path,
                 convert_to_16_bit,
+                cache_format,
                 original_tuning,
                 target_sample_rate,
-                &loader_tx,
-            )?
+                progress_tx,
+                planned_tasks,
+            )
         } else if extension == "orgue" {
             organ_grandorgue::load_grandorgue_zip(
                 path,
                 convert_to_16_bit,
+                cache_format,
                 original_tuning,
                 target_sample_rate,
-                &loader_tx,
-            )?
+                progress_tx,
+                planned_tasks,
+            )
         } else if extension == "Organ_Hauptwerk_xml" || extension == "xml" {
             organ_hauptwerk::load_hauptwerk(
                 path,
                 convert_to_16_bit,
+                cache_format,
                 false,
                 original_tuning,
                 target_sample_rate,
-                &loader_tx,
-            )?
+                progress_tx,
+                planned_tasks,
+            )
         } else {
-            return Err(anyhow!("Unsupported organ file format: {:?}", path));
-        };
-
-        if pre_cache {
-            log::info!("[Organ] Pre-caching mode enabled. This may take a moment...");
-
-            // Initialize the caches
-            organ.sample_cache = Some(HashMap::new());
-            organ.metadata_cache = Some(HashMap::new());
+            Err(anyhow!("Unsupported organ file format: {:?}", path))
+        }
+    }
 
-            // Run the parallel loader
-            organ.run_parallel_precache(target_sample_rate, progress_tx)?;
-        } else {
-            // Dynamically calculate frame count based on RAM budget
-            organ.preload_attack_samples(
-                target_sample_rate,
-                progress_tx,
-                max_preload_ram_mb,
-                original_tuning,
-                convert_to_16_bit,
-            )?;
+    /// Records which source every cached sample of the organ was converted
+    /// from, so the next load can tell when one of them changes.
+    fn update_cache_manifest(&self) {
+        let mut manifest = CacheManifest::load(&self.cache_path);
+        let cache_files: HashSet<&Path> = self
+            .ranks
+            .values()
+            .flat_map(|rank| rank.pipes.values())
+            .flat_map(|pipe| {
+                let attacks = pipe.attacks.iter().map(|a| a.path.as_path());
+                let releases = pipe.releases.iter().map(|r| r.path.as_path());
+                attacks.chain(releases)
+            })
+            .collect();
+        manifest.record(&self.base_path, &self.cache_path, cache_files);
+        if let Err(e) = manifest.save(&self.cache_path) {
+            log::warn!("[Cache] Failed to save manifest: {}", e);
         }
-        Ok(organ)
     }
 
     /// Normalizes a path to an absolute path without resolving symlinks.
@@ -200,16 +453,21 @@ impl Organ {
         }
     }
 
-    /// Helper to get the cache directory for a specific organ
-    pub fn get_organ_cache_dir(organ_name: &str) -> Result<PathBuf> {
+    /// Helper to get the directory holding every organ's cache (.../rusty-pipes/cache/)
+    pub fn get_cache_root_dir() -> Result<PathBuf> {
         let settings_path = confy::get_configuration_file_path("rusty-pipes", "settings")?;
 
         // Get the parent directory (e.g., .../Application Support/rusty-pipes/)
         let config_dir = settings_path
             .parent()
             .ok_or_else(|| anyhow::anyhow!("Could not get cache directory"))?;
-        // Append "cache/<OrganName>"
-        let organ_cache = config_dir.join("cache").join(organ_name);
+        Ok(config_dir.join("cache"))
+    }
+
+    /// Helper to get the cache directory for a specific organ
+    pub fn get_organ_cache_dir(organ_name: &str) -> Result<PathBuf> {
+        // Append "<OrganName>" to the cache root
+        let organ_cache = Self::get_cache_root_dir()?.join(organ_name);
         if !organ_cache.exists() {
             std::fs::create_dir_all(&organ_cache)?;
         }
@@ -234,6 +492,38 @@ impl Organ {
         target_sample_rate: u32,
         progress_tx: &Option<mpsc::Sender<(f32, String)>>,
     ) -> Result<()> {
+        // Samples converted from a source that changed since are deleted here,
+        // so the tasks below convert them again.
+        let mut manifest = CacheManifest::load(cache_path);
+        let changed = manifest.invalidate_changed_sources(base_path, cache_path);
+        if !changed.is_empty() {
+            log::info!(
+                "{} source sample(s) changed since they were converted.",
+                changed.len()
+            );
+            if let Some(tx) = progress_tx {
+                let _ = tx.send((
+                    0.0,
+                    t!(
+                        "gui.progress_sources_changed_fmt",
+                        count = changed.len(),
+                        first = changed[0]
+                    )
+                    .to_string(),
+                ));
+            }
+            if let Err(e) = manifest.save(cache_path) {
+                log::warn!("[Cache] Failed to save manifest: {}", e);
+            }
+            // The release alignment analysis and the transient cache were made
+            // from the old samples.
+            let _ = fs::remove_file(cache_path.join(PHASE_ALIGNMENT_CACHE_FILE));
+            let organ_name = cache_path.file_name().map(|n| n.to_string_lossy());
+            if let Some(Ok(transient)) = organ_name.map(|n| Self::get_transient_cache_file(&n)) {
+                let _ = fs::remove_file(transient);
+            }
+        }
+
         let task_list: Vec<ConversionTask> = tasks.into_iter().collect();
         let total = task_list.len();
         if total == 0 {
@@ -252,6 +542,7 @@ impl Organ {
                 cache_path,
                 cents,
                 task.to_16bit,
+                task.cache_format,
                 target_sample_rate,
             ) {
                 Ok(_) => {}
@@ -278,6 +569,11 @@ impl Organ {
 
     /// Helper to get the transient cache directory (~/.config/transientcache/)
     fn get_transient_cache_path(&self) -> Result<PathBuf> {
+        Self::get_transient_cache_file(&self.name)
+    }
+
+    /// The transient cache file of the named organ.
+    pub fn get_transient_cache_file(organ_name: &str) -> Result<PathBuf> {
         let settings_path = confy::get_configuration_file_path("rusty-pipes", "settings")?;
         let config_dir = settings_path
             .parent()
@@ -289,8 +585,7 @@ impl Organ {
         }
 
         // Sanitize organ name for filename
-        let safe_name: String = self
-            .name
+        let safe_name: String = organ_name
             .chars()
             .map(|c| {
                 if c.is_alphanumeric() || c == '-' || c == '_' {
@@ -525,7 +820,9 @@ impl Organ {
         let mut paths = HashSet::new();
         for rank in self.ranks.values() {
             for pipe in rank.pipes.values() {
-                paths.insert(pipe.attack_sample_path.clone());
+                for a in &pipe.attacks {
+                    paths.insert(a.path.clone());
+                }
                 for r in &pipe.releases {
                     paths.insert(r.path.clone());
                 }
@@ -539,23 +836,8 @@ impl Organ {
             return Ok(());
         }
 
-        // Calculate frames per file based on RAM budget
-        // Total bytes available
-        let total_bytes_budget = max_preload_ram_mb as usize * 1024 * 1024;
-
-        // Bytes available per unique file
-        let bytes_per_file = total_bytes_budget / total_files;
-
-        // Size of one f32 sample
-        let bytes_per_float = std::mem::size_of::<f32>();
-
-        // Heuristic: Assume Stereo (2 channels) to be safe.
-        // If files are mono, we simply load less duration than we could have, but we won't crash RAM.
-        // If files are stereo, we hit the target exactly.
-        let assumed_channels = 2;
-        let bytes_per_frame = bytes_per_float * assumed_channels;
-
-        let frames_to_preload = bytes_per_file / bytes_per_frame;
+        let frames_to_preload = preload_frames_per_sample(max_preload_ram_mb, total_files);
+        let bytes_per_file = max_preload_ram_mb * 1024 * 1024 / total_files;
 
         // Convert to milliseconds for logging (just for user info)
         let ms_preload = (frames_to_preload as f32 / target_sample_rate as f32) * 1000.0;
@@ -591,10 +873,15 @@ impl Organ {
                     convert_to_16bit,
                     &progress_tx,
                 ) {
-                    if let Some(tx) = &progress_tx {
-                        let _ = tx.send((1.0, t!("gui.progress_cache_done").to_string()));
+                    // Sample paths change with the cache format, for one
+                    if unique_paths.iter().all(|p| cached_data.contains_key(p)) {
+                        if let Some(tx) = &progress_tx {
+                            let _ = tx.send((1.0, t!("gui.progress_cache_done").to_string()));
+                        }
+                        loaded_chunks = Some(cached_data);
+                    } else {
+                        log::info!("[Cache] Sample files changed. Invalidating.");
                     }
-                    loaded_chunks = Some(cached_data);
                 }
             }
         }
@@ -655,8 +942,10 @@ impl Organ {
         // Assign the loaded chunks back to the pipes
         for rank in self.ranks.values_mut() {
             for pipe in rank.pipes.values_mut() {
-                if let Some(data) = chunks_map.get(&pipe.attack_sample_path) {
-                    pipe.preloaded_bytes = Some(data.clone());
+                for attack in &mut pipe.attacks {
+                    if let Some(data) = chunks_map.get(&attack.path) {
+                        attack.preloaded_bytes = Some(data.clone());
+                    }
                 }
                 for release in &mut pipe.releases {
                     if let Some(data) = chunks_map.get(&release.path) {
@@ -673,11 +962,109 @@ impl Organ {
         Ok(())
     }
 
+    /// Attaches the release alignment analysis to every attack and release.
+    /// Results are kept in the organ's cache directory, so only samples that are
+    /// new or changed since the last load are analysed.
+    pub fn load_phase_alignment(
+        &mut self,
+        target_sample_rate: u32,
+        progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+    ) {
+        let cache_path = Self::get_organ_cache_dir(&self.name)
+            .map(|dir| dir.join(PHASE_ALIGNMENT_CACHE_FILE))
+            .ok();
+        let mut cached = cache_path
+            .as_deref()
+            .and_then(|path| load_phase_alignment_cache(path, target_sample_rate))
+            .unwrap_or_default();
+
+        let mut jobs: HashMap<PathBuf, bool> = HashMap::new();
+        for rank in self.ranks.values() {
+            for pipe in rank.pipes.values() {
+                for attack in &pipe.attacks {
+                    jobs.entry(attack.path.clone()).or_insert(false);
+                }
+                for release in &pipe.releases {
+                    jobs.insert(release.path.clone(), true);
+                }
+            }
+        }
+        let stale: Vec<(PathBuf, bool, u64)> = jobs
+            .into_iter()
+            .filter_map(|(path, is_release)| {
+                let file_len = fs::metadata(&path).ok()?.len();
+                let up_to_date = cached
+                    .get(&path)
+                    .is_some_and(|entry| entry.file_len == file_len);
+                (!up_to_date).then_some((path, is_release, file_len))
+            })
+            .collect();
+
+        if !stale.is_empty() {
+            log::info!(
+                "[Cache] Analysing release alignment of {} samples...",
+                stale.len()
+            );
+            let total = stale.len();
+            let done = AtomicUsize::new(0);
+            let analysed: Vec<(PathBuf, CachedAlignment)> = stale
+                .par_iter()
+                .map(|(path, is_release, file_len)| {
+                    let result = if *is_release {
+                        wav_converter::analyse_release_head(path, target_sample_rate).map(Some)
+                    } else {
+                        wav_converter::analyse_attack_loop(path, target_sample_rate)
+                    };
+                    let alignment = result.unwrap_or_else(|e| {
+                        log::warn!("Failed to analyse {:?} for release alignment: {}", path, e);
+                        None
+                    });
+                    let current = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    if let Some(tx) = progress_tx
+                        && (current.is_multiple_of(50) || current == total)
+                    {
+                        let _ = tx.send((
+                            current as f32 / total as f32,
+                            t!("gui.progress_phase_alignment").to_string(),
+                        ));
+                    }
+                    (
+                        path.clone(),
+                        CachedAlignment {
+                            file_len: *file_len,
+                            alignment: alignment.map(Arc::new),
+                        },
+                    )
+                })
+                .collect();
+            cached.extend(analysed);
+            if let Some(path) = &cache_path
+                && let Err(e) = save_phase_alignment_cache(path, target_sample_rate, &cached)
+            {
+                log::error!("Failed to save release alignment cache: {}", e);
+            }
+        }
+
+        let lookup = |path: &Path| cached.get(path).and_then(|entry| entry.alignment.clone());
+        for rank in self.ranks.values_mut() {
+            for pipe in rank.pipes.values_mut() {
+                for attack in &mut pipe.attacks {
+                    attack.phase_alignment = lookup(&attack.path);
+                }
+                for release in &mut pipe.releases {
+                    release.phase_alignment = lookup(&release.path);
+                }
+            }
+        }
+    }
+
     fn get_all_unique_sample_paths(&self) -> HashSet<PathBuf> {
         let mut paths = HashSet::new();
         for rank in self.ranks.values() {
             for pipe in rank.pipes.values() {
-                paths.insert(pipe.attack_sample_path.clone());
+                for attack in &pipe.attacks {
+                    paths.insert(attack.path.clone());
+                }
                 for release in &pipe.releases {
                     paths.insert(release.path.clone());
                 }
@@ -686,6 +1073,114 @@ impl Organ {
         paths
     }
 
+    /// Maps every float32 sample into memory, then warms up the attack regions:
+    /// the part of each attack before its loop starts, as far as the RAM
+    /// budget allows. Samples that can't be mapped stream from disk.
+    fn map_samples(
+        &mut self,
+        target_sample_rate: u32,
+        progress_tx: Option<mpsc::Sender<(f32, String)>>,
+        max_preload_ram_mb: usize,
+    ) {
+        let paths: Vec<PathBuf> = self.get_all_unique_sample_paths().into_iter().collect();
+        let total = paths.len();
+        if total == 0 {
+            return;
+        }
+        log::info!("[Cache] Mapping {} unique samples into memory...", total);
+        let report = |progress: f32, message: &str| {
+            if let Some(tx) = &progress_tx {
+                let _ = tx.send((progress, message.to_string()));
+            }
+        };
+
+        let mapped_count = AtomicUsize::new(0);
+        let mapped: HashMap<PathBuf, Arc<MappedSample>> = paths
+            .par_iter()
+            .filter_map(|path| {
+                let result = MappedSample::open(path, target_sample_rate);
+                let count = mapped_count.fetch_add(1, Ordering::Relaxed) + 1;
+                if count.is_multiple_of(50) || count == total {
+                    report(count as f32 / total as f32, &t!("gui.progress_map_samples"));
+                }
+                match result {
+                    Ok(sample) => Some((path.clone(), Arc::new(sample?))),
+                    Err(e) => {
+                        log::warn!("Failed to map {:?}: {}", path, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        if mapped.len() < total {
+            log::warn!(
+                "[Cache] {} of {} samples aren't float32 WAV files and will stream from disk. \
+                 Turn off 16-bit conversion and cache compression to map them.",
+                total - mapped.len(),
+                total
+            );
+        }
+
+        // Warm up the attacks
+        let attacks: HashSet<&Path> = self
+            .ranks
+            .values()
+            .flat_map(|rank| rank.pipes.values())
+            .flat_map(|pipe| pipe.attacks.iter().map(|a| a.path.as_path()))
+            .collect();
+        let attacks: Vec<&Arc<MappedSample>> =
+            attacks.iter().filter_map(|p| mapped.get(*p)).collect();
+        let budget_frames = preload_frames_per_sample(max_preload_ram_mb, attacks.len());
+        let warmed_count = AtomicUsize::new(0);
+        attacks.par_iter().for_each(|sample| {
+            let loop_start = sample
+                .loop_info
+                .map_or(usize::MAX, |(start, _)| start as usize);
+            sample.warm_up(loop_start.min(budget_frames));
+            let count = warmed_count.fetch_add(1, Ordering::Relaxed) + 1;
+            if count.is_multiple_of(50) || count == attacks.len() {
+                report(
+                    count as f32 / attacks.len() as f32,
+                    &t!("gui.progress_warm_up"),
+                );
+            }
+        });
+
+        let status = Self::mapped_status_of(mapped.values());
+        log::info!(
+            "[Cache] Mapped {} samples ({} MB), {} MB resident after warm-up.",
+            mapped.len(),
+            status.mapped_bytes / (1024 * 1024),
+            status.resident_bytes.unwrap_or(0) / (1024 * 1024)
+        );
+        self.mapped_samples = Some(mapped);
+    }
+
+    /// Size of the memory-mapped samples and how much of it is in RAM. `None`
+    /// unless the organ plays from mapped samples.
+    pub fn mapped_memory_status(&self) -> Option<MappedMemoryStatus> {
+        self.mapped_samples
+            .as_ref()
+            .map(|mapped| Self::mapped_status_of(mapped.values()))
+    }
+
+    fn mapped_status_of<'a>(
+        samples: impl Iterator<Item = &'a Arc<MappedSample>>,
+    ) -> MappedMemoryStatus {
+        let mut status = MappedMemoryStatus {
+            mapped_bytes: 0,
+            resident_bytes: Some(0),
+        };
+        for sample in samples {
+            status.mapped_bytes += sample.mapped_bytes() as u64;
+            status.resident_bytes = status
+                .resident_bytes
+                .zip(sample.resident_bytes())
+                .map(|(total, resident)| total + resident as u64);
+        }
+        status
+    }
+
     /// Runs the pre-caching in parallel after the organ struct is built.
     fn run_parallel_precache(
         &mut self,
@@ -735,3 +1230,129 @@ impl Organ {
         Ok(())
     }
 }
+
+/// Frames of each of `sample_count` samples that fit into the RAM budget.
+pub fn preload_frames_per_sample(max_preload_ram_mb: usize, sample_count: usize) -> usize {
+    // Total bytes available
+    let total_bytes_budget = max_preload_ram_mb * 1024 * 1024;
+
+    // Bytes available per unique file
+    let bytes_per_file = total_bytes_budget / sample_count.max(1);
+
+    // Size of one f32 sample
+    let bytes_per_float = std::mem::size_of::<f32>();
+
+    // Heuristic: Assume Stereo (2 channels) to be safe.
+    // If files are mono, we simply load less duration than we could have, but we won't crash RAM.
+    // If files are stereo, we hit the target exactly.
+    let assumed_channels = 2;
+    let bytes_per_frame = bytes_per_float * assumed_channels;
+
+    bytes_per_file / bytes_per_frame
+}
+
+/// File in the organ's cache directory holding the release alignment analysis.
+pub const PHASE_ALIGNMENT_CACHE_FILE: &str = "phase_alignment.bin";
+
+/// A sample's alignment analysis, with the file size it was made from.
+struct CachedAlignment {
+    file_len: u64,
+    alignment: Option<Arc<PhaseAlignment>>,
+}
+
+fn read_u32(reader: &mut impl Read) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    reader.read_exact(&mut buf).ok()?;
+    Some(u32::from_le_bytes(buf))
+}
+
+fn read_u64(reader: &mut impl Read) -> Option<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf).ok()?;
+    Some(u64::from_le_bytes(buf))
+}
+
+/// Reads the alignment cache. `None` if it is missing, corrupt or was made for
+/// another sample rate.
+fn load_phase_alignment_cache(
+    path: &Path,
+    expected_sample_rate: u32,
+) -> Option<HashMap<PathBuf, CachedAlignment>> {
+    let file = fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+
+    let mut magic = [0u8; 4];
+    if reader.read_exact(&mut magic).is_err() || &magic != b"ALGN" {
+        log::warn!("[Cache] Release alignment cache invalid. Rebuilding.");
+        return None;
+    }
+    if read_u32(&mut reader)? != expected_sample_rate {
+        log::info!("[Cache] Sample rate changed. Rebuilding release alignment cache.");
+        return None;
+    }
+
+    let count = read_u64(&mut reader)? as usize;
+    let mut entries = HashMap::with_capacity(count);
+    for _ in 0..count {
+        let path_len = read_u64(&mut reader)? as usize;
+        let mut path_bytes = vec![0u8; path_len];
+        reader.read_exact(&mut path_bytes).ok()?;
+        let sample_path = PathBuf::from(String::from_utf8_lossy(&path_bytes).to_string());
+        let file_len = read_u64(&mut reader)?;
+
+        let mut flag = [0u8; 1];
+        reader.read_exact(&mut flag).ok()?;
+        let alignment = if flag[0] != 0 {
+            let peak_amplitude = f32::from_bits(read_u32(&mut reader)?);
+            let offset_count = read_u32(&mut reader)? as usize;
+            let release_offsets = (0..offset_count)
+                .map(|_| read_u32(&mut reader))
+                .collect::<Option<Vec<u32>>>()?;
+            Some(Arc::new(PhaseAlignment {
+                peak_amplitude,
+                release_offsets,
+            }))
+        } else {
+            None
+        };
+        entries.insert(
+            sample_path,
+            CachedAlignment {
+                file_len,
+                alignment,
+            },
+        );
+    }
+    Some(entries)
+}
+
+fn save_phase_alignment_cache(
+    path: &Path,
+    sample_rate: u32,
+    entries: &HashMap<PathBuf, CachedAlignment>,
+) -> Result<()> {
+    let file = fs::File::create(path)?;
+    let mut writer = BufWriter::new(file);
+    writer.write_all(b"ALGN")?;
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(entries.len() as u64).to_le_bytes())?;
+    for (sample_path, entry) in entries {
+        let path_str = sample_path.to_string_lossy();
+        writer.write_all(&(path_str.len() as u64).to_le_bytes())?;
+        writer.write_all(path_str.as_bytes())?;
+        writer.write_all(&entry.file_len.to_le_bytes())?;
+        match &entry.alignment {
+            Some(alignment) => {
+                writer.write_all(&[1u8])?;
+                writer.write_all(&alignment.peak_amplitude.to_bits().to_le_bytes())?;
+                writer.write_all(&(alignment.release_offsets.len() as u32).to_le_bytes())?;
+                for offset in &alignment.release_offsets {
+                    writer.write_all(&offset.to_le_bytes())?;
+                }
+            }
+            None => writer.write_all(&[0u8])?,
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
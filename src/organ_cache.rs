@@ -0,0 +1,488 @@
+use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use crate::cache_manifest::{CACHE_MANIFEST_FILE, SourceIndex};
+use crate::flac::{FlacSampleReader, read_flac_frame_count, read_flac_metadata};
+use crate::organ::{Organ, PHASE_ALIGNMENT_CACHE_FILE};
+use crate::organ_grandorgue;
+use crate::organ_hauptwerk;
+use crate::wav_converter::{CacheFileName, CacheFormat, SourceFormat};
+
+/// Directory in a zipped organ's cache holding the samples extracted from the archive.
+const EXTRACTED_SOURCE_DIR: &str = "extracted_source";
+
+/// Frames a converted sample may differ from the length expected from its
+/// source, for the resampler's rounding. A truncated file is off by far more.
+const LENGTH_TOLERANCE_FRAMES: u64 = 16;
+
+/// The settings that decide which variant of its cache an organ plays from.
+#[derive(Debug, Clone, Copy)]
+pub struct CacheSettings {
+    pub sample_rate: u32,
+    pub convert_to_16_bit: bool,
+    pub cache_format: CacheFormat,
+    pub original_tuning: bool,
+}
+
+/// Disk usage of the samples converted for one output rate, bit depth and format.
+#[derive(Debug, Clone, Serialize)]
+pub struct VariantUsage {
+    pub sample_rate: u32,
+    pub bits_per_sample: u16,
+    pub format: CacheFormat,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Disk usage of one organ's cache.
+#[derive(Debug, Clone, Serialize)]
+pub struct OrganCacheUsage {
+    /// Name of the cache directory.
+    pub name: String,
+    /// The library organ the cache belongs to. None if it is not in the library.
+    pub organ_file: Option<PathBuf>,
+    pub variants: Vec<VariantUsage>,
+    /// Samples extracted from an archive, the release alignment analysis and
+    /// the cache manifest.
+    pub other_bytes: u64,
+    /// Attack heads kept in the transient cache for fast startup.
+    pub transient_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// A cache file that does not match what it was converted from.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheProblem {
+    pub path: PathBuf,
+    pub problem: String,
+}
+
+/// Files deleted from an organ's cache.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PruneSummary {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+/// Where an organ's cache lives and what its sample paths are relative to.
+struct OrganLocation {
+    name: String,
+    cache_dir: PathBuf,
+    source_dir: PathBuf,
+}
+
+impl OrganLocation {
+    /// Resolves the directories the loaders use for an organ definition.
+    fn resolve(organ_file: &Path) -> Result<Self> {
+        let extension = organ_file
+            .extension()
+            .and_then(|s| s.to_str())
+            .unwrap_or("");
+        let name = cache_name(organ_file)
+            .ok_or_else(|| anyhow!("Unsupported organ file format: {:?}", organ_file))?;
+        let cache_dir = Organ::get_cache_root_dir()?.join(&name);
+        let source_dir = match extension {
+            "organ" => organ_grandorgue::get_organ_base_path(organ_file)?,
+            "orgue" => cache_dir.join(EXTRACTED_SOURCE_DIR),
+            _ => organ_hauptwerk::detect_hauptwerk_organ_root(organ_file)?,
+        };
+        Ok(Self {
+            name,
+            cache_dir,
+            source_dir,
+        })
+    }
+
+    /// The converted samples in the cache, with their parsed names.
+    fn cached_samples(&self) -> Vec<(PathBuf, CacheFileName)> {
+        let mut files = Vec::new();
+        collect_files(&self.cache_dir, &mut files);
+        files
+            .into_iter()
+            .filter(|path| !path.starts_with(self.cache_dir.join(EXTRACTED_SOURCE_DIR)))
+            .filter_map(|path| {
+                let name = CacheFileName::parse(path.file_name()?.to_str()?)?;
+                Some((path, name))
+            })
+            .collect()
+    }
+
+    /// Maps each cached sample to the source it was converted from, if that
+    /// still exists. Sources are matched by directory and file stem. Also
+    /// returns the source directories that could not be listed.
+    fn find_sources(
+        &self,
+        samples: &[(PathBuf, CacheFileName)],
+    ) -> (Vec<Option<PathBuf>>, Vec<PathBuf>) {
+        let mut index = SourceIndex::new(&self.source_dir, &self.cache_dir);
+        let sources = samples
+            .iter()
+            .map(|(path, name)| index.find(path, &name.stem))
+            .collect();
+        (sources, index.unreadable_dirs().to_vec())
+    }
+}
+
+/// Name of the cache directory the loaders use for an organ definition.
+pub fn cache_name(organ_file: &Path) -> Option<String> {
+    match organ_file.extension().and_then(|s| s.to_str()) {
+        Some("organ" | "orgue") => Some(organ_grandorgue::get_organ_name(organ_file)),
+        Some("Organ_Hauptwerk_xml" | "xml") => Some(organ_hauptwerk::get_organ_name(organ_file)),
+        _ => None,
+    }
+}
+
+/// Appends every file below `dir` to `files`.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+/// Deletes a cache file, then its directory if that is left empty.
+fn remove_cache_file(path: &Path, summary: &mut PruneSummary) -> Result<()> {
+    let bytes = file_size(path);
+    fs::remove_file(path)?;
+    summary.files += 1;
+    summary.bytes += bytes;
+    if let Some(parent) = path.parent() {
+        // Fails while the directory still holds other files
+        let _ = fs::remove_dir(parent);
+    }
+    Ok(())
+}
+
+/// Disk usage of every organ cache, per conversion variant. `organ_files` are
+/// the library organs, used to tell which cache belongs to which definition.
+pub fn list(organ_files: &[PathBuf]) -> Result<Vec<OrganCacheUsage>> {
+    let cache_root = Organ::get_cache_root_dir()?;
+    let library: HashMap<String, &PathBuf> = organ_files
+        .iter()
+        .filter_map(|path| Some((cache_name(path)?, path)))
+        .collect();
+
+    let mut names: Vec<String> = match fs::read_dir(&cache_root) {
+        Ok(entries) => entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort();
+
+    let mut usages = Vec::with_capacity(names.len());
+    for name in names {
+        let cache_dir = cache_root.join(&name);
+        let mut files = Vec::new();
+        collect_files(&cache_dir, &mut files);
+
+        let mut variants: Vec<VariantUsage> = Vec::new();
+        let mut other_bytes = 0;
+        for path in &files {
+            let bytes = file_size(path);
+            let parsed = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(CacheFileName::parse)
+                .filter(|_| !path.starts_with(cache_dir.join(EXTRACTED_SOURCE_DIR)));
+            let Some(parsed) = parsed else {
+                other_bytes += bytes;
+                continue;
+            };
+            let existing = variants.iter_mut().find(|v| {
+                v.sample_rate == parsed.sample_rate
+                    && v.bits_per_sample == parsed.bits_per_sample
+                    && v.format == parsed.format
+            });
+            match existing {
+                Some(variant) => {
+                    variant.files += 1;
+                    variant.bytes += bytes;
+                }
+                None => variants.push(VariantUsage {
+                    sample_rate: parsed.sample_rate,
+                    bits_per_sample: parsed.bits_per_sample,
+                    format: parsed.format,
+                    files: 1,
+                    bytes,
+                }),
+            }
+        }
+        variants.sort_by_key(|v| {
+            (
+                v.sample_rate,
+                v.bits_per_sample,
+                v.format == CacheFormat::Flac,
+            )
+        });
+
+        let transient_bytes = Organ::get_transient_cache_file(&name)
+            .map(|path| file_size(&path))
+            .unwrap_or(0);
+        let total_bytes =
+            variants.iter().map(|v| v.bytes).sum::<u64>() + other_bytes + transient_bytes;
+        usages.push(OrganCacheUsage {
+            organ_file: library.get(&name).map(|path| path.to_path_buf()),
+            name,
+            variants,
+            other_bytes,
+            transient_bytes,
+            total_bytes,
+        });
+    }
+    Ok(usages)
+}
+
+/// Checks every converted sample of an organ: that its header is intact, that
+/// the file holds all the audio the header promises, and that format and
+/// length agree with the source sample it was converted from.
+pub fn verify(organ_file: &Path) -> Result<Vec<CacheProblem>> {
+    let location = OrganLocation::resolve(organ_file)?;
+    let samples = location.cached_samples();
+    let (sources, _) = location.find_sources(&samples);
+
+    let mut problems: Vec<CacheProblem> = samples
+        .par_iter()
+        .zip(sources.par_iter())
+        .filter_map(|((path, name), source)| {
+            let problem = match check_cache_file(path, name, source.as_deref()) {
+                Ok(None) => return None,
+                Ok(Some(problem)) => problem,
+                Err(e) => format!("Unreadable: {}", e),
+            };
+            Some(CacheProblem {
+                path: path.clone(),
+                problem,
+            })
+        })
+        .collect();
+    problems.sort_by(|a, b| a.path.cmp(&b.path));
+    log::info!(
+        "[Cache] Verified {} cached samples of {}: {} problem(s).",
+        samples.len(),
+        location.name,
+        problems.len()
+    );
+    Ok(problems)
+}
+
+/// Describes what is wrong with one cache file, if anything.
+fn check_cache_file(
+    path: &Path,
+    name: &CacheFileName,
+    source: Option<&Path>,
+) -> Result<Option<String>> {
+    // Header and length of the file itself
+    let (sample_rate, channels, bits_per_sample, frames) = match name.format {
+        CacheFormat::Wav => {
+            let mut reader = BufReader::new(File::open(path)?);
+            let (fmt, _, data_offset, data_size) =
+                crate::wav::parse_wav_metadata(&mut reader, path)?;
+            let file_len = file_size(path);
+            if data_offset + data_size as u64 > file_len {
+                return Ok(Some(format!(
+                    "Truncated: {} of {} bytes of audio data",
+                    file_len.saturating_sub(data_offset),
+                    data_size
+                )));
+            }
+            let block_align = (fmt.num_channels as u64 * fmt.bits_per_sample as u64 / 8).max(1);
+            (
+                fmt.sample_rate,
+                fmt.num_channels,
+                fmt.bits_per_sample,
+                data_size as u64 / block_align,
+            )
+        }
+        CacheFormat::Flac => {
+            let (fmt, _) = read_flac_metadata(path)?;
+            let frames = read_flac_frame_count(path)?;
+            if frames == 0 {
+                return Ok(Some(
+                    "Incomplete: the conversion never finished".to_string(),
+                ));
+            }
+            let decoded = FlacSampleReader::open(path)?.count() as u64 / fmt.num_channels as u64;
+            if decoded != frames {
+                return Ok(Some(format!(
+                    "Truncated: {} of {} frames decode",
+                    decoded, frames
+                )));
+            }
+            (
+                fmt.sample_rate,
+                fmt.num_channels,
+                fmt.bits_per_sample,
+                frames,
+            )
+        }
+    };
+    if sample_rate != name.sample_rate || bits_per_sample != name.bits_per_sample {
+        return Ok(Some(format!(
+            "Header says {} Hz {}-bit, file name {} Hz {}-bit",
+            sample_rate, bits_per_sample, name.sample_rate, name.bits_per_sample
+        )));
+    }
+
+    // Agreement with the source
+    let Some(source_path) = source else {
+        return Ok(Some("Source sample is missing".to_string()));
+    };
+    let source = SourceFormat::read(source_path)?;
+    if source.channels != channels {
+        return Ok(Some(format!(
+            "{} channel(s), source has {}",
+            channels, source.channels
+        )));
+    }
+    // Release tails are cut from the source, so only attacks have a known length
+    if !name.is_release && source.frames > 0 {
+        let expected = source.converted_frames(name.pitch_tuning_cents, name.sample_rate);
+        if frames.abs_diff(expected) > LENGTH_TOLERANCE_FRAMES {
+            return Ok(Some(format!(
+                "{} frames, expected {} from the source",
+                frames, expected
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Deletes the converted samples of an organ the given settings no longer
+/// play: other rates, bit depths and formats, and samples whose source is
+/// gone. Variants for other tunings are kept, since which tunings are in use
+/// depends on the organ definition. With `dry_run` nothing is deleted and the
+/// summary counts what would be.
+///
+/// Nothing is pruned unless every source could be looked up: with the sample
+/// directory missing (e.g. on a drive that isn't mounted) or unreadable,
+/// every cached sample would look unused.
+pub fn prune(organ_file: &Path, settings: &CacheSettings, dry_run: bool) -> Result<PruneSummary> {
+    let location = OrganLocation::resolve(organ_file)?;
+    if !location.source_dir.is_dir() {
+        return Err(anyhow!(
+            "Sample directory {:?} of {} not found. Nothing was pruned.",
+            location.source_dir,
+            location.name
+        ));
+    }
+    let samples = location.cached_samples();
+    let (sources, unreadable_dirs) = location.find_sources(&samples);
+    if let Some(dir) = unreadable_dirs.first() {
+        return Err(anyhow!(
+            "Sample directory {:?} could not be read. Nothing was pruned.",
+            dir
+        ));
+    }
+
+    let mut unused = Vec::new();
+    for ((path, name), source) in samples.iter().zip(&sources) {
+        let expected = match source {
+            Some(source) => {
+                let source_format = SourceFormat::read(source).map_err(|e| {
+                    anyhow!(
+                        "Source sample {:?} could not be read ({}). Nothing was pruned.",
+                        source,
+                        e
+                    )
+                })?;
+                source_format.cache_file_name(
+                    &name.stem,
+                    name.is_release,
+                    name.pitch_tuning_cents,
+                    settings.convert_to_16_bit,
+                    settings.cache_format,
+                    settings.sample_rate,
+                )
+            }
+            None => None,
+        };
+        if expected.as_ref() != Some(name) {
+            unused.push(path);
+        }
+    }
+
+    let mut summary = PruneSummary::default();
+    for path in unused {
+        if dry_run {
+            log::debug!("[Cache] Would prune unused {:?}", path);
+            summary.files += 1;
+            summary.bytes += file_size(path);
+        } else {
+            log::debug!("[Cache] Pruning unused {:?}", path);
+            remove_cache_file(path, &mut summary)?;
+        }
+    }
+    log::info!(
+        "[Cache] {} {} unused file(s) ({} bytes) from {}.",
+        if dry_run { "Would prune" } else { "Pruned" },
+        summary.files,
+        summary.bytes,
+        location.name
+    );
+    Ok(summary)
+}
+
+/// Throws away every converted sample of an organ, with its release alignment
+/// analysis, manifest and transient cache, and converts the samples again for
+/// the given settings. The loader runs the conversion through
+/// `Organ::process_tasks_parallel`.
+pub fn rebuild(
+    organ_file: &Path,
+    settings: &CacheSettings,
+    progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+) -> Result<PruneSummary> {
+    let location = OrganLocation::resolve(organ_file)?;
+    let mut summary = PruneSummary::default();
+    for (path, _) in location.cached_samples() {
+        remove_cache_file(&path, &mut summary)?;
+    }
+    for path in [
+        Some(location.cache_dir.join(PHASE_ALIGNMENT_CACHE_FILE)),
+        Some(location.cache_dir.join(CACHE_MANIFEST_FILE)),
+        Organ::get_transient_cache_file(&location.name).ok(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        if path.exists() {
+            remove_cache_file(&path, &mut summary)?;
+        }
+    }
+    log::info!(
+        "[Cache] Removed {} file(s) ({} bytes) from {}. Rebuilding...",
+        summary.files,
+        summary.bytes,
+        location.name
+    );
+
+    let mut organ = Organ::load_definition(
+        organ_file,
+        settings.convert_to_16_bit,
+        settings.cache_format,
+        settings.original_tuning,
+        settings.sample_rate,
+        progress_tx,
+    )?;
+    organ.load_phase_alignment(settings.sample_rate, progress_tx);
+    Ok(summary)
+}
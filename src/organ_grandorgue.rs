@@ -8,10 +8,12 @@ use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
 use std::sync::{Mutex, mpsc};
 
+use crate::cache_manifest::CacheManifest;
 use crate::organ::{
-    ConversionTask, Organ, Pipe, Rank, ReleaseSample, Stop, Tremulant, WindchestGroup,
+    AttackSample, ConversionTask, Coupler, Enclosure, Manual, Organ, Pipe, Rank, ReleaseSample,
+    Stop, Tremulant, WindchestGroup,
 };
-use crate::wav_converter;
+use crate::wav_converter::{self, CacheFormat};
 
 trait NonEmpty: Sized {
     fn non_empty_or(self, default: Option<Self>) -> Option<Self>;
@@ -48,8 +50,18 @@ fn infer_division_from_name(name: &str) -> String {
     String::new()
 }
 
+/// Normalizes an object reference ("1", "01", "001") to the zero-padded
+/// form used in section names, so manual/stop/coupler IDs compare equal.
+fn normalize_ref(raw: &str) -> String {
+    match raw.trim().parse::<u32>() {
+        Ok(n) => format!("{:03}", n),
+        Err(_) => raw.trim().to_string(),
+    }
+}
+
 /// Helper to get a clean organ name from a file path
-fn get_organ_name(path: &Path) -> String {
+/// Name of the organ's cache directory: the definition's file stem.
+pub fn get_organ_name(path: &Path) -> String {
     path.file_stem()
         .unwrap_or_default()
         .to_string_lossy()
@@ -218,23 +230,34 @@ fn resolve_definition_content(data: Vec<u8>) -> Result<String> {
     Ok(Organ::bytes_to_string_tolerant(data))
 }
 
+/// The directory sample paths of a folder-based organ are relative to: the one
+/// holding the definition file.
+pub fn get_organ_base_path(path: &Path) -> Result<PathBuf> {
+    let logical_path = Organ::normalize_path_preserve_symlinks(path)?;
+
+    // Determine the root directory relative to the definition file
+    let organ_base_path = if let Ok(physical_file) = canonicalize(path) {
+        physical_file.parent().unwrap().to_path_buf()
+    } else {
+        logical_path.parent().unwrap().to_path_buf()
+    };
+    Ok(organ_base_path)
+}
+
 /// Standard Folder Loader: Reads the file (or unzips it if it's a compressed .organ)
 /// and points base_path to the folder.
+/// With `planned_tasks`, the samples are only collected into it; see `Organ::plan_definition`.
 pub fn load_grandorgue_dir(
     path: &Path,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     original_tuning: bool,
     target_sample_rate: u32,
     progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+    planned_tasks: Option<&mut HashSet<ConversionTask>>,
 ) -> Result<Organ> {
     let logical_path = Organ::normalize_path_preserve_symlinks(path)?;
-
-    // Determine the root directory relative to the definition file
-    let organ_base_path = if let Ok(physical_file) = canonicalize(path) {
-        physical_file.parent().unwrap().to_path_buf()
-    } else {
-        logical_path.parent().unwrap().to_path_buf()
-    };
+    let organ_base_path = get_organ_base_path(path)?;
 
     log::info!(
         "Loading GrandOrgue organ from directory: {:?}",
@@ -257,20 +280,25 @@ pub fn load_grandorgue_dir(
         organ_base_path,
         cache_path,
         convert_to_16_bit,
+        cache_format,
         original_tuning,
         target_sample_rate,
         progress_tx,
         file_provisioner,
+        planned_tasks,
     )
 }
 
 /// Zip Loader: Extracts definition, finds samples, extracts samples, then loads.
+/// With `planned_tasks`, nothing is extracted; see `Organ::plan_definition`.
 pub fn load_grandorgue_zip(
     zip_path: &Path,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     original_tuning: bool,
     target_sample_rate: u32,
     progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+    planned_tasks: Option<&mut HashSet<ConversionTask>>,
 ) -> Result<Organ> {
     let zip_file = fs::File::open(zip_path)?;
     let mut archive = zip::ZipArchive::new(zip_file)?;
@@ -313,6 +341,7 @@ pub fn load_grandorgue_zip(
     // Prepare Closure
     // We clone the path so the closure owns its own copy, allowing the original to be moved later.
     let source_path_for_closure = extracted_source_path.clone();
+    let cache_path_for_closure = cache_path.clone();
     let archive_mutex = Mutex::new(archive);
 
     let zip_provisioner = move |tasks: &HashSet<ConversionTask>| -> Result<()> {
@@ -325,48 +354,48 @@ pub fn load_grandorgue_zip(
             let _ = tx.send((0.0, t!("gui.progress_extract_samples").to_string()));
         }
 
+        // Samples already extracted are kept only while they match their
+        // archive entry, so an updated archive replaces what changed.
+        let mut manifest = CacheManifest::load(&cache_path_for_closure);
+        let mut replaced = 0;
+        let mut fuzzy_names = None;
+
         for (i, task) in tasks.iter().enumerate() {
             let entry_name = task.relative_path.to_string_lossy().replace('\\', "/");
             let dest_path = source_path_for_closure.join(&task.relative_path);
 
-            // Skip if already extracted
-            if dest_path.exists() {
+            // Try Strict Lookup, then Fuzzy Lookup
+            let index = archive.index_for_name(&entry_name).or_else(|| {
+                let names = fuzzy_names.get_or_insert_with(|| {
+                    (0..archive.len())
+                        .filter_map(|idx| Some((archive.name_for_index(idx)?.to_lowercase(), idx)))
+                        .collect::<HashMap<String, usize>>()
+                });
+                names.get(&entry_name.to_lowercase()).copied()
+            });
+            let Some(index) = index else {
+                log::warn!("Sample not found in zip: {}", entry_name);
                 continue;
-            }
-
-            if let Some(parent) = dest_path.parent() {
-                fs::create_dir_all(parent)?;
-            }
-
-            // Try Strict Lookup
-            let mut extracted = false;
-            // Create a scope so 'file' is dropped immediately after use
-            if let Ok(mut file) = archive.by_name(&entry_name) {
-                let mut out = fs::File::create(&dest_path)?;
-                std::io::copy(&mut file, &mut out)?;
-                extracted = true;
-            }
+            };
+            let mut file = archive.by_index(index)?;
 
-            // Fuzzy Lookup (only if strict failed)
-            // The strict borrow is now gone, so we can iterate safely.
-            if !extracted {
-                let len = archive.len();
-                for idx in 0..len {
-                    // Open file once
-                    let mut file = archive.by_index(idx)?;
-                    if file.name().eq_ignore_ascii_case(&entry_name) {
-                        let mut out = fs::File::create(&dest_path)?;
-                        // We use the same file handle we just checked
-                        std::io::copy(&mut file, &mut out)?;
-                        extracted = true;
-                        break;
-                    }
+            if dest_path.exists() {
+                // Skip if already extracted and unchanged
+                if manifest.source_matches_crc(&source_path_for_closure, &dest_path, file.crc32()) {
+                    continue;
                 }
+                log::info!(
+                    "[Cache] {} changed in the archive. Extracting again.",
+                    entry_name
+                );
+                replaced += 1;
             }
 
-            if !extracted {
-                log::warn!("Sample not found in zip: {}", entry_name);
+            if let Some(parent) = dest_path.parent() {
+                fs::create_dir_all(parent)?;
             }
+            let mut out = fs::File::create(&dest_path)?;
+            std::io::copy(&mut file, &mut out)?;
 
             if let Some(tx) = progress_tx {
                 if i % 20 == 0 {
@@ -377,6 +406,13 @@ pub fn load_grandorgue_zip(
                 }
             }
         }
+
+        if replaced > 0 {
+            log::info!("[Cache] Extracted {} changed sample(s) again.", replaced);
+        }
+        if let Err(e) = manifest.save(&cache_path_for_closure) {
+            log::warn!("[Cache] Failed to save manifest: {}", e);
+        }
         Ok(())
     };
 
@@ -387,10 +423,12 @@ pub fn load_grandorgue_zip(
         extracted_source_path,
         cache_path,
         convert_to_16_bit,
+        cache_format,
         original_tuning,
         target_sample_rate,
         progress_tx,
         zip_provisioner,
+        planned_tasks,
     )
 }
 
@@ -402,10 +440,12 @@ fn load_grandorgue_common<F>(
     base_path: PathBuf, // Extracted source folder OR original folder
     cache_path: PathBuf,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     original_tuning: bool,
     target_sample_rate: u32,
     progress_tx: &Option<mpsc::Sender<(f32, String)>>,
     provision_samples_fn: F,
+    planned_tasks: Option<&mut HashSet<ConversionTask>>,
 ) -> Result<Organ>
 where
     F: Fn(&HashSet<ConversionTask>) -> Result<()>,
@@ -493,9 +533,36 @@ where
                         relative_path: PathBuf::from(&attack_path_str),
                         tuning_cents_int: (pitch_tuning_cents * 100.0) as i32,
                         to_16bit: convert_to_16_bit,
+                        cache_format,
                     });
                 }
 
+                let attack_count: usize = get_prop(
+                    &format!("{}AttackCount", pipe_key_prefix_upper),
+                    &format!("{}attackcount", pipe_key_prefix_lower),
+                    "0",
+                )
+                .parse()
+                .unwrap_or(0);
+                for a_idx in 1..=attack_count {
+                    let att_key_upper = format!("{}Attack{:03}", pipe_key_prefix_upper, a_idx);
+                    let att_key_lower = format!("{}attack{:03}", pipe_key_prefix_lower, a_idx);
+                    if let Some(att_path_str) =
+                        get_prop(&att_key_upper, &att_key_lower, "").non_empty_or(None)
+                    {
+                        if att_path_str.starts_with("REF:") {
+                            continue;
+                        }
+
+                        conversion_tasks.insert(ConversionTask {
+                            relative_path: PathBuf::from(att_path_str.replace('\\', "/")),
+                            tuning_cents_int: (pitch_tuning_cents * 100.0) as i32,
+                            to_16bit: convert_to_16_bit,
+                            cache_format,
+                        });
+                    }
+                }
+
                 let release_count: usize = get_prop(
                     &format!("{}ReleaseCount", pipe_key_prefix_upper),
                     &format!("{}releasecount", pipe_key_prefix_lower),
@@ -517,6 +584,7 @@ where
                             relative_path: PathBuf::from(rel_path_str.replace('\\', "/")),
                             tuning_cents_int: (pitch_tuning_cents * 100.0) as i32,
                             to_16bit: convert_to_16_bit,
+                            cache_format,
                         });
                     }
                 }
@@ -524,6 +592,12 @@ where
         }
     }
 
+    // Planning stops here, before anything is extracted or converted.
+    if let Some(planned) = planned_tasks {
+        *planned = conversion_tasks;
+        return Ok(organ);
+    }
+
     // Provision Samples (Extract from Zip if needed)
     // If loading from disk, this does nothing. If Zip, this extracts only the files found above.
     provision_samples_fn(&conversion_tasks)?;
@@ -546,6 +620,7 @@ where
     let mut ranks_map: HashMap<String, Rank> = HashMap::new();
     let mut windchest_groups_map: HashMap<String, WindchestGroup> = HashMap::new();
     let mut tremulants_map: HashMap<String, Tremulant> = HashMap::new();
+    let mut enclosures_map: HashMap<String, Enclosure> = HashMap::new();
 
     // Build Tremulants
     for (section_name, props) in conf.iter() {
@@ -611,6 +686,52 @@ where
         }
     }
 
+    // Build Enclosures
+    for (section_name, props) in conf.iter() {
+        if !section_name.to_lowercase().starts_with("enclosure") {
+            continue;
+        }
+        let get_prop = |key_upper: &str, key_lower: &str, default: &str| {
+            props
+                .get(key_upper)
+                .or_else(|| props.get(key_lower))
+                .and_then(|opt| opt.as_deref())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| default.to_string())
+                .trim()
+                .replace("__HASH__", "#")
+                .to_string()
+        };
+
+        let id_str = section_name
+            .trim_start_matches("enclosure")
+            .trim_start_matches("Enclosure")
+            .to_string();
+        let name = get_prop("Name", "name", "");
+        // AmpMinimumLevel is given in percent (0-100)
+        let min_amplitude = get_prop("AmpMinimumLevel", "ampminimumlevel", "0")
+            .parse::<f32>()
+            .unwrap_or(0.0)
+            .clamp(0.0, 100.0)
+            / 100.0;
+
+        log::info!(
+            "Loaded Enclosure '{}' (ID: {}) with minimum level {:.0}%.",
+            name,
+            id_str,
+            min_amplitude * 100.0
+        );
+
+        enclosures_map.insert(
+            id_str.clone(),
+            Enclosure {
+                name,
+                id_str,
+                min_amplitude,
+            },
+        );
+    }
+
     // Build Windchest Groups
     for (section_name, props) in conf.iter() {
         let section_lower = section_name.to_lowercase();
@@ -649,11 +770,28 @@ where
                 }
             }
 
+            let enclosure_count: usize = get_prop("NumberOfEnclosures", "numberofenclosures", "0")
+                .parse()
+                .unwrap_or(0);
+            let mut enclosure_ids = Vec::new();
+            for i in 1..=enclosure_count {
+                if let Some(enc_id) = get_prop(
+                    &format!("Enclosure{:03}", i),
+                    &format!("enclosure{:03}", i),
+                    "",
+                )
+                .non_empty_or(None)
+                {
+                    enclosure_ids.push(normalize_ref(&enc_id));
+                }
+            }
+
             log::info!(
-                "Loaded Windchest Group '{}' (ID: {}) with {} tremulants.",
+                "Loaded Windchest Group '{}' (ID: {}) with {} tremulants and {} enclosures.",
                 name,
                 id_str,
-                tremulant_ids.len()
+                tremulant_ids.len(),
+                enclosure_ids.len()
             );
 
             windchest_groups_map.insert(
@@ -662,6 +800,7 @@ where
                     id_str,
                     name,
                     tremulant_ids,
+                    enclosure_ids,
                 },
             );
         }
@@ -692,6 +831,11 @@ where
         };
 
         let is_percussive = get_prop("Percussive", "percussive", "N").eq_ignore_ascii_case("Y");
+        let harmonic_number = get_prop("HarmonicNumber", "harmonicnumber", "8")
+            .parse::<f32>()
+            .ok()
+            .filter(|h| h.is_finite() && *h > 0.0)
+            .unwrap_or(8.0);
 
         let id_str = if is_explicit_rank {
             section_name
@@ -732,7 +876,9 @@ where
         let windchest_group_id =
             get_prop("WindchestGroup", "windchestgroup", "").non_empty_or(None);
 
-        let tracker_delay_ms: u32 = 0;
+        let tracker_delay_ms: u32 = get_prop("TrackerDelay", "trackerdelay", "0")
+            .parse()
+            .unwrap_or(0);
         let mut pipes = HashMap::new();
 
         for i in 1..=pipe_count {
@@ -766,6 +912,7 @@ where
                     &organ.cache_path,
                     pitch_tuning_cents,
                     convert_to_16_bit,
+                    cache_format,
                     target_sample_rate,
                 ) {
                     Ok(path) => path,
@@ -779,6 +926,79 @@ where
                     }
                 };
 
+                let attack_velocity: u8 = get_prop(
+                    &format!("{}AttackVelocity", pipe_key_prefix_upper),
+                    &format!("{}attackvelocity", pipe_key_prefix_lower),
+                    "0",
+                )
+                .parse()
+                .unwrap_or(0);
+                let mut attacks = vec![AttackSample {
+                    path: final_attack_path,
+                    min_velocity: attack_velocity,
+                    preloaded_bytes: None,
+                    phase_alignment: None,
+                    is_tremulant: false,
+                }];
+
+                // Additional attack layers (PipeNNNAttackMMM), e.g. recorded at other velocities
+                let attack_count: usize = get_prop(
+                    &format!("{}AttackCount", pipe_key_prefix_upper),
+                    &format!("{}attackcount", pipe_key_prefix_lower),
+                    "0",
+                )
+                .parse()
+                .unwrap_or(0);
+                for a_idx in 1..=attack_count {
+                    let att_key_upper = format!("{}Attack{:03}", pipe_key_prefix_upper, a_idx);
+                    let att_key_lower = format!("{}attack{:03}", pipe_key_prefix_lower, a_idx);
+
+                    let Some(att_path_str) =
+                        get_prop(&att_key_upper, &att_key_lower, "").non_empty_or(None)
+                    else {
+                        continue;
+                    };
+                    if att_path_str.starts_with("REF:") {
+                        continue;
+                    }
+
+                    let att_path_buf = PathBuf::from(att_path_str.replace('\\', "/"));
+                    match wav_converter::process_sample_file(
+                        &att_path_buf,
+                        &organ.base_path,
+                        &organ.cache_path,
+                        pitch_tuning_cents,
+                        convert_to_16_bit,
+                        cache_format,
+                        target_sample_rate,
+                    ) {
+                        Ok(final_att_path) => {
+                            let min_velocity: u8 = get_prop(
+                                &format!("{}AttackVelocity", att_key_upper),
+                                &format!("{}attackvelocity", att_key_lower),
+                                "0",
+                            )
+                            .parse()
+                            .unwrap_or(0);
+                            attacks.push(AttackSample {
+                                path: final_att_path,
+                                min_velocity,
+                                preloaded_bytes: None,
+                                phase_alignment: None,
+                                is_tremulant: false,
+                            });
+                        }
+                        Err(e) => {
+                            log::warn!(
+                                "GrandOrgue: Skipping attack sample {:?} due to error: {}",
+                                att_path_buf,
+                                e
+                            );
+                        }
+                    }
+                }
+                attacks.sort_by_key(|a| a.min_velocity);
+
                 let release_count: usize = get_prop(
                     &format!("{}ReleaseCount", pipe_key_prefix_upper),
                     &format!("{}releasecount", pipe_key_prefix_lower),
@@ -811,6 +1031,7 @@ where
                                     &organ.cache_path,
                                     pitch_tuning_cents,
                                     convert_to_16_bit,
+                                    cache_format,
                                     target_sample_rate,
                                 )
                             {
@@ -825,6 +1046,8 @@ where
                                     path: extracted_path,
                                     max_key_press_time_ms: max_time,
                                     preloaded_bytes: None,
+                                    phase_alignment: None,
+                                    is_tremulant: false,
                                 });
                             }
                         } else {
@@ -834,6 +1057,7 @@ where
                                 &organ.cache_path,
                                 pitch_tuning_cents,
                                 convert_to_16_bit,
+                                cache_format,
                                 target_sample_rate,
                             ) {
                                 Ok(final_rel_path) => {
@@ -848,6 +1072,8 @@ where
                                         path: final_rel_path,
                                         max_key_press_time_ms: max_time,
                                         preloaded_bytes: None,
+                                        phase_alignment: None,
+                                        is_tremulant: false,
                                     });
                                 }
                                 Err(e) => {
@@ -881,6 +1107,7 @@ where
                         &organ.cache_path,
                         pitch_tuning_cents,
                         convert_to_16_bit,
+                        cache_format,
                         target_sample_rate,
                     ) {
                         log::info!(
@@ -891,6 +1118,8 @@ where
                             path: extracted_path,
                             max_key_press_time_ms: -1,
                             preloaded_bytes: None,
+                            phase_alignment: None,
+                            is_tremulant: false,
                         });
                     }
                 }
@@ -898,11 +1127,10 @@ where
                 pipes.insert(
                     midi_note,
                     Pipe {
-                        attack_sample_path: final_attack_path,
+                        attacks,
                         gain_db: 0.0,
                         pitch_tuning_cents: 0.0,
                         releases,
-                        preloaded_bytes: None,
                     },
                 );
             }
@@ -921,6 +1149,8 @@ where
                 windchest_group_id,
                 pipes,
                 is_percussive,
+                harmonic_number,
+                wave_tremulant_id: None,
             },
         );
     }
@@ -983,6 +1213,183 @@ where
         }
     }
 
+    // Build Manuals
+    let mut manuals: Vec<Manual> = Vec::new();
+    let mut coupler_sources: HashMap<String, String> = HashMap::new();
+    let mut stop_manuals: HashMap<String, String> = HashMap::new();
+    for (section_name, props) in conf.iter() {
+        if !section_name.to_lowercase().starts_with("manual") {
+            continue;
+        }
+        let get_prop = |key_upper: &str, key_lower: &str, default: &str| {
+            props
+                .get(key_upper)
+                .or_else(|| props.get(key_lower))
+                .and_then(|opt| opt.as_deref())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| default.to_string())
+                .trim()
+                .replace("__HASH__", "#")
+                .to_string()
+        };
+
+        let id_str = section_name
+            .trim_start_matches("manual")
+            .trim_start_matches("Manual")
+            .to_string();
+        let name = get_prop("Name", "name", "");
+        let first_midi_note: u8 = get_prop(
+            "FirstAccessibleKeyMIDINoteNumber",
+            "firstaccessiblekeymidinotenumber",
+            "36",
+        )
+        .parse()
+        .unwrap_or(36);
+        let key_count: usize = get_prop("NumberOfLogicalKeys", "numberoflogicalkeys", "0")
+            .parse()
+            .unwrap_or(0);
+
+        let stop_count: usize = get_prop("NumberOfStops", "numberofstops", "0")
+            .parse()
+            .unwrap_or(0);
+        let mut stop_ids = Vec::new();
+        for i in 1..=stop_count {
+            if let Some(stop_id) = get_prop(&format!("Stop{:03}", i), &format!("stop{:03}", i), "")
+                .non_empty_or(None)
+            {
+                let stop_id = normalize_ref(&stop_id);
+                stop_manuals.entry(stop_id.clone()).or_insert(id_str.clone());
+                stop_ids.push(stop_id);
+            }
+        }
+
+        let coupler_count: usize = get_prop("NumberOfCouplers", "numberofcouplers", "0")
+            .parse()
+            .unwrap_or(0);
+        let mut coupler_ids = Vec::new();
+        for i in 1..=coupler_count {
+            if let Some(coupler_id) = get_prop(
+                &format!("Coupler{:03}", i),
+                &format!("coupler{:03}", i),
+                "",
+            )
+            .non_empty_or(None)
+            {
+                let coupler_id = normalize_ref(&coupler_id);
+                coupler_sources
+                    .entry(coupler_id.clone())
+                    .or_insert(id_str.clone());
+                coupler_ids.push(coupler_id);
+            }
+        }
+
+        log::info!(
+            "Loaded Manual '{}' (ID: {}) with {} stops and {} couplers.",
+            name,
+            id_str,
+            stop_ids.len(),
+            coupler_ids.len()
+        );
+
+        manuals.push(Manual {
+            name,
+            id_str,
+            first_midi_note,
+            key_count,
+            stop_ids,
+            coupler_ids,
+        });
+    }
+    manuals.sort_by(|a, b| a.id_str.cmp(&b.id_str));
+
+    // Build Couplers. GrandOrgue defines them as global sections that are
+    // referenced from the owning manual, which becomes the coupler's source.
+    let mut couplers: Vec<Coupler> = Vec::new();
+    for (section_name, props) in conf.iter() {
+        if !section_name.to_lowercase().starts_with("coupler") {
+            continue;
+        }
+        let get_prop = |key_upper: &str, key_lower: &str, default: &str| {
+            props
+                .get(key_upper)
+                .or_else(|| props.get(key_lower))
+                .and_then(|opt| opt.as_deref())
+                .map(|s| s.to_string())
+                .unwrap_or_else(|| default.to_string())
+                .trim()
+                .replace("__HASH__", "#")
+                .to_string()
+        };
+        let get_flag = |key_upper: &str, key_lower: &str| {
+            get_prop(key_upper, key_lower, "N").eq_ignore_ascii_case("Y")
+        };
+
+        let id_str = section_name
+            .trim_start_matches("coupler")
+            .trim_start_matches("Coupler")
+            .to_string();
+        let Some(source_manual_id) = coupler_sources.get(&id_str).cloned() else {
+            log::warn!("Coupler {} is not referenced by any manual. Skipping.", id_str);
+            continue;
+        };
+        let name = get_prop("Name", "name", "");
+        let unison_off = get_flag("UnisonOff", "unisonoff");
+        let destination_manual_id = if unison_off {
+            source_manual_id.clone()
+        } else {
+            match get_prop("DestinationManual", "destinationmanual", "").non_empty_or(None) {
+                Some(dest) => normalize_ref(&dest),
+                None => {
+                    log::warn!("Coupler '{}' has no destination manual. Skipping.", name);
+                    continue;
+                }
+            }
+        };
+        let key_shift: i8 = get_prop("DestinationKeyshift", "destinationkeyshift", "0")
+            .parse()
+            .unwrap_or(0);
+
+        log::info!(
+            "Loaded Coupler '{}' (ID: {}): manual {} -> {} ({:+} semitones{})",
+            name,
+            id_str,
+            source_manual_id,
+            destination_manual_id,
+            key_shift,
+            if unison_off { ", unison off" } else { "" }
+        );
+
+        couplers.push(Coupler {
+            name,
+            id_str,
+            source_manual_id,
+            destination_manual_id,
+            key_shift,
+            unison_off,
+            couple_to_unison_intermanual: get_flag(
+                "CoupleToSubsequentUnisonIntermanualCouplers",
+                "coupletosubsequentunisonintermanualcouplers",
+            ),
+            couple_to_upward_intermanual: get_flag(
+                "CoupleToSubsequentUpwardIntermanualCouplers",
+                "coupletosubsequentupwardintermanualcouplers",
+            ),
+            couple_to_downward_intermanual: get_flag(
+                "CoupleToSubsequentDownwardIntermanualCouplers",
+                "coupletosubsequentdownwardintermanualcouplers",
+            ),
+            couple_to_upward_intramanual: get_flag(
+                "CoupleToSubsequentUpwardIntramanualCouplers",
+                "coupletosubsequentupwardintramanualcouplers",
+            ),
+            couple_to_downward_intramanual: get_flag(
+                "CoupleToSubsequentDownwardIntramanualCouplers",
+                "coupletosubsequentdownwardintramanualcouplers",
+            ),
+        });
+    }
+    couplers.sort_by(|a, b| a.id_str.cmp(&b.id_str));
+
     // Build Stops
     for (section_name, props) in conf.iter() {
         let get_prop = |key_upper: &str, key_lower: &str, default: &str| {
@@ -1049,6 +1456,7 @@ where
             }
             if !rank_ids.is_empty() {
                 let division_id = infer_division_from_name(&name);
+                let manual_id = stop_manuals.get(&normalize_ref(&id_str)).cloned();
                 stops_map.insert(
                     id_str.clone(),
                     Stop {
@@ -1056,6 +1464,7 @@ where
                         id_str,
                         rank_ids,
                         division_id,
+                        manual_id,
                     },
                 );
             }
@@ -1068,6 +1477,9 @@ where
     organ.ranks = ranks_map;
     organ.windchest_groups = windchest_groups_map;
     organ.tremulants = tremulants_map;
+    organ.enclosures = enclosures_map;
+    organ.manuals = manuals;
+    organ.couplers = couplers;
 
     Ok(organ)
 }
@@ -4,14 +4,47 @@ use quick_xml::events::{BytesStart, Event};
 use quick_xml::reader::Reader;
 use rust_i18n::t;
 use serde::Deserialize;
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet};
 use std::fs::{File, canonicalize};
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
 use std::sync::mpsc;
 
-use crate::organ::{ConversionTask, Organ, Pipe, Rank, ReleaseSample, Stop};
-use crate::wav_converter;
+use crate::organ::{
+    AttackSample, ConversionTask, Enclosure, Organ, Pipe, Rank, ReleaseSample, Stop, Tremulant,
+    WindchestGroup,
+};
+use crate::wav_converter::{self, CacheFormat};
+
+/// Tuning correction in cents that brings a sample to the target MIDI note,
+/// based on its recorded pitch (or the note inferred from its filename).
+fn sample_tuning_cents(sample: &XmlSample, target_midi_note: f32) -> f32 {
+    let original_midi_note = if let Some(pitch_hz) = sample.pitch_exact_sample_pitch {
+        if pitch_hz > 0.0 {
+            12.0 * (pitch_hz / 440.0).log2() + 69.0
+        } else {
+            target_midi_note
+        }
+    } else if let Some(midi_note) = sample.pitch_normal_midi_note_number {
+        midi_note as f32
+    } else {
+        Organ::try_infer_midi_note_from_filename(&sample.path).unwrap_or(target_midi_note)
+    };
+    (target_midi_note - original_midi_note) * 100.0
+}
+
+/// Hauptwerk keeps the shutter attenuation in per-pipe tables we don't parse,
+/// so closed enclosures fall back to roughly -20 dB.
+const HAUPTWERK_ENCLOSURE_MIN_AMPLITUDE: f32 = 0.1;
+
+/// Hauptwerk shapes a synthesised tremulant with waveform samples we don't
+/// analyse; we model it as a sine with these defaults for what the
+/// definition leaves out.
+const HAUPTWERK_TREMULANT_PERIOD_MS: f32 = 250.0;
+const HAUPTWERK_TREMULANT_AMP_MOD_DEPTH: f32 = 18.0;
+/// Level change per second as the tremulant motor starts or stops.
+const HAUPTWERK_TREMULANT_RATE: f32 = 4.0;
 
 // XML Helper Definitions
 
@@ -82,6 +115,22 @@ struct XmlRank {
     division_id: String,
 }
 
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlEnclosure {
+    #[serde(rename = "EnclosureID")]
+    id: String,
+    #[serde(rename = "Name", default = "default_string")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlEnclosurePipe {
+    #[serde(rename = "EnclosureID")]
+    enclosure_id: String,
+    #[serde(rename = "PipeID")]
+    pipe_id: String,
+}
+
 #[derive(Debug, Deserialize, PartialEq)]
 struct XmlPipe {
     #[serde(rename = "PipeID", default = "default_string")]
@@ -90,6 +139,11 @@ struct XmlPipe {
     rank_id: String,
     #[serde(rename = "NormalMIDINoteNumber", default = "default_u8")]
     midi_note: u8,
+    #[serde(
+        rename = "WindSupply_SourceWindCompartmentID",
+        default = "default_string"
+    )]
+    wind_compartment_id: String,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -98,6 +152,61 @@ struct XmlLayer {
     id: String,
     #[serde(rename = "PipeID")]
     pipe_id: String,
+    #[serde(
+        rename = "WaveTremulantStateFor_TremulantID",
+        default = "default_string"
+    )]
+    wave_tremulant_id: String,
+    /// "Y" for the layer sampled with the tremulant running, "N" for the one without.
+    #[serde(rename = "WaveTremulantStateIsOn", default = "default_string")]
+    wave_tremulant_state: String,
+}
+
+impl XmlLayer {
+    /// The tremulant this layer was recorded with, if it holds wave tremulant samples.
+    fn wave_tremulant(&self) -> Option<&str> {
+        (!self.wave_tremulant_id.is_empty() && self.wave_tremulant_state.eq_ignore_ascii_case("Y"))
+            .then_some(self.wave_tremulant_id.as_str())
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlTremulant {
+    #[serde(rename = "TremulantID")]
+    id: String,
+    #[serde(rename = "Name", default = "default_string")]
+    name: String,
+    #[serde(rename = "ControllingSwitchID", default = "default_string")]
+    switch_id: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlTremulantWaveform {
+    #[serde(rename = "TremulantWaveformID")]
+    id: String,
+    #[serde(rename = "TremulantID", default = "default_string")]
+    tremulant_id: String,
+    #[serde(rename = "CyclePeriodMs")]
+    period_ms: Option<f32>,
+    #[serde(rename = "AmplitudeModulationDepthPercent")]
+    amp_mod_depth: Option<f32>,
+}
+
+/// Links a tremulant waveform to a pipe whose wind it modulates.
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlTremulantWaveformPipe {
+    #[serde(rename = "TremulantWaveformID")]
+    waveform_id: String,
+    #[serde(rename = "PipeID")]
+    pipe_id: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+struct XmlWindCompartment {
+    #[serde(rename = "WindCompartmentID")]
+    id: String,
+    #[serde(rename = "Name", default = "default_string")]
+    name: String,
 }
 
 #[derive(Debug, Deserialize, PartialEq)]
@@ -130,9 +239,18 @@ struct XmlReleaseSample {
     sample_id: String,
 }
 
+/// Name of the organ's cache directory: the definition's file name without
+/// the Hauptwerk extension.
+pub fn get_organ_name(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .replace(".Organ_Hauptwerk_xml", "")
+}
+
 /// Determine the organ root directory by checking for the existence
 /// of the 'OrganInstallationPackages' sibling directory.
-fn detect_hauptwerk_organ_root(xml_path: &Path) -> Result<PathBuf> {
+pub fn detect_hauptwerk_organ_root(xml_path: &Path) -> Result<PathBuf> {
     // Helper to validate and resolve the Packages directory
     let resolve_packages = |root: &Path| -> Option<PathBuf> {
         let packages_link = root.join("OrganInstallationPackages");
@@ -184,13 +302,17 @@ fn detect_hauptwerk_organ_root(xml_path: &Path) -> Result<PathBuf> {
 }
 
 /// Loads and parses a Hauptwerk (.Organ_Hauptwerk_xml) file.
+/// With `planned_tasks`, the samples are only collected into it; see `Organ::plan_definition`.
+#[allow(clippy::too_many_arguments)]
 pub fn load_hauptwerk(
     path: &Path,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     pre_cache: bool,
     _original_tuning: bool,
     target_sample_rate: u32,
     progress_tx: &Option<mpsc::Sender<(f32, String)>>,
+    planned_tasks: Option<&mut HashSet<ConversionTask>>,
 ) -> Result<Organ> {
     log::info!("Loading Hauptwerk organ from: {:?}", path);
     let organ_root_path = detect_hauptwerk_organ_root(path)?;
@@ -204,11 +326,7 @@ pub fn load_hauptwerk(
         let _ = tx.send((0.0, t!("gui.progress_parse_xml").to_string()));
     }
 
-    let organ_name = path
-        .file_stem()
-        .unwrap_or_default()
-        .to_string_lossy()
-        .replace(".Organ_Hauptwerk_xml", "");
+    let organ_name = get_organ_name(path);
     let cache_path = Organ::get_organ_cache_dir(&organ_name)?;
 
     let mut organ = Organ {
@@ -237,6 +355,12 @@ pub fn load_hauptwerk(
     let mut xml_release_samples = Vec::new();
     let mut xml_samples = Vec::new();
     let mut xml_divisions = Vec::new();
+    let mut xml_enclosures: Vec<XmlEnclosure> = Vec::new();
+    let mut xml_enclosure_pipes: Vec<XmlEnclosurePipe> = Vec::new();
+    let mut xml_tremulants: Vec<XmlTremulant> = Vec::new();
+    let mut xml_tremulant_waveforms: Vec<XmlTremulantWaveform> = Vec::new();
+    let mut xml_tremulant_waveform_pipes: Vec<XmlTremulantWaveformPipe> = Vec::new();
+    let mut xml_wind_compartments: Vec<XmlWindCompartment> = Vec::new();
     let mut organ_defined_name = String::new();
 
     let mut buf = Vec::new();
@@ -421,6 +545,48 @@ pub fn load_hauptwerk(
                             }
                         }
                     }
+                    b"Enclosure" if current_object_type == "Enclosure" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(enc) = parse_snippet(&raw)
+                        {
+                            xml_enclosures.push(enc);
+                        }
+                    }
+                    b"EnclosurePipe" if current_object_type == "EnclosurePipe" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(ep) = parse_snippet(&raw)
+                        {
+                            xml_enclosure_pipes.push(ep);
+                        }
+                    }
+                    b"Tremulant" if current_object_type == "Tremulant" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(trem) = parse_snippet(&raw)
+                        {
+                            xml_tremulants.push(trem);
+                        }
+                    }
+                    b"TremulantWaveform" if current_object_type == "TremulantWaveform" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(wf) = parse_snippet(&raw)
+                        {
+                            xml_tremulant_waveforms.push(wf);
+                        }
+                    }
+                    b"TremulantWaveformPipe" if current_object_type == "TremulantWaveformPipe" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(wp) = parse_snippet(&raw)
+                        {
+                            xml_tremulant_waveform_pipes.push(wp);
+                        }
+                    }
+                    b"WindCompartment" if current_object_type == "WindCompartment" => {
+                        if let Ok(raw) = read_element_raw(&mut reader, e, tag_name)
+                            && let Ok(wc) = parse_snippet(&raw)
+                        {
+                            xml_wind_compartments.push(wc);
+                        }
+                    }
                     b"General" | b"_General" => {
                         if let Ok(raw) = read_element_raw(&mut reader, e, tag_name) {
                             if let Ok(g) = parse_snippet::<XmlGeneral>(&raw) {
@@ -461,10 +627,13 @@ pub fn load_hauptwerk(
                                             .unwrap_or("0")
                                             .parse()
                                             .unwrap_or(0),
+                                        wind_compartment_id: String::new(),
                                     }),
                                     "Pipe_SoundEngine01_Layer" => xml_layers.push(XmlLayer {
                                         id: obj.a.unwrap_or_default(),
                                         pipe_id: obj.b.unwrap_or_default(),
+                                        wave_tremulant_id: String::new(),
+                                        wave_tremulant_state: String::new(),
                                     }),
                                     "Pipe_SoundEngine01_AttackSample" => {
                                         xml_attack_samples.push(XmlAttackSample {
@@ -490,6 +659,21 @@ pub fn load_hauptwerk(
                                         id: obj.a.unwrap_or_default(),
                                         name: obj.b.unwrap_or_default(),
                                     }),
+                                    "Enclosure" => xml_enclosures.push(XmlEnclosure {
+                                        id: obj.a.unwrap_or_default(),
+                                        name: obj.b.unwrap_or_default(),
+                                    }),
+                                    "Tremulant" => xml_tremulants.push(XmlTremulant {
+                                        id: obj.a.unwrap_or_default(),
+                                        name: obj.b.unwrap_or_default(),
+                                        switch_id: obj.c.unwrap_or_default(),
+                                    }),
+                                    "WindCompartment" => {
+                                        xml_wind_compartments.push(XmlWindCompartment {
+                                            id: obj.a.unwrap_or_default(),
+                                            name: obj.b.unwrap_or_default(),
+                                        })
+                                    }
                                     _ => {}
                                 }
                             }
@@ -549,6 +733,39 @@ pub fn load_hauptwerk(
                             xml_divisions.push(d);
                         }
                     }
+                    b"Enclosure" if current_object_type == "Enclosure" => {
+                        if let Ok(enc) = deserialize_empty_item::<XmlEnclosure>(e, tag_name) {
+                            xml_enclosures.push(enc);
+                        }
+                    }
+                    b"EnclosurePipe" if current_object_type == "EnclosurePipe" => {
+                        if let Ok(ep) = deserialize_empty_item::<XmlEnclosurePipe>(e, tag_name) {
+                            xml_enclosure_pipes.push(ep);
+                        }
+                    }
+                    b"Tremulant" if current_object_type == "Tremulant" => {
+                        if let Ok(trem) = deserialize_empty_item::<XmlTremulant>(e, tag_name) {
+                            xml_tremulants.push(trem);
+                        }
+                    }
+                    b"TremulantWaveform" if current_object_type == "TremulantWaveform" => {
+                        if let Ok(wf) = deserialize_empty_item::<XmlTremulantWaveform>(e, tag_name)
+                        {
+                            xml_tremulant_waveforms.push(wf);
+                        }
+                    }
+                    b"TremulantWaveformPipe" if current_object_type == "TremulantWaveformPipe" => {
+                        if let Ok(wp) =
+                            deserialize_empty_item::<XmlTremulantWaveformPipe>(e, tag_name)
+                        {
+                            xml_tremulant_waveform_pipes.push(wp);
+                        }
+                    }
+                    b"WindCompartment" if current_object_type == "WindCompartment" => {
+                        if let Ok(wc) = deserialize_empty_item::<XmlWindCompartment>(e, tag_name) {
+                            xml_wind_compartments.push(wc);
+                        }
+                    }
                     b"o" => {
                         if let Ok(obj) = deserialize_empty_item::<XmlV7Object>(e, tag_name) {
                             match current_object_type.as_str() {
@@ -574,10 +791,13 @@ pub fn load_hauptwerk(
                                     id: obj.a.unwrap_or_default(),
                                     rank_id: obj.b.unwrap_or_default(),
                                     midi_note: obj.d.as_deref().unwrap_or("0").parse().unwrap_or(0),
+                                    wind_compartment_id: String::new(),
                                 }),
                                 "Pipe_SoundEngine01_Layer" => xml_layers.push(XmlLayer {
                                     id: obj.a.unwrap_or_default(),
                                     pipe_id: obj.b.unwrap_or_default(),
+                                    wave_tremulant_id: String::new(),
+                                    wave_tremulant_state: String::new(),
                                 }),
                                 "Pipe_SoundEngine01_AttackSample" => {
                                     xml_attack_samples.push(XmlAttackSample {
@@ -603,6 +823,21 @@ pub fn load_hauptwerk(
                                     id: obj.a.unwrap_or_default(),
                                     name: obj.b.unwrap_or_default(),
                                 }),
+                                "Enclosure" => xml_enclosures.push(XmlEnclosure {
+                                    id: obj.a.unwrap_or_default(),
+                                    name: obj.b.unwrap_or_default(),
+                                }),
+                                "Tremulant" => xml_tremulants.push(XmlTremulant {
+                                    id: obj.a.unwrap_or_default(),
+                                    name: obj.b.unwrap_or_default(),
+                                    switch_id: obj.c.unwrap_or_default(),
+                                }),
+                                "WindCompartment" => {
+                                    xml_wind_compartments.push(XmlWindCompartment {
+                                        id: obj.a.unwrap_or_default(),
+                                        name: obj.b.unwrap_or_default(),
+                                    })
+                                }
                                 _ => {}
                             }
                         }
@@ -699,6 +934,10 @@ pub fn load_hauptwerk(
                 tracker_delay_ms: 0,
                 windchest_group_id: None,
                 is_percussive: false,
+                // Pipes are keyed by their own pitch, NormalMIDINoteNumber, and
+                // tuned to it, so they sound in the pitch class of that key
+                harmonic_number: 8.0,
+                wave_tremulant_id: None,
             },
         );
     }
@@ -709,10 +948,14 @@ pub fn load_hauptwerk(
         .filter(|s| !s.path.is_empty())
         .map(|s| (s.id.clone(), s))
         .collect();
-    let attack_map: HashMap<String, &XmlAttackSample> = xml_attack_samples
-        .iter()
-        .map(|a| (a.layer_id.clone(), a))
-        .collect();
+    // A layer may list several attack samples: alternatives for the same pipe.
+    let mut attack_map: HashMap<String, Vec<&XmlAttackSample>> = HashMap::new();
+    for att in &xml_attack_samples {
+        attack_map
+            .entry(att.layer_id.clone())
+            .or_default()
+            .push(att);
+    }
     let mut release_map: HashMap<String, Vec<&XmlReleaseSample>> = HashMap::new();
     for rel in &xml_release_samples {
         release_map
@@ -722,7 +965,8 @@ pub fn load_hauptwerk(
     }
 
     let mut conversion_tasks: HashSet<ConversionTask> = HashSet::new();
-    let mut seen_pipes: HashSet<(String, u8)> = HashSet::new();
+    // One layer per pipe, plus one sampled with the wave tremulant running
+    let mut seen_pipes: HashSet<(String, u8, bool)> = HashSet::new();
 
     for layer in &xml_layers {
         let Some(pipe_info) = pipe_map.get(&layer.pipe_id) else {
@@ -732,44 +976,41 @@ pub fn load_hauptwerk(
             continue;
         }
 
-        if seen_pipes.contains(&(pipe_info.rank_id.clone(), pipe_info.midi_note)) {
+        let pipe_key = (
+            pipe_info.rank_id.clone(),
+            pipe_info.midi_note,
+            layer.wave_tremulant().is_some(),
+        );
+        if !seen_pipes.insert(pipe_key) {
             continue;
         }
-        seen_pipes.insert((pipe_info.rank_id.clone(), pipe_info.midi_note));
 
-        let Some(attack_link) = attack_map.get(&layer.id) else {
-            continue;
-        };
-        let Some(attack_sample_info) = sample_map.get(&attack_link.sample_id) else {
+        let attack_infos: Vec<&XmlSample> = attack_map
+            .get(&layer.id)
+            .into_iter()
+            .flatten()
+            .filter_map(|link| sample_map.get(&link.sample_id).copied())
+            .collect();
+        let Some(attack_sample_info) = attack_infos.first() else {
             continue;
         };
 
         let target_midi_note = pipe_info.midi_note as f32;
-        let original_midi_note = if let Some(pitch_hz) = attack_sample_info.pitch_exact_sample_pitch
-        {
-            if pitch_hz > 0.0 {
-                12.0 * (pitch_hz / 440.0).log2() + 69.0
-            } else {
-                target_midi_note
-            }
-        } else if let Some(midi_note) = attack_sample_info.pitch_normal_midi_note_number {
-            midi_note as f32
-        } else {
-            Organ::try_infer_midi_note_from_filename(&attack_sample_info.path)
-                .unwrap_or(target_midi_note)
-        };
-        let tuning = (target_midi_note - original_midi_note) * 100.0;
+        let tuning = sample_tuning_cents(attack_sample_info, target_midi_note);
 
-        let path_str = format!(
-            "OrganInstallationPackages/{:0>6}/{}",
-            attack_sample_info.installation_package_id,
-            attack_sample_info.path.replace('\\', "/")
-        );
-        conversion_tasks.insert(ConversionTask {
-            relative_path: PathBuf::from(path_str),
-            tuning_cents_int: (tuning * 100.0) as i32,
-            to_16bit: convert_to_16_bit,
-        });
+        for info in &attack_infos {
+            let path_str = format!(
+                "OrganInstallationPackages/{:0>6}/{}",
+                info.installation_package_id,
+                info.path.replace('\\', "/")
+            );
+            conversion_tasks.insert(ConversionTask {
+                relative_path: PathBuf::from(path_str),
+                tuning_cents_int: (sample_tuning_cents(info, target_midi_note) * 100.0) as i32,
+                to_16bit: convert_to_16_bit,
+                cache_format,
+            });
+        }
 
         if let Some(xml_release_links) = release_map.get(&layer.id) {
             for release_link in xml_release_links {
@@ -783,12 +1024,18 @@ pub fn load_hauptwerk(
                         relative_path: PathBuf::from(path_str),
                         tuning_cents_int: (tuning * 100.0) as i32,
                         to_16bit: convert_to_16_bit,
+                        cache_format,
                     });
                 }
             }
         }
     }
 
+    if let Some(planned) = planned_tasks {
+        *planned = conversion_tasks;
+        return Ok(organ);
+    }
+
     Organ::process_tasks_parallel(
         &organ.base_path,
         &organ.cache_path,
@@ -820,46 +1067,32 @@ pub fn load_hauptwerk(
             continue;
         };
 
-        if rank.pipes.contains_key(&pipe_info.midi_note) {
+        let wave_tremulant = layer.wave_tremulant();
+        let is_tremulant = wave_tremulant.is_some();
+        if rank
+            .pipes
+            .get(&pipe_info.midi_note)
+            .is_some_and(|pipe| pipe.attacks.iter().any(|a| a.is_tremulant == is_tremulant))
+        {
             continue;
         }
 
-        let Some(attack_link) = attack_map.get(&layer.id) else {
+        let Some(attack_links) = attack_map.get(&layer.id) else {
             log::warn!("Layer {} has no attack sample link.", layer.id);
             continue;
         };
 
-        let Some(attack_sample_info) = sample_map.get(&attack_link.sample_id) else {
+        let Some(attack_sample_info) = sample_map.get(&attack_links[0].sample_id) else {
             log::warn!(
                 "Layer {} references non-existent SampleID {}",
                 layer.id,
-                attack_link.sample_id
+                attack_links[0].sample_id
             );
             continue;
         };
 
         let target_midi_note = pipe_info.midi_note as f32;
-
-        let original_midi_note = if let Some(pitch_hz) = attack_sample_info.pitch_exact_sample_pitch
-        {
-            if pitch_hz > 0.0 {
-                12.0 * (pitch_hz / 440.0).log2() + 69.0
-            } else {
-                target_midi_note
-            }
-        } else if let Some(midi_note) = attack_sample_info.pitch_normal_midi_note_number {
-            midi_note as f32
-        } else {
-            if let Some(inferred_note) =
-                Organ::try_infer_midi_note_from_filename(&attack_sample_info.path)
-            {
-                inferred_note
-            } else {
-                target_midi_note
-            }
-        };
-
-        let final_pitch_tuning_cents = (target_midi_note - original_midi_note) * 100.0;
+        let final_pitch_tuning_cents = sample_tuning_cents(attack_sample_info, target_midi_note);
 
         let attack_path_str = format!(
             "OrganInstallationPackages/{:0>6}/{}",
@@ -874,6 +1107,7 @@ pub fn load_hauptwerk(
             &organ.cache_path,
             final_pitch_tuning_cents,
             convert_to_16_bit,
+            cache_format,
             target_sample_rate,
         ) {
             Ok(path) => path,
@@ -887,6 +1121,50 @@ pub fn load_hauptwerk(
                 continue;
             }
         };
+        let mut attacks = vec![AttackSample {
+            path: final_attack_path,
+            min_velocity: 0,
+            preloaded_bytes: None,
+            phase_alignment: None,
+            is_tremulant,
+        }];
+
+        // Alternative attacks for the same layer
+        for attack_link in &attack_links[1..] {
+            let Some(alt_info) = sample_map.get(&attack_link.sample_id) else {
+                continue;
+            };
+            let alt_path_buf = PathBuf::from(format!(
+                "OrganInstallationPackages/{:0>6}/{}",
+                alt_info.installation_package_id,
+                alt_info.path.replace('\\', "/")
+            ));
+            match wav_converter::process_sample_file(
+                &alt_path_buf,
+                &organ.base_path,
+                &organ.cache_path,
+                sample_tuning_cents(alt_info, target_midi_note),
+                convert_to_16_bit,
+                cache_format,
+                target_sample_rate,
+            ) {
+                Ok(path) => attacks.push(AttackSample {
+                    path,
+                    min_velocity: 0,
+                    preloaded_bytes: None,
+                    phase_alignment: None,
+                    is_tremulant,
+                }),
+                Err(e) => {
+                    log::warn!(
+                        "Skipping attack sample for LayerID {} due to error: {:?} - {}",
+                        layer.id,
+                        alt_path_buf,
+                        e
+                    );
+                }
+            }
+        }
 
         let mut releases = Vec::new();
         if let Some(xml_release_links) = release_map.get(&layer.id) {
@@ -910,12 +1188,15 @@ pub fn load_hauptwerk(
                             &organ.cache_path,
                             final_pitch_tuning_cents,
                             convert_to_16_bit,
+                            cache_format,
                             target_sample_rate,
                         ) {
                             releases.push(ReleaseSample {
                                 path: extracted_path,
                                 max_key_press_time_ms: release_link.max_key_press_time_ms,
                                 preloaded_bytes: None,
+                                phase_alignment: None,
+                                is_tremulant,
                             });
                         }
                     } else {
@@ -925,6 +1206,7 @@ pub fn load_hauptwerk(
                             &organ.cache_path,
                             final_pitch_tuning_cents,
                             convert_to_16_bit,
+                            cache_format,
                             target_sample_rate,
                         ) {
                             Ok(final_rel_path) => {
@@ -932,6 +1214,8 @@ pub fn load_hauptwerk(
                                     path: final_rel_path,
                                     max_key_press_time_ms: release_link.max_key_press_time_ms,
                                     preloaded_bytes: None,
+                                    phase_alignment: None,
+                                    is_tremulant,
                                 });
                             }
                             Err(e) => {
@@ -962,26 +1246,41 @@ pub fn load_hauptwerk(
                 &organ.cache_path,
                 final_pitch_tuning_cents,
                 convert_to_16_bit,
+                cache_format,
                 target_sample_rate,
             ) {
                 releases.push(ReleaseSample {
                     path: extracted_path,
                     max_key_press_time_ms: -1,
                     preloaded_bytes: None,
+                    phase_alignment: None,
+                    is_tremulant,
                 });
             }
         }
 
-        rank.pipes.insert(
-            pipe_info.midi_note,
-            Pipe {
-                attack_sample_path: final_attack_path,
-                gain_db: 0.0,
-                pitch_tuning_cents: 0.0,
-                releases,
-                preloaded_bytes: None,
-            },
-        );
+        if let Some(tremulant_id) = wave_tremulant {
+            rank.wave_tremulant_id
+                .get_or_insert_with(|| tremulant_id.to_string());
+        }
+        match rank.pipes.entry(pipe_info.midi_note) {
+            Entry::Occupied(mut entry) => {
+                // The other layer of a wave tremulant pipe
+                let pipe = entry.get_mut();
+                pipe.attacks.extend(attacks);
+                pipe.attacks.sort_by_key(|a| a.is_tremulant);
+                pipe.releases.extend(releases);
+                pipe.releases.sort_by_key(|r| r.is_tremulant);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(Pipe {
+                    attacks,
+                    gain_db: 0.0,
+                    pitch_tuning_cents: 0.0,
+                    releases,
+                });
+            }
+        }
     }
 
     for rank in ranks_map.values_mut() {
@@ -991,6 +1290,172 @@ pub fn load_hauptwerk(
         }
     }
 
+    // Tremulants. One with a waveform modulates the wind of the pipes the
+    // waveform is linked to; one without only switches ranks to their wave
+    // tremulant samples.
+    for xt in &xml_tremulants {
+        let waveform = xml_tremulant_waveforms
+            .iter()
+            .find(|wf| wf.tremulant_id == xt.id);
+        organ.tremulants.insert(
+            xt.id.clone(),
+            Tremulant {
+                name: xt.name.clone(),
+                id_str: xt.id.clone(),
+                period: waveform
+                    .and_then(|wf| wf.period_ms)
+                    .unwrap_or(HAUPTWERK_TREMULANT_PERIOD_MS),
+                start_rate: HAUPTWERK_TREMULANT_RATE,
+                stop_rate: HAUPTWERK_TREMULANT_RATE,
+                amp_mod_depth: waveform.map_or(0.0, |wf| {
+                    wf.amp_mod_depth
+                        .unwrap_or(HAUPTWERK_TREMULANT_AMP_MOD_DEPTH)
+                }),
+                switch_ids: Some(xt.switch_id.clone())
+                    .filter(|id| !id.is_empty())
+                    .into_iter()
+                    .collect(),
+            },
+        );
+    }
+
+    // Enclosures: Hauptwerk assigns pipes to enclosures individually. We place a
+    // rank in the enclosure that holds its pipes.
+    for xe in &xml_enclosures {
+        organ.enclosures.insert(
+            xe.id.clone(),
+            Enclosure {
+                name: xe.name.clone(),
+                id_str: xe.id.clone(),
+                min_amplitude: HAUPTWERK_ENCLOSURE_MIN_AMPLITUDE,
+            },
+        );
+    }
+    let mut rank_enclosures: HashMap<&str, &str> = HashMap::new();
+    for ep in &xml_enclosure_pipes {
+        if !organ.enclosures.contains_key(&ep.enclosure_id) {
+            continue;
+        }
+        if let Some(pipe) = pipe_map.get(&ep.pipe_id) {
+            rank_enclosures
+                .entry(pipe.rank_id.as_str())
+                .or_insert(ep.enclosure_id.as_str());
+        }
+    }
+
+    // Wind compartments: likewise, a rank sits on the compartment of its pipes.
+    let compartment_names: HashMap<&str, &str> = xml_wind_compartments
+        .iter()
+        .map(|wc| (wc.id.as_str(), wc.name.as_str()))
+        .collect();
+    let mut rank_compartments: HashMap<&str, &str> = HashMap::new();
+    for xp in &xml_pipes {
+        if compartment_names.contains_key(xp.wind_compartment_id.as_str()) {
+            rank_compartments
+                .entry(xp.rank_id.as_str())
+                .or_insert(xp.wind_compartment_id.as_str());
+        }
+    }
+
+    // A tremulant modulates the whole compartment its pipes draw wind from, or
+    // just their rank if they have none.
+    let waveform_tremulants: HashMap<&str, &str> = xml_tremulant_waveforms
+        .iter()
+        .filter(|wf| organ.tremulants.contains_key(&wf.tremulant_id))
+        .map(|wf| (wf.id.as_str(), wf.tremulant_id.as_str()))
+        .collect();
+    let mut compartment_tremulants: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut rank_tremulants: HashMap<&str, Vec<&str>> = HashMap::new();
+    for link in &xml_tremulant_waveform_pipes {
+        let (Some(&trem_id), Some(xp)) = (
+            waveform_tremulants.get(link.waveform_id.as_str()),
+            pipe_map.get(&link.pipe_id),
+        ) else {
+            continue;
+        };
+        let tremulants = if compartment_names.contains_key(xp.wind_compartment_id.as_str()) {
+            compartment_tremulants
+                .entry(xp.wind_compartment_id.as_str())
+                .or_default()
+        } else {
+            rank_tremulants.entry(xp.rank_id.as_str()).or_default()
+        };
+        if !tremulants.contains(&trem_id) {
+            tremulants.push(trem_id);
+        }
+    }
+
+    // One windchest group per combination of compartment, enclosure and
+    // tremulants, so the audio engine can modulate and route the ranks.
+    for (rank_id, rank) in ranks_map.iter_mut() {
+        let compartment = rank_compartments.get(rank_id.as_str()).copied();
+        let enclosure = rank_enclosures.get(rank_id.as_str()).copied();
+        let mut tremulant_ids: Vec<String> = compartment
+            .and_then(|wc| compartment_tremulants.get(wc))
+            .into_iter()
+            .chain(rank_tremulants.get(rank_id.as_str()))
+            .flatten()
+            // A rank sampled with the tremulant takes the motion from its samples
+            .filter(|&&trem_id| rank.wave_tremulant_id.as_deref() != Some(trem_id))
+            .map(|trem_id| trem_id.to_string())
+            .collect();
+        tremulant_ids.sort();
+        tremulant_ids.dedup();
+
+        let mut id_parts = Vec::new();
+        let mut name_parts = Vec::new();
+        if let Some(wc) = compartment {
+            id_parts.push(format!("WC{}", wc));
+            name_parts.push(match compartment_names[wc] {
+                "" => format!("Wind compartment {}", wc),
+                name => name.to_string(),
+            });
+        }
+        if let Some(enc) = enclosure {
+            id_parts.push(format!("ENC{}", enc));
+            name_parts.push(format!("Enclosure {}", enc));
+        }
+        if !tremulant_ids.is_empty() {
+            id_parts.push(format!("TREM{}", tremulant_ids.join("+")));
+            for trem_id in &tremulant_ids {
+                name_parts.push(organ.tremulants[trem_id].name.clone());
+            }
+        }
+        if id_parts.is_empty() {
+            continue;
+        }
+
+        let group_id = id_parts.join("-");
+        organ
+            .windchest_groups
+            .entry(group_id.clone())
+            .or_insert_with(|| WindchestGroup {
+                name: name_parts.join(", "),
+                id_str: group_id.clone(),
+                tremulant_ids,
+                enclosure_ids: enclosure.map(str::to_string).into_iter().collect(),
+            });
+        rank.windchest_group_id = Some(group_id);
+    }
+    if !organ.enclosures.is_empty() {
+        log::info!(
+            "Loaded {} enclosures covering {} ranks.",
+            organ.enclosures.len(),
+            rank_enclosures.len()
+        );
+    }
+    if !organ.tremulants.is_empty() || !compartment_names.is_empty() {
+        log::info!(
+            "Loaded {} tremulants and {} wind compartments, {} ranks with wave tremulant samples.",
+            organ.tremulants.len(),
+            compartment_names.len(),
+            ranks_map
+                .values()
+                .filter(|r| r.wave_tremulant_id.is_some())
+                .count()
+        );
+    }
+
     let mut stops_filtered = 0;
     let mut stops_map: HashMap<String, Stop> = HashMap::new();
 
@@ -1198,6 +1663,7 @@ pub fn load_hauptwerk(
                 id_str: xs.id,
                 rank_ids,
                 division_id: prefix,
+                manual_id: None,
             },
         );
     }
@@ -0,0 +1,233 @@
+use anyhow::{Result, anyhow};
+use ringbuf::traits::Consumer;
+use rust_i18n::t;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::sync::{Arc, Mutex, mpsc};
+use std::time::Instant;
+
+use crate::app::{AppMessage, TuiMessage};
+use crate::app_state::AppState;
+use crate::audio_command::command_queue;
+use crate::audio_engine::{AudioEngine, EngineConfig};
+use crate::audio_loader::{default_loader_workers, spawn_loader_pool};
+use crate::config::AppSettings;
+use crate::flac::FlacWriter;
+use crate::input::KeyboardLayout;
+use crate::midi;
+use crate::organ::Organ;
+
+/// Silence kept after the last voice has ended, so the reverb can decay.
+const RENDER_TAIL_SECS: f64 = 3.0;
+/// Upper bound on rendering past the last MIDI event (e.g. for pipes that never stop,
+/// or notes the file never releases).
+const RENDER_MAX_TAIL_SECS: f64 = 30.0;
+
+/// Output file for an offline render, chosen by file extension.
+enum RenderWriter {
+    Wav(hound::WavWriter<BufWriter<File>>),
+    Flac(FlacWriter),
+}
+
+impl RenderWriter {
+    fn create(path: &Path, sample_rate: u32, channels: usize) -> Result<Self> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match extension.as_str() {
+            "wav" => {
+                let spec = hound::WavSpec {
+                    channels: channels as u16,
+                    sample_rate,
+                    bits_per_sample: 32,
+                    sample_format: hound::SampleFormat::Float,
+                };
+                Ok(Self::Wav(hound::WavWriter::create(path, spec)?))
+            }
+            "flac" => Ok(Self::Flac(FlacWriter::create(path, sample_rate, channels)?)),
+            _ => Err(anyhow!(
+                "Unsupported output format '{}' (use .wav or .flac)",
+                path.display()
+            )),
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<()> {
+        match self {
+            Self::Wav(writer) => {
+                for &sample in samples {
+                    writer.write_sample(sample)?;
+                }
+                Ok(())
+            }
+            Self::Flac(writer) => writer.write_interleaved(samples),
+        }
+    }
+
+    fn finalize(self) -> Result<()> {
+        match self {
+            Self::Wav(writer) => Ok(writer.finalize()?),
+            Self::Flac(writer) => writer.finalize(),
+        }
+    }
+}
+
+/// Renders a MIDI file to an audio file without an audio device, as fast as the
+/// CPU allows. The mix runs on a virtual clock and waits for every sample to be
+/// loaded, so the result does not depend on disk or CPU speed.
+///
+/// The registration comes from the given preset slot (1-12), and can be changed
+/// during the piece by MIDI events that are bound to stops, tremulants or
+/// presets via MIDI learn.
+pub fn render_midi_file(
+    settings: &AppSettings,
+    midi_path: &Path,
+    output_path: &Path,
+    preset_slot: Option<usize>,
+    keyboard_layout: KeyboardLayout,
+) -> Result<()> {
+    let organ_file = settings
+        .organ_file
+        .clone()
+        .filter(|p| !p.as_os_str().is_empty())
+        .ok_or_else(|| anyhow!("No organ file specified for rendering."))?;
+    let sample_rate = settings.sample_rate;
+    let block_frames = settings.audio_buffer_frames;
+    let block_secs = block_frames as f64 / sample_rate as f64;
+
+    let events = midi::read_midi_file_events(midi_path)?;
+    let last_event_secs = events.last().map(|(t, _)| *t).unwrap_or(0.0);
+
+    println!("{}", t!("main.loading_organ"));
+    let organ = Arc::new(Organ::load(
+        &organ_file,
+        settings.convert_to_16bit,
+        settings.cache_format,
+        settings.precache,
+        settings.mmap_samples,
+        settings.original_tuning,
+        sample_rate,
+        None,
+        (settings.max_ram_gb * 1024.0) as usize,
+    )?);
+    println!("{}", t!("main.organ_loaded_fmt", name = organ.name));
+
+    // Status messages from the engine have no UI to go to
+    let (tui_tx, _tui_rx) = mpsc::channel::<TuiMessage>();
+    let engine_config = EngineConfig {
+        sample_rate,
+        buffer_size_frames: block_frames,
+        gain: settings.gain,
+        polyphony: settings.polyphony,
+        max_new_voices_per_block: settings.max_new_voices_per_block,
+        attack_selection: settings.attack_selection,
+        interpolation: settings.interpolation,
+        tracker_delay_scale: settings.tracker_delay_scale,
+        output_channels: settings.output_channels,
+        output_routes: settings.output_routes.clone(),
+    };
+    let mut engine = AudioEngine::new(
+        Arc::clone(&organ),
+        &engine_config,
+        tui_tx,
+        Arc::new(Mutex::new(None)),
+        &spawn_loader_pool(default_loader_workers()).jobs,
+    );
+    engine.use_virtual_clock();
+    if let Some(ir_path) = &settings.ir_file {
+        if ir_path.exists() {
+            log::info!("Loading IR file: {}", ir_path.display());
+            engine.load_reverb_ir(ir_path, settings.reverb_mix)?;
+        } else {
+            log::warn!("IR file not found: {}", ir_path.display());
+        }
+    }
+
+    let (command_tx, mut command_rx) = command_queue();
+    let mut app_state = AppState::new(
+        Arc::clone(&organ),
+        command_tx,
+        settings.gain,
+        settings.polyphony,
+        engine_config.max_polyphony(),
+        settings.tracker_delay_scale,
+        keyboard_layout,
+    )?;
+    engine.handle_message(AppMessage::SetTuning(app_state.tuning.clone()));
+    engine.handle_message(AppMessage::SetVelocityCurves(
+        app_state.velocity_curves.clone(),
+    ));
+    if let Some(slot) = preset_slot {
+        let slot_index = slot.wrapping_sub(1);
+        if app_state
+            .presets
+            .get(slot_index)
+            .is_none_or(|p| p.is_none())
+        {
+            return Err(anyhow!("No preset found in slot F{}", slot));
+        }
+        app_state.recall_preset(slot_index)?;
+        while let Some(command) = command_rx.try_pop() {
+            engine.handle_command(command);
+        }
+    }
+
+    println!(
+        "{}",
+        t!(
+            "main.render_started_fmt",
+            midi = midi_path.display(),
+            output = output_path.display()
+        )
+    );
+    let started_at = Instant::now();
+    let mut writer = RenderWriter::create(output_path, sample_rate, engine.output_channels())?;
+    let mut events = events.into_iter().peekable();
+    let mut rendered_secs = 0.0;
+    let mut silent_since: Option<f64> = None;
+
+    loop {
+        // Events are applied at the start of the block they fall into, like live input
+        let block_end = rendered_secs + block_secs;
+        while let Some((_, msg)) = events.next_if(|(t, _)| *t < block_end) {
+            app_state.handle_tui_message(msg)?;
+            while let Some(command) = command_rx.try_pop() {
+                engine.handle_command(command);
+            }
+        }
+
+        engine.render_block(true);
+        writer.write(engine.output())?;
+        rendered_secs = block_end;
+
+        if events.peek().is_some() {
+            continue;
+        }
+        if rendered_secs - last_event_secs >= RENDER_MAX_TAIL_SECS {
+            log::warn!("[Render] Voices still sounding, cutting off the tail.");
+            break;
+        }
+        if engine.is_silent() {
+            let since = *silent_since.get_or_insert(rendered_secs);
+            if rendered_secs - since >= RENDER_TAIL_SECS {
+                break;
+            }
+        } else {
+            silent_since = None;
+        }
+    }
+
+    writer.finalize()?;
+    println!(
+        "{}",
+        t!(
+            "main.render_finished_fmt",
+            seconds = format!("{:.1}", rendered_secs),
+            elapsed = format!("{:.1}", started_at.elapsed().as_secs_f64()),
+            path = output_path.display()
+        )
+    );
+    Ok(())
+}
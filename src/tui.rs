@@ -26,6 +26,7 @@ use std::{
 
 use crate::app::{AppMessage, MainLoopAction};
 use crate::app_state::AppState;
+use crate::audio_command::EngineCommand;
 use crate::config::{MidiEventSpec, load_organ_library};
 use crate::input::MusicCommand;
 use crate::tui_midi_learn::{MidiLearnTuiState, draw_midi_learn_modal};
@@ -226,38 +227,37 @@ impl TuiState {
         }
     }
 
-    fn toggle_stop_channel(&mut self, channel: u8, audio_tx: &Sender<AppMessage>) -> Result<()> {
+    fn toggle_stop_channel(&mut self, channel: u8) -> Result<()> {
         if let MainViewMode::Stops = self.main_view_mode {
             if let Some(selected_index) = self.stop_list_state.selected() {
-                self.app_state.lock().unwrap().toggle_stop_channel(
-                    selected_index,
-                    channel,
-                    audio_tx,
-                )?;
+                self.app_state
+                    .lock()
+                    .unwrap()
+                    .toggle_stop_channel(selected_index, channel)?;
             }
         }
         Ok(())
     }
 
-    fn select_all_channels_for_stop(&mut self, audio_tx: &Sender<AppMessage>) -> Result<()> {
+    fn select_all_channels_for_stop(&mut self) -> Result<()> {
         if let MainViewMode::Stops = self.main_view_mode {
             if let Some(selected_index) = self.stop_list_state.selected() {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .select_all_channels_for_stop(selected_index, audio_tx)?;
+                    .select_all_channels_for_stop(selected_index)?;
             }
         }
         Ok(())
     }
 
-    fn select_none_channels_for_stop(&mut self, audio_tx: &Sender<AppMessage>) -> Result<()> {
+    fn select_none_channels_for_stop(&mut self) -> Result<()> {
         if let MainViewMode::Stops = self.main_view_mode {
             if let Some(selected_index) = self.stop_list_state.selected() {
                 self.app_state
                     .lock()
                     .unwrap()
-                    .select_none_channels_for_stop(selected_index, audio_tx)?;
+                    .select_none_channels_for_stop(selected_index)?;
             }
         }
         Ok(())
@@ -307,7 +307,9 @@ pub fn run_tui_loop(
                         .iter()
                         .find(|o| {
                             o.name != current_name
-                                && o.activation_trigger.as_ref().map_or(false, |t| t == &event)
+                                && o.activation_trigger
+                                    .as_ref()
+                                    .is_some_and(|t| t.matches(&event))
                         }) // Check Name
                         .map(|o| o.path.clone())
                 } else if let Some(sysex) = state.last_sysex.take() {
@@ -318,7 +320,9 @@ pub fn run_tui_loop(
                         .iter()
                         .find(|o| {
                             o.name != current_name
-                                && o.activation_trigger.as_ref().map_or(false, |t| t == &event)
+                                && o.activation_trigger
+                                    .as_ref()
+                                    .is_some_and(|t| t.matches(&event))
                         }) // Check Name
                         .map(|o| o.path.clone())
                 } else {
@@ -372,8 +376,8 @@ pub fn run_tui_loop(
                         let note = state.get_keyboard_midi_note(semitone);
 
                         match key.kind {
-                            KeyEventKind::Press => state.handle_keyboard_note(note, 100, &audio_tx),
-                            KeyEventKind::Release => state.handle_keyboard_note(note, 0, &audio_tx),
+                            KeyEventKind::Press => state.handle_keyboard_note(note, 100),
+                            KeyEventKind::Release => state.handle_keyboard_note(note, 0),
                             _ => {}
                         }
                     }
@@ -476,9 +480,7 @@ pub fn run_tui_loop(
                                                             let active = state
                                                                 .active_tremulants
                                                                 .contains(&id);
-                                                            state.set_tremulant_active(
-                                                                id, !active, &audio_tx,
-                                                            );
+                                                            state.set_tremulant_active(id, !active);
                                                         }
                                                     }
                                                 }
@@ -490,7 +492,7 @@ pub fn run_tui_loop(
                                                             .app_state
                                                             .lock()
                                                             .unwrap()
-                                                            .recall_preset(slot, &audio_tx);
+                                                            .recall_preset(slot);
                                                     }
                                                 }
                                                 _ => {}
@@ -507,7 +509,7 @@ pub fn run_tui_loop(
                                                 ) =>
                                         {
                                             let channel = c as u8 - b'1';
-                                            tui_state.toggle_stop_channel(channel, &audio_tx)?;
+                                            tui_state.toggle_stop_channel(channel)?;
                                         }
                                         KeyCode::Char('0')
                                             if matches!(
@@ -515,7 +517,7 @@ pub fn run_tui_loop(
                                                 MainViewMode::Stops
                                             ) =>
                                         {
-                                            tui_state.toggle_stop_channel(9, &audio_tx)?;
+                                            tui_state.toggle_stop_channel(9)?;
                                         }
 
                                         // Passthrough to other keys
@@ -532,7 +534,11 @@ pub fn run_tui_loop(
                                                 KeyCode::Right => tui_state.next_col(),
                                                 KeyCode::Left => tui_state.prev_col(),
                                                 KeyCode::Char('p') => {
-                                                    audio_tx.send(AppMessage::AllNotesOff)?;
+                                                    tui_state
+                                                        .app_state
+                                                        .lock()
+                                                        .unwrap()
+                                                        .send_command(EngineCommand::AllNotesOff);
                                                 }
                                                 KeyCode::Char('m')
                                                     if key
@@ -574,7 +580,7 @@ pub fn run_tui_loop(
                                                         .modifiers
                                                         .contains(KeyModifiers::SHIFT) =>
                                                 {
-                                                    tui_state.select_all_channels_for_stop(&audio_tx)?;
+                                                    tui_state.select_all_channels_for_stop()?;
                                                 }
                                                 KeyCode::Char('n')
                                                     if key
@@ -582,7 +588,7 @@ pub fn run_tui_loop(
                                                         .contains(KeyModifiers::SHIFT) =>
                                                 {
                                                     tui_state
-                                                        .select_none_channels_for_stop(&audio_tx)?;
+                                                        .select_none_channels_for_stop()?;
                                                 }
                                                 KeyCode::F(n)
                                                     if (1..=12).contains(&n)
@@ -616,7 +622,7 @@ pub fn run_tui_loop(
                                                         .app_state
                                                         .lock()
                                                         .unwrap()
-                                                        .recall_preset((n - 1) as usize, &audio_tx)
+                                                        .recall_preset((n - 1) as usize)
                                                     {
                                                         tui_state
                                                             .app_state
@@ -637,14 +643,14 @@ pub fn run_tui_loop(
                                                         .app_state
                                                         .lock()
                                                         .unwrap()
-                                                        .modify_gain(0.05, &audio_tx);
+                                                        .modify_gain(0.05);
                                                 }
                                                 KeyCode::Char('-') => {
                                                     tui_state
                                                         .app_state
                                                         .lock()
                                                         .unwrap()
-                                                        .modify_gain(-0.05, &audio_tx);
+                                                        .modify_gain(-0.05);
                                                 }
                                                 // Polyphony
                                                 KeyCode::Char(']') => {
@@ -652,14 +658,14 @@ pub fn run_tui_loop(
                                                         .app_state
                                                         .lock()
                                                         .unwrap()
-                                                        .modify_polyphony(16, &audio_tx);
+                                                        .modify_polyphony(16);
                                                 }
                                                 KeyCode::Char('[') => {
                                                     tui_state
                                                         .app_state
                                                         .lock()
                                                         .unwrap()
-                                                        .modify_polyphony(-16, &audio_tx);
+                                                        .modify_polyphony(-16);
                                                 }
                                                 _ => {}
                                             }
@@ -908,6 +914,29 @@ fn draw_main_app_ui(frame: &mut Frame, app_state: &mut AppState, tui_state: &mut
     } else {
         "".to_string()
     };
+    let loader = &app_state.loader_status;
+    let mapped_status = match app_state.mapped_memory {
+        Some(mapped) => t!(
+            "tui.mapped_memory_fmt",
+            resident = mapped
+                .resident_bytes
+                .map_or("?".to_string(), |b| (b / (1024 * 1024)).to_string()),
+            mapped = mapped.mapped_bytes / (1024 * 1024)
+        )
+        .to_string(),
+        None => String::new(),
+    };
+    let rec_status = format!(
+        "{}{}{}",
+        rec_status,
+        t!(
+            "tui.loader_status_fmt",
+            busy = loader.busy_workers,
+            workers = loader.workers,
+            queued = loader.queued_jobs
+        ),
+        mapped_status
+    );
 
     let footer_widget = if let Some(err) = &app_state.error_msg {
         Paragraph::new(err.as_str()).style(Style::default().fg(Color::White).bg(Color::Red))
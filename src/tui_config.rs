@@ -11,7 +11,7 @@ use std::time::Duration;
 
 use crate::app::LOGO;
 use crate::audio::get_supported_sample_rates;
-use crate::config::{AppSettings, ConfigShared, ConfigState, RuntimeConfig};
+use crate::config::{AppSettings, CacheFormat, ConfigShared, ConfigState, RuntimeConfig};
 use crate::tui::{cleanup_terminal, setup_terminal};
 use crate::tui_filepicker;
 use crate::tui_lcd;
@@ -26,6 +26,7 @@ enum ConfigMode {
     TextInput(usize, String), // Holds (config_index, buffer)
     MidiDeviceList,           // List of detected devices
     MidiMapping(usize),       // Editing device at specific index in settings.midi_devices
+    VirtualMidiMapping,       // Editing the virtual input port
     LcdConfig,                // New LCD Config Mode
 }
 
@@ -55,11 +56,13 @@ enum SettingRow {
     AudioBuffer = 9,
     MaxRAMGB = 10,
     Precache = 11,
-    ConvertTo16Bit = 12,
-    OriginalTuning = 13,
-    LcdConfiguration = 14,
-    Start = 15,
-    Quit = 16,
+    MmapSamples = 12,
+    ConvertTo16Bit = 13,
+    CompressCache = 14,
+    OriginalTuning = 15,
+    LcdConfiguration = 16,
+    Start = 17,
+    Quit = 18,
 }
 
 impl SettingRow {
@@ -78,11 +81,13 @@ impl SettingRow {
             9 => Some(Self::AudioBuffer),
             10 => Some(Self::MaxRAMGB),
             11 => Some(Self::Precache),
-            12 => Some(Self::ConvertTo16Bit),
-            13 => Some(Self::OriginalTuning),
-            14 => Some(Self::LcdConfiguration),
-            15 => Some(Self::Start),
-            16 => Some(Self::Quit),
+            12 => Some(Self::MmapSamples),
+            13 => Some(Self::ConvertTo16Bit),
+            14 => Some(Self::CompressCache),
+            15 => Some(Self::OriginalTuning),
+            16 => Some(Self::LcdConfiguration),
+            17 => Some(Self::Start),
+            18 => Some(Self::Quit),
             _ => None,
         }
     }
@@ -138,11 +143,21 @@ fn get_item_display(idx: usize, state: &ConfigState) -> String {
             val = bool_to_str(settings.precache)
         )
         .to_string(),
+        SettingRow::MmapSamples => t!(
+            "tui_config.fmt_mmap",
+            val = bool_to_str(settings.mmap_samples)
+        )
+        .to_string(),
         SettingRow::ConvertTo16Bit => t!(
             "tui_config.fmt_convert",
             val = bool_to_str(settings.convert_to_16bit)
         )
         .to_string(),
+        SettingRow::CompressCache => t!(
+            "tui_config.fmt_compress_cache",
+            val = bool_to_str(settings.cache_format == CacheFormat::Flac)
+        )
+        .to_string(),
         SettingRow::OriginalTuning => t!(
             "tui_config.fmt_tuning",
             val = bool_to_str(settings.original_tuning)
@@ -294,11 +309,11 @@ pub fn run_config_ui(
                     match key.code {
                         KeyCode::Char('q') | KeyCode::Esc => break 'config_loop,
                         KeyCode::Down | KeyCode::Char('j') => {
-                            let i = state.list_state.selected().map_or(0, |i| (i + 1) % 17);
+                            let i = state.list_state.selected().map_or(0, |i| (i + 1) % 19);
                             state.list_state.select(Some(i));
                         }
                         KeyCode::Up | KeyCode::Char('k') => {
-                            let i = state.list_state.selected().map_or(16, |i| (i + 16) % 17);
+                            let i = state.list_state.selected().map_or(18, |i| (i + 18) % 19);
                             state.list_state.select(Some(i));
                         }
                         KeyCode::Enter => {
@@ -376,10 +391,23 @@ pub fn run_config_ui(
                                         state.config_state.settings.precache =
                                             !state.config_state.settings.precache
                                     }
+                                    SettingRow::MmapSamples => {
+                                        if !state.config_state.settings.precache {
+                                            state.config_state.settings.mmap_samples =
+                                                !state.config_state.settings.mmap_samples
+                                        }
+                                    }
                                     SettingRow::ConvertTo16Bit => {
                                         state.config_state.settings.convert_to_16bit =
                                             !state.config_state.settings.convert_to_16bit
                                     }
+                                    SettingRow::CompressCache => {
+                                        let settings = &mut state.config_state.settings;
+                                        settings.cache_format = match settings.cache_format {
+                                            CacheFormat::Wav => CacheFormat::Flac,
+                                            CacheFormat::Flac => CacheFormat::Wav,
+                                        };
+                                    }
                                     SettingRow::OriginalTuning => {
                                         state.config_state.settings.original_tuning =
                                             !state.config_state.settings.original_tuning
@@ -415,14 +443,22 @@ pub fn run_config_ui(
                                                 audio_buffer_frames: s.audio_buffer_frames,
                                                 max_ram_gb: s.max_ram_gb,
                                                 precache: s.precache,
+                                                mmap_samples: s.mmap_samples,
                                                 convert_to_16bit: s.convert_to_16bit,
+                                                cache_format: s.cache_format,
                                                 original_tuning: s.original_tuning,
                                                 midi_file: state.config_state.midi_file.clone(),
                                                 active_midi_devices: active_devices,
+                                                virtual_midi_input: s.virtual_midi_input.clone(),
                                                 gain: s.gain,
                                                 polyphony: s.polyphony,
                                                 max_new_voices_per_block: s
                                                     .max_new_voices_per_block,
+                                                attack_selection: s.attack_selection,
+                                                interpolation: s.interpolation,
+                                                tracker_delay_scale: s.tracker_delay_scale,
+                                                output_channels: s.output_channels,
+                                                output_routes: s.output_routes.clone(),
                                                 audio_device_name: state
                                                     .config_state
                                                     .selected_audio_device_name
@@ -441,7 +477,10 @@ pub fn run_config_ui(
                     }
                 }
                 ConfigMode::MidiDeviceList => {
-                    let count = state.config_state.system_midi_ports.len();
+                    // The virtual port (where supported) is listed after the detected devices
+                    let port_count = state.config_state.system_midi_ports.len();
+                    let virtual_row = cfg!(unix).then_some(port_count);
+                    let count = port_count + virtual_row.map_or(0, |_| 1);
                     match key.code {
                         KeyCode::Esc => state.mode = ConfigMode::Main,
                         KeyCode::Down | KeyCode::Char('j') => {
@@ -464,6 +503,12 @@ pub fn run_config_ui(
                         }
                         KeyCode::Char(' ') => {
                             // Toggle Enabled
+                            if virtual_row.is_some()
+                                && state.midi_dev_list_state.selected() == virtual_row
+                            {
+                                let port = &mut state.config_state.settings.virtual_midi_input;
+                                port.enabled = !port.enabled;
+                            }
                             if let Some(idx) = state.midi_dev_list_state.selected() {
                                 if let Some((_, name)) =
                                     state.config_state.system_midi_ports.get(idx)
@@ -482,6 +527,12 @@ pub fn run_config_ui(
                         }
                         KeyCode::Enter => {
                             // Go to Mapping
+                            if virtual_row.is_some()
+                                && state.midi_dev_list_state.selected() == virtual_row
+                            {
+                                state.midi_mapping_state = tui_midi::TuiMidiState::new();
+                                state.mode = ConfigMode::VirtualMidiMapping;
+                            }
                             if let Some(idx) = state.midi_dev_list_state.selected() {
                                 if let Some((_, name)) =
                                     state.config_state.system_midi_ports.get(idx)
@@ -518,6 +569,16 @@ pub fn run_config_ui(
                         _ => {}
                     }
                 }
+                ConfigMode::VirtualMidiMapping => {
+                    let action = tui_midi::handle_input(
+                        key,
+                        &mut state.midi_mapping_state,
+                        &mut state.config_state.settings.virtual_midi_input,
+                    );
+                    if let tui_midi::MappingAction::Back = action {
+                        state.mode = ConfigMode::MidiDeviceList;
+                    }
+                }
                 ConfigMode::LcdConfig => {
                     let action = tui_lcd::handle_input(
                         key,
@@ -725,18 +786,26 @@ fn draw_config_ui(frame: &mut Frame, state: &mut TuiConfigState) {
             return;
         }
     }
+    if let ConfigMode::VirtualMidiMapping = state.mode {
+        let dev = state.config_state.settings.virtual_midi_input.clone();
+        tui_midi::draw(frame, area, &mut state.midi_mapping_state, &dev);
+        return;
+    }
 
     // --- Calculate header height ---
     let pipes_lines_count = LOGO.lines().count();
     let header_height = (pipes_lines_count + 5) as u16;
+    let memory_plan_lines = state.config_state.memory_plan().summary_lines();
+    let memory_plan_height = memory_plan_lines.len() as u16 + 2;
 
     // --- Main Layout ---
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(header_height), // Pipes + Title
-            Constraint::Min(0),                // Config List
-            Constraint::Length(3),             // Help/Error
+            Constraint::Length(header_height),      // Pipes + Title
+            Constraint::Min(0),                     // Config List
+            Constraint::Length(memory_plan_height), // Memory Estimate
+            Constraint::Length(3),                  // Help/Error
         ])
         .split(area);
 
@@ -785,7 +854,9 @@ fn draw_config_ui(frame: &mut Frame, state: &mut TuiConfigState) {
             }
 
             // Style the Disabled RAM option
-            if row == Some(SettingRow::MaxRAMGB) && state.config_state.settings.precache {
+            if matches!(row, Some(SettingRow::MaxRAMGB | SettingRow::MmapSamples))
+                && state.config_state.settings.precache
+            {
                 list_item = list_item.style(Style::default().fg(Color::DarkGray));
             }
 
@@ -799,6 +870,20 @@ fn draw_config_ui(frame: &mut Frame, state: &mut TuiConfigState) {
         .highlight_symbol("» ");
     frame.render_stateful_widget(list_widget, main_layout[1], &mut state.list_state);
 
+    // Memory Estimate
+    let memory_plan_widget = Paragraph::new(
+        memory_plan_lines
+            .into_iter()
+            .map(Line::from)
+            .collect::<Vec<_>>(),
+    )
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(t!("tui_config.memory_plan_title").to_string()),
+    );
+    frame.render_widget(memory_plan_widget, main_layout[2]);
+
     // Footer
     let footer_text = if let Some(err) = &state.config_state.error_msg {
         Line::styled(err.clone(), Style::default().fg(Color::Red))
@@ -808,7 +893,7 @@ fn draw_config_ui(frame: &mut Frame, state: &mut TuiConfigState) {
     let footer = Paragraph::new(footer_text)
         .alignment(Alignment::Center)
         .block(Block::default().borders(Borders::ALL));
-    frame.render_widget(footer, main_layout[2]);
+    frame.render_widget(footer, main_layout[3]);
 
     // --- Modals ---
     match state.mode {
@@ -882,7 +967,7 @@ fn draw_config_ui(frame: &mut Frame, state: &mut TuiConfigState) {
 fn draw_midi_device_list(frame: &mut Frame, state: &mut TuiConfigState) {
     let area = centered_rect(frame.area(), 60, 60);
 
-    let items: Vec<ListItem> = state
+    let mut items: Vec<ListItem> = state
         .config_state
         .system_midi_ports
         .iter()
@@ -905,6 +990,16 @@ fn draw_midi_device_list(frame: &mut Frame, state: &mut TuiConfigState) {
         })
         .collect();
 
+    if cfg!(unix) {
+        let port = &state.config_state.settings.virtual_midi_input;
+        let checkbox = if port.enabled { "[x]" } else { "[ ]" };
+        items.push(ListItem::new(format!(
+            "{} {}",
+            checkbox,
+            t!("tui_config.lbl_virtual_port", name = port.name)
+        )));
+    }
+
     let block = Block::default()
         .borders(Borders::ALL)
         .title(t!("tui_config.title_midi_devs"))
@@ -1,4 +1,5 @@
-use crate::config::{MidiDeviceConfig, MidiMappingMode};
+use crate::config::{KeyboardZone, MidiDeviceConfig, MidiMappingMode};
+use crate::midi::midi_note_to_name;
 use crossterm::event::{KeyCode, KeyEvent};
 use ratatui::{
     prelude::*,
@@ -46,7 +47,10 @@ pub fn handle_input(
     // Row 0 is always "Mode Selection"
     // If Simple: Row 1 is "Target Channel" -> Total 2
     // If Complex: Rows 1-16 are input mappings -> Total 17
-    let total_rows = if is_simple { 2 } else { 17 };
+    // After those, each keyboard zone takes 4 rows, followed by the "Add zone" row
+    let mapping_rows = if is_simple { 2 } else { 17 };
+    let add_zone_row = mapping_rows + device.zones.len() * ZONE_ROWS;
+    let total_rows = add_zone_row + 1;
 
     match event.code {
         KeyCode::Esc => {
@@ -69,7 +73,9 @@ pub fn handle_input(
                 // Which row are we editing?
                 let idx = state.list_state.selected().unwrap_or(0);
 
-                if idx == 1 && is_simple {
+                if idx >= mapping_rows {
+                    adjust_zone(device, idx - mapping_rows, -1);
+                } else if idx == 1 && is_simple {
                     // Decrement Simple Target (wrap 0-15)
                     device.simple_target_channel = (device.simple_target_channel + 16 - 1) % 16;
                 } else if idx > 0 && !is_simple {
@@ -91,7 +97,9 @@ pub fn handle_input(
                 // --- Editing Mode ---
                 let idx = state.list_state.selected().unwrap_or(0);
 
-                if idx == 1 && is_simple {
+                if idx >= mapping_rows {
+                    adjust_zone(device, idx - mapping_rows, 1);
+                } else if idx == 1 && is_simple {
                     // Increment Simple Target
                     device.simple_target_channel = (device.simple_target_channel + 1) % 16;
                 } else if idx > 0 && !is_simple {
@@ -115,6 +123,8 @@ pub fn handle_input(
                 // If we switched modes, reset selection to avoid out of bounds
                 state.list_state.select(Some(0));
                 state.editing_row = None;
+            } else if idx == add_zone_row {
+                device.zones.push(KeyboardZone::default());
             } else {
                 // Row > 0: Toggle Edit Mode
                 if state.editing_row.is_some() {
@@ -124,11 +134,49 @@ pub fn handle_input(
                 }
             }
         }
+
+        KeyCode::Delete | KeyCode::Char('x') => {
+            let idx = state.list_state.selected().unwrap_or(0);
+            if idx >= mapping_rows && idx < add_zone_row {
+                device.zones.remove((idx - mapping_rows) / ZONE_ROWS);
+                state.editing_row = None;
+                // Keep the selection inside the shortened list
+                state
+                    .list_state
+                    .select(Some(idx.min(total_rows - 1 - ZONE_ROWS)));
+            }
+        }
         _ => {}
     }
     MappingAction::None
 }
 
+/// Number of list rows per keyboard zone (low note, high note, channel, octave).
+const ZONE_ROWS: usize = 4;
+
+/// Steps one field of a keyboard zone. `row` counts from the first zone row.
+fn adjust_zone(device: &mut MidiDeviceConfig, row: usize, delta: i8) {
+    let Some(zone) = device.zones.get_mut(row / ZONE_ROWS) else {
+        return;
+    };
+    match row % ZONE_ROWS {
+        0 => {
+            zone.low_note = zone
+                .low_note
+                .saturating_add_signed(delta)
+                .min(zone.high_note)
+        }
+        1 => {
+            zone.high_note = zone
+                .high_note
+                .saturating_add_signed(delta)
+                .clamp(zone.low_note, 127)
+        }
+        2 => zone.target_channel = (zone.target_channel as i8 + 16 + delta) as u8 % 16,
+        _ => zone.octave_shift = (zone.octave_shift + delta).clamp(-4, 4),
+    }
+}
+
 /// Renders the MIDI mapping UI into the given area.
 pub fn draw(frame: &mut Frame, area: Rect, state: &mut TuiMidiState, device: &MidiDeviceConfig) {
     let block = Block::default()
@@ -173,6 +221,42 @@ pub fn draw(frame: &mut Frame, area: Rect, state: &mut TuiMidiState, device: &Mi
         }
     }
 
+    // -- Keyboard zones --
+    let mapping_rows = items.len();
+    for (z, zone) in device.zones.iter().enumerate() {
+        let num = z + 1;
+        let labels = [
+            t!(
+                "tui_midi.lbl_zone_low",
+                num = num,
+                val = midi_note_to_name(zone.low_note)
+            ),
+            t!(
+                "tui_midi.lbl_zone_high",
+                num = num,
+                val = midi_note_to_name(zone.high_note)
+            ),
+            t!(
+                "tui_midi.lbl_zone_channel",
+                num = num,
+                ch = zone.target_channel + 1
+            ),
+            t!(
+                "tui_midi.lbl_zone_octave",
+                num = num,
+                val = format!("{:+}", zone.octave_shift)
+            ),
+        ];
+        for (f, label) in labels.into_iter().enumerate() {
+            let row = mapping_rows + z * ZONE_ROWS + f;
+            items.push(create_list_item(
+                label.to_string(),
+                state.editing_row == Some(row),
+            ));
+        }
+    }
+    items.push(ListItem::new(t!("tui_midi.lbl_add_zone").to_string()));
+
     // Highlighting logic
     // If we are editing, we usually want the background to look different or the text to be yellow
     let list = List::new(items)
@@ -0,0 +1,219 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::{OnceLock, mpsc};
+
+pub const TUNING_FILE_NAME: &str = "rusty-pipes.tuning.json";
+
+/// Transposition range in semitones. Together with the A4 range this keeps the
+/// playback rate well below the 2.0 the voice mixer's scratch buffers are sized for.
+pub const MAX_TRANSPOSE: i8 = 6;
+pub const MIN_A4_HZ: f32 = 392.0;
+pub const MAX_A4_HZ: f32 = 494.0;
+/// Largest deviation from equal temperament accepted in a custom cent table.
+pub const MAX_CUSTOM_CENTS: f32 = 50.0;
+
+/// Temperaments the pipes can be tuned to. The tables are in cents from equal
+/// temperament for C, C#, D, ... B.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Temperament {
+    /// Tuning as recorded; the sample set is assumed to be in equal temperament.
+    #[default]
+    Equal,
+    WerckmeisterIii,
+    KirnbergerIii,
+    /// Quarter-comma meantone, wolf fifth between G# and Eb.
+    Meantone,
+    Vallotti,
+    /// The cent table from `Tuning::custom_cents`.
+    Custom,
+}
+
+impl Temperament {
+    pub const ALL: [Temperament; 6] = [
+        Temperament::Equal,
+        Temperament::WerckmeisterIii,
+        Temperament::KirnbergerIii,
+        Temperament::Meantone,
+        Temperament::Vallotti,
+        Temperament::Custom,
+    ];
+
+    /// Identifier used in the settings file and the REST API.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Temperament::Equal => "equal",
+            Temperament::WerckmeisterIii => "werckmeister_iii",
+            Temperament::KirnbergerIii => "kirnberger_iii",
+            Temperament::Meantone => "meantone",
+            Temperament::Vallotti => "vallotti",
+            Temperament::Custom => "custom",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.id() == id)
+    }
+
+    fn cents(&self) -> [f32; 12] {
+        match self {
+            Temperament::Equal | Temperament::Custom => [0.0; 12],
+            Temperament::WerckmeisterIii => [
+                0.0, -9.8, -7.8, -5.9, -9.8, -2.0, -11.7, -3.9, -7.8, -11.7, -3.9, -7.8,
+            ],
+            Temperament::KirnbergerIii => [
+                0.0, -9.8, -6.8, -5.9, -13.7, -2.0, -9.8, -3.4, -7.8, -10.3, -3.9, -11.7,
+            ],
+            Temperament::Meantone => [
+                10.3, -13.7, 3.4, 20.5, -3.4, 13.7, -10.3, 6.8, -17.1, 0.0, 17.1, -6.8,
+            ],
+            Temperament::Vallotti => [
+                5.9, 0.0, 2.0, 3.9, -2.0, 7.8, -2.0, 3.9, 2.0, 0.0, 5.9, -3.9,
+            ],
+        }
+    }
+}
+
+/// Pitch settings applied to every pipe as a playback rate, so they can be
+/// changed while playing without touching the sample cache. Stored per organ.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Tuning {
+    pub temperament: Temperament,
+    /// Cents from equal temperament for C, C#, ... B, used by `Temperament::Custom`.
+    pub custom_cents: [f32; 12],
+    /// Transposition in semitones. Every pipe is re-pitched, so the temperament
+    /// moves with the keys like on a transposing console.
+    pub transpose: i8,
+    /// Reference pitch of A4 in Hz.
+    pub a4_hz: f32,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self {
+            temperament: Temperament::Equal,
+            custom_cents: [0.0; 12],
+            transpose: 0,
+            a4_hz: 440.0,
+        }
+    }
+}
+
+impl Tuning {
+    /// Clamps every value into the supported range.
+    pub fn sanitized(mut self) -> Self {
+        self.transpose = self.transpose.clamp(-MAX_TRANSPOSE, MAX_TRANSPOSE);
+        self.a4_hz = if self.a4_hz.is_finite() {
+            self.a4_hz.clamp(MIN_A4_HZ, MAX_A4_HZ)
+        } else {
+            440.0
+        };
+        for cents in self.custom_cents.iter_mut() {
+            *cents = if cents.is_finite() {
+                cents.clamp(-MAX_CUSTOM_CENTS, MAX_CUSTOM_CENTS)
+            } else {
+                0.0
+            };
+        }
+        self
+    }
+
+    /// Cents from equal temperament per pitch class. Tables are shifted so A
+    /// stays at 0 and sounds at exactly `a4_hz`.
+    pub fn cents_offsets(&self) -> [f32; 12] {
+        let table = match self.temperament {
+            Temperament::Custom => self.custom_cents,
+            temperament => temperament.cents(),
+        };
+        table.map(|cents| cents - table[9])
+    }
+
+    /// Playback rate for the pipe at MIDI `note`, relative to its recorded pitch.
+    /// `sounding_interval` is how many semitones above the key the pipe sounds
+    /// (see `Rank::sounding_interval`): a mutation takes the temperament offset
+    /// of the pitch it sounds, so it stays in tune with the unisons.
+    pub fn pitch_ratio(&self, note: u8, sounding_interval: i32) -> f32 {
+        let pitch_class = (note as i32 + sounding_interval).rem_euclid(12) as usize;
+        let cents = self.cents_offsets()[pitch_class] + self.transpose as f32 * 100.0;
+        2.0f32.powf(cents / 1200.0) * self.a4_hz / 440.0
+    }
+
+    /// Loads the tuning saved for the given organ, or the default (equal, A4 = 440 Hz).
+    pub fn load(organ_name: &str) -> Self {
+        let Some(path) = get_tuning_file_path() else {
+            return Self::default();
+        };
+        File::open(path)
+            .ok()
+            .and_then(|file| {
+                serde_json::from_reader::<_, HashMap<String, Tuning>>(BufReader::new(file)).ok()
+            })
+            .and_then(|mut config| config.remove(organ_name))
+            .map(Self::sanitized)
+            .unwrap_or_default()
+    }
+
+    /// Saves this tuning for the given organ, keeping the other organs' entries.
+    pub fn save(&self, organ_name: &str) -> Result<()> {
+        let path =
+            get_tuning_file_path().ok_or_else(|| anyhow::anyhow!("No configuration directory"))?;
+        let mut config: HashMap<String, Tuning> = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+        config.insert(organ_name.to_string(), self.clone());
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &config)?;
+        Ok(())
+    }
+
+    /// Saves this tuning on a background thread. All saves go through one
+    /// writer in the order they were queued, so none of them is lost.
+    pub fn save_in_background(&self, organ_name: &str) {
+        static WRITER: OnceLock<mpsc::Sender<(String, Tuning)>> = OnceLock::new();
+        let writer = WRITER.get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<(String, Tuning)>();
+            std::thread::spawn(move || {
+                for (organ_name, tuning) in rx {
+                    if let Err(e) = tuning.save(&organ_name) {
+                        log::error!("Failed to save tuning: {}", e);
+                    }
+                }
+            });
+            tx
+        });
+        let _ = writer.send((organ_name.to_string(), self.clone()));
+    }
+}
+
+fn get_tuning_file_path() -> Option<PathBuf> {
+    let config_path = confy::get_configuration_file_path("rusty-pipes", "settings").ok()?;
+    Some(config_path.parent()?.join(TUNING_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutations_take_the_offset_of_the_pitch_they_sound() {
+        let tuning = Tuning {
+            temperament: Temperament::Meantone,
+            ..Tuning::default()
+        };
+        let c3 = 48;
+        // A Quint 2 2/3' on C sounds G: it must be tuned like the G key, not like C
+        let quint = tuning.pitch_ratio(c3, 7);
+        let g = tuning.pitch_ratio(c3 + 7, 0);
+        assert!((quint - g).abs() < 1e-6);
+        assert!((quint - tuning.pitch_ratio(c3, 0)).abs() > 1e-3);
+        // A Tierce 1 3/5' on C sounds E, two octaves up
+        assert!((tuning.pitch_ratio(c3, 28) - tuning.pitch_ratio(c3 + 4, 0)).abs() < 1e-6);
+        // A 16' rank keeps its key's offset
+        assert!((tuning.pitch_ratio(c3, -12) - tuning.pitch_ratio(c3, 0)).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::{OnceLock, mpsc};
+
+pub const VELOCITY_FILE_NAME: &str = "rusty-pipes.velocity.json";
+
+/// Steepest velocity curve accepted. At 4.0 a note struck at half velocity is
+/// already 24 dB down.
+pub const MAX_VELOCITY_EXPONENT: f32 = 4.0;
+
+/// Per-rank velocity sensitivity for dynamically recorded ranks. Ranks without
+/// an entry ignore velocity, like a real organ. Stored per organ.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct VelocityCurves {
+    /// Exponent of the velocity-to-gain curve per rank ID,
+    /// gain = (velocity / 127) ^ exponent.
+    pub ranks: HashMap<String, f32>,
+}
+
+/// Gain offset in dB for a note struck with the given velocity on a rank with
+/// the given curve exponent.
+pub fn velocity_gain_db(curve: Option<f32>, velocity: u8) -> f32 {
+    match curve {
+        Some(exponent) if velocity > 0 => {
+            let amplitude = (velocity.min(127) as f32 / 127.0).powf(exponent);
+            20.0 * amplitude.log10()
+        }
+        Some(_) => -96.0,
+        None => 0.0,
+    }
+}
+
+impl VelocityCurves {
+    /// Drops curves that aren't finite and clamps the rest into the supported range.
+    pub fn sanitized(mut self) -> Self {
+        self.ranks
+            .retain(|_, exponent| exponent.is_finite() && *exponent > 0.0);
+        for exponent in self.ranks.values_mut() {
+            *exponent = exponent.min(MAX_VELOCITY_EXPONENT);
+        }
+        self
+    }
+
+    /// Curve exponent of the given rank, `None` if it ignores velocity.
+    pub fn curve(&self, rank_id: &str) -> Option<f32> {
+        self.ranks.get(rank_id).copied()
+    }
+
+    /// Loads the curves saved for the given organ, or none.
+    pub fn load(organ_name: &str) -> Self {
+        let Some(path) = get_velocity_file_path() else {
+            return Self::default();
+        };
+        File::open(path)
+            .ok()
+            .and_then(|file| {
+                serde_json::from_reader::<_, HashMap<String, VelocityCurves>>(BufReader::new(file))
+                    .ok()
+            })
+            .and_then(|mut config| config.remove(organ_name))
+            .map(Self::sanitized)
+            .unwrap_or_default()
+    }
+
+    /// Saves these curves for the given organ, keeping the other organs' entries.
+    pub fn save(&self, organ_name: &str) -> Result<()> {
+        let path = get_velocity_file_path()
+            .ok_or_else(|| anyhow::anyhow!("No configuration directory"))?;
+        let mut config: HashMap<String, VelocityCurves> = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default();
+        config.insert(organ_name.to_string(), self.clone());
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &config)?;
+        Ok(())
+    }
+
+    /// Saves these curves on a background thread, in the order they were queued.
+    pub fn save_in_background(&self, organ_name: &str) {
+        static WRITER: OnceLock<mpsc::Sender<(String, VelocityCurves)>> = OnceLock::new();
+        let writer = WRITER.get_or_init(|| {
+            let (tx, rx) = mpsc::channel::<(String, VelocityCurves)>();
+            std::thread::spawn(move || {
+                for (organ_name, curves) in rx {
+                    if let Err(e) = curves.save(&organ_name) {
+                        log::error!("Failed to save velocity curves: {}", e);
+                    }
+                }
+            });
+            tx
+        });
+        let _ = writer.send((organ_name.to_string(), self.clone()));
+    }
+}
+
+fn get_velocity_file_path() -> Option<PathBuf> {
+    let config_path = confy::get_configuration_file_path("rusty-pipes", "settings").ok()?;
+    Some(config_path.parent()?.join(VELOCITY_FILE_NAME))
+}
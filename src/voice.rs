@@ -1,13 +1,15 @@
-use anyhow::Result;
 use decibel::{AmplitudeRatio, DecibelRatio};
-use ringbuf::traits::{Producer, Split};
+use ringbuf::traits::{Consumer, Observer, Producer};
 use ringbuf::{HeapCons, HeapProd, HeapRb};
-use std::path::{Path, PathBuf};
+use std::path::Path;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, mpsc};
 use std::time::Instant;
 
+use crate::audio_command::OrganIndex;
+use crate::audio_loader::{JobQueue, LoaderWaker};
 use crate::organ::Organ;
+use crate::wav_converter::PhaseAlignment;
 
 // Common Audio Constants
 pub const CHANNEL_COUNT: usize = 2;
@@ -16,122 +18,447 @@ pub const CROSSFADE_TIME: f32 = 0.10;
 pub const VOICE_STEALING_FADE_TIME: f32 = 1.00;
 pub const MAX_NEW_VOICES_PER_BLOCK: usize = 28;
 pub const TREMULANT_AM_BOOST: f32 = 1.0;
+pub const ENCLOSURE_SMOOTHING_TIME: f32 = 0.05;
+pub const ENCLOSURE_CLOSED_CUTOFF_HZ: f32 = 2000.0;
 
 pub struct TremulantLfo {
     pub phase: f32,
     pub current_level: f32,
 }
 
+/// Runtime state of a swell box. Position 0.0 is closed, 1.0 is fully open.
+pub struct EnclosureState {
+    pub target_position: f32,
+    pub current_position: f32,
+    pub min_amplitude: f32,
+    /// Low-pass filter state, one per output pair
+    pub filter_state: Vec<[f32; CHANNEL_COUNT]>,
+}
+
+/// One of a pipe's samples, by its position in `Pipe::attacks` or `Pipe::releases`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeSample {
+    Attack(usize),
+    Release(usize),
+}
+
+/// A sample of the organ, given by indices so loader jobs carry no heap data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleRef {
+    /// Rank index in `OrganIndex`
+    pub rank_index: usize,
+    pub note: u8,
+    pub kind: PipeSample,
+}
+
+impl SampleRef {
+    /// The sample's file, or `None` if the organ has no such sample.
+    pub fn path<'a>(&self, organ: &'a Organ, index: &OrganIndex) -> Option<&'a Path> {
+        let rank = organ.ranks.get(index.rank_ids.get(self.rank_index)?)?;
+        let pipe = rank.pipes.get(&self.note)?;
+        match self.kind {
+            PipeSample::Attack(i) => pipe.attacks.get(i).map(|a| a.path.as_path()),
+            PipeSample::Release(i) => pipe.releases.get(i).map(|r| r.path.as_path()),
+        }
+    }
+}
+
 pub struct SpawnJob {
-    pub path: PathBuf,
     pub organ: Arc<Organ>,
+    pub index: Arc<OrganIndex>,
+    pub sample: SampleRef,
     pub sample_rate: u32,
-    pub is_attack_sample: bool,
+    /// Sustain the sample by honouring its `smpl` loop (wind-blown attacks only).
+    pub use_loop: bool,
     pub frames_to_skip: usize,
+    /// Attack starts are loaded ahead of releases and refills.
+    pub is_attack: bool,
+    /// Writes into the voice's ring buffer. Dropping it tells the voice that
+    /// the loader has finished.
     pub producer: HeapProd<f32>,
-    pub is_finished: Arc<AtomicBool>,
     pub is_cancelled: Arc<AtomicBool>,
 }
 
+/// What a `VoiceSlab` needs to queue its voices' samples with the loader.
+pub struct VoiceLoader {
+    pub organ: Arc<Organ>,
+    pub index: Arc<OrganIndex>,
+    pub sample_rate: u32,
+    pub jobs: JobQueue,
+}
+
+/// A voice to start, see `VoiceSlab::insert`.
+pub struct VoiceStart<'a> {
+    pub sample: SampleRef,
+    /// Head of the sample kept in memory, played while the loader opens the file
+    pub preloaded: Option<&'a [f32]>,
+    pub gain_db: f32,
+    pub start_fading_in: bool,
+    pub is_attack_sample: bool,
+    pub is_percussive: bool,
+    pub note_on_time: Instant,
+    /// Frame of the sample to start playing at
+    pub start_frame: usize,
+    pub windchest: Option<usize>,
+}
+
 /// Represents one playing sample, either attack or release.
 pub struct Voice {
     pub gain: f32,
     pub consumer: HeapCons<f32>,
-    pub is_finished: Arc<AtomicBool>,
     pub is_cancelled: Arc<AtomicBool>,
 
     pub fade_level: f32,
     pub is_fading_out: bool,
     pub is_fading_in: bool,
     pub is_awaiting_release_sample: bool,
-    pub release_voice_id: Option<u64>,
+    pub release_voice_id: Option<VoiceId>,
+    /// A release that is not read until its crossfade starts, when the
+    /// engine moves it to the frame matching the attack's phase.
+    pub is_waiting_for_crossfade: bool,
+    /// Loop level (attacks) or alignment table (releases), see `PhaseAlignment`.
+    pub phase_alignment: Option<Arc<PhaseAlignment>>,
+
+    /// Output frames of silence before this voice starts (tracker delay).
+    pub delay_frames: usize,
+    /// Frames until a pending release takes over from this attack (tracker delay).
+    pub release_delay_frames: usize,
 
     pub note_on_time: Instant,
     pub is_attack_sample: bool,
+    /// An attack of a percussive rank, which rings out on its own after the key is released.
+    pub is_percussive: bool,
     pub fade_increment: f32,
 
-    pub windchest_group_id: Option<String>,
+    /// Windchest group index in `OrganIndex`, for tremulants and enclosures.
+    pub windchest: Option<usize>,
+    /// Stereo output pair this voice is mixed into (see `OutputRouting`).
+    pub output_pair: usize,
+    /// Playback rate from the temperament, transposition and A4 pitch.
+    pub pitch_ratio: f32,
 
     pub input_buffer: Vec<f32>,
     pub buffer_start_idx: usize,
     pub cursor_pos: f32,
+
+    /// The ring buffer behind `consumer`, handed to the loader for each new sample
+    ring: Arc<HeapRb<f32>>,
+    /// Tells the loader when the ring buffer has room for a refill
+    loader_waker: LoaderWaker,
 }
 
 impl Voice {
-    #[cfg_attr(feature = "hotpath", hotpath::measure)]
-    pub fn new(
-        path: &Path,
-        organ: Arc<Organ>,
-        sample_rate: u32,
-        gain_db: f32,
-        start_fading_in: bool,
-        is_attack_sample: bool,
-        note_on_time: Instant,
-        preloaded_bytes: Option<Arc<Vec<f32>>>,
-        spawner_tx: &mpsc::Sender<SpawnJob>,
-        windchest_group_id: Option<String>,
-    ) -> Result<Self> {
+    /// An idle voice with its buffers allocated, for a `VoiceSlab` slot.
+    fn with_buffers(input_buffer_capacity: usize, loader_waker: LoaderWaker) -> Self {
+        let ring = Arc::new(HeapRb::<f32>::new(VOICE_BUFFER_FRAMES * CHANNEL_COUNT));
+        Self {
+            gain: 1.0,
+            consumer: HeapCons::new(Arc::clone(&ring)),
+            is_cancelled: Arc::new(AtomicBool::new(false)),
+            fade_level: 1.0,
+            is_fading_out: false,
+            is_fading_in: false,
+            is_awaiting_release_sample: false,
+            release_voice_id: None,
+            is_waiting_for_crossfade: false,
+            phase_alignment: None,
+            delay_frames: 0,
+            release_delay_frames: 0,
+            note_on_time: Instant::now(),
+            is_attack_sample: false,
+            is_percussive: false,
+            fade_increment: 1.0,
+            windchest: None,
+            output_pair: 0,
+            pitch_ratio: 1.0,
+            input_buffer: Vec::with_capacity(input_buffer_capacity),
+            buffer_start_idx: 0,
+            cursor_pos: 0.0,
+            ring,
+            loader_waker,
+        }
+    }
+
+    /// True once the loader has pushed everything it is going to.
+    pub fn is_finished(&self) -> bool {
+        !self.consumer.write_is_held()
+    }
+
+    /// Takes samples off the ring buffer, waking the loader once it can refill it.
+    pub fn read_samples(&mut self, buffer: &mut [f32]) -> usize {
+        let vacant_before = self.consumer.vacant_len();
+        let read = self.consumer.pop_slice(buffer);
+        self.loader_waker
+            .samples_read(vacant_before, self.consumer.vacant_len());
+        read
+    }
+
+    /// Left + right level at the frame this voice plays next, and its slope per
+    /// frame. `None` if those frames aren't buffered yet.
+    pub fn current_level(&self, lead_in_frames: usize) -> Option<(f32, f32)> {
+        let frame = self.cursor_pos.floor() as usize + lead_in_frames;
+        let start = self.buffer_start_idx + frame * CHANNEL_COUNT;
+        let frames = self.input_buffer.get(start..start + 2 * CHANNEL_COUNT)?;
+        let level = frames[0] + frames[1];
+        let slope = frames[2] + frames[3] - level;
+        Some((level + slope * self.cursor_pos.fract(), slope))
+    }
+
+    /// Moves a release that hasn't played yet to `offset` frames into its
+    /// sample, keeping the interpolator's lead-in in front of it.
+    pub fn skip_to(&mut self, offset: usize, lead_in_frames: usize) {
+        if offset >= lead_in_frames {
+            // The samples before the offset replace the silent lead-in
+            self.input_buffer.clear();
+            self.buffer_start_idx = 0;
+            let vacant_before = self.consumer.vacant_len();
+            self.consumer
+                .skip((offset - lead_in_frames) * CHANNEL_COUNT);
+            self.loader_waker
+                .samples_read(vacant_before, self.consumer.vacant_len());
+        } else {
+            self.buffer_start_idx += offset * CHANNEL_COUNT;
+        }
+    }
+}
+
+impl Voice {
+    /// Readies the voice for a new sample, keeping its buffers. Only called
+    /// once the loader has let go of the ring buffer.
+    fn reset(&mut self, start: &VoiceStart, sample_rate: u32, lead_in_frames: usize) {
         let fade_frames = (sample_rate as f32 * CROSSFADE_TIME) as usize;
-        let fade_increment = if fade_frames > 0 {
+        let amplitude_ratio: AmplitudeRatio<f64> = DecibelRatio(start.gain_db as f64).into();
+
+        self.gain = amplitude_ratio.amplitude_value() as f32;
+        self.consumer.clear();
+        self.is_cancelled.store(false, Ordering::SeqCst);
+        self.fade_level = if start.start_fading_in { 0.0 } else { 1.0 };
+        self.is_fading_out = false;
+        self.is_fading_in = start.start_fading_in;
+        self.is_awaiting_release_sample = false;
+        self.release_voice_id = None;
+        self.is_waiting_for_crossfade = false;
+        self.phase_alignment = None;
+        self.delay_frames = 0;
+        self.release_delay_frames = 0;
+        self.note_on_time = start.note_on_time;
+        self.is_attack_sample = start.is_attack_sample;
+        self.is_percussive = start.is_attack_sample && start.is_percussive;
+        self.fade_increment = if fade_frames > 0 {
             1.0 / fade_frames as f32
         } else {
             1.0
         };
+        self.windchest = start.windchest;
+        self.output_pair = 0;
+        self.pitch_ratio = 1.0;
+        // Silence in front of the sample, for the interpolator's lead-in
+        self.input_buffer.clear();
+        self.input_buffer
+            .resize(lead_in_frames * CHANNEL_COUNT, 0.0);
+        self.buffer_start_idx = 0;
+        self.cursor_pos = 0.0;
+    }
+}
+
+impl Drop for Voice {
+    fn drop(&mut self) {
+        self.is_cancelled.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Handle to a voice in a `VoiceSlab`. The generation tells a reused slot
+/// apart from the voice that held it before.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoiceId {
+    slot: u32,
+    generation: u32,
+}
+
+struct VoiceSlot {
+    generation: u32,
+    is_active: bool,
+    voice: Voice,
+}
 
-        let amplitude_ratio: AmplitudeRatio<f64> = DecibelRatio(gain_db as f64).into();
-        let gain = amplitude_ratio.amplitude_value() as f32;
+/// Preallocated storage for the playing voices. Every slot owns a ring buffer
+/// and an input buffer that each voice it holds reuses, so voices come and go
+/// without touching the heap. A removed voice's slot is reused once the loader
+/// has let go of its ring buffer; while every slot is taken, no voice starts.
+pub struct VoiceSlab {
+    slots: Vec<VoiceSlot>,
+    free_slots: Vec<u32>,
+    /// Slots whose voice was removed while the loader still held its ring buffer
+    retiring_slots: Vec<u32>,
+    len: usize,
+    /// Frames of silence each voice starts with, for the interpolator's lead-in
+    lead_in_frames: usize,
+    loader: VoiceLoader,
+}
 
-        let ring_buf = HeapRb::<f32>::new(VOICE_BUFFER_FRAMES * CHANNEL_COUNT);
-        let (mut producer, consumer) = ring_buf.split();
+impl VoiceSlab {
+    /// `input_buffer_capacity` is the samples each voice's input buffer holds,
+    /// so the mixer never grows it.
+    pub fn new(
+        capacity: usize,
+        input_buffer_capacity: usize,
+        lead_in_frames: usize,
+        loader: VoiceLoader,
+    ) -> Self {
+        let slots = (0..capacity)
+            .map(|_| VoiceSlot {
+                generation: 0,
+                is_active: false,
+                voice: Voice::with_buffers(input_buffer_capacity, loader.jobs.waker()),
+            })
+            .collect();
+        // Hand out the lowest slots first
+        let free_slots = (0..capacity as u32).rev().collect();
+        Self {
+            slots,
+            free_slots,
+            retiring_slots: Vec::with_capacity(capacity),
+            len: 0,
+            lead_in_frames,
+            loader,
+        }
+    }
 
-        let is_finished = Arc::new(AtomicBool::new(false));
-        let is_cancelled = Arc::new(AtomicBool::new(false));
+    pub fn len(&self) -> usize {
+        self.len
+    }
 
-        let mut preloaded_frames_count = 0;
-        if let Some(ref preloaded) = preloaded_bytes {
-            let pushed = producer.push_slice(preloaded);
-            preloaded_frames_count = pushed / CHANNEL_COUNT;
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of voices the slab holds.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Starts a voice in a free slot and queues its sample with the loader.
+    /// Returns `None` if every slot is taken.
+    #[cfg_attr(feature = "hotpath", hotpath::measure)]
+    pub fn insert(&mut self, start: &VoiceStart) -> Option<(VoiceId, &mut Voice)> {
+        if self.free_slots.is_empty() {
+            self.reclaim_slots();
         }
+        let slot = self.free_slots.pop()?;
+        let entry = &mut self.slots[slot as usize];
+        entry.is_active = true;
+        self.len += 1;
+        let id = VoiceId {
+            slot,
+            generation: entry.generation,
+        };
+        let voice = &mut entry.voice;
+        voice.reset(start, self.loader.sample_rate, self.lead_in_frames);
 
+        let mut producer = HeapProd::new(Arc::clone(&voice.ring));
+        // The loader continues after what the preloaded head covers
+        let mut frames_to_skip = start.start_frame;
+        if let Some(preloaded) = start.preloaded {
+            let skipped = (start.start_frame * CHANNEL_COUNT).min(preloaded.len());
+            let pushed = producer.push_slice(&preloaded[skipped..]);
+            frames_to_skip = frames_to_skip.max((skipped + pushed) / CHANNEL_COUNT);
+        }
         let job = SpawnJob {
-            path: path.to_path_buf(),
-            organ: Arc::clone(&organ),
-            sample_rate,
-            is_attack_sample,
-            frames_to_skip: preloaded_frames_count,
+            organ: Arc::clone(&self.loader.organ),
+            index: Arc::clone(&self.loader.index),
+            sample: start.sample,
+            sample_rate: self.loader.sample_rate,
+            use_loop: start.is_attack_sample && !start.is_percussive,
+            frames_to_skip,
+            is_attack: start.is_attack_sample,
             producer,
-            is_finished: Arc::clone(&is_finished),
-            is_cancelled: Arc::clone(&is_cancelled),
+            is_cancelled: Arc::clone(&voice.is_cancelled),
         };
+        // The queue holds a job for every slot, so this doesn't fail. If it did,
+        // dropping the job would end the voice after its preloaded head.
+        let _ = self.loader.jobs.push(job);
+        Some((id, voice))
+    }
 
-        if let Err(e) = spawner_tx.send(job) {
-            log::error!("Failed to queue voice spawn job: {}", e);
-            is_finished.store(true, Ordering::Relaxed);
-        }
+    /// Moves the retiring slots the loader has let go of to the free list.
+    fn reclaim_slots(&mut self) {
+        let slots = &self.slots;
+        let free_slots = &mut self.free_slots;
+        self.retiring_slots.retain(|&slot| {
+            let is_released = slots[slot as usize].voice.is_finished();
+            if is_released {
+                free_slots.push(slot);
+            }
+            !is_released
+        });
+    }
 
-        Ok(Self {
-            gain,
-            consumer,
-            is_finished,
-            is_cancelled,
-            fade_level: if start_fading_in { 0.0 } else { 1.0 },
-            is_fading_out: false,
-            is_fading_in: start_fading_in,
-            is_awaiting_release_sample: false,
-            release_voice_id: None,
-            note_on_time,
-            is_attack_sample,
-            fade_increment,
-            windchest_group_id,
-            input_buffer: Vec::with_capacity(4096),
-            buffer_start_idx: 0,
-            cursor_pos: 0.0,
-        })
+    pub fn get(&self, id: VoiceId) -> Option<&Voice> {
+        self.slots
+            .get(id.slot as usize)
+            .filter(|entry| entry.is_active && entry.generation == id.generation)
+            .map(|entry| &entry.voice)
     }
-}
 
-impl Drop for Voice {
-    fn drop(&mut self) {
-        self.is_cancelled.store(true, Ordering::SeqCst);
+    pub fn get_mut(&mut self, id: VoiceId) -> Option<&mut Voice> {
+        self.slots
+            .get_mut(id.slot as usize)
+            .filter(|entry| entry.is_active && entry.generation == id.generation)
+            .map(|entry| &mut entry.voice)
+    }
+
+    /// Stops a voice and tells its loader to stop. Returns false if it had
+    /// already been removed.
+    pub fn remove(&mut self, id: VoiceId) -> bool {
+        let Some(entry) = self
+            .slots
+            .get_mut(id.slot as usize)
+            .filter(|entry| entry.is_active && entry.generation == id.generation)
+        else {
+            return false;
+        };
+        entry.voice.is_cancelled.store(true, Ordering::SeqCst);
+        // A parked stream is only dropped, letting go of the slot, once the loader looks
+        entry.voice.loader_waker.wake();
+        entry.is_active = false;
+        entry.generation = entry.generation.wrapping_add(1);
+        self.retiring_slots.push(id.slot);
+        self.len -= 1;
+        true
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (VoiceId, &Voice)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_active)
+            .map(|(slot, entry)| {
+                let id = VoiceId {
+                    slot: slot as u32,
+                    generation: entry.generation,
+                };
+                (id, &entry.voice)
+            })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (VoiceId, &mut Voice)> {
+        self.slots
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, entry)| entry.is_active)
+            .map(|(slot, entry)| {
+                let id = VoiceId {
+                    slot: slot as u32,
+                    generation: entry.generation,
+                };
+                (id, &mut entry.voice)
+            })
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Voice> {
+        self.slots
+            .iter()
+            .filter(|entry| entry.is_active)
+            .map(|entry| &entry.voice)
     }
 }
@@ -32,6 +32,16 @@ impl std::fmt::Display for IsWavPackError {
 }
 impl std::error::Error for IsWavPackError {}
 
+// Custom error type to signal a FLAC file (a compressed cache entry)
+#[derive(Debug)]
+pub struct IsFlacError;
+impl std::fmt::Display for IsFlacError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "File appears to be FLAC")
+    }
+}
+impl std::error::Error for IsFlacError {}
+
 /// Parses all necessary metadata from a WAV file in one pass.
 pub fn parse_wav_metadata<R: Read + Seek>(
     reader: &mut R,
@@ -44,6 +54,9 @@ pub fn parse_wav_metadata<R: Read + Seek>(
     if &header == b"wvpk" {
         return Err(Error::new(IsWavPackError));
     }
+    if &header == b"fLaC" {
+        return Err(Error::new(IsFlacError));
+    }
 
     // --- Standard RIFF Check ---
     if &header != b"RIFF" {
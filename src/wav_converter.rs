@@ -5,18 +5,24 @@ use rubato::{
     Async, FixedAsync, Indexing, Resampler, SincInterpolationParameters, SincInterpolationType,
     WindowFunction,
 };
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{BufReader, BufWriter, Cursor, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use symphonia::core::audio::SampleBuffer;
 use symphonia::core::io::MediaSourceStream;
 use symphonia::core::probe::Hint;
 
-use crate::wav::{IsWavPackError, OtherChunk, WavFmt, parse_smpl_chunk};
+use crate::flac::{FlacSampleReader, FlacWriter, read_flac_metadata};
+use crate::wav::{IsFlacError, IsWavPackError, OtherChunk, WavFmt, parse_smpl_chunk};
 
 const I16_MAX_F: f32 = 32768.0; // 2^15
 const I24_MAX_F: f32 = 8388608.0; // 2^23
 const I32_MAX_F: f32 = 2147483648.0; // 2^31
+/// Input frames the cache resampler processes at a time.
+const RESAMPLER_CHUNK_FRAMES: usize = 1024;
 
 #[derive(Debug)]
 pub struct SampleMetadata {
@@ -24,6 +30,382 @@ pub struct SampleMetadata {
     pub channel_count: u16,
 }
 
+/// File format of the resampled samples written to an organ's cache.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+pub enum CacheFormat {
+    /// Uncompressed WAV. Cheapest to stream.
+    #[default]
+    Wav,
+    /// Lossless FLAC, roughly half the disk space. Decoded as the samples stream.
+    Flac,
+}
+
+impl CacheFormat {
+    /// The format a sample is cached in. FLAC holds 16 and 24-bit integer
+    /// samples only, so float and 32-bit samples stay WAV.
+    fn for_sample(self, bits_per_sample: u16, is_float: bool) -> CacheFormat {
+        match self {
+            CacheFormat::Flac if !is_float && matches!(bits_per_sample, 16 | 24) => {
+                CacheFormat::Flac
+            }
+            _ => CacheFormat::Wav,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            CacheFormat::Wav => "wav",
+            CacheFormat::Flac => "flac",
+        }
+    }
+}
+
+impl FromStr for CacheFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "wav" => Ok(CacheFormat::Wav),
+            "flac" => Ok(CacheFormat::Flac),
+            _ => Err(anyhow!(
+                "Unknown cache format {:?} (expected wav or flac)",
+                s
+            )),
+        }
+    }
+}
+
+/// Name of a converted sample in an organ's cache:
+/// `{stem}[.rel].{rate}hz.p{cents}.{bits}b.{wav|flac}`. Release tails cut from
+/// an attack sample carry the `.rel` tag.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CacheFileName {
+    pub stem: String,
+    pub is_release: bool,
+    pub sample_rate: u32,
+    pub pitch_tuning_cents: f32,
+    pub bits_per_sample: u16,
+    pub format: CacheFormat,
+}
+
+impl CacheFileName {
+    /// Splits a cache file name into its parts. None for anything else in the
+    /// cache directory.
+    pub fn parse(file_name: &str) -> Option<Self> {
+        let (rest, format) = if let Some(rest) = file_name.strip_suffix(".wav") {
+            (rest, CacheFormat::Wav)
+        } else {
+            (file_name.strip_suffix(".flac")?, CacheFormat::Flac)
+        };
+        let (rest, bits) = rest.rsplit_once('.')?;
+        let bits_per_sample = bits.strip_suffix('b')?.parse().ok()?;
+        // The cents carry one decimal, so they span two dot-separated parts
+        let (rest, cents_fraction) = rest.rsplit_once('.')?;
+        let (rest, cents) = rest.rsplit_once('.')?;
+        let pitch_tuning_cents = format!("{}.{}", cents.strip_prefix('p')?, cents_fraction)
+            .parse()
+            .ok()?;
+        let (rest, rate) = rest.rsplit_once('.')?;
+        let sample_rate = rate.strip_suffix("hz")?.parse().ok()?;
+        let (stem, is_release) = match rest.strip_suffix(".rel") {
+            Some(stem) => (stem, true),
+            None => (rest, false),
+        };
+        Some(Self {
+            stem: stem.to_string(),
+            is_release,
+            sample_rate,
+            pitch_tuning_cents,
+            bits_per_sample,
+            format,
+        })
+    }
+}
+
+impl fmt::Display for CacheFileName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{}.{}hz.p{:+0.1}.{}b.{}",
+            self.stem,
+            if self.is_release { ".rel" } else { "" },
+            self.sample_rate,
+            self.pitch_tuning_cents,
+            self.bits_per_sample,
+            self.format.extension()
+        )
+    }
+}
+
+/// Format of a source sample, as far as its conversion depends on it.
+#[derive(Debug, Clone)]
+pub struct SourceFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits_per_sample: u16,
+    pub is_float: bool,
+    pub is_wavpack: bool,
+    /// Length in frames, 0 if the container doesn't say.
+    pub frames: u64,
+}
+
+impl SourceFormat {
+    /// Reads the format from a WAV header, or by probing a WavPack file.
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        match crate::wav::parse_wav_metadata(&mut reader, path) {
+            Ok((fmt, _, _, data_size)) => Ok(Self::from_wav(&fmt, data_size)),
+            Err(e) if e.is::<IsWavPackError>() => peek_wavpack_info(path),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads the format from the first bytes of a WAV file, e.g. the head of
+    /// an archive entry. The data chunk needn't be included.
+    pub fn read_wav_head(head: &[u8], path: &Path) -> Result<Self> {
+        let (fmt, _, _, data_size) = crate::wav::parse_wav_metadata(&mut Cursor::new(head), path)?;
+        Ok(Self::from_wav(&fmt, data_size))
+    }
+
+    fn from_wav(fmt: &WavFmt, data_size: u32) -> Self {
+        let block_align = (fmt.num_channels as u64 * fmt.bits_per_sample as u64 / 8).max(1);
+        Self {
+            sample_rate: fmt.sample_rate,
+            channels: fmt.num_channels,
+            bits_per_sample: fmt.bits_per_sample,
+            is_float: fmt.audio_format == 3,
+            is_wavpack: false,
+            frames: data_size as u64 / block_align,
+        }
+    }
+
+    /// The cache file a sample with this source is converted into, or None when
+    /// the attack needs no conversion and is played from the source. Release
+    /// tails are always cut into a cache file.
+    pub fn cache_file_name(
+        &self,
+        stem: &str,
+        is_release: bool,
+        pitch_tuning_cents: f32,
+        convert_to_16_bit: bool,
+        cache_format: CacheFormat,
+        target_sample_rate: u32,
+    ) -> Option<CacheFileName> {
+        let target_is_float = self.is_float && !convert_to_16_bit;
+        let target_bits = if convert_to_16_bit {
+            16
+        } else if target_is_float {
+            32
+        } else {
+            self.bits_per_sample
+        };
+
+        let needs_resample = self.sample_rate != target_sample_rate || pitch_tuning_cents != 0.0;
+        let needs_bit_change =
+            target_bits != self.bits_per_sample || (self.is_float && !target_is_float);
+        if !is_release && !needs_resample && !needs_bit_change && !self.is_wavpack {
+            return None;
+        }
+
+        Some(CacheFileName {
+            stem: stem.to_string(),
+            is_release,
+            sample_rate: target_sample_rate,
+            pitch_tuning_cents,
+            bits_per_sample: target_bits,
+            format: cache_format.for_sample(target_bits, target_is_float),
+        })
+    }
+
+    /// Frames a cache file converted from this source holds. The resampler
+    /// pads the input to whole chunks, give or take a few frames of rounding.
+    pub fn converted_frames(&self, pitch_tuning_cents: f32, target_sample_rate: u32) -> u64 {
+        if self.sample_rate == target_sample_rate && pitch_tuning_cents == 0.0 {
+            return self.frames;
+        }
+        let pitch_factor = 2.0f64.powf(-pitch_tuning_cents as f64 / 1200.0);
+        let effective_input_rate = self.sample_rate as f64 / pitch_factor;
+        let padded_frames = self.frames.next_multiple_of(RESAMPLER_CHUNK_FRAMES as u64);
+        (padded_frames as f64 * target_sample_rate as f64 / effective_input_rate).round() as u64
+    }
+}
+
+/// Slope bins of the release alignment table (falling, rising).
+const PHASE_ALIGN_SLOPES: usize = 2;
+/// Amplitude bins of the release alignment table.
+const PHASE_ALIGN_AMPLITUDES: usize = 32;
+/// Lowest fundamental the alignment handles. One period of it is searched.
+const PHASE_ALIGN_MIN_FREQUENCY: u32 = 20;
+/// Periods of the lowest fundamental an attack loop is analysed over.
+const ATTACK_LOOP_ANALYSIS_PERIODS: u32 = 4;
+
+/// Cache-time analysis that lets a release start in phase with the attack it
+/// takes over from, instead of at frame 0 (which comb-filters during the
+/// crossfade). Levels are of the left + right sum, as the mixer sees it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PhaseAlignment {
+    /// Peak level over the analysed range: the attack's loop, or the release's head.
+    pub peak_amplitude: f32,
+    /// Releases only: for each slope and amplitude bin, the first frame of the
+    /// head where the release passes through it. Empty for attacks.
+    pub release_offsets: Vec<u32>,
+}
+
+/// Table bin for a level (relative to its peak) and slope.
+fn phase_align_bin(relative_level: f32, slope: f32) -> usize {
+    let amplitude = ((relative_level * 0.5 + 0.5) * PHASE_ALIGN_AMPLITUDES as f32) as isize;
+    let amplitude = amplitude.clamp(0, PHASE_ALIGN_AMPLITUDES as isize - 1) as usize;
+    let slope_bin = usize::from(slope >= 0.0);
+    slope_bin * PHASE_ALIGN_AMPLITUDES + amplitude
+}
+
+impl PhaseAlignment {
+    /// Frame of the release head that continues an attack currently at `level`
+    /// and moving by `slope` per frame. `attack_peak` is the attack's own peak,
+    /// so loud attacks and soft releases still match by phase.
+    pub fn release_offset(&self, level: f32, slope: f32, attack_peak: f32) -> usize {
+        if self.release_offsets.is_empty() || attack_peak <= 0.0 {
+            return 0;
+        }
+        self.release_offsets[phase_align_bin(level / attack_peak, slope)] as usize
+    }
+}
+
+/// Left + right sums of a range of frames, and the sample's loop (start, end).
+type LevelRange = (Vec<f32>, Option<(u32, u32)>);
+
+/// Reads up to `frame_count` frames from `start_frame` on as left + right sums
+/// (mono doubled, like the loader does). Also returns the `smpl` loop, if any.
+fn read_level_range(
+    path: &Path,
+    target_sample_rate: u32,
+    start_frame: u32,
+    frame_count: u32,
+) -> Result<LevelRange> {
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    match crate::wav::parse_wav_metadata(&mut reader, path) {
+        Ok((fmt, chunks, data_offset, data_size)) => {
+            if fmt.sample_rate != target_sample_rate {
+                return Err(anyhow!(
+                    "Sample rate mismatch in alignment analysis: {} != {}",
+                    fmt.sample_rate,
+                    target_sample_rate
+                ));
+            }
+            let loop_info = chunks
+                .iter()
+                .find(|chunk| &chunk.id == b"smpl")
+                .and_then(|chunk| parse_smpl_chunk(&chunk.data));
+            let block_align = (fmt.bits_per_sample / 8) as u32 * fmt.num_channels as u32;
+            let total_frames = data_size / block_align;
+            let start_frame = start_frame.min(total_frames);
+            let frames = frame_count.min(total_frames - start_frame);
+            reader.seek(SeekFrom::Start(
+                data_offset + start_frame as u64 * block_align as u64,
+            ))?;
+            let waves = read_f32_waves(reader, fmt, frames * block_align)?;
+            Ok((stereo_sum(&waves), loop_info))
+        }
+        Err(e) if e.is::<IsWavPackError>() => {
+            // No loop points from WavPack (see `load_sample_as_f32`), so only heads
+            let head = load_sample_head(
+                path,
+                target_sample_rate,
+                (start_frame + frame_count) as usize,
+            )?;
+            let levels = head
+                .chunks_exact(2)
+                .skip(start_frame as usize)
+                .map(|frame| frame[0] + frame[1])
+                .collect();
+            Ok((levels, None))
+        }
+        Err(e) if e.is::<IsFlacError>() => {
+            let (fmt, loop_info, decoder) = open_flac_cache(path, target_sample_rate)?;
+            let channels = fmt.num_channels.max(1) as usize;
+            let samples: Vec<f32> = decoder
+                .skip(start_frame as usize * channels)
+                .take(frame_count as usize * channels)
+                .collect();
+            let levels = samples
+                .chunks_exact(channels)
+                .map(|frame| match frame {
+                    [mono] => mono * 2.0,
+                    [left, right, ..] => left + right,
+                    [] => 0.0,
+                })
+                .collect();
+            Ok((levels, loop_info))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn stereo_sum(waves: &[Vec<f32>]) -> Vec<f32> {
+    match waves {
+        [] => Vec::new(),
+        [mono] => mono.iter().map(|s| s * 2.0).collect(),
+        [left, right, ..] => left.iter().zip(right).map(|(l, r)| l + r).collect(),
+    }
+}
+
+/// Measures the level of an attack's loop. `None` if the sample has no loop.
+pub fn analyse_attack_loop(path: &Path, target_sample_rate: u32) -> Result<Option<PhaseAlignment>> {
+    let window = target_sample_rate / PHASE_ALIGN_MIN_FREQUENCY * ATTACK_LOOP_ANALYSIS_PERIODS;
+    // Read the header first to find the loop, then the loop itself
+    let (_, loop_info) = read_level_range(path, target_sample_rate, 0, 0)?;
+    let Some((loop_start, loop_end)) = loop_info else {
+        return Ok(None);
+    };
+    let loop_len = if loop_end > loop_start {
+        loop_end - loop_start
+    } else {
+        window
+    };
+    let (levels, _) = read_level_range(path, target_sample_rate, loop_start, loop_len.min(window))?;
+    let peak_amplitude = levels.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    Ok((peak_amplitude > 0.0).then_some(PhaseAlignment {
+        peak_amplitude,
+        release_offsets: Vec::new(),
+    }))
+}
+
+/// Builds the alignment table of a release from one period of the lowest
+/// fundamental at its start, after GrandOrgue's release alignment.
+pub fn analyse_release_head(path: &Path, target_sample_rate: u32) -> Result<PhaseAlignment> {
+    let window = target_sample_rate / PHASE_ALIGN_MIN_FREQUENCY;
+    let (levels, _) = read_level_range(path, target_sample_rate, 0, window + 1)?;
+    let peak_amplitude = levels.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    if peak_amplitude <= 0.0 || levels.len() < 2 {
+        return Ok(PhaseAlignment::default());
+    }
+
+    let bins = PHASE_ALIGN_SLOPES * PHASE_ALIGN_AMPLITUDES;
+    let mut found: Vec<Option<u32>> = vec![None; bins];
+    for (frame, pair) in levels.windows(2).enumerate() {
+        let bin = phase_align_bin(pair[1] / peak_amplitude, pair[1] - pair[0]);
+        found[bin].get_or_insert(frame as u32 + 1);
+    }
+
+    // Levels the head never passes through take the nearest one it does
+    let mut release_offsets = vec![0; bins];
+    for (slope, row) in found.chunks(PHASE_ALIGN_AMPLITUDES).enumerate() {
+        for amplitude in 0..PHASE_ALIGN_AMPLITUDES {
+            let nearest = (0..PHASE_ALIGN_AMPLITUDES).find_map(|distance| {
+                let below = amplitude.checked_sub(distance).and_then(|i| row[i]);
+                let above = row.get(amplitude + distance).copied().flatten();
+                below.or(above)
+            });
+            release_offsets[slope * PHASE_ALIGN_AMPLITUDES + amplitude] = nearest.unwrap_or(0);
+        }
+    }
+    Ok(PhaseAlignment {
+        peak_amplitude,
+        release_offsets,
+    })
+}
+
 /// Helper to read a 24-bit sample from a reader
 fn read_i24<R: Read>(reader: &mut R) -> std::io::Result<i32> {
     let b1 = reader.read_u8()? as i32;
@@ -62,6 +444,16 @@ fn read_f32_waves<R: Read>(mut reader: R, format: WavFmt, data_size: u32) -> Res
     Ok(output_waves)
 }
 
+/// Integer value of a sample at `bits` depth, as stored in the cache.
+fn quantize(sample: f32, bits: u16) -> i32 {
+    let max = match bits {
+        16 => I16_MAX_F,
+        24 => I24_MAX_F,
+        _ => I32_MAX_F,
+    };
+    (sample.clamp(-1.0, 1.0) * (max - 1.0)) as i32
+}
+
 /// Helper to convert f32 waves into an interleaved byte buffer
 fn write_f32_waves_to_bytes(
     waves: &[Vec<f32>],
@@ -86,12 +478,10 @@ fn write_f32_waves_to_bytes(
                     output_bytes.write_f32::<LittleEndian>(sample_f32)?;
                 }
                 (false, 16) => {
-                    let sample_i16 = (sample_f32.clamp(-1.0, 1.0) * (I16_MAX_F - 1.0)) as i16;
-                    output_bytes.write_i16::<LittleEndian>(sample_i16)?;
+                    output_bytes.write_i16::<LittleEndian>(quantize(sample_f32, 16) as i16)?;
                 }
                 (false, 24) => {
-                    let sample_i32 = (sample_f32.clamp(-1.0, 1.0) * (I24_MAX_F - 1.0)) as i32;
-                    output_bytes.write_i24::<LittleEndian>(sample_i32)?;
+                    output_bytes.write_i24::<LittleEndian>(quantize(sample_f32, 24))?;
                 }
                 (false, 32) => {
                     let sample_i32 = (sample_f32.clamp(-1.0, 1.0) * (I32_MAX_F - 1.0)) as i32;
@@ -110,6 +500,50 @@ fn write_f32_waves_to_bytes(
     Ok(output_bytes)
 }
 
+/// Writes resampled waves to a FLAC cache file at `bits` (16 or 24), with the
+/// same quantization as the WAV cache. `chunks` keep their loops and markers.
+fn write_flac_cache_file(
+    path: &Path,
+    waves: &[Vec<f32>],
+    channels: u16,
+    sample_rate: u32,
+    bits: u16,
+    chunks: &[OtherChunk],
+) -> Result<()> {
+    let mut writer =
+        FlacWriter::create_pcm(path, sample_rate, channels as usize, bits as u32, chunks)
+            .with_context(|| format!("Failed to create cache file {:?}", path))?;
+    let num_frames = waves.first().map_or(0, Vec::len);
+    let mut interleaved = Vec::with_capacity(num_frames * waves.len());
+    for i in 0..num_frames {
+        for wave in waves {
+            interleaved.push(quantize(wave[i], bits));
+        }
+    }
+    writer.write_interleaved_pcm(&interleaved)?;
+    writer.finalize()
+}
+
+/// A FLAC cache file's format, `smpl` loop (if any) and sample decoder.
+type FlacCacheEntry = (WavFmt, Option<(u32, u32)>, FlacSampleReader);
+
+/// Opens a FLAC cache file for decoding.
+fn open_flac_cache(path: &Path, target_sample_rate: u32) -> Result<FlacCacheEntry> {
+    let (fmt, chunks) = read_flac_metadata(path)?;
+    if fmt.sample_rate != target_sample_rate {
+        return Err(anyhow!(
+            "Sample rate mismatch in cache (FLAC): {} != {}",
+            fmt.sample_rate,
+            target_sample_rate
+        ));
+    }
+    let loop_info = chunks
+        .iter()
+        .find(|chunk| &chunk.id == b"smpl")
+        .and_then(|chunk| parse_smpl_chunk(&chunk.data));
+    Ok((fmt, loop_info, FlacSampleReader::open(path)?))
+}
+
 /// Helper function to scale loop points within a 'smpl' chunk's binary data.
 /// This modifies the `smpl_data` buffer in place.
 fn scale_smpl_chunk_loops(smpl_data: &mut Vec<u8>, ratio: f64, new_sample_rate: u32) -> Result<()> {
@@ -270,7 +704,7 @@ fn scale_cue_chunk_markers(cue_data: &mut Vec<u8>, ratio: f64) -> Result<()> {
 // --- WAVPACK SUPPORT ---
 
 /// Fast probe to get metadata without decoding the whole file.
-fn peek_wavpack_info(path: &Path) -> Result<(u32, u16, u16, bool)> {
+fn peek_wavpack_info(path: &Path) -> Result<SourceFormat> {
     let src = File::open(path)
         .with_context(|| format!("Failed to open WavPack file for peeking: {:?}", path))?;
     let mss = MediaSourceStream::new(Box::new(src), Default::default());
@@ -298,7 +732,14 @@ fn peek_wavpack_info(path: &Path) -> Result<(u32, u16, u16, bool)> {
     // We treat it as potential float for conversion logic purposes.
     let is_float = true;
 
-    Ok((sample_rate, channels, bits_per_sample, is_float))
+    Ok(SourceFormat {
+        sample_rate,
+        channels,
+        bits_per_sample,
+        is_float,
+        is_wavpack: true,
+        frames: params.n_frames.unwrap_or(0),
+    })
 }
 
 /// Uses Symphonia to read audio data. This supports WavPack and others.
@@ -471,6 +912,14 @@ pub fn load_sample_as_f32(
                     }
                 }
                 Ok((interleaved, metadata))
+            } else if e.is::<IsFlacError>() {
+                // Compressed cache entry, loops kept in its metadata
+                let (fmt, loop_info, decoder) = open_flac_cache(path, target_sample_rate)?;
+                let metadata = SampleMetadata {
+                    loop_info,
+                    channel_count: fmt.num_channels,
+                };
+                Ok((decoder.collect(), metadata))
             } else {
                 // Real error
                 Err(e)
@@ -479,13 +928,15 @@ pub fn load_sample_as_f32(
     }
 }
 
-/// Checks and processes audio file. Supports WavPack input, always outputs WAV to cache.
+/// Checks and processes audio file. Supports WavPack input, outputs WAV or
+/// FLAC (per `cache_format`) to cache.
 pub fn process_sample_file(
     relative_path: &Path,
     base_dir: &Path,
     cache_dir: &Path,
     pitch_tuning_cents: f32,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     target_sample_rate: u32,
 ) -> Result<PathBuf> {
     let full_source_path = base_dir.join(relative_path);
@@ -495,68 +946,45 @@ pub fn process_sample_file(
 
     // --- Format Detection Phase ---
     // We need format details to decide if we skip processing.
-    let mut input_waves: Vec<Vec<f32>> = Vec::new();
-    let sample_rate;
-    let bits_per_sample;
-    let channels;
-    let is_float;
     let mut other_chunks: Vec<OtherChunk> = Vec::new();
 
     let file = File::open(&full_source_path)
         .with_context(|| format!("Failed to open source file: {:?}", full_source_path))?;
     let mut reader = BufReader::new(file);
 
-    match crate::wav::parse_wav_metadata(&mut reader, &full_source_path) {
-        Ok((fmt, chunks, _data_offset, _data_size)) => {
+    let source = match crate::wav::parse_wav_metadata(&mut reader, &full_source_path) {
+        Ok((fmt, chunks, _data_offset, data_size)) => {
             // It is a WAV
-            sample_rate = fmt.sample_rate;
-            bits_per_sample = fmt.bits_per_sample;
-            channels = fmt.num_channels;
-            is_float = fmt.audio_format == 3;
             other_chunks = chunks;
-            input_waves = Vec::new();
-        }
-        Err(e) if e.is::<IsWavPackError>() => {
-            let (rate, ch, bits, float) = peek_wavpack_info(&full_source_path)?;
-            sample_rate = rate;
-            channels = ch;
-            bits_per_sample = bits;
-            is_float = float;
+            SourceFormat::from_wav(&fmt, data_size)
         }
+        Err(e) if e.is::<IsWavPackError>() => peek_wavpack_info(&full_source_path)?,
         Err(e) => {
             return Err(e)
                 .with_context(|| format!("Failed to parse metadata for {:?}", full_source_path));
         }
-    }
-
-    let mut target_bits = if convert_to_16_bit {
-        16
-    } else {
-        bits_per_sample
     };
-    let target_is_float = is_float && !convert_to_16_bit;
-    if target_is_float {
-        target_bits = 32;
-    }
-
-    let needs_resample = sample_rate != target_sample_rate || pitch_tuning_cents != 0.0;
-    let needs_bit_change = target_bits != bits_per_sample || (is_float && !target_is_float);
-    let is_source_wavpack = other_chunks.is_empty() && input_waves.is_empty();
-
-    if !needs_resample && !needs_bit_change && !is_source_wavpack {
-        return Ok(full_source_path);
-    }
 
     // --- Generate Cache Filename ---
     let original_stem = relative_path
         .file_stem()
         .unwrap_or_default()
         .to_string_lossy();
-    // Always use .wav extension for cache
-    let new_file_name = format!(
-        "{}.{}hz.p{:+0.1}.{}b.wav",
-        original_stem, target_sample_rate, pitch_tuning_cents, target_bits
-    );
+    let Some(cache_file_name) = source.cache_file_name(
+        &original_stem,
+        false,
+        pitch_tuning_cents,
+        convert_to_16_bit,
+        cache_format,
+        target_sample_rate,
+    ) else {
+        return Ok(full_source_path);
+    };
+    let target_bits = cache_file_name.bits_per_sample;
+    let target_is_float = source.is_float && !convert_to_16_bit;
+    let cache_format = cache_file_name.format;
+    let needs_resample = source.sample_rate != target_sample_rate || pitch_tuning_cents != 0.0;
+    let channels = source.channels;
 
     let parent_in_cache = if let Some(parent) = relative_path.parent() {
         cache_dir.join(parent)
@@ -564,7 +992,7 @@ pub fn process_sample_file(
         cache_dir.to_path_buf()
     };
 
-    let cache_full_path = parent_in_cache.join(new_file_name);
+    let cache_full_path = parent_in_cache.join(cache_file_name.to_string());
 
     if cache_full_path.exists() {
         return Ok(cache_full_path);
@@ -577,29 +1005,29 @@ pub fn process_sample_file(
     );
 
     // Now we actually read the data
-    if is_source_wavpack {
+    let input_waves = if source.is_wavpack {
         // Full decode of WavPack
         let (waves, _, _, _) = read_wavpack_file(&full_source_path)?;
-        input_waves = waves;
+        waves
     } else {
         let file = File::open(&full_source_path)?;
         let mut reader = BufReader::new(file);
         let (_, _, data_offset, data_size) =
             crate::wav::parse_wav_metadata(&mut reader, &full_source_path)?;
         let fmt = WavFmt {
-            audio_format: if is_float { 3 } else { 1 },
+            audio_format: if source.is_float { 3 } else { 1 },
             num_channels: channels,
-            sample_rate,
-            bits_per_sample,
+            sample_rate: source.sample_rate,
+            bits_per_sample: source.bits_per_sample,
         };
         reader.seek(SeekFrom::Start(data_offset))?;
-        input_waves = read_f32_waves(reader, fmt, data_size)
-            .with_context(|| format!("Failed to read PCM data from {:?}", full_source_path))?;
-    }
+        read_f32_waves(reader, fmt, data_size)
+            .with_context(|| format!("Failed to read PCM data from {:?}", full_source_path))?
+    };
 
     let resample_ratio = if needs_resample {
         let pitch_factor = 2.0f64.powf(-pitch_tuning_cents as f64 / 1200.0);
-        let effective_input_rate = sample_rate as f64 / pitch_factor;
+        let effective_input_rate = source.sample_rate as f64 / pitch_factor;
         target_sample_rate as f64 / effective_input_rate
     } else {
         1.0
@@ -627,7 +1055,7 @@ pub fn process_sample_file(
             window: WindowFunction::BlackmanHarris,
         };
 
-        let chunk_size = 1024;
+        let chunk_size = RESAMPLER_CHUNK_FRAMES;
         let mut resampler = Async::<f32>::new_sinc(
             resample_ratio,
             1.1, // Max ratio
@@ -719,6 +1147,18 @@ pub fn process_sample_file(
         }
     }
 
+    if cache_format == CacheFormat::Flac {
+        write_flac_cache_file(
+            &cache_full_path,
+            &output_waves,
+            channels,
+            target_sample_rate,
+            target_bits,
+            &other_chunks,
+        )?;
+        return Ok(cache_full_path);
+    }
+
     // Write to WAV
     let final_data_chunk = write_f32_waves_to_bytes(&output_waves, target_bits, target_is_float)?;
     let out_file = File::create(&cache_full_path)
@@ -901,6 +1341,18 @@ pub fn load_sample_head(
 
             Ok(interleaved_stereo)
         }
+        Err(e) if e.is::<IsFlacError>() => {
+            // --- FLAC CACHE PATH ---
+            let (fmt, _, decoder) = open_flac_cache(path, target_sample_rate)?;
+            let channels = fmt.num_channels.max(1) as usize;
+            let samples: Vec<f32> = decoder.take(max_frames * channels).collect();
+            let mut interleaved_stereo = Vec::with_capacity(samples.len() / channels * 2);
+            for frame in samples.chunks_exact(channels) {
+                interleaved_stereo.push(frame[0]); // L
+                interleaved_stereo.push(if channels == 1 { frame[0] } else { frame[1] }); // R
+            }
+            Ok(interleaved_stereo)
+        }
         Err(e) => {
             Err(e).with_context(|| format!("Failed to parse metadata for head load: {:?}", path))
         }
@@ -915,6 +1367,7 @@ pub fn try_extract_release_sample(
     cache_dir: &Path,
     pitch_tuning_cents: f32,
     convert_to_16_bit: bool,
+    cache_format: CacheFormat,
     target_sample_rate: u32,
 ) -> Result<Option<PathBuf>> {
     let full_source_path = base_dir.join(relative_path);
@@ -974,22 +1427,26 @@ pub fn try_extract_release_sample(
         .unwrap_or_default()
         .to_string_lossy();
     // We add ".rel" to the filename
-    let target_bits = if convert_to_16_bit {
-        16
-    } else {
-        fmt.bits_per_sample
+    let Some(cache_file_name) = SourceFormat::from_wav(&fmt, data_size).cache_file_name(
+        &original_stem,
+        true,
+        pitch_tuning_cents,
+        convert_to_16_bit,
+        cache_format,
+        target_sample_rate,
+    ) else {
+        return Ok(None);
     };
-    let new_file_name = format!(
-        "{}.rel.{}hz.p{:+0.1}.{}b.wav",
-        original_stem, target_sample_rate, pitch_tuning_cents, target_bits
-    );
+    let target_bits = cache_file_name.bits_per_sample;
+    let target_is_float = fmt.audio_format == 3 && !convert_to_16_bit;
+    let cache_format = cache_file_name.format;
 
     let parent_in_cache = if let Some(parent) = relative_path.parent() {
         cache_dir.join(parent)
     } else {
         cache_dir.to_path_buf()
     };
-    let cache_full_path = parent_in_cache.join(new_file_name);
+    let cache_full_path = parent_in_cache.join(cache_file_name.to_string());
 
     if cache_full_path.exists() {
         return Ok(Some(cache_full_path));
@@ -1047,7 +1504,7 @@ pub fn try_extract_release_sample(
             window: WindowFunction::BlackmanHarris,
         };
 
-        let chunk_size = 1024;
+        let chunk_size = RESAMPLER_CHUNK_FRAMES;
         let mut resampler = Async::<f32>::new_sinc(
             resample_ratio,
             1.1,
@@ -1117,7 +1574,17 @@ pub fn try_extract_release_sample(
 
     // Write to Cache
     fs::create_dir_all(&parent_in_cache)?;
-    let target_is_float = fmt.audio_format == 3 && !convert_to_16_bit;
+    if cache_format == CacheFormat::Flac {
+        write_flac_cache_file(
+            &cache_full_path,
+            &output_waves,
+            fmt.num_channels,
+            target_sample_rate,
+            target_bits,
+            &[],
+        )?;
+        return Ok(Some(cache_full_path));
+    }
     let target_bits = if target_is_float { 32 } else { target_bits }; // Ensure 32 for float
 
     let final_data = write_f32_waves_to_bytes(&output_waves, target_bits, target_is_float)?;